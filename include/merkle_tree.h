@@ -0,0 +1,46 @@
+#ifndef MERKLE_TREE_H
+#define MERKLE_TREE_H
+
+/* Generated with cbindgen from src/ffi.rs (see cbindgen.toml). Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define MT_OK 0
+
+#define MT_ERR_NULL 1
+
+#define MT_ERR_EMPTY 2
+
+#define MT_ERR_INDEX 3
+
+#define MT_ERR_BUFFER_TOO_SMALL 4
+
+#define MT_ERR_DECODE 5
+
+#define MT_ERR_PANIC 6
+
+typedef struct MerkleTreeHandle MerkleTreeHandle;
+
+MerkleTreeHandle *mt_build(const uint64_t *leaf_hashes, size_t n);
+
+int32_t mt_root(const MerkleTreeHandle *handle, uint64_t *out_root);
+
+int32_t mt_get_proof(const MerkleTreeHandle *handle,
+                     size_t index,
+                     uint8_t *buffer,
+                     size_t buffer_len,
+                     size_t *out_len);
+
+int32_t mt_proof_verify(const uint8_t *proof,
+                        size_t proof_len,
+                        uint64_t leaf_hash,
+                        uint64_t root,
+                        bool *out_valid);
+
+void mt_free(MerkleTreeHandle *handle);
+
+#endif /* MERKLE_TREE_H */
@@ -0,0 +1,152 @@
+use std::hash::Hash;
+
+use crate::{MerkleError, MerkleTreeBuilder};
+
+/// Computes the root of a stream of elements without ever holding their leaves: only
+/// the roots of the complete subtrees formed so far are kept, `O(log n)` hashes, and
+/// they are combined when the stream ends. The root equals the one of `MerkleTree::build`
+/// over every element pushed.
+/// Accumulators over consecutive parts of a stream can be merged, as long as the left
+/// one's subtrees line up with the right one's (see `merge`), which lets the parts be
+/// hashed in parallel.
+#[derive(Clone, Debug, Default)]
+pub struct RootAccumulator {
+    builder: MerkleTreeBuilder,
+}
+
+impl RootAccumulator {
+    /// Creates an accumulator without elements.
+    pub fn new() -> RootAccumulator {
+        RootAccumulator::default()
+    }
+
+    /// Returns the amount of elements pushed.
+    pub fn len(&self) -> usize {
+        usize::try_from(self.builder.len()).expect("Pushed elements are counted in a usize")
+    }
+
+    /// Returns whether no element was pushed.
+    pub fn is_empty(&self) -> bool {
+        self.builder.is_empty()
+    }
+
+    /// Pushes an `Hash` element as the next leaf.
+    /// * `value` - The `Hash` value to be added.
+    pub fn push<H: Hash>(&mut self, value: H) {
+        self.builder.push(value);
+    }
+
+    /// Pushes an already hashed leaf.
+    /// * `leaf` - The leaf hash to be added.
+    pub fn push_hash(&mut self, leaf: u64) {
+        self.builder.push_leaf_hash(leaf);
+    }
+
+    /// Appends the elements accumulated by another accumulator, as if they had been
+    /// pushed into this one.
+    /// Only the roots of their complete subtrees are known, so each of them must cover
+    /// a node of the merged tree: the length must be a multiple of the size of the
+    /// largest one. This holds whenever the length is a power of two and the other
+    /// accumulator holds fewer than twice as many elements, or either one is empty.
+    /// * `right` - The accumulator over the elements following this one's.
+    pub fn merge(&mut self, right: &RootAccumulator) -> Result<(), MerkleError> {
+        let right_len = right.builder.len();
+        if right_len == 0 {
+            return Ok(());
+        }
+        let largest = right_len.ilog2();
+        if self.builder.len().trailing_zeros() < largest {
+            return Err(MerkleError::UnalignedMerge {
+                left_len: self.len(),
+                right_len: right.len(),
+            });
+        }
+
+        let levels = (0..=largest)
+            .rev()
+            .filter(|&level| right_len & (1 << level) != 0);
+        for (level, &node) in levels.zip(right.builder.frontier()) {
+            self.builder.push_subtree(node, level as usize);
+        }
+        Ok(())
+    }
+
+    /// Returns the root of the tree holding every element pushed, padded up to a power
+    /// of two like `MerkleTree` does. If no elements were pushed, the root will be `None`.
+    pub fn finalize(self) -> Option<u64> {
+        self.builder.root()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{MerkleTree, hash_single};
+
+    fn accumulate(values: &[u32]) -> RootAccumulator {
+        let mut accumulator = RootAccumulator::new();
+        values.iter().for_each(|value| accumulator.push(value));
+        accumulator
+    }
+
+    proptest! {
+        #[test]
+        fn root_matches_full_tree(len in 0..4000_u32) {
+            let values: Vec<u32> = (0..len).collect();
+            let accumulator = accumulate(&values);
+            prop_assert_eq!(accumulator.len(), values.len());
+            prop_assert_eq!(accumulator.finalize(), MerkleTree::build(&values).root());
+        }
+
+        #[test]
+        fn merges_match_full_tree(len in 0..3000_u32, split in 0..12_u32) {
+            let values: Vec<u32> = (0..len).collect();
+            let split = (1 << split).min(values.len());
+            let mut left = accumulate(&values[..split]);
+            let right = accumulate(&values[split..]);
+
+            let merged = left.merge(&right);
+            if split.is_power_of_two() && values.len() - split >= 2 * split {
+                prop_assert!(merged.is_err());
+            } else {
+                prop_assert_eq!(merged, Ok(()));
+                prop_assert_eq!(left.len(), values.len());
+                prop_assert_eq!(left.finalize(), MerkleTree::build(&values).root());
+            }
+        }
+    }
+
+    #[test]
+    fn hashes_and_values_accumulate_alike() {
+        let mut accumulator = RootAccumulator::new();
+        assert!(accumulator.is_empty());
+        accumulator.push_hash(hash_single(7_u8));
+        accumulator.push(8_u8);
+        assert_eq!(accumulator.finalize(), MerkleTree::build(&[7_u8, 8]).root());
+        assert_eq!(RootAccumulator::new().finalize(), None);
+    }
+
+    #[test]
+    fn misaligned_merge_rejected() {
+        let values: Vec<u32> = (0..20).collect();
+        let mut left = accumulate(&values[..4]);
+        let unchanged = left.clone();
+        assert_eq!(
+            left.merge(&accumulate(&values[4..12])),
+            Err(MerkleError::UnalignedMerge {
+                left_len: 4,
+                right_len: 8
+            })
+        );
+        assert_eq!(left.finalize(), unchanged.finalize());
+
+        // Lengths other than powers of two merge if they line up.
+        let mut left = accumulate(&values[..12]);
+        left.merge(&accumulate(&values[12..16])).unwrap();
+        assert_eq!(left.finalize(), MerkleTree::build(&values[..16]).root());
+        let mut left = accumulate(&values[..12]);
+        assert!(left.merge(&accumulate(&values[12..20])).is_err());
+    }
+}
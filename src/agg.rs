@@ -0,0 +1,281 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::{MerkleTree, hash_single, proof_depth};
+
+/// Domain separation tag hashed before the children of an `AggMerkleTree` node, distinct
+/// from the tags of `MerkleTree` so that no node of one can be passed off as a node of
+/// the other.
+const AGG_NODE_TAG: u8 = 2;
+
+/// Aggregate computed over the leaves of every subtree of an `AggMerkleTree`, such as
+/// the sum of their weights.
+pub trait Aggregate {
+    /// Type of the leaves.
+    type Leaf: Hash;
+
+    /// Type of the aggregates, hashed into the nodes along with their hashes.
+    type Value: Clone + Hash + PartialEq;
+
+    /// Returns the aggregate of a subtree without leaves, such as the ones padding the
+    /// tree to a power of two. It should be the identity of `combine`, so that padding
+    /// does not alter the total.
+    fn empty() -> Self::Value;
+
+    /// Returns the aggregate of a single leaf.
+    /// * `leaf` - The leaf to be extracted from.
+    fn leaf(leaf: &Self::Leaf) -> Self::Value;
+
+    /// Returns the aggregate of a subtree out of the ones of its children.
+    /// * `left` - The aggregate of the left child.
+    /// * `right` - The aggregate of the right child.
+    fn combine(left: &Self::Value, right: &Self::Value) -> Self::Value;
+}
+
+/// Returns the hash of a node out of the hashes and aggregates of its children.
+/// * `left` - The hash and aggregate of the left child.
+/// * `right` - The hash and aggregate of the right child.
+fn agg_node_hash<V: Hash>(left: (u64, &V), right: (u64, &V)) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    AGG_NODE_TAG.hash(&mut hasher);
+    left.0.hash(&mut hasher);
+    right.0.hash(&mut hasher);
+    left.1.hash(&mut hasher);
+    right.1.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Tree whose nodes carry, along with their hash, an aggregate over the leaves below
+/// them, which is hashed into their parent. The root thus commits to the total of every
+/// leaf, and a proof of a leaf also proves the total, as `AggProof::verify` recomputes
+/// both the hashes and the aggregates up the path.
+/// Leaves are hashed like `MerkleTree` hashes its elements, and the tree is padded to
+/// a power of two with `MerkleTree::PAD_HASH` leaves holding `Aggregate::empty`.
+pub struct AggMerkleTree<A: Aggregate> {
+    /// Hash and aggregate of every node, from the leaves (`0`) up to the root.
+    levels: Vec<Vec<(u64, A::Value)>>,
+    len: usize,
+}
+
+/// Proof of a leaf of an `AggMerkleTree` and of the tree's total, as returned by
+/// `AggMerkleTree::prove`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AggProof<V> {
+    /// Index of the leaf.
+    pub index: usize,
+    /// Length of the tree.
+    pub len: usize,
+    /// Hash and aggregate of the sibling of every node of the leaf's path, from the
+    /// leaves' level upwards.
+    pub siblings: Vec<(u64, V)>,
+}
+
+impl<A: Aggregate> AggMerkleTree<A> {
+    /// Creates a tree holding the given leaves, in order.
+    /// * `leaves` - The leaves of the tree.
+    pub fn build(leaves: &[A::Leaf]) -> AggMerkleTree<A> {
+        if leaves.is_empty() {
+            return AggMerkleTree {
+                levels: Vec::new(),
+                len: 0,
+            };
+        }
+        let mut level: Vec<(u64, A::Value)> = leaves
+            .iter()
+            .map(|leaf| (hash_single(leaf), A::leaf(leaf)))
+            .collect();
+        level.resize(
+            leaves.len().next_power_of_two(),
+            (MerkleTree::PAD_HASH, A::empty()),
+        );
+
+        let mut levels = vec![level];
+        while let Some(below) = levels.last().filter(|level| level.len() > 1) {
+            let level = below
+                .chunks_exact(2)
+                .map(|pair| {
+                    let ((left, left_value), (right, right_value)) = (&pair[0], &pair[1]);
+                    (
+                        agg_node_hash((*left, left_value), (*right, right_value)),
+                        A::combine(left_value, right_value),
+                    )
+                })
+                .collect();
+            levels.push(level);
+        }
+        AggMerkleTree {
+            levels,
+            len: leaves.len(),
+        }
+    }
+
+    /// Returns the amount of leaves.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the tree holds no leaf.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the root, or `None` if the tree is empty.
+    pub fn root(&self) -> Option<u64> {
+        Some(self.levels.last()?[0].0)
+    }
+
+    /// Returns the aggregate of every leaf, which is `Aggregate::empty` if the tree is
+    /// empty.
+    pub fn total(&self) -> A::Value {
+        self.levels
+            .last()
+            .map_or_else(A::empty, |level| level[0].1.clone())
+    }
+
+    /// Returns the hash and aggregate of the node at the given coordinates, or `None` if
+    /// they are out of range. See `MerkleTree::node` for the level orientation.
+    /// * `level` - Level of the node.
+    /// * `index` - Index of the node within its level.
+    pub fn node(&self, level: usize, index: usize) -> Option<(u64, &A::Value)> {
+        self.levels
+            .get(level)?
+            .get(index)
+            .map(|(hash, value)| (*hash, value))
+    }
+
+    /// Creates a proof of the leaf at a given index and of the tree's total, or `None` if
+    /// the index does not hold a leaf.
+    /// * `index` - index value to generate the proof for.
+    pub fn prove(&self, index: usize) -> Option<AggProof<A::Value>> {
+        if index >= self.len {
+            return None;
+        }
+
+        let siblings = self.levels[..self.levels.len() - 1]
+            .iter()
+            .enumerate()
+            .map(|(level, nodes)| nodes[(index >> level) ^ 1].clone())
+            .collect();
+        Some(AggProof {
+            index,
+            len: self.len,
+            siblings,
+        })
+    }
+}
+
+impl<V: Clone + Hash + PartialEq> AggProof<V> {
+    /// Returns whether the proof shows that the leaf is at its index in a tree of the
+    /// given root and total. Both the hashes and the aggregates are recomputed up the
+    /// path, so a sibling whose aggregate was tampered with never verifies.
+    /// * `leaf` - The leaf to be tested.
+    /// * `root` - The trusted root of the tree.
+    /// * `total` - The expected total of the tree.
+    pub fn verify<A: Aggregate<Value = V>>(&self, leaf: &A::Leaf, root: u64, total: &V) -> bool {
+        if self.index >= self.len || self.siblings.len() != proof_depth(self.len) {
+            return false;
+        }
+
+        let (mut hash, mut value) = (hash_single(leaf), A::leaf(leaf));
+        for (level, (sibling, sibling_value)) in self.siblings.iter().enumerate() {
+            (hash, value) = if (self.index >> level) & 1 == 0 {
+                (
+                    agg_node_hash((hash, &value), (*sibling, sibling_value)),
+                    A::combine(&value, sibling_value),
+                )
+            } else {
+                (
+                    agg_node_hash((*sibling, sibling_value), (hash, &value)),
+                    A::combine(sibling_value, &value),
+                )
+            };
+        }
+        hash == root && value == *total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sum of the leaves' weights.
+    struct Sum;
+
+    impl Aggregate for Sum {
+        type Leaf = u64;
+        type Value = u64;
+
+        fn empty() -> u64 {
+            0
+        }
+
+        fn leaf(leaf: &u64) -> u64 {
+            *leaf
+        }
+
+        fn combine(left: &u64, right: &u64) -> u64 {
+            left + right
+        }
+    }
+
+    #[test]
+    fn totals() {
+        for len in 0..=9 {
+            let weights: Vec<u64> = (1..=len).map(|weight| weight * 10).collect();
+            let tree = AggMerkleTree::<Sum>::build(&weights);
+            assert_eq!(tree.len(), len as usize);
+            assert_eq!(tree.total(), weights.iter().sum::<u64>());
+            assert_eq!(tree.root().is_none(), tree.is_empty());
+        }
+
+        // Subtotals are held by the inner nodes.
+        let tree = AggMerkleTree::<Sum>::build(&[1, 2, 3, 4, 5]);
+        assert_eq!(tree.node(1, 0).map(|(_, value)| *value), Some(3));
+        assert_eq!(tree.node(2, 1).map(|(_, value)| *value), Some(5));
+        assert_eq!(tree.node(3, 0).map(|(_, value)| *value), Some(15));
+        assert_eq!(tree.node(4, 0), None);
+    }
+
+    #[test]
+    fn proofs_verify() {
+        let weights = [4, 8, 15, 16, 23, 42];
+        let tree = AggMerkleTree::<Sum>::build(&weights);
+        let (root, total) = (tree.root().unwrap(), tree.total());
+        for (index, weight) in weights.iter().enumerate() {
+            let proof = tree.prove(index).unwrap();
+            assert!(proof.verify::<Sum>(weight, root, &total));
+            assert!(!proof.verify::<Sum>(&(weight + 1), root, &total));
+            assert!(!proof.verify::<Sum>(weight, root, &(total + 1)));
+            assert!(!proof.verify::<Sum>(weight, root ^ 1, &total));
+        }
+        assert_eq!(tree.prove(weights.len()), None);
+
+        let tree = AggMerkleTree::<Sum>::build(&[7]);
+        let proof = tree.prove(0).unwrap();
+        assert!(proof.siblings.is_empty());
+        assert!(proof.verify::<Sum>(&7, tree.root().unwrap(), &7));
+    }
+
+    #[test]
+    fn tampered_proofs_fail() {
+        let weights = [4, 8, 15, 16, 23, 42];
+        let tree = AggMerkleTree::<Sum>::build(&weights);
+        let (root, total) = (tree.root().unwrap(), tree.total());
+        let proof = tree.prove(2).unwrap();
+
+        // Raising a sibling's aggregate raises the recomputed total, but the hashes
+        // committing to it no longer lead to the root.
+        for level in 0..proof.siblings.len() {
+            let mut tampered = proof.clone();
+            tampered.siblings[level].1 += 1;
+            assert!(!tampered.verify::<Sum>(&15, root, &total));
+            assert!(!tampered.verify::<Sum>(&15, root, &(total + 1)));
+        }
+
+        let mut moved = proof.clone();
+        moved.index = 3;
+        assert!(!moved.verify::<Sum>(&15, root, &total));
+        let mut short = proof;
+        short.siblings.pop();
+        assert!(!short.verify::<Sum>(&15, root, &total));
+    }
+}
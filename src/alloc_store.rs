@@ -0,0 +1,182 @@
+use allocator_api2::alloc::Allocator;
+use allocator_api2::vec::Vec;
+
+use crate::{MerkleTree, NodeStore, empty_node};
+
+/// `NodeStore` keeping its levels in memory obtained from an allocator, such as a
+/// per-request arena, rather than the global one. Trees are built in it with
+/// `MerkleTree::build_in`, or created empty with `MerkleTree::new_in`.
+/// Like `FileStore`, each level only holds the nodes up to the last one written, and the
+/// nodes beyond them are empty. Every vector of the store is allocated from the
+/// allocator, while the rest of the tree's state, such as its occupancy bitmap, is not.
+pub struct AllocStore<A: Allocator + Clone> {
+    alloc: A,
+    /// Nodes of each level, up to the last one written.
+    levels: Vec<Vec<u64, A>, A>,
+    /// Amount of nodes of each level, written or not.
+    widths: Vec<usize, A>,
+}
+
+impl<A: Allocator + Clone> AllocStore<A> {
+    /// Creates an empty store allocating from the given allocator, like `Vec::new_in`.
+    /// Nothing is allocated until a tree is built in it.
+    /// * `alloc` - The allocator every level is allocated from.
+    pub fn new_in(alloc: A) -> AllocStore<A> {
+        AllocStore {
+            levels: Vec::new_in(alloc.clone()),
+            widths: Vec::new_in(alloc.clone()),
+            alloc,
+        }
+    }
+
+    /// Returns the allocator the levels are allocated from.
+    pub fn allocator(&self) -> &A {
+        &self.alloc
+    }
+}
+
+impl<A: Allocator + Clone> NodeStore for AllocStore<A> {
+    fn height(&self) -> usize {
+        self.widths.len()
+    }
+
+    fn len(&self, level: usize) -> Option<usize> {
+        self.widths.get(level).copied()
+    }
+
+    fn get(&self, level: usize, index: usize) -> Option<u64> {
+        if index >= *self.widths.get(level)? {
+            return None;
+        }
+        Some(
+            self.levels[level]
+                .get(index)
+                .copied()
+                .unwrap_or_else(|| empty_node(level)),
+        )
+    }
+
+    #[track_caller]
+    fn put(&mut self, level: usize, index: usize, node: u64) {
+        self.put_batch(level, index, &[node]);
+    }
+
+    #[track_caller]
+    fn put_batch(&mut self, level: usize, first: usize, nodes: &[u64]) {
+        let width = self.widths[level];
+        let end = first + nodes.len();
+        assert!(
+            end <= width,
+            "nodes up to {end} are out of range for a level of {width} nodes"
+        );
+        let stored = &mut self.levels[level];
+        if stored.len() < end {
+            stored.resize(end, empty_node(level));
+        }
+        stored[first..end].copy_from_slice(nodes);
+    }
+
+    fn reset(&mut self, capacity: usize) {
+        self.levels.clear();
+        self.widths.clear();
+        for level_n in 0..=capacity.trailing_zeros() as usize {
+            self.levels.push(Vec::new_in(self.alloc.clone()));
+            self.widths.push(capacity >> level_n);
+        }
+    }
+
+    fn grow(&mut self, root: u64) {
+        for width in self.widths.iter_mut() {
+            *width *= 2;
+        }
+        let top = self.widths.len();
+        self.levels.push(Vec::new_in(self.alloc.clone()));
+        self.widths.push(1);
+        self.put(top, 0, root);
+    }
+}
+
+impl<A: Allocator + Clone> MerkleTree<AllocStore<A>> {
+    /// Creates an empty tree whose levels are allocated from the given allocator, like
+    /// `Vec::new_in`. See `AllocStore`.
+    /// * `alloc` - The allocator every level is allocated from.
+    pub fn new_in(alloc: A) -> MerkleTree<AllocStore<A>> {
+        MerkleTree::build_in(AllocStore::new_in(alloc), &[0_u8; 0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::Layout;
+    use std::cell::Cell;
+    use std::ptr::NonNull;
+
+    use allocator_api2::alloc::{AllocError, Global};
+
+    use super::*;
+
+    /// Allocator forwarding to the global one, accounting for what it holds like an
+    /// arena would.
+    #[derive(Default)]
+    struct Arena {
+        live_bytes: Cell<usize>,
+        allocations: Cell<usize>,
+    }
+
+    unsafe impl Allocator for Arena {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.live_bytes.set(self.live_bytes.get() + layout.size());
+            self.allocations.set(self.allocations.get() + 1);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.live_bytes.set(self.live_bytes.get() - layout.size());
+            // SAFETY: the memory was allocated by `Global`, with the same layout.
+            unsafe { Global.deallocate(ptr, layout) }
+        }
+    }
+
+    #[test]
+    fn levels_are_allocated_from_the_arena() {
+        let arena = Arena::default();
+        let values: Vec<u32> = (0..1000).collect();
+        let mut tree = MerkleTree::build_in(AllocStore::new_in(&arena), &values);
+        let expected = MerkleTree::build(&values);
+        assert_eq!(tree.root(), expected.root());
+        assert!(tree.get_proof(999).verify(999));
+        // The leaves and their ancestors, about as many.
+        assert!(arena.live_bytes.get() >= 2 * 1000 * size_of::<u64>());
+
+        let allocations = arena.allocations.get();
+        (1000..5000_u32).for_each(|value| tree.push(value));
+        assert!(arena.allocations.get() > allocations);
+        assert!(arena.live_bytes.get() >= 2 * 5000 * size_of::<u64>());
+
+        drop(tree);
+        assert_eq!(arena.live_bytes.get(), 0);
+    }
+
+    #[test]
+    fn empty_trees_grow_in_the_arena() {
+        let arena = Arena::default();
+        let mut tree = MerkleTree::new_in(&arena);
+        assert!(tree.is_empty());
+        assert_eq!(tree.root(), None);
+        let mut expected = MerkleTree::build::<u32>(&[]);
+        for value in 0..100_u32 {
+            tree.push(value);
+            expected.push(value);
+            assert_eq!(tree.root(), expected.root());
+        }
+        assert_eq!(
+            tree.store().allocator().live_bytes.get(),
+            arena.live_bytes.get()
+        );
+
+        let store = tree.into_store();
+        assert!(arena.live_bytes.get() > 0);
+        drop(store);
+        assert_eq!(arena.live_bytes.get(), 0);
+    }
+}
@@ -0,0 +1,200 @@
+use crate::{MerkleTree, ancestor_index, sibling_index};
+
+/// A node on the path from a leaf to the root, as returned by `MerkleTree::path`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PathStep {
+    pub level: usize,
+    pub index: usize,
+    pub hash: u64,
+    /// Hash of the node's sibling, or `None` for the root.
+    pub sibling_hash: Option<u64>,
+    /// Whether the node is the left child of its parent. Always `false` for the root.
+    pub is_left_child: bool,
+}
+
+impl MerkleTree {
+    /// Returns an iterator over the path from an occupied leaf to the root: the leaf,
+    /// each of its ancestors and the root, with their coordinates and sibling hashes.
+    /// The sibling hashes are the nodes of the leaf's `MerkleProof`, in the same order.
+    /// Returns `None` if the index does not correspond to an occupied leaf.
+    /// * `index` - Index of the leaf.
+    pub fn path(&self, index: usize) -> Option<impl Iterator<Item = PathStep> + '_> {
+        self.leaf(index)?;
+        let root_level = self.height() - 1;
+
+        Some((0..self.height()).map(move |level| {
+            let node_index = ancestor_index(index, level);
+            let is_root = level == root_level;
+            PathStep {
+                level,
+                index: node_index,
+                hash: self.levels[(level, node_index)],
+                sibling_hash: (!is_root).then(|| self.levels[(level, sibling_index(node_index))]),
+                is_left_child: !is_root && node_index.is_multiple_of(2),
+            }
+        }))
+    }
+
+    /// Returns the lowest common ancestor of two leaves, as its `(level, index, hash)`.
+    /// Level 0 corresponds to the leaves, so the ancestor of a leaf with itself is the
+    /// leaf. Returns `None` if any of the indices does not correspond to an occupied leaf,
+    /// or to one whose hash is held (see `from_frontier`).
+    /// * `first` - Index of the first leaf.
+    /// * `second` - Index of the second leaf.
+    pub fn lca(&self, first: usize, second: usize) -> Option<(usize, usize, u64)> {
+        if first >= self.len() || second >= self.len() || first.min(second) < self.pruned {
+            return None;
+        }
+
+        // Two leaves share an ancestor once every bit in which they differ is shifted out.
+        let level = (usize::BITS - (first ^ second).leading_zeros()) as usize;
+        let index = ancestor_index(first, level);
+        Some((level, index, self.levels[(level, index)]))
+    }
+
+    /// Returns whether the node at the given coordinates is an ancestor of the leaf
+    /// (or the leaf itself, at level 0). Out of range coordinates and unoccupied leaves
+    /// are never covered.
+    /// * `level` - Level of the node.
+    /// * `index` - Index of the node within its level.
+    /// * `leaf` - Index of the leaf.
+    pub fn covers(&self, level: usize, index: usize, leaf: usize) -> bool {
+        level < self.height() && leaf < self.len() && ancestor_index(leaf, level) == index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MerkleProof, hash_pair, hash_single};
+
+    /// Returns the `(level, index)` of the lowest common ancestor by walking upwards one
+    /// level at a time.
+    fn brute_force_lca(mut first: usize, mut second: usize) -> (usize, usize) {
+        let mut level = 0;
+        while first != second {
+            first /= 2;
+            second /= 2;
+            level += 1;
+        }
+        (level, first)
+    }
+
+    #[test]
+    fn lca_of_leaf_with_itself() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let (level, index, hash) = tree.lca(2, 2).unwrap();
+        assert_eq!((level, index), (0, 2));
+        assert_eq!(hash, tree.levels[(0, 2)]);
+    }
+
+    #[test]
+    fn lca_of_siblings() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4]);
+        let (level, index, hash) = tree.lca(3, 2).unwrap();
+        assert_eq!((level, index), (1, 1));
+        assert_eq!(hash, tree.levels[(1, 1)]);
+    }
+
+    #[test]
+    fn lca_of_distant_leaves_is_root() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        let (level, index, hash) = tree.lca(0, 4).unwrap();
+        assert_eq!((level, index), (3, 0));
+        assert_eq!(Some(hash), tree.root());
+    }
+
+    #[test]
+    fn lca_out_of_range() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        assert!(tree.lca(0, 3).is_none());
+        assert!(tree.lca(7, 1).is_none());
+        assert!(MerkleTree::build::<u8>(&[]).lca(0, 0).is_none());
+    }
+
+    #[test]
+    fn lca_matches_brute_force() {
+        for len in 1..=20 {
+            let tree = MerkleTree::build(&vec![0; len]);
+            for first in 0..len {
+                for second in 0..len {
+                    let (level, index, hash) = tree.lca(first, second).unwrap();
+                    assert_eq!((level, index), brute_force_lca(first, second));
+                    assert_eq!(hash, tree.levels[(level, index)]);
+                    assert!(tree.covers(level, index, first));
+                    assert!(tree.covers(level, index, second));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn covers_subtree_members_only() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5, 6]);
+        assert!(tree.covers(0, 4, 4));
+        assert!(tree.covers(2, 1, 5));
+        assert!(!tree.covers(2, 0, 5));
+        assert!(!tree.covers(1, 3, 6)); // Padded leaf.
+        assert!(!tree.covers(4, 0, 0)); // Above the root.
+    }
+
+    #[test]
+    fn path_folds_into_root() {
+        let elements = [1, 2, 3, 4, 5, 6];
+        let tree = MerkleTree::build(&elements);
+
+        for (index, element) in elements.iter().enumerate() {
+            let steps: Vec<PathStep> = tree.path(index).unwrap().collect();
+            assert_eq!(steps.len(), tree.height());
+            assert_eq!(steps[0].hash, hash_single(element));
+
+            let mut computed = steps[0].hash;
+            for step in &steps[..steps.len() - 1] {
+                assert_eq!(step.hash, computed);
+                let sibling = step.sibling_hash.unwrap();
+                computed = if step.is_left_child {
+                    hash_pair(computed, sibling)
+                } else {
+                    hash_pair(sibling, computed)
+                };
+            }
+            assert_eq!(Some(computed), tree.root());
+            assert_eq!(steps.last().unwrap().sibling_hash, None);
+        }
+    }
+
+    #[test]
+    fn path_siblings_match_proof() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        let siblings: Vec<u64> = tree
+            .path(4)
+            .unwrap()
+            .filter_map(|step| step.sibling_hash)
+            .collect();
+        let MerkleProof::Proof { nodes, .. } = tree.get_proof(4) else {
+            panic!("Expected a valid proof");
+        };
+        assert_eq!(siblings, nodes);
+    }
+
+    #[test]
+    fn path_coordinates() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        let coords: Vec<(usize, usize, bool)> = tree
+            .path(3)
+            .unwrap()
+            .map(|step| (step.level, step.index, step.is_left_child))
+            .collect();
+        assert_eq!(
+            coords,
+            [(0, 3, false), (1, 1, false), (2, 0, true), (3, 0, false)]
+        );
+    }
+
+    #[test]
+    fn path_of_unoccupied_leaf() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        assert!(tree.path(3).is_none());
+        assert!(MerkleTree::build::<u8>(&[]).path(0).is_none());
+    }
+}
@@ -0,0 +1,331 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::iter;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    HashAlgorithm, LevelCheck, MerkleTree, ParseError, ValidationError, parse_root, root_to_hex,
+};
+
+/// A node of an audit document.
+/// Fields are declared in alphabetical order, so keys are written sorted.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AuditNode {
+    hash: String,
+    /// Whether the node covers only padding slots.
+    padding: bool,
+}
+
+/// A level of an audit document.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AuditLevel {
+    level: usize,
+    nodes: Vec<AuditNode>,
+}
+
+/// Audit document of a whole tree.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AuditDocument {
+    algorithm: String,
+    capacity: usize,
+    epoch: u64,
+    len: usize,
+    /// Every level, bottom-up.
+    levels: Vec<AuditLevel>,
+    padding: usize,
+    root: Option<String>,
+}
+
+/// Error returned when reconstructing a tree out of an audit document fails.
+#[derive(Debug)]
+pub enum AuditError {
+    /// The document is not valid JSON for an audit document.
+    Parse(serde_json::Error),
+    /// A hash is not in its canonical hex representation.
+    Hash(ParseError),
+    /// The document was produced with a hash algorithm this crate does not know.
+    UnknownAlgorithm(String),
+    /// A level is listed out of order.
+    LevelOrder { expected: usize, found: usize },
+    /// The levels do not form a consistent tree.
+    Validation(ValidationError),
+    /// A node's padding flag disagrees with the tree's length.
+    PaddingFlag { level: usize, index: usize },
+    /// A metadata field disagrees with the levels.
+    Field {
+        field: &'static str,
+        expected: String,
+        found: String,
+    },
+}
+
+impl Display for AuditError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditError::Parse(error) => write!(f, "invalid audit document: {error}"),
+            AuditError::Hash(error) => write!(f, "invalid hash: {error}"),
+            AuditError::UnknownAlgorithm(algorithm) => {
+                write!(f, "unknown hash algorithm {algorithm:?}")
+            }
+            AuditError::LevelOrder { expected, found } => {
+                write!(f, "expected level {expected}, found level {found}")
+            }
+            AuditError::Validation(error) => write!(f, "inconsistent levels: {error}"),
+            AuditError::PaddingFlag { level, index } => {
+                write!(f, "node ({level}, {index}) has a wrong padding flag")
+            }
+            AuditError::Field {
+                field,
+                expected,
+                found,
+            } => write!(f, "{field} is {found}, expected {expected}"),
+        }
+    }
+}
+
+impl Error for AuditError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AuditError::Parse(error) => Some(error),
+            AuditError::Hash(error) => Some(error),
+            AuditError::Validation(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Returns an error if a metadata field disagrees with the value derived from the levels.
+fn check_field<T: PartialEq + fmt::Debug>(
+    field: &'static str,
+    expected: T,
+    found: T,
+) -> Result<(), AuditError> {
+    if expected != found {
+        return Err(AuditError::Field {
+            field,
+            expected: format!("{expected:?}"),
+            found: format!("{found:?}"),
+        });
+    }
+    Ok(())
+}
+
+impl MerkleTree {
+    /// Returns whether a node covers only padding slots.
+    fn is_padding_node(&self, level: usize, index: usize) -> bool {
+        index << level >= self.len()
+    }
+
+    /// Returns a human readable JSON document of the whole tree, meant to be inspected
+    /// and diffed by auditors: the metadata and every level bottom-up, with each node's
+    /// hash and whether it covers only padding slots.
+    /// The output is deterministic: keys are sorted, hashes are written in their
+    /// canonical hex representation (see `root_to_hex`) and the layout is fixed. It
+    /// optimizes for readability, not size, so it is only meant for small trees; see
+    /// `write_snapshot` for persistence.
+    /// The leaves are listed out of `iter_occupied`, so those pruned from the tree (see
+    /// `from_frontier`) are left out.
+    pub fn to_audit_json(&self) -> String {
+        let padding = AuditNode {
+            hash: root_to_hex(MerkleTree::PAD_HASH),
+            padding: true,
+        };
+        let leaves = AuditLevel {
+            level: 0,
+            nodes: self
+                .iter_occupied()
+                .map(|(_, hash)| AuditNode {
+                    hash: root_to_hex(hash),
+                    padding: false,
+                })
+                .chain((self.len()..self.capacity()).map(|_| padding.clone()))
+                .collect(),
+        };
+        let levels = self
+            .levels_iter()
+            .enumerate()
+            .skip(1)
+            .map(|(level, nodes)| AuditLevel {
+                level,
+                nodes: nodes
+                    .iter()
+                    .enumerate()
+                    .map(|(index, &hash)| AuditNode {
+                        hash: root_to_hex(hash),
+                        padding: self.is_padding_node(level, index),
+                    })
+                    .collect(),
+            });
+        let levels = iter::once(leaves).chain(levels).collect();
+
+        let document = AuditDocument {
+            algorithm: format!("{:?}", HashAlgorithm::DefaultHasher),
+            capacity: self.capacity(),
+            epoch: self.epoch(),
+            len: self.len(),
+            levels,
+            padding: self.capacity() - self.len(),
+            root: self.root_hex(),
+        };
+        serde_json::to_string_pretty(&document).expect("Audit documents always serialize")
+    }
+
+    /// Reconstructs a tree out of the document returned by `to_audit_json`, checking it
+    /// entirely: the levels must form a consistent tree, every padding flag must agree
+    /// with the length, and the metadata with the levels.
+    /// The tree keeps the document's epoch. Options such as the proof cache are not
+    /// part of the document, so they are disabled.
+    /// * `json` - The audit document.
+    pub fn from_audit_json(json: &str) -> Result<MerkleTree, AuditError> {
+        let document: AuditDocument = serde_json::from_str(json).map_err(AuditError::Parse)?;
+        if document.algorithm != format!("{:?}", HashAlgorithm::DefaultHasher) {
+            return Err(AuditError::UnknownAlgorithm(document.algorithm));
+        }
+
+        let mut levels = Vec::with_capacity(document.levels.len());
+        for (expected, level) in document.levels.iter().enumerate() {
+            if level.level != expected {
+                return Err(AuditError::LevelOrder {
+                    expected,
+                    found: level.level,
+                });
+            }
+            let nodes = level
+                .nodes
+                .iter()
+                .map(|node| parse_root(&node.hash))
+                .collect::<Result<Vec<u64>, ParseError>>()
+                .map_err(AuditError::Hash)?;
+            levels.push(nodes);
+        }
+
+        let leaves = document
+            .levels
+            .first()
+            .map_or(&[][..], |level| &level.nodes);
+        if leaves.is_empty() {
+            return Err(AuditError::Validation(ValidationError::LevelLength {
+                level: 0,
+                expected: 1,
+                found: 0,
+            }));
+        }
+        let padding = leaves.iter().filter(|leaf| leaf.padding).count();
+        let capacity = leaves.len();
+
+        let mut tree = MerkleTree::from_parts(levels, capacity, padding);
+        tree.check_levels(LevelCheck::Strict)
+            .map_err(AuditError::Validation)?;
+        for (level, nodes) in document.levels.iter().enumerate() {
+            for (index, node) in nodes.nodes.iter().enumerate() {
+                if node.padding != tree.is_padding_node(level, index) {
+                    return Err(AuditError::PaddingFlag { level, index });
+                }
+            }
+        }
+
+        check_field("capacity", tree.capacity(), document.capacity)?;
+        check_field("len", tree.len(), document.len)?;
+        check_field("padding", padding, document.padding)?;
+        check_field("root", tree.root_hex(), document.root)?;
+        tree.epoch = document.epoch;
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOLDEN: &str = include_str!("../tests/audit.json");
+
+    /// Returns the tree pinned by the golden file.
+    fn golden_tree() -> MerkleTree {
+        let mut tree = MerkleTree::build(&[b"a", b"b", b"c"]);
+        tree.push(b"d");
+        tree.push(b"e");
+        tree
+    }
+
+    #[test]
+    fn output_matches_golden_file() {
+        assert_eq!(golden_tree().to_audit_json(), GOLDEN.trim_end());
+    }
+
+    #[test]
+    fn round_trip() {
+        let tree = MerkleTree::from_audit_json(GOLDEN).unwrap();
+        assert_eq!(tree.root(), golden_tree().root());
+        assert_eq!(tree.epoch(), 2);
+        assert_eq!(tree.len(), 5);
+        assert_eq!(tree.to_audit_json(), GOLDEN.trim_end());
+
+        let empty = MerkleTree::build::<u8>(&[]);
+        let restored = MerkleTree::from_audit_json(&empty.to_audit_json()).unwrap();
+        assert!(restored.is_empty());
+        assert_eq!(restored.capacity(), 1);
+    }
+
+    #[test]
+    fn tampered_documents_rejected() {
+        let tree = golden_tree();
+        let leaf = root_to_hex(tree.leaves()[1]);
+        let parent = root_to_hex(tree.level(1).unwrap()[0]);
+
+        let tampered = GOLDEN.replacen(&leaf, &root_to_hex(7), 1);
+        assert!(matches!(
+            MerkleTree::from_audit_json(&tampered),
+            Err(AuditError::Validation(ValidationError::Hash {
+                level: 1,
+                index: 0,
+                ..
+            }))
+        ));
+
+        let tampered = GOLDEN.replacen(&parent, "not hex", 1);
+        assert!(matches!(
+            MerkleTree::from_audit_json(&tampered),
+            Err(AuditError::Hash(_))
+        ));
+
+        let tampered = GOLDEN.replacen(r#""epoch": 2,"#, r#""epoch": 2, "extra": 0,"#, 1);
+        assert!(matches!(
+            MerkleTree::from_audit_json(&tampered),
+            Err(AuditError::Parse(_))
+        ));
+
+        let tampered = GOLDEN.replacen(r#""len": 5"#, r#""len": 4"#, 1);
+        assert!(matches!(
+            MerkleTree::from_audit_json(&tampered),
+            Err(AuditError::Field { field: "len", .. })
+        ));
+
+        let tampered = GOLDEN.replacen("DefaultHasher", "Sha256", 1);
+        assert!(matches!(
+            MerkleTree::from_audit_json(&tampered),
+            Err(AuditError::UnknownAlgorithm(_))
+        ));
+    }
+
+    #[test]
+    fn padding_flags_checked() {
+        // The sixth leaf is padding: flagging it as occupied moves the padding count,
+        // while flagging its parent as occupied contradicts the length.
+        let document = MerkleTree::build(&[1, 2, 3, 4, 5]).to_audit_json();
+        let mut json: serde_json::Value = serde_json::from_str(&document).unwrap();
+
+        let mut tampered = json.clone();
+        tampered["levels"][1]["nodes"][3]["padding"] = false.into();
+        assert!(matches!(
+            MerkleTree::from_audit_json(&tampered.to_string()),
+            Err(AuditError::PaddingFlag { level: 1, index: 3 })
+        ));
+
+        json["levels"][0]["nodes"][5]["padding"] = false.into();
+        assert!(MerkleTree::from_audit_json(&json.to_string()).is_err());
+    }
+}
@@ -0,0 +1,310 @@
+use borsh::io::{Error, ErrorKind, Read, Result, Write};
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::{DecodeOptions, HashAlgorithm, MerkleProof, TreeHead, is_consistent_path};
+
+/// Maximum amount of nodes accepted in a deserialized proof: one per level of the
+/// largest tree whose leaves can be indexed by a `u64`. This is the default
+/// `DecodeOptions::max_depth`.
+pub const MAX_PROOF_NODES: usize = DecodeOptions::DEFAULT.max_depth;
+
+/// Tag preceding an invalid proof.
+const INVALID_TAG: u8 = 0;
+
+/// Tag preceding a valid proof.
+const PROOF_TAG: u8 = 1;
+
+/// Reads a `u64` and converts it into a `usize`, failing if it does not fit.
+fn read_usize<R: Read>(reader: &mut R) -> Result<usize> {
+    let value = u64::deserialize_reader(reader)?;
+    usize::try_from(value)
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "value does not fit in a usize"))
+}
+
+/// Encodes the proof as a tag byte, `0` for `Invalid` and `1` for valid proofs, followed
+/// for valid proofs by the index, the nodes, the root, the length and the epoch.
+/// Indices and lengths are encoded as `u64`, and the nodes as a `u32` count followed by
+/// every node, as borsh does for vectors.
+impl BorshSerialize for MerkleProof {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
+        match self {
+            MerkleProof::Invalid => INVALID_TAG.serialize(writer),
+            MerkleProof::Proof {
+                index,
+                nodes,
+                root,
+                len,
+                epoch,
+            } => {
+                PROOF_TAG.serialize(writer)?;
+                (*index as u64).serialize(writer)?;
+                nodes.serialize(writer)?;
+                root.serialize(writer)?;
+                (*len as u64).serialize(writer)?;
+                epoch.serialize(writer)
+            }
+        }
+    }
+}
+
+/// Reads a proof encoded by its `BorshSerialize` implementation. The declared node count
+/// is checked against the limits before any node is read, and the proof is rejected if
+/// its nodes do not form a path to its index in a tree of its length.
+/// * `reader` - Where the proof is read from.
+/// * `options` - The limits enforced.
+fn read_proof<R: Read>(reader: &mut R, options: &DecodeOptions) -> Result<MerkleProof> {
+    match u8::deserialize_reader(reader)? {
+        INVALID_TAG => Ok(MerkleProof::Invalid),
+        PROOF_TAG => {
+            let index = read_usize(reader)?;
+            let node_count = u32::deserialize_reader(reader)?;
+            options
+                .check_node_count(u64::from(node_count))
+                .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+            let nodes = (0..node_count)
+                .map(|_| u64::deserialize_reader(reader))
+                .collect::<Result<Vec<u64>>>()?;
+            let root = u64::deserialize_reader(reader)?;
+            let len = read_usize(reader)?;
+            if !is_consistent_path(index, len, &nodes) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "a path of {} nodes to leaf {index} does not fit a tree of length {len}",
+                        nodes.len()
+                    ),
+                ));
+            }
+            Ok(MerkleProof::Proof {
+                index,
+                nodes,
+                root,
+                len,
+                epoch: u64::deserialize_reader(reader)?,
+            })
+        }
+        tag => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid proof tag {tag}"),
+        )),
+    }
+}
+
+/// Decodes a proof encoded by its `BorshSerialize` implementation within the default
+/// `DecodeOptions`: proofs with more than `MAX_PROOF_NODES` nodes are rejected before
+/// allocating them. Readers carry no length, so `max_total_bytes` is only enforced by
+/// `MerkleProof::decode_borsh`.
+impl BorshDeserialize for MerkleProof {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> Result<MerkleProof> {
+        read_proof(reader, &DecodeOptions::DEFAULT)
+    }
+}
+
+impl MerkleProof {
+    /// Decodes a proof encoded by its `BorshSerialize` implementation, failing if the
+    /// input exceeds any of the limits or holds trailing bytes.
+    /// * `bytes` - The encoded proof.
+    /// * `options` - The limits enforced.
+    pub fn decode_borsh(mut bytes: &[u8], options: &DecodeOptions) -> Result<MerkleProof> {
+        options
+            .check_len(bytes.len())
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+        let proof = read_proof(&mut bytes, options)?;
+        if !bytes.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} trailing bytes", bytes.len()),
+            ));
+        }
+        Ok(proof)
+    }
+}
+
+/// Encodes the head as the algorithm id, followed by the length (as a `u64`), the root
+/// and the epoch. This is the field order of `TreeHead::to_bytes`, in little-endian.
+impl BorshSerialize for TreeHead {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.algo.id().serialize(writer)?;
+        (self.len as u64).serialize(writer)?;
+        self.root.serialize(writer)?;
+        self.epoch.serialize(writer)
+    }
+}
+
+/// Decodes a head encoded by its `BorshSerialize` implementation, rejecting unknown
+/// algorithm ids.
+impl BorshDeserialize for TreeHead {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> Result<TreeHead> {
+        let id = u8::deserialize_reader(reader)?;
+        let algo = HashAlgorithm::from_id(id).ok_or_else(|| {
+            Error::new(ErrorKind::InvalidData, format!("unknown algorithm id {id}"))
+        })?;
+        Ok(TreeHead {
+            algo,
+            len: read_usize(reader)?,
+            root: u64::deserialize_reader(reader)?,
+            epoch: u64::deserialize_reader(reader)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn proof_round_trip() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        for index in 0..tree.len() {
+            let proof = tree.get_proof(index);
+            let bytes = borsh::to_vec(&proof).unwrap();
+            let restored: MerkleProof = borsh::from_slice(&bytes).unwrap();
+            assert!(restored == proof);
+            assert!(restored.verify(index as i32 + 1));
+        }
+
+        let bytes = borsh::to_vec(&MerkleProof::Invalid).unwrap();
+        assert_eq!(bytes, [0]);
+        assert!(borsh::from_slice::<MerkleProof>(&bytes).unwrap() == MerkleProof::Invalid);
+    }
+
+    #[test]
+    fn head_round_trip() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        tree.push(4);
+        let head = tree.head().unwrap();
+        let bytes = borsh::to_vec(&head).unwrap();
+        assert_eq!(borsh::from_slice::<TreeHead>(&bytes).unwrap(), head);
+    }
+
+    #[test]
+    fn proof_layout() {
+        let proof = MerkleProof::Proof {
+            index: 1,
+            nodes: vec![0x0102],
+            root: 0xff,
+            len: 2,
+            epoch: 3,
+        };
+        #[rustfmt::skip]
+        let expected = [
+            1,
+            1, 0, 0, 0, 0, 0, 0, 0,
+            1, 0, 0, 0,
+            0x02, 0x01, 0, 0, 0, 0, 0, 0,
+            0xff, 0, 0, 0, 0, 0, 0, 0,
+            2, 0, 0, 0, 0, 0, 0, 0,
+            3, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        assert_eq!(borsh::to_vec(&proof).unwrap(), expected);
+    }
+
+    #[test]
+    fn head_layout() {
+        let head = TreeHead {
+            root: 0xab,
+            len: 5,
+            algo: HashAlgorithm::DefaultHasher,
+            epoch: 7,
+        };
+        #[rustfmt::skip]
+        let expected = [
+            0,
+            5, 0, 0, 0, 0, 0, 0, 0,
+            0xab, 0, 0, 0, 0, 0, 0, 0,
+            7, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        assert_eq!(borsh::to_vec(&head).unwrap(), expected);
+    }
+
+    #[test]
+    fn oversized_proof_rejected() {
+        let mut bytes = vec![PROOF_TAG];
+        bytes.extend_from_slice(&0_u64.to_le_bytes());
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert!(borsh::from_slice::<MerkleProof>(&bytes).is_err());
+
+        // A declared node count of u32::MAX followed by a 10 byte body.
+        bytes.extend_from_slice(&[0; 10]);
+        let error = MerkleProof::decode_borsh(&bytes, &DecodeOptions::DEFAULT).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("depth 4294967295"));
+    }
+
+    #[test]
+    fn decode_limits_enforced() {
+        let proof = MerkleProof::Proof {
+            index: 0,
+            nodes: vec![1; 1000],
+            root: 2,
+            len: 1,
+            epoch: 0,
+        };
+        let bytes = borsh::to_vec(&proof).unwrap();
+        assert!(borsh::from_slice::<MerkleProof>(&bytes).is_err());
+        assert!(MerkleProof::decode_borsh(&bytes, &DecodeOptions::DEFAULT).is_err());
+
+        let options = DecodeOptions {
+            max_depth: 1000,
+            max_nodes: 1001,
+            max_total_bytes: bytes.len(),
+        };
+        // Within the limits, the path is still too long for the proof's length.
+        let error = MerkleProof::decode_borsh(&bytes, &options).unwrap_err();
+        assert!(error.to_string().contains("does not fit"), "{error}");
+
+        let short = DecodeOptions {
+            max_total_bytes: bytes.len() - 1,
+            ..options
+        };
+        assert!(MerkleProof::decode_borsh(&bytes, &short).is_err());
+        let trailing = [&bytes[..], &[0]].concat();
+        let long = DecodeOptions {
+            max_total_bytes: trailing.len(),
+            ..options
+        };
+        assert!(MerkleProof::decode_borsh(&trailing, &long).is_err());
+    }
+
+    #[test]
+    fn random_input_never_panics() {
+        let mut state = 0x2545f4914f6cdd1d_u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let valid = borsh::to_vec(&MerkleTree::build(&[1, 2, 3, 4, 5]).get_proof(3)).unwrap();
+        for _ in 0..10_000 {
+            let len = (next() % 128) as usize;
+            let mut bytes: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            if let Some(tag) = bytes.first_mut() {
+                *tag = PROOF_TAG;
+            }
+            if let Ok(proof) = MerkleProof::decode_borsh(&bytes, &DecodeOptions::DEFAULT) {
+                proof.verify(4);
+            }
+
+            let mut bytes = valid.clone();
+            let position = (next() % bytes.len() as u64) as usize;
+            bytes[position] = next() as u8;
+            if let Ok(proof) = MerkleProof::decode_borsh(&bytes, &DecodeOptions::DEFAULT) {
+                proof.verify(4);
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_input_rejected() {
+        assert!(borsh::from_slice::<MerkleProof>(&[2]).is_err());
+        assert!(borsh::from_slice::<MerkleProof>(&[1, 0, 0]).is_err());
+        assert!(borsh::from_slice::<MerkleProof>(&[0, 0]).is_err());
+
+        let mut bytes = borsh::to_vec(&MerkleTree::build(&[1]).head().unwrap()).unwrap();
+        bytes[0] = 9;
+        assert!(borsh::from_slice::<TreeHead>(&bytes).is_err());
+    }
+}
@@ -0,0 +1,352 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::hash::Hash;
+
+use crate::wire::{Format, HEADER_LEN, WireError};
+use crate::{empty_nodes, hash_pair, hash_single};
+
+/// Checkpoints define no flags yet.
+const CHECKPOINT_FORMAT: Format = Format {
+    magic: *b"MRKB",
+    version: 1,
+    known_flags: 0,
+};
+
+/// Largest length of a checkpoint: the root of longer builds would be padded up to a
+/// capacity beyond the range of a `u64`.
+const MAX_LEN: u64 = 1 << 63;
+
+/// Error returned when decoding a checkpoint fails.
+#[derive(Debug)]
+pub enum CheckpointError {
+    /// The header was rejected. Truncated headers are reported as `Truncated`.
+    Header(WireError),
+    /// The data ended before the checkpoint did.
+    Truncated,
+    /// The amount of frontier nodes does not match the length, which needs one per set bit.
+    FrontierMismatch { expected: usize, found: usize },
+    /// The length exceeds `1 << 63`, beyond which the capacity of the tree does not fit
+    /// in a `u64`.
+    Length(u64),
+}
+
+impl Display for CheckpointError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Header(error) => write!(f, "invalid header: {error}"),
+            CheckpointError::Truncated => f.write_str("truncated checkpoint"),
+            CheckpointError::FrontierMismatch { expected, found } => {
+                write!(f, "expected {expected} frontier nodes, found {found}")
+            }
+            CheckpointError::Length(len) => write!(f, "length {len} exceeds {MAX_LEN}"),
+        }
+    }
+}
+
+impl Error for CheckpointError {}
+
+impl From<WireError> for CheckpointError {
+    fn from(error: WireError) -> CheckpointError {
+        match error {
+            WireError::Io(_) => CheckpointError::Truncated,
+            error => CheckpointError::Header(error),
+        }
+    }
+}
+
+/// State of a `MerkleTreeBuilder`, from which it can be resumed.
+/// It holds the amount of leaves pushed and the roots of the complete subtrees they
+/// form, one per set bit of the length: `O(log n)` hashes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BuilderCheckpoint {
+    pub(crate) len: u64,
+    /// Roots of the complete subtrees, from the largest (leftmost) to the smallest.
+    pub(crate) frontier: Vec<u64>,
+}
+
+impl BuilderCheckpoint {
+    /// Returns the amount of leaves pushed before the checkpoint.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether no leaves were pushed before the checkpoint.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the binary encoding of the checkpoint: a wire header followed by the
+    /// length and every frontier node, largest subtree first, as little-endian `u64`
+    /// values.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + 8 * (self.frontier.len() + 1));
+        CHECKPOINT_FORMAT
+            .write_header(&mut bytes, 0)
+            .expect("Writing to a vector never fails");
+        for value in [self.len].iter().chain(&self.frontier) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decodes a checkpoint out of the encoding returned by `to_bytes`.
+    /// * `bytes` - The encoded checkpoint.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<BuilderCheckpoint, CheckpointError> {
+        CHECKPOINT_FORMAT.read_header(&mut bytes)?;
+        let (len, nodes) = bytes
+            .split_first_chunk::<8>()
+            .ok_or(CheckpointError::Truncated)?;
+        let len = u64::from_le_bytes(*len);
+        if len > MAX_LEN {
+            return Err(CheckpointError::Length(len));
+        }
+
+        let expected = len.count_ones() as usize;
+        if nodes.len() < 8 * expected {
+            return Err(CheckpointError::Truncated);
+        }
+        if nodes.len() > 8 * expected {
+            return Err(CheckpointError::FrontierMismatch {
+                expected,
+                found: nodes.len().div_ceil(8),
+            });
+        }
+
+        let frontier = nodes
+            .chunks_exact(8)
+            .map(|node| u64::from_le_bytes(node.try_into().expect("Chunks hold 8 bytes")))
+            .collect();
+        Ok(BuilderCheckpoint { len, frontier })
+    }
+
+    /// Checks that the length is at most `1 << 63` and that the frontier holds a node per
+    /// set bit of it.
+    pub(crate) fn check(&self) -> Result<(), CheckpointError> {
+        if self.len > MAX_LEN {
+            return Err(CheckpointError::Length(self.len));
+        }
+        let expected = self.len.count_ones() as usize;
+        if self.frontier.len() != expected {
+            return Err(CheckpointError::FrontierMismatch {
+                expected,
+                found: self.frontier.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Computes the root of a tree out of a stream of leaves, keeping only the roots of
+/// the complete subtrees formed so far, so arbitrarily long inputs need `O(log n)`
+/// memory. The root is identical to the one of a `MerkleTree` built out of the same
+/// leaves.
+/// Its state can be exported with `checkpoint` and resumed with `resume`, so long
+/// builds can survive restarts.
+#[derive(Clone, Debug, Default)]
+pub struct MerkleTreeBuilder {
+    len: u64,
+    /// Roots of the complete subtrees, from the largest (leftmost) to the smallest.
+    frontier: Vec<u64>,
+}
+
+impl MerkleTreeBuilder {
+    /// Creates a builder without leaves.
+    pub fn new() -> MerkleTreeBuilder {
+        MerkleTreeBuilder::default()
+    }
+
+    /// Creates a builder in the state recorded by a checkpoint. Pushing the leaves that
+    /// followed the checkpoint yields the same root as an uninterrupted build.
+    /// Fails if the length exceeds `1 << 63` or if the frontier does not hold a node per
+    /// set bit of it.
+    /// * `checkpoint` - The state to be resumed.
+    pub fn resume(checkpoint: BuilderCheckpoint) -> Result<MerkleTreeBuilder, CheckpointError> {
+        checkpoint.check()?;
+        Ok(MerkleTreeBuilder {
+            len: checkpoint.len,
+            frontier: checkpoint.frontier,
+        })
+    }
+
+    /// Returns the state of the builder.
+    pub fn checkpoint(&self) -> BuilderCheckpoint {
+        BuilderCheckpoint {
+            len: self.len,
+            frontier: self.frontier.clone(),
+        }
+    }
+
+    /// Returns the amount of leaves pushed.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether no leaves were pushed.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Pushes an `Hash` element as the next leaf.
+    /// * `value` - The `Hash` value to be added.
+    pub fn push<H: Hash>(&mut self, value: H) {
+        self.push_leaf_hash(hash_single(value));
+    }
+
+    /// Pushes an already hashed leaf.
+    /// The new leaf merges with every complete subtree of its size, the way a binary
+    /// counter carries.
+    /// * `leaf` - The leaf hash to be added.
+    pub fn push_leaf_hash(&mut self, leaf: u64) {
+        self.push_subtree(leaf, 0);
+    }
+
+    /// Pushes the root of a complete subtree as the next `2^level` leaves, merging it
+    /// with every complete subtree of its size like `push_leaf_hash` does.
+    /// The length must be a multiple of the subtree's size, so that it lines up with the
+    /// nodes of the tree.
+    /// * `node` - The subtree's root.
+    /// * `level` - The subtree's height, `0` for a single leaf.
+    pub(crate) fn push_subtree(&mut self, node: u64, level: usize) {
+        debug_assert!(self.len.trailing_zeros() as usize >= level);
+        let mut node = node;
+        for _ in 0..(self.len >> level).trailing_ones() {
+            let left = self.frontier.pop().expect("A node is kept per set bit");
+            node = hash_pair(left, node);
+        }
+        self.frontier.push(node);
+        self.len += 1 << level;
+    }
+
+    /// Returns the roots of the complete subtrees, from the largest (leftmost) to the
+    /// smallest.
+    pub(crate) fn frontier(&self) -> &[u64] {
+        &self.frontier
+    }
+
+    /// Returns the root of a tree holding the leaves pushed so far, padded up to a power
+    /// of two like `MerkleTree` does. If no leaves were pushed, the root will be `None`.
+    pub fn root(&self) -> Option<u64> {
+        if self.len.is_power_of_two() {
+            return self.frontier.first().copied();
+        }
+
+        // Every subtree but the largest one is completed with padding up to the size of
+        // the next one, smallest first.
+        let height = self.len.next_power_of_two().ilog2() as usize;
+        let empty = empty_nodes(height);
+        let mut subtrees = self.frontier.iter().rev();
+        let mut node = *subtrees.next()?;
+        let mut level = self.len.trailing_zeros() as usize;
+        for (bit, &left) in (0..height)
+            .filter(|&bit| self.len & (1 << bit) != 0)
+            .skip(1)
+            .zip(subtrees)
+        {
+            while level < bit {
+                node = hash_pair(node, empty[level]);
+                level += 1;
+            }
+            node = hash_pair(left, node);
+            level += 1;
+        }
+        while level < height {
+            node = hash_pair(node, empty[level]);
+            level += 1;
+        }
+        Some(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn root_matches_tree_at_every_length() {
+        let mut builder = MerkleTreeBuilder::new();
+        assert_eq!(builder.root(), None);
+        let mut tree = MerkleTree::build::<u32>(&[]);
+        for value in 0..70_u32 {
+            builder.push(value);
+            tree.push(value);
+            assert_eq!(builder.root(), tree.root(), "length {}", value + 1);
+        }
+    }
+
+    #[test]
+    fn resumed_builds_match_straight_build() {
+        let values: Vec<u32> = (0..10_000).collect();
+        let expected = MerkleTree::build(&values).root();
+
+        for split in [3, 8, 1000] {
+            let mut builder = MerkleTreeBuilder::new();
+            values[..split].iter().for_each(|value| builder.push(value));
+
+            let checkpoint = builder.checkpoint();
+            assert_eq!(checkpoint.frontier.len(), split.count_ones() as usize);
+            let bytes = checkpoint.to_bytes();
+            drop(builder);
+
+            let checkpoint = BuilderCheckpoint::from_bytes(&bytes).unwrap();
+            let mut builder = MerkleTreeBuilder::resume(checkpoint).unwrap();
+            values[split..].iter().for_each(|value| builder.push(value));
+            assert_eq!(builder.len(), 10_000);
+            assert_eq!(builder.root(), expected);
+        }
+    }
+
+    #[test]
+    fn malformed_checkpoint_rejected() {
+        let mut builder = MerkleTreeBuilder::new();
+        (0..5).for_each(|value| builder.push(value));
+        let bytes = builder.checkpoint().to_bytes();
+
+        for len in 0..bytes.len() {
+            assert!(matches!(
+                BuilderCheckpoint::from_bytes(&bytes[..len]),
+                Err(CheckpointError::Truncated)
+            ));
+        }
+        assert!(matches!(
+            BuilderCheckpoint::from_bytes(&[&bytes[..], &[0; 8]].concat()),
+            Err(CheckpointError::FrontierMismatch {
+                expected: 2,
+                found: 3
+            })
+        ));
+
+        let forged = BuilderCheckpoint {
+            len: 5,
+            frontier: vec![1],
+        };
+        assert!(MerkleTreeBuilder::resume(forged).is_err());
+    }
+
+    #[test]
+    fn overlong_checkpoint_rejected() {
+        let forged = BuilderCheckpoint {
+            len: u64::MAX,
+            frontier: vec![1; 64],
+        };
+        assert!(matches!(
+            BuilderCheckpoint::from_bytes(&forged.to_bytes()),
+            Err(CheckpointError::Length(u64::MAX))
+        ));
+        assert!(matches!(
+            MerkleTreeBuilder::resume(forged),
+            Err(CheckpointError::Length(u64::MAX))
+        ));
+
+        // The longest checkpoint still has a root.
+        let longest = BuilderCheckpoint {
+            len: 1 << 63,
+            frontier: vec![7],
+        };
+        let checkpoint = BuilderCheckpoint::from_bytes(&longest.to_bytes()).unwrap();
+        assert_eq!(
+            MerkleTreeBuilder::resume(checkpoint).unwrap().root(),
+            Some(7)
+        );
+    }
+}
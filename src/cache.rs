@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+use crate::{MerkleError, MerkleProof, MerkleTree, NodeStore};
+
+/// Hit and miss counters of a tree's proof cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProofCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Least recently used cache of generated proofs, keyed by leaf index.
+pub(crate) struct ProofCache {
+    max_entries: usize,
+    entries: HashMap<usize, (MerkleProof, u64)>,
+    clock: u64,
+    stats: ProofCacheStats,
+}
+
+impl ProofCache {
+    fn new(max_entries: usize) -> ProofCache {
+        ProofCache {
+            max_entries,
+            entries: HashMap::new(),
+            clock: 0,
+            stats: ProofCacheStats::default(),
+        }
+    }
+
+    /// Returns the cached proof for the given index, marking it as the most recently used.
+    fn get(&mut self, index: usize) -> Option<MerkleProof> {
+        self.clock += 1;
+        match self.entries.get_mut(&index) {
+            Option::None => {
+                self.stats.misses += 1;
+                None
+            }
+            Option::Some((proof, last_used)) => {
+                self.stats.hits += 1;
+                *last_used = self.clock;
+                Some(proof.clone())
+            }
+        }
+    }
+
+    /// Stores a proof, evicting the least recently used entry if the cache is full.
+    fn insert(&mut self, index: usize, proof: MerkleProof) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        if self.entries.len() >= self.max_entries && !self.entries.contains_key(&index) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(&index, _)| index);
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.entries.insert(index, (proof, self.clock));
+    }
+
+    /// Drops every cached proof. Counters are kept.
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl<S: NodeStore> MerkleTree<S> {
+    /// Enables memoization of the proofs returned by `get_proof`.
+    /// Up to `max_entries` proofs are kept, evicting the least recently used ones
+    /// beyond that. Every mutation of the tree invalidates the whole cache, so cached
+    /// proofs are always identical to freshly generated ones.
+    /// Enabling the cache again replaces the previous one and resets its counters.
+    /// * `max_entries` - The maximum amount of proofs to be cached.
+    pub fn enable_proof_cache(&mut self, max_entries: usize) {
+        self.proof_cache = Some(Mutex::new(ProofCache::new(max_entries)));
+    }
+
+    /// Disables the proof cache, releasing its memory.
+    pub fn disable_proof_cache(&mut self) {
+        self.proof_cache = None;
+    }
+
+    /// Returns the hit and miss counters of the proof cache, or `None` if it is disabled.
+    pub fn proof_cache_stats(&self) -> Option<ProofCacheStats> {
+        let cache = self.proof_cache.as_ref()?;
+        Some(cache.lock().unwrap_or_else(PoisonError::into_inner).stats)
+    }
+
+    /// Returns the proof for the given index through the cache, if enabled.
+    pub(crate) fn get_proof_cached(&self, index: usize) -> Result<MerkleProof, MerkleError> {
+        let Some(cache) = &self.proof_cache else {
+            return self.generate_proof(index);
+        };
+
+        let mut cache = cache.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(proof) = cache.get(index) {
+            return Ok(proof);
+        }
+
+        let proof = self.generate_proof(index)?;
+        cache.insert(index, proof.clone());
+        Ok(proof)
+    }
+
+    /// Invalidates every cached proof. Must be called by every mutating operation.
+    pub(crate) fn invalidate_proof_cache(&mut self) {
+        if let Some(cache) = &mut self.proof_cache {
+            cache
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cached_proofs_verify() {
+        let mut tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        tree.enable_proof_cache(4);
+
+        assert!(tree.get_proof(2).verify(3));
+        assert!(tree.get_proof(2).verify(3));
+        assert!(tree.get_proof(2) == tree.generate_proof(2).unwrap());
+        assert_eq!(
+            tree.proof_cache_stats(),
+            Some(ProofCacheStats { hits: 2, misses: 1 })
+        );
+    }
+
+    #[test]
+    fn mutation_invalidates_cache() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        tree.enable_proof_cache(4);
+        let before = tree.get_proof(1);
+
+        tree.push(4);
+        let after = tree.get_proof(1);
+
+        assert!(before != after);
+        assert!(after == tree.generate_proof(1).unwrap());
+        assert!(after.verify(2));
+        assert_eq!(tree.proof_cache_stats().unwrap().hits, 0);
+    }
+
+    #[test]
+    fn least_recently_used_is_evicted() {
+        let mut tree = MerkleTree::build(&[1, 2, 3, 4]);
+        tree.enable_proof_cache(2);
+
+        tree.get_proof(0);
+        tree.get_proof(1);
+        tree.get_proof(0); // Hit, 1 becomes the least recently used.
+        tree.get_proof(2); // Evicts 1.
+        tree.get_proof(0); // Hit.
+        tree.get_proof(1); // Miss.
+
+        assert_eq!(
+            tree.proof_cache_stats(),
+            Some(ProofCacheStats { hits: 2, misses: 4 })
+        );
+    }
+
+    #[test]
+    fn invalid_proofs_are_not_cached() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        tree.enable_proof_cache(2);
+        tree.get_proof(3);
+        tree.push(4);
+        assert!(tree.get_proof(3).verify(4));
+    }
+
+    #[test]
+    fn disabled_cache_has_no_stats() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        assert_eq!(tree.proof_cache_stats(), None);
+
+        tree.enable_proof_cache(2);
+        tree.disable_proof_cache();
+        assert_eq!(tree.proof_cache_stats(), None);
+        assert!(tree.get_proof(0).verify(1));
+    }
+}
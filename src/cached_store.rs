@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::io;
+use std::ops::Range;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::NodeStore;
+
+/// Hit and miss counters of a `CachedStore`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NodeCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Least recently used cache of nodes, keyed by their coordinates.
+struct NodeCache {
+    max_entries: usize,
+    entries: HashMap<(usize, usize), (u64, u64)>,
+    clock: u64,
+    stats: NodeCacheStats,
+}
+
+impl NodeCache {
+    /// Returns the cached node at the given coordinates, marking it as the most recently
+    /// used.
+    fn get(&mut self, coord: (usize, usize)) -> Option<u64> {
+        self.clock += 1;
+        match self.entries.get_mut(&coord) {
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+            Some((node, last_used)) => {
+                self.stats.hits += 1;
+                *last_used = self.clock;
+                Some(*node)
+            }
+        }
+    }
+
+    /// Stores a node, evicting the least recently used entry if the cache is full.
+    fn insert(&mut self, coord: (usize, usize), node: u64) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        if self.entries.len() >= self.max_entries && !self.entries.contains_key(&coord) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(&coord, _)| coord);
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.entries.insert(coord, (node, self.clock));
+    }
+
+    /// Drops the cached nodes of a level within a range of indices.
+    /// * `level` - Level of the nodes.
+    /// * `indices` - Indices of the nodes within their level.
+    fn invalidate(&mut self, level: usize, indices: Range<usize>) {
+        if indices.len() < self.entries.len() {
+            for index in indices {
+                self.entries.remove(&(level, index));
+            }
+        } else {
+            self.entries
+                .retain(|&(node_level, index), _| node_level != level || !indices.contains(&index));
+        }
+    }
+}
+
+/// `NodeStore` wrapper keeping the most recently read nodes of a slower store in memory,
+/// up to a fixed amount, so that the reads of proofs and pushes mostly skip it.
+/// Writes go through to the wrapped store right away, updating the cached node. Batched
+/// writes and resets invalidate the cached nodes they replace instead of caching every
+/// node written, so that building a tree does not flush the cache with cold nodes.
+/// The cache never changes what is read: a tree gets the same roots and proofs with or
+/// without it.
+pub struct CachedStore<S> {
+    store: S,
+    cache: Mutex<NodeCache>,
+}
+
+impl<S: NodeStore> CachedStore<S> {
+    /// Wraps a store with a cache of the given capacity.
+    /// * `store` - The store whose nodes are cached.
+    /// * `max_entries` - The maximum amount of nodes to be cached.
+    pub fn new(store: S, max_entries: usize) -> CachedStore<S> {
+        CachedStore {
+            store,
+            cache: Mutex::new(NodeCache {
+                max_entries,
+                entries: HashMap::new(),
+                clock: 0,
+                stats: NodeCacheStats::default(),
+            }),
+        }
+    }
+
+    /// Returns the wrapped store.
+    pub fn get_ref(&self) -> &S {
+        &self.store
+    }
+
+    /// Consumes the cache, returning the wrapped store.
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    /// Returns the hit and miss counters of the cache.
+    pub fn stats(&self) -> NodeCacheStats {
+        self.lock().stats
+    }
+
+    /// Drops every cached node, so that the next reads reach the wrapped store. Must be
+    /// called if the wrapped store is changed other than through the cache. Counters are
+    /// kept.
+    pub fn invalidate_all(&mut self) {
+        self.cache_mut().entries.clear();
+    }
+
+    /// Returns the cache, locked.
+    fn lock(&self) -> MutexGuard<'_, NodeCache> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the cache of a store borrowed mutably, which needs no locking.
+    fn cache_mut(&mut self) -> &mut NodeCache {
+        self.cache.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<S: NodeStore> NodeStore for CachedStore<S> {
+    fn height(&self) -> usize {
+        self.store.height()
+    }
+
+    fn len(&self, level: usize) -> Option<usize> {
+        self.store.len(level)
+    }
+
+    fn get(&self, level: usize, index: usize) -> Option<u64> {
+        if let Some(node) = self.lock().get((level, index)) {
+            return Some(node);
+        }
+        // The cache is not locked while reading, so that slow reads do not block the
+        // other readers' hits.
+        let node = self.store.get(level, index)?;
+        self.lock().insert((level, index), node);
+        Some(node)
+    }
+
+    #[track_caller]
+    fn put(&mut self, level: usize, index: usize, node: u64) {
+        self.store.put(level, index, node);
+        self.cache_mut().insert((level, index), node);
+    }
+
+    #[track_caller]
+    fn put_batch(&mut self, level: usize, first: usize, nodes: &[u64]) {
+        self.store.put_batch(level, first, nodes);
+        self.cache_mut()
+            .invalidate(level, first..first + nodes.len());
+    }
+
+    fn reset(&mut self, capacity: usize) {
+        self.store.reset(capacity);
+        self.invalidate_all();
+    }
+
+    fn grow(&mut self, root: u64) {
+        // The nodes already stored keep their coordinates, so none is invalidated.
+        self.store.grow(root);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.store.flush()
+    }
+
+    fn lowest_written(&self) -> usize {
+        self.store.lowest_written()
+    }
+
+    fn release_reads(&mut self) {
+        self.store.release_reads();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{FileStore, MemStore, MerkleTree};
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Push(u32),
+        Prove(usize),
+        Rebuild(Vec<u32>),
+    }
+
+    fn arb_op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            4 => any::<u32>().prop_map(Op::Push),
+            4 => any::<usize>().prop_map(Op::Prove),
+            1 => proptest::collection::vec(any::<u32>(), 0..40).prop_map(Op::Rebuild),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn cached_file_store_matches_memory(
+            elements in proptest::collection::vec(any::<u32>(), 0..40),
+            max_entries in 0_usize..16,
+            ops in proptest::collection::vec(arb_op(), 0..60),
+        ) {
+            let dir = tempfile::tempdir().unwrap();
+            let store = CachedStore::new(FileStore::create(dir.path()).unwrap(), max_entries);
+            let mut tree = MerkleTree::build_in(store, &elements);
+            let mut expected = MerkleTree::build_in(MemStore::default(), &elements);
+            for op in ops {
+                match op {
+                    Op::Push(value) => {
+                        tree.push(value);
+                        expected.push(value);
+                    }
+                    Op::Prove(index) => {
+                        let index = index % (expected.len() + 1);
+                        prop_assert!(tree.get_proof(index) == expected.get_proof(index));
+                    }
+                    Op::Rebuild(elements) => {
+                        tree = MerkleTree::build_in(tree.into_store(), &elements);
+                        expected = MerkleTree::build(&elements);
+                    }
+                }
+                prop_assert_eq!(tree.root(), expected.root());
+            }
+        }
+    }
+
+    #[test]
+    fn evictions_keep_proofs_exact() {
+        let dir = tempfile::tempdir().unwrap();
+        let elements: Vec<u32> = (0..10_000).collect();
+        let store = CachedStore::new(FileStore::create(dir.path()).unwrap(), 8);
+        let mut tree = MerkleTree::build_in(store, &elements);
+        let mut expected = MerkleTree::build(&elements);
+        for index in (0..elements.len()).step_by(7) {
+            assert!(tree.get_proof(index) == expected.get_proof(index));
+        }
+        for value in 10_000..10_100 {
+            tree.push(value);
+            expected.push(value);
+            assert!(
+                tree.get_proof(value as usize - 5_000)
+                    == expected.get_proof(value as usize - 5_000)
+            );
+        }
+        assert_eq!(tree.root(), expected.root());
+        assert!(tree.store().lock().entries.len() <= 8);
+        assert!(tree.store().stats().misses > 0);
+    }
+
+    #[test]
+    fn repeated_reads_hit() {
+        let mut tree =
+            MerkleTree::build_in(CachedStore::new(MemStore::default(), 64), &[1, 2, 3, 4, 5]);
+        tree.get_proof(2);
+        let misses = tree.store().stats().misses;
+        assert!(tree.get_proof(2).verify(3));
+        assert_eq!(tree.store().stats().misses, misses);
+        assert!(tree.store().stats().hits >= 3);
+
+        // Pushes write through, so the new leaf's path is read from the cache.
+        tree.push(6);
+        let misses = tree.store().stats().misses;
+        assert!(tree.get_proof(5).verify(6));
+        assert_eq!(tree.store().stats().misses, misses);
+    }
+}
@@ -0,0 +1,170 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::hash::Hash;
+
+use crate::{MerkleError, MerkleTree, NodeStore};
+
+/// Error returned by `MerkleTree::compare_and_push` and `compare_and_extend`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CasError {
+    /// The tree's root is not the expected one, so the tree was left untouched.
+    Stale { expected: u64, actual: u64 },
+    /// Pushing failed, see `try_push`.
+    Tree(MerkleError),
+}
+
+impl Display for CasError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CasError::Stale { expected, actual } => {
+                write!(f, "expected root {expected:016x}, found {actual:016x}")
+            }
+            CasError::Tree(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for CasError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CasError::Stale { .. } => None,
+            CasError::Tree(error) => Some(error),
+        }
+    }
+}
+
+impl From<MerkleError> for CasError {
+    fn from(error: MerkleError) -> Self {
+        CasError::Tree(error)
+    }
+}
+
+impl<S: NodeStore> MerkleTree<S> {
+    /// Pushes an element only if the tree's root is the expected one, so that writers
+    /// appending on top of a root they read earlier detect having lost a race instead of
+    /// interleaving their leaves with someone else's. The root of an empty tree is
+    /// `PAD_HASH`, like the one reported to `on_root_change`.
+    /// The check and the push happen under the same mutable borrow, so they are atomic
+    /// for any tree shared behind a lock.
+    /// Returns the new root, or the actual one inside `CasError::Stale`.
+    /// * `expected_root` - The root the element is to be pushed on top of.
+    /// * `value` - The `Hash` value to be added to the tree.
+    pub fn compare_and_push<H: Hash>(
+        &mut self,
+        expected_root: u64,
+        value: H,
+    ) -> Result<u64, CasError> {
+        self.compare_and_extend(expected_root, [value])
+    }
+
+    /// Pushes several elements only if the tree's root is the expected one, like
+    /// `compare_and_push`. The elements are pushed in order, and none is if the root does
+    /// not match. Fails after pushing some of them if the tree is corrupted.
+    /// Returns the new root, or the actual one inside `CasError::Stale`.
+    /// * `expected_root` - The root the elements are to be pushed on top of.
+    /// * `values` - The `Hash` values to be added to the tree.
+    pub fn compare_and_extend<H: Hash, I: IntoIterator<Item = H>>(
+        &mut self,
+        expected_root: u64,
+        values: I,
+    ) -> Result<u64, CasError> {
+        let actual = self.top_node();
+        if actual != expected_root {
+            return Err(CasError::Stale {
+                expected: expected_root,
+                actual,
+            });
+        }
+        for value in values {
+            self.try_push(value)?;
+        }
+        Ok(self.top_node())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Barrier, Mutex};
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn cas_pushes_chain() {
+        let mut tree = MerkleTree::build::<u32>(&[]);
+        let mut root = MerkleTree::PAD_HASH;
+        for value in 0..20_u32 {
+            root = tree.compare_and_push(root, value).unwrap();
+            assert_eq!(tree.root(), Some(root));
+        }
+        root = tree.compare_and_extend(root, 20..50_u32).unwrap();
+        assert_eq!(
+            root,
+            MerkleTree::build(&(0..50).collect::<Vec<u32>>()).top_node()
+        );
+    }
+
+    #[test]
+    fn stale_roots_are_rejected() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        let stale = tree.top_node();
+        tree.push(4);
+        let actual = tree.top_node();
+        assert_eq!(
+            tree.compare_and_push(stale, 5),
+            Err(CasError::Stale {
+                expected: stale,
+                actual
+            })
+        );
+        assert_eq!(
+            tree.compare_and_extend(stale, [5, 6]),
+            Err(CasError::Stale {
+                expected: stale,
+                actual
+            })
+        );
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.epoch(), 1);
+    }
+
+    #[test]
+    fn exactly_one_racing_cas_wins() {
+        let tree = Arc::new(Mutex::new(MerkleTree::build(&[0_u32])));
+        for round in 1..200_u32 {
+            let root = tree.lock().unwrap().top_node();
+            let barrier = Arc::new(Barrier::new(2));
+            let racers: Vec<_> = (0..2)
+                .map(|racer| {
+                    let tree = Arc::clone(&tree);
+                    let barrier = Arc::clone(&barrier);
+                    thread::spawn(move || {
+                        barrier.wait();
+                        tree.lock().unwrap().compare_and_push(root, (round, racer))
+                    })
+                })
+                .collect();
+            let results: Vec<_> = racers
+                .into_iter()
+                .map(|racer| racer.join().unwrap())
+                .collect();
+            let winners: Vec<_> = results
+                .iter()
+                .filter_map(|result| result.clone().ok())
+                .collect();
+            assert_eq!(winners.len(), 1);
+            let loser = results
+                .iter()
+                .find_map(|result| result.clone().err())
+                .unwrap();
+            assert_eq!(
+                loser,
+                CasError::Stale {
+                    expected: root,
+                    actual: winners[0]
+                }
+            );
+        }
+        assert_eq!(tree.lock().unwrap().len(), 200);
+    }
+}
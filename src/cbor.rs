@@ -0,0 +1,466 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use crate::{DecodeOptions, HashAlgorithm, LimitError, MerkleProof, is_consistent_path};
+
+/// Major type of unsigned integers.
+const UNSIGNED: u8 = 0;
+
+/// Major type of byte strings.
+const BYTES: u8 = 2;
+
+/// Major type of arrays.
+const ARRAY: u8 = 4;
+
+/// Major type of maps.
+const MAP: u8 = 5;
+
+/// Keys of the map of a valid proof, in their canonical order.
+const KEY_INDEX: u64 = 1;
+const KEY_PATH: u64 = 2;
+const KEY_ROOT: u64 = 3;
+const KEY_ALGO: u64 = 4;
+const KEY_LEN: u64 = 5;
+const KEY_EPOCH: u64 = 6;
+
+/// Amount of entries of the map of a valid proof.
+const PROOF_ENTRIES: u64 = 6;
+
+/// Width of every hash, encoded as a byte string holding a big-endian `u64`.
+const HASH_BYTES: u64 = 8;
+
+/// Error returned when decoding a CBOR proof fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CborError {
+    /// The data ended before the proof did.
+    Truncated,
+    /// An item is not in its deterministic encoding: an argument is not in its shortest
+    /// form, or a length is indefinite.
+    NonCanonical,
+    /// An item has another major type than the expected one.
+    UnexpectedType { expected: u8, found: u8 },
+    /// The map holds neither no entry, for invalid proofs, nor every key of a proof.
+    MapSize(u64),
+    /// A key is unknown, missing or out of order.
+    UnexpectedKey { expected: u64, found: u64 },
+    /// A hash does not have the width of a `u64`.
+    HashWidth { found: u64 },
+    /// The hash algorithm identifier is unknown.
+    UnknownAlgorithm(u64),
+    /// An index or a length does not fit in a `usize`.
+    Overflow(u64),
+    /// The input exceeds a limit of the `DecodeOptions`.
+    Limit(LimitError),
+    /// The proof's index is not below its length.
+    IndexOutOfRange { index: usize, len: usize },
+    /// The proof's nodes do not form a path in a tree of its length (see
+    /// `MerkleProof::verify`).
+    InconsistentPath { len: usize, nodes: usize },
+    /// Bytes remain after the proof.
+    TrailingBytes(usize),
+}
+
+impl Display for CborError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CborError::Truncated => f.write_str("truncated proof"),
+            CborError::NonCanonical => f.write_str("item not in its deterministic encoding"),
+            CborError::UnexpectedType { expected, found } => {
+                write!(f, "expected major type {expected}, found {found}")
+            }
+            CborError::MapSize(size) => write!(f, "invalid map of {size} entries"),
+            CborError::UnexpectedKey { expected, found } => {
+                write!(f, "expected key {expected}, found {found}")
+            }
+            CborError::HashWidth { found } => {
+                write!(f, "expected hashes of {HASH_BYTES} bytes, found {found}")
+            }
+            CborError::UnknownAlgorithm(id) => write!(f, "unknown hash algorithm {id}"),
+            CborError::Overflow(value) => write!(f, "value {value} does not fit in a usize"),
+            CborError::Limit(error) => write!(f, "limit exceeded: {error}"),
+            CborError::IndexOutOfRange { index, len } => {
+                write!(f, "index {index} out of range for length {len}")
+            }
+            CborError::InconsistentPath { len, nodes } => {
+                write!(
+                    f,
+                    "a path of {nodes} nodes does not fit a tree of length {len}"
+                )
+            }
+            CborError::TrailingBytes(count) => write!(f, "{count} trailing bytes"),
+        }
+    }
+}
+
+impl Error for CborError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CborError::Limit(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<LimitError> for CborError {
+    fn from(error: LimitError) -> CborError {
+        CborError::Limit(error)
+    }
+}
+
+/// Appends the head of an item in its shortest form: its major type and argument.
+/// * `out` - Where the head is written.
+/// * `major` - The major type of the item.
+/// * `value` - The argument: a value, a length or an amount of entries.
+fn write_head(out: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    match value {
+        0..24 => out.push(major | value as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, value as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+/// Appends a hash, as a byte string holding it in big-endian.
+fn write_hash(out: &mut Vec<u8>, hash: u64) {
+    write_head(out, BYTES, HASH_BYTES);
+    out.extend_from_slice(&hash.to_be_bytes());
+}
+
+/// Cursor over the bytes of a CBOR proof, accepting deterministic encodings only.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    /// Reads `N` bytes.
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], CborError> {
+        let (taken, rest) = self
+            .bytes
+            .split_first_chunk::<N>()
+            .ok_or(CborError::Truncated)?;
+        self.bytes = rest;
+        Ok(*taken)
+    }
+
+    /// Reads the head of an item, returning its major type and argument. Fails unless
+    /// the argument is in its shortest form.
+    fn head(&mut self) -> Result<(u8, u64), CborError> {
+        let [initial] = self.take::<1>()?;
+        let (value, min) = match initial & 0x1f {
+            info @ 0..24 => return Ok((initial >> 5, u64::from(info))),
+            24 => (u64::from(u8::from_be_bytes(self.take()?)), 24),
+            25 => (u64::from(u16::from_be_bytes(self.take()?)), 0x100),
+            26 => (u64::from(u32::from_be_bytes(self.take()?)), 0x1_0000),
+            27 => (u64::from_be_bytes(self.take()?), 0x1_0000_0000),
+            _ => return Err(CborError::NonCanonical),
+        };
+        if value < min {
+            return Err(CborError::NonCanonical);
+        }
+        Ok((initial >> 5, value))
+    }
+
+    /// Reads the head of an item of the given major type, returning its argument.
+    fn expect(&mut self, major: u8) -> Result<u64, CborError> {
+        match self.head()? {
+            (found, value) if found == major => Ok(value),
+            (found, _) => Err(CborError::UnexpectedType {
+                expected: major,
+                found,
+            }),
+        }
+    }
+
+    /// Reads the given key of a map.
+    fn key(&mut self, expected: u64) -> Result<(), CborError> {
+        match self.expect(UNSIGNED)? {
+            found if found == expected => Ok(()),
+            found => Err(CborError::UnexpectedKey { expected, found }),
+        }
+    }
+
+    /// Reads an unsigned integer fitting in a `usize`.
+    fn usize(&mut self) -> Result<usize, CborError> {
+        let value = self.expect(UNSIGNED)?;
+        usize::try_from(value).map_err(|_| CborError::Overflow(value))
+    }
+
+    /// Reads a hash written by `write_hash`.
+    fn hash(&mut self) -> Result<u64, CborError> {
+        match self.expect(BYTES)? {
+            HASH_BYTES => Ok(u64::from_be_bytes(self.take()?)),
+            found => Err(CborError::HashWidth { found }),
+        }
+    }
+}
+
+impl MerkleProof {
+    /// Returns the canonical CBOR encoding of the proof, deterministic as RFC 8949 defines
+    /// it so that signatures over the bytes are stable. A valid proof is a map of integer
+    /// keys in ascending order: 1 for the index, 2 for the path (an array of the sibling
+    /// nodes, from the leaves' level upwards), 3 for the root, 4 for the hash algorithm
+    /// (see `HashAlgorithm::id`), 5 for the tree's length and 6 for its epoch. Integers
+    /// take their shortest form, and hashes are 8 byte strings holding them in
+    /// big-endian. An invalid proof is an empty map.
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let MerkleProof::Proof {
+            index,
+            nodes,
+            root,
+            len,
+            epoch,
+        } = self
+        else {
+            write_head(&mut out, MAP, 0);
+            return out;
+        };
+
+        write_head(&mut out, MAP, PROOF_ENTRIES);
+        write_head(&mut out, UNSIGNED, KEY_INDEX);
+        write_head(&mut out, UNSIGNED, *index as u64);
+        write_head(&mut out, UNSIGNED, KEY_PATH);
+        write_head(&mut out, ARRAY, nodes.len() as u64);
+        for &node in nodes {
+            write_hash(&mut out, node);
+        }
+        write_head(&mut out, UNSIGNED, KEY_ROOT);
+        write_hash(&mut out, *root);
+        write_head(&mut out, UNSIGNED, KEY_ALGO);
+        write_head(&mut out, UNSIGNED, HashAlgorithm::DefaultHasher.id().into());
+        write_head(&mut out, UNSIGNED, KEY_LEN);
+        write_head(&mut out, UNSIGNED, *len as u64);
+        write_head(&mut out, UNSIGNED, KEY_EPOCH);
+        write_head(&mut out, UNSIGNED, *epoch);
+        out
+    }
+
+    /// Decodes a proof out of its canonical CBOR encoding, written by `to_cbor`, within
+    /// the default `DecodeOptions`.
+    /// Decoding is strict: only the canonical encoding is accepted, so unknown, missing
+    /// and unordered keys are rejected, and so are items not in their shortest form.
+    /// Fails without panicking on any malformed input.
+    /// * `bytes` - The encoded proof.
+    pub fn from_cbor(bytes: &[u8]) -> Result<MerkleProof, CborError> {
+        MerkleProof::from_cbor_with(bytes, &DecodeOptions::DEFAULT)
+    }
+
+    /// Decodes a proof out of its canonical CBOR encoding, like `from_cbor`. The input
+    /// length and the length of the path are checked against the limits before any node
+    /// is read.
+    /// * `bytes` - The encoded proof.
+    /// * `options` - The limits enforced.
+    pub fn from_cbor_with(bytes: &[u8], options: &DecodeOptions) -> Result<MerkleProof, CborError> {
+        options.check_len(bytes.len())?;
+        let mut reader = Reader { bytes };
+
+        let proof = match reader.expect(MAP)? {
+            0 => MerkleProof::Invalid,
+            PROOF_ENTRIES => {
+                reader.key(KEY_INDEX)?;
+                let index = reader.usize()?;
+                reader.key(KEY_PATH)?;
+                let node_count = reader.expect(ARRAY)?;
+                options.check_node_count(node_count)?;
+                let nodes = (0..node_count)
+                    .map(|_| reader.hash())
+                    .collect::<Result<Vec<u64>, CborError>>()?;
+                reader.key(KEY_ROOT)?;
+                let root = reader.hash()?;
+                reader.key(KEY_ALGO)?;
+                let algo = reader.expect(UNSIGNED)?;
+                if u8::try_from(algo)
+                    .ok()
+                    .and_then(HashAlgorithm::from_id)
+                    .is_none()
+                {
+                    return Err(CborError::UnknownAlgorithm(algo));
+                }
+                reader.key(KEY_LEN)?;
+                let len = reader.usize()?;
+                reader.key(KEY_EPOCH)?;
+                let epoch = reader.expect(UNSIGNED)?;
+
+                if index >= len {
+                    return Err(CborError::IndexOutOfRange { index, len });
+                }
+                if !is_consistent_path(index, len, &nodes) {
+                    return Err(CborError::InconsistentPath {
+                        len,
+                        nodes: nodes.len(),
+                    });
+                }
+                MerkleProof::Proof {
+                    index,
+                    nodes,
+                    root,
+                    len,
+                    epoch,
+                }
+            }
+            size => return Err(CborError::MapSize(size)),
+        };
+
+        if !reader.bytes.is_empty() {
+            return Err(CborError::TrailingBytes(reader.bytes.len()));
+        }
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    /// Proof whose canonical encoding is pinned by `GOLDEN`.
+    fn golden_proof() -> MerkleProof {
+        MerkleProof::Proof {
+            index: 2,
+            nodes: vec![0x0102_0304_0506_0708, 0x1112_1314_1516_1718],
+            root: 0xa1a2_a3a4_a5a6_a7a8,
+            len: 3,
+            epoch: 300,
+        }
+    }
+
+    #[rustfmt::skip]
+    const GOLDEN: [u8; 41] = [
+        0xa6,
+        0x01, 0x02,
+        0x02, 0x82,
+        0x48, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08,
+        0x48, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18,
+        0x03, 0x48, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8,
+        0x04, 0x00,
+        0x05, 0x03,
+        0x06, 0x19, 0x01, 0x2c,
+    ];
+
+    #[test]
+    fn canonical_form_is_pinned() {
+        assert_eq!(golden_proof().to_cbor(), GOLDEN);
+        assert!(MerkleProof::from_cbor(&GOLDEN).unwrap() == golden_proof());
+        assert_eq!(MerkleProof::Invalid.to_cbor(), [0xa0]);
+        assert!(MerkleProof::from_cbor(&[0xa0]).unwrap() == MerkleProof::Invalid);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut tree = MerkleTree::build(&(0..1000).collect::<Vec<u32>>());
+        tree.push(1000);
+        for index in [0, 23, 24, 255, 256, 1000] {
+            let proof = tree.get_proof(index);
+            let decoded = MerkleProof::from_cbor(&proof.to_cbor()).unwrap();
+            assert!(decoded == proof);
+            assert!(decoded.verify(index as u32));
+        }
+
+        let mut out = Vec::new();
+        for value in [
+            0,
+            23,
+            24,
+            0xff,
+            0x100,
+            0xffff,
+            0x1_0000,
+            u32::MAX.into(),
+            u64::MAX,
+        ] {
+            out.clear();
+            write_head(&mut out, UNSIGNED, value);
+            assert_eq!(Reader { bytes: &out }.expect(UNSIGNED), Ok(value));
+        }
+    }
+
+    #[test]
+    fn non_canonical_input_rejected() {
+        let decode = |bytes: &[u8]| MerkleProof::from_cbor(bytes).err();
+
+        // The epoch, 300, written on 4 bytes rather than 2.
+        let mut long = GOLDEN[..GOLDEN.len() - 3].to_vec();
+        long.extend_from_slice(&[0x1a, 0x00, 0x00, 0x01, 0x2c]);
+        assert_eq!(decode(&long), Some(CborError::NonCanonical));
+
+        // An indefinite length map.
+        assert_eq!(decode(&[0xbf]), Some(CborError::NonCanonical));
+
+        // Unknown and unordered keys.
+        let mut unknown = GOLDEN.to_vec();
+        unknown[1] = 0x07;
+        assert_eq!(
+            decode(&unknown),
+            Some(CborError::UnexpectedKey {
+                expected: 1,
+                found: 7
+            })
+        );
+        let mut swapped = GOLDEN.to_vec();
+        swapped[33] = 0x05;
+        assert_eq!(
+            decode(&swapped),
+            Some(CborError::UnexpectedKey {
+                expected: 4,
+                found: 5
+            })
+        );
+
+        assert_eq!(decode(&[0xa1, 0x01, 0x02]), Some(CborError::MapSize(1)));
+        let mut algo = GOLDEN.to_vec();
+        algo[34] = 0x01;
+        assert_eq!(decode(&algo), Some(CborError::UnknownAlgorithm(1)));
+        let mut width = GOLDEN.to_vec();
+        width[5] = 0x47;
+        assert_eq!(decode(&width), Some(CborError::HashWidth { found: 7 }));
+        let mut trailing = GOLDEN.to_vec();
+        trailing.push(0);
+        assert_eq!(decode(&trailing), Some(CborError::TrailingBytes(1)));
+        assert_eq!(
+            decode(&[0x80]),
+            Some(CborError::UnexpectedType {
+                expected: MAP,
+                found: ARRAY
+            })
+        );
+    }
+
+    #[test]
+    fn oversized_paths_rejected() {
+        // A path declaring 2^32 nodes is rejected before any is read.
+        let mut bytes = vec![0xa6, 0x01, 0x00, 0x02];
+        write_head(&mut bytes, ARRAY, 1 << 32);
+        assert_eq!(
+            MerkleProof::from_cbor(&bytes).err(),
+            Some(CborError::Limit(LimitError::Depth {
+                found: 1 << 32,
+                max: 64
+            }))
+        );
+    }
+
+    #[test]
+    fn malformed_input_never_panics() {
+        for len in 0..GOLDEN.len() {
+            assert!(MerkleProof::from_cbor(&GOLDEN[..len]).is_err());
+        }
+        for position in 0..GOLDEN.len() {
+            for byte in 0..=u8::MAX {
+                let mut bytes = GOLDEN.to_vec();
+                bytes[position] = byte;
+                let _ = MerkleProof::from_cbor(&bytes);
+            }
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use std::io::{self, ErrorKind, Read};
+use std::ops::Range;
+
+use crate::chunk::fill;
+use crate::{MerkleTree, hash_single};
+
+/// Returns 256 pseudo-random values for the rolling hash, out of a fixed seed, so that
+/// chunk boundaries never change across builds or platforms.
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut state: u64 = 0x6d65_726b_6c65_6364;
+    let mut byte = 0;
+    while byte < 256 {
+        // SplitMix64.
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut value = state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[byte] = value ^ (value >> 31);
+        byte += 1;
+    }
+    table
+}
+
+const GEAR: [u64; 256] = gear_table();
+
+/// Sizes bounding the chunks found by content-defined chunking, in bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CdcParams {
+    /// Length below which no boundary is looked for. Only the last chunk may be shorter.
+    pub min_size: usize,
+    /// Length chunks tend to, rounded down to a power of two.
+    pub avg_size: usize,
+    /// Length at which chunks are cut if no boundary was found before.
+    pub max_size: usize,
+}
+
+impl Default for CdcParams {
+    /// 2 KiB, 8 KiB and 64 KiB chunks.
+    fn default() -> Self {
+        CdcParams {
+            min_size: 2 * 1024,
+            avg_size: 8 * 1024,
+            max_size: 64 * 1024,
+        }
+    }
+}
+
+impl CdcParams {
+    /// Fails unless `0 < min_size <= avg_size <= max_size`, with an average of at least
+    /// 16 bytes.
+    fn check(&self) -> io::Result<()> {
+        if self.min_size == 0
+            || self.avg_size < 16
+            || self.min_size > self.avg_size
+            || self.avg_size > self.max_size
+        {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "chunk sizes must satisfy 0 < min <= avg <= max, with avg >= 16",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the length of the chunk at the start of the data, which holds at least
+    /// `max_size` bytes unless it is the end of the input.
+    /// Following FastCDC, the rolling hash must match a mask of more bits before the
+    /// average size than after it, so that chunk sizes gather around the average.
+    /// * `data` - The bytes following the previous boundary.
+    fn cut_point(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min_size {
+            return data.len();
+        }
+        let end = data.len().min(self.max_size);
+        let normal = self.avg_size.min(end);
+        let bits = self.avg_size.ilog2();
+        // The high bits of the hash depend on the most bytes.
+        let strict = !0 << (64 - (bits + 2));
+        let loose = !0 << (64 - (bits - 2));
+
+        let mut hash: u64 = 0;
+        for (index, &byte) in data.iter().enumerate().take(end).skip(self.min_size) {
+            hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+            let mask = if index < normal { strict } else { loose };
+            if hash & mask == 0 {
+                return index + 1;
+            }
+        }
+        end
+    }
+}
+
+/// Position of a chunk within the bytes a tree was built out of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkInfo {
+    /// Offset of the chunk's first byte.
+    pub offset: u64,
+    /// Length of the chunk, in bytes.
+    pub len: usize,
+}
+
+impl ChunkInfo {
+    /// Returns the range of bytes covered by the chunk.
+    pub fn range(&self) -> Range<u64> {
+        self.offset..self.offset + self.len as u64
+    }
+}
+
+impl MerkleTree {
+    /// Builds a tree out of the bytes of a reader, split into chunks whose boundaries
+    /// depend on their content rather than their offset, like FastCDC does: a rolling
+    /// hash over the bytes marks a boundary wherever its high bits are zero, within the
+    /// sizes allowed by the parameters. Inserting or removing bytes only moves the
+    /// boundaries around the change, so the leaves of the unchanged bytes are the same.
+    /// Boundaries only depend on the bytes and the parameters, never on how the reader
+    /// returns them. Each chunk becomes a leaf, hashed as a `&[u8]` value like
+    /// `build_from_read` does, and is described by the `ChunkInfo` at the same index.
+    /// An empty input produces an empty tree.
+    /// Fails if the parameters are invalid or reading fails.
+    /// * `reader` - Where the bytes are read from.
+    /// * `params` - The sizes bounding the chunks.
+    pub fn build_cdc<R: Read>(
+        mut reader: R,
+        params: CdcParams,
+    ) -> io::Result<(MerkleTree, Vec<ChunkInfo>)> {
+        params.check()?;
+
+        let mut buffer = vec![0; params.max_size];
+        let mut filled = 0;
+        let mut offset = 0;
+        let mut leaves = Vec::new();
+        let mut chunks = Vec::new();
+        loop {
+            filled += fill(&mut reader, &mut buffer[filled..])?;
+            if filled == 0 {
+                break;
+            }
+            let len = params.cut_point(&buffer[..filled]);
+            leaves.push(hash_single(&buffer[..len]));
+            chunks.push(ChunkInfo { offset, len });
+            offset += len as u64;
+            buffer.copy_within(len..filled, 0);
+            filled -= len;
+        }
+        Ok((MerkleTree::from_leaf_hashes(leaves), chunks))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    /// Returns `len` pseudo-random bytes.
+    fn data(len: usize) -> Vec<u8> {
+        let mut state: u32 = 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    /// Reader returning a few bytes at a time.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read = self.0.len().min(buf.len()).min(7);
+            buf[..read].copy_from_slice(&self.0[..read]);
+            self.0 = &self.0[read..];
+            Ok(read)
+        }
+    }
+
+    #[test]
+    fn boundaries_are_deterministic() {
+        let bytes = data(300_000);
+        let params = CdcParams::default();
+        let (tree, chunks) = MerkleTree::build_cdc(&bytes[..], params).unwrap();
+        let (trickled, trickled_chunks) = MerkleTree::build_cdc(Trickle(&bytes), params).unwrap();
+        assert_eq!(chunks, trickled_chunks);
+        assert_eq!(tree.root(), trickled.root());
+        assert_eq!(chunks, MerkleTree::build_cdc(&bytes[..], params).unwrap().1);
+
+        let mut offset = 0;
+        for (index, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.offset, offset);
+            assert!(chunk.len <= params.max_size);
+            assert!(chunk.len >= params.min_size || index == chunks.len() - 1);
+            let range = chunk.range();
+            assert!(
+                tree.get_proof(index)
+                    .verify(&bytes[range.start as usize..range.end as usize])
+            );
+            offset = range.end;
+        }
+        assert_eq!(offset, bytes.len() as u64);
+        // Boundaries are found by content, not only forced at the maximum size.
+        assert!(chunks.len() > bytes.len() / params.max_size * 2);
+    }
+
+    #[test]
+    fn insertions_keep_most_leaves() {
+        let bytes = data(1 << 20);
+        let mut edited = bytes.clone();
+        edited.splice(100..100, *b"inserted");
+        let params = CdcParams::default();
+        let (tree, _) = MerkleTree::build_cdc(&bytes[..], params).unwrap();
+        let (edited_tree, _) = MerkleTree::build_cdc(&edited[..], params).unwrap();
+
+        let original: HashSet<u64> = tree.leaves().iter().copied().collect();
+        let kept = edited_tree
+            .leaves()
+            .iter()
+            .filter(|leaf| original.contains(leaf))
+            .count();
+        assert!(kept * 10 >= tree.len() * 9);
+        assert_ne!(tree.root(), edited_tree.root());
+
+        // Fixed-size chunks shift instead, changing every leaf after the insertion.
+        let fixed = MerkleTree::build_from_read(&bytes[..], 8 * 1024).unwrap();
+        let fixed_edited = MerkleTree::build_from_read(&edited[..], 8 * 1024).unwrap();
+        let original: HashSet<u64> = fixed.leaves().iter().copied().collect();
+        assert!(
+            fixed_edited
+                .leaves()
+                .iter()
+                .all(|leaf| !original.contains(leaf))
+        );
+    }
+
+    #[test]
+    fn small_inputs_and_invalid_params() {
+        let params = CdcParams {
+            min_size: 64,
+            avg_size: 256,
+            max_size: 1024,
+        };
+        let (tree, chunks) = MerkleTree::build_cdc(&[][..], params).unwrap();
+        assert!(tree.is_empty());
+        assert!(chunks.is_empty());
+
+        let (tree, chunks) = MerkleTree::build_cdc(&[1, 2, 3][..], params).unwrap();
+        assert_eq!(chunks, [ChunkInfo { offset: 0, len: 3 }]);
+        assert!(tree.get_proof(0).verify(&[1_u8, 2, 3][..]));
+
+        for (min_size, avg_size, max_size) in [(0, 256, 1024), (512, 256, 1024), (64, 2048, 1024)] {
+            let params = CdcParams {
+                min_size,
+                avg_size,
+                max_size,
+            };
+            let error = MerkleTree::build_cdc(&[1][..], params).err().unwrap();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        }
+    }
+}
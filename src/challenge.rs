@@ -0,0 +1,107 @@
+use rand::{Rng, RngCore};
+
+use crate::{MerkleProof, MerkleTree, hash_pair};
+
+impl MerkleTree {
+    /// Picks a uniformly random occupied leaf and returns its index together with
+    /// its `MerkleProof`, to be used as a storage audit challenge.
+    /// Padded slots are never picked. If the tree is empty, `None` is returned.
+    /// * `rng` - The random number generator used to pick the leaf.
+    pub fn random_challenge(&self, rng: &mut impl RngCore) -> Option<(usize, MerkleProof)> {
+        if self.is_empty() {
+            return None;
+        }
+        let index = rng.random_range(0..self.len());
+        Some((index, self.get_proof(index)))
+    }
+
+    /// Deterministically derives a challenge from a shared seed, so that both the
+    /// auditor and the storage provider pick the same leaf.
+    /// The index is derived from the seed and the tree's root with the tree's own
+    /// hash function, and is uniformly distributed over the occupied leaves.
+    /// If the tree is empty, `None` is returned.
+    /// * `seed` - The seed shared by both parties.
+    pub fn challenge_seeded(&self, seed: u64) -> Option<(usize, MerkleProof)> {
+        let root = self.root()?;
+        let len = self.len() as u64;
+
+        // Rejection sampling avoids the modulo bias of the last, incomplete, range.
+        let limit = u64::MAX - u64::MAX % len;
+        let mut candidate = hash_pair(seed, root);
+        while candidate >= limit {
+            candidate = hash_pair(seed, candidate);
+        }
+
+        let index = (candidate % len) as usize;
+        Some((index, self.get_proof(index)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    /// Returns the chi-squared statistic of the observed counts against a uniform distribution.
+    fn chi_squared(counts: &[u64]) -> f64 {
+        let total: u64 = counts.iter().sum();
+        let expected = total as f64 / counts.len() as f64;
+        counts
+            .iter()
+            .map(|&count| (count as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    #[test]
+    fn empty_tree_has_no_challenge() {
+        let tree = MerkleTree::build::<u8>(&[]);
+        assert!(
+            tree.random_challenge(&mut StdRng::seed_from_u64(1))
+                .is_none()
+        );
+        assert!(tree.challenge_seeded(1).is_none());
+    }
+
+    #[test]
+    fn random_challenge_is_uniform_over_occupied_leaves() {
+        let elements = [10, 20, 30, 40, 50];
+        let tree = MerkleTree::build(&elements);
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut counts = [0_u64; 5];
+
+        for _ in 0..10_000 {
+            let (index, proof) = tree.random_challenge(&mut rng).unwrap();
+            assert!(proof.verify(elements[index]));
+            counts[index] += 1;
+        }
+
+        // 4 degrees of freedom, p < 0.001.
+        assert!(chi_squared(&counts) < 18.47);
+    }
+
+    #[test]
+    fn seeded_challenge_is_reproducible() {
+        let elements = [1, 2, 3, 4, 5, 6];
+        let tree = MerkleTree::build(&elements);
+        let (index, proof) = tree.challenge_seeded(42).unwrap();
+        let (other_index, other_proof) = tree.challenge_seeded(42).unwrap();
+
+        assert_eq!(index, other_index);
+        assert!(proof == other_proof);
+        assert!(proof.verify(elements[index]));
+    }
+
+    #[test]
+    fn seeded_challenge_is_uniform_over_occupied_leaves() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        let mut counts = [0_u64; 5];
+
+        for seed in 0..10_000 {
+            let (index, _) = tree.challenge_seeded(seed).unwrap();
+            counts[index] += 1;
+        }
+
+        assert!(chi_squared(&counts) < 18.47);
+    }
+}
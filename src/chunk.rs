@@ -0,0 +1,614 @@
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Read};
+use std::ops::ControlFlow;
+use std::path::Path;
+
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::{MemStore, MerkleProof, MerkleTree, Progress, Reporter, hash_single};
+
+/// Returns the error reported for a zero chunk size.
+fn zero_chunk_size() -> io::Error {
+    io::Error::new(ErrorKind::InvalidInput, "chunk size must not be zero")
+}
+
+/// Fills the buffer out of the reader, stopping early only at the end of the input.
+/// Returns the amount of bytes read.
+pub(crate) fn fill<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(filled)
+}
+
+/// Fills the buffer out of the async reader, stopping early only at the end of the input.
+/// Returns the amount of bytes read.
+#[cfg(feature = "tokio")]
+async fn fill_async<R: AsyncRead + Unpin>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]).await {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(filled)
+}
+
+/// Reads the bytes of a reader in chunks of `chunk_size` bytes, the last of which may be
+/// shorter, returning the hash of every chunk and the amount of bytes read. Every chunk
+/// hashed is reported as a leaf, and a cancelled build fails with an error of kind
+/// `ErrorKind::Other` wrapping `MerkleError::Cancelled`.
+fn read_chunks<R: Read>(
+    mut reader: R,
+    chunk_size: usize,
+    reporter: &mut Reporter,
+) -> io::Result<(Vec<u64>, u64)> {
+    if chunk_size == 0 {
+        return Err(zero_chunk_size());
+    }
+
+    let mut buffer = vec![0; chunk_size];
+    let mut leaves = Vec::new();
+    let mut size = 0;
+    loop {
+        let read = fill(&mut reader, &mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        leaves.push(hash_single(&buffer[..read]));
+        reporter.leaf_hashed().map_err(io::Error::other)?;
+        size += read as u64;
+        if read < chunk_size {
+            break;
+        }
+    }
+    Ok((leaves, size))
+}
+
+/// Layout of a chunked file: its size and the size of its chunks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileMeta {
+    /// Total size of the file, in bytes.
+    pub size: u64,
+    /// Length of every chunk but the last, which may be shorter.
+    pub chunk_size: usize,
+}
+
+impl FileMeta {
+    /// Returns the amount of chunks of the file. Empty files have none, and neither do
+    /// layouts with a zero `chunk_size`, which no file is ever chunked with.
+    pub fn chunk_count(&self) -> u64 {
+        if self.chunk_size == 0 {
+            return 0;
+        }
+        self.size.div_ceil(self.chunk_size as u64)
+    }
+
+    /// Returns the length of a chunk, or `None` if the file has no such chunk, which is
+    /// always the case for layouts with a zero `chunk_size`.
+    /// * `index` - Index of the chunk.
+    pub fn chunk_len(&self, index: u64) -> Option<usize> {
+        if index >= self.chunk_count() {
+            return None;
+        }
+        let start = index * self.chunk_size as u64;
+        Some((self.size - start).min(self.chunk_size as u64) as usize)
+    }
+}
+
+/// Builds trees over the chunks of files and verifies chunks against them, so downloads
+/// can be verified piece by piece.
+pub struct FileMerkle;
+
+impl FileMerkle {
+    /// Builds a tree whose leaves are the chunks of the reader's bytes, as
+    /// `MerkleTree::build_from_read` does, together with the layout of the chunks.
+    /// An empty input produces an empty tree, which has no root.
+    /// Fails if `chunk_size` is zero or reading fails.
+    /// * `reader` - Where the bytes are read from.
+    /// * `chunk_size` - Length of every chunk but the last.
+    pub fn build<R: Read>(reader: R, chunk_size: usize) -> io::Result<(MerkleTree, FileMeta)> {
+        FileMerkle::build_reported(reader, chunk_size, &mut Reporter::silent())
+    }
+
+    /// Builds the tree over the chunks of the reader's bytes like `build`, reporting its
+    /// progress as `MerkleTree::build_with_progress` does: every chunk hashed counts as a
+    /// leaf.
+    /// Fails if `chunk_size` is zero or reading fails, and with an error of kind
+    /// `ErrorKind::Other` wrapping `MerkleError::Cancelled` if the callback cancels the
+    /// build.
+    /// * `reader` - Where the bytes are read from.
+    /// * `chunk_size` - Length of every chunk but the last.
+    /// * `progress` - Callback receiving the progress.
+    pub fn build_with_progress<R: Read>(
+        reader: R,
+        chunk_size: usize,
+        mut progress: impl FnMut(Progress) -> ControlFlow<()> + Send,
+    ) -> io::Result<(MerkleTree, FileMeta)> {
+        FileMerkle::build_reported(reader, chunk_size, &mut Reporter::new(&mut progress))
+    }
+
+    /// Builds the tree over the chunks of the reader's bytes, see `build_with_progress`.
+    /// * `reader` - Where the bytes are read from.
+    /// * `chunk_size` - Length of every chunk but the last.
+    /// * `reporter` - Where the progress is reported.
+    fn build_reported<R: Read>(
+        reader: R,
+        chunk_size: usize,
+        reporter: &mut Reporter,
+    ) -> io::Result<(MerkleTree, FileMeta)> {
+        let (leaves, size) = read_chunks(reader, chunk_size, reporter)?;
+        let meta = FileMeta { size, chunk_size };
+        let tree = MerkleTree::from_reported_leaf_hashes(MemStore::default(), leaves, reporter)
+            .map_err(io::Error::other)?;
+        Ok((tree, meta))
+    }
+
+    /// Builds the tree over the chunks of a file, see `build`.
+    /// * `path` - Path of the file.
+    /// * `chunk_size` - Length of every chunk but the last.
+    pub fn build_path<P: AsRef<Path>>(
+        path: P,
+        chunk_size: usize,
+    ) -> io::Result<(MerkleTree, FileMeta)> {
+        FileMerkle::build(BufReader::new(File::open(path)?), chunk_size)
+    }
+
+    /// Builds the tree over the chunks of a file, reporting its progress, see
+    /// `build_with_progress`.
+    /// * `path` - Path of the file.
+    /// * `chunk_size` - Length of every chunk but the last.
+    /// * `progress` - Callback receiving the progress.
+    pub fn build_path_with_progress<P: AsRef<Path>>(
+        path: P,
+        chunk_size: usize,
+        progress: impl FnMut(Progress) -> ControlFlow<()> + Send,
+    ) -> io::Result<(MerkleTree, FileMeta)> {
+        FileMerkle::build_with_progress(BufReader::new(File::open(path)?), chunk_size, progress)
+    }
+
+    /// Returns whether a chunk belongs to the file with the given root and layout.
+    /// The chunk must have the length the layout gives it, and the proof must be the
+    /// chunk's, generated for a tree of as many leaves as chunks, with the given root.
+    /// * `chunk_index` - Index of the chunk.
+    /// * `chunk` - The bytes of the chunk.
+    /// * `proof` - The proof of the chunk.
+    /// * `root` - The trusted root of the file's tree.
+    /// * `meta` - The trusted layout of the file.
+    pub fn verify_chunk(
+        chunk_index: u64,
+        chunk: &[u8],
+        proof: &MerkleProof,
+        root: u64,
+        meta: &FileMeta,
+    ) -> bool {
+        let MerkleProof::Proof {
+            index,
+            root: proven_root,
+            len,
+            ..
+        } = proof
+        else {
+            return false;
+        };
+        meta.chunk_len(chunk_index) == Some(chunk.len())
+            && *index as u64 == chunk_index
+            && *len as u64 == meta.chunk_count()
+            && *proven_root == root
+            && proof.verify(chunk)
+    }
+}
+
+impl MerkleTree {
+    /// Builds a tree out of the bytes of a reader, split into chunks of `chunk_size`
+    /// bytes. Each chunk becomes a leaf, hashed as a `&[u8]` value, and the last chunk
+    /// may be shorter. An empty input produces an empty tree.
+    /// Fails if `chunk_size` is zero or reading fails.
+    /// * `reader` - Where the bytes are read from.
+    /// * `chunk_size` - Length of every chunk but the last.
+    pub fn build_from_read<R: Read>(reader: R, chunk_size: usize) -> io::Result<MerkleTree> {
+        let (tree, _) = FileMerkle::build(reader, chunk_size)?;
+        Ok(tree)
+    }
+
+    /// Builds a tree out of the bytes of a reader like `build_from_read`, reporting its
+    /// progress as `build_with_progress` does: every chunk hashed counts as a leaf.
+    /// Fails if `chunk_size` is zero or reading fails, and with an error of kind
+    /// `ErrorKind::Other` wrapping `MerkleError::Cancelled` if the callback cancels the
+    /// build.
+    /// * `reader` - Where the bytes are read from.
+    /// * `chunk_size` - Length of every chunk but the last.
+    /// * `progress` - Callback receiving the progress.
+    pub fn build_from_read_with_progress<R: Read>(
+        reader: R,
+        chunk_size: usize,
+        progress: impl FnMut(Progress) -> ControlFlow<()> + Send,
+    ) -> io::Result<MerkleTree> {
+        let (tree, _) = FileMerkle::build_with_progress(reader, chunk_size, progress)?;
+        Ok(tree)
+    }
+
+    /// Asynchronous version of `build_from_read`, yielding to the runtime between chunks.
+    /// The resulting tree is identical to the one built out of the same bytes by
+    /// `build_from_read`.
+    /// * `reader` - Where the bytes are read from.
+    /// * `chunk_size` - Length of every chunk but the last.
+    #[cfg(feature = "tokio")]
+    pub async fn build_from_async_read<R: AsyncRead + Unpin>(
+        reader: R,
+        chunk_size: usize,
+    ) -> io::Result<MerkleTree> {
+        MerkleTree::build_from_async_read_reported(reader, chunk_size, &mut Reporter::silent())
+            .await
+    }
+
+    /// Asynchronous version of `build_from_read_with_progress`, yielding to the runtime
+    /// between chunks like `build_from_async_read`.
+    /// Fails if `chunk_size` is zero or reading fails, and with an error of kind
+    /// `ErrorKind::Other` wrapping `MerkleError::Cancelled` if the callback cancels the
+    /// build.
+    /// * `reader` - Where the bytes are read from.
+    /// * `chunk_size` - Length of every chunk but the last.
+    /// * `progress` - Callback receiving the progress.
+    #[cfg(feature = "tokio")]
+    pub async fn build_from_async_read_with_progress<R: AsyncRead + Unpin>(
+        reader: R,
+        chunk_size: usize,
+        mut progress: impl FnMut(Progress) -> ControlFlow<()> + Send,
+    ) -> io::Result<MerkleTree> {
+        let mut reporter = Reporter::new(&mut progress);
+        MerkleTree::build_from_async_read_reported(reader, chunk_size, &mut reporter).await
+    }
+
+    /// Builds a tree out of the bytes of an async reader, see
+    /// `build_from_async_read_with_progress`.
+    /// * `reader` - Where the bytes are read from.
+    /// * `chunk_size` - Length of every chunk but the last.
+    /// * `reporter` - Where the progress is reported.
+    #[cfg(feature = "tokio")]
+    async fn build_from_async_read_reported<R: AsyncRead + Unpin>(
+        mut reader: R,
+        chunk_size: usize,
+        reporter: &mut Reporter<'_>,
+    ) -> io::Result<MerkleTree> {
+        if chunk_size == 0 {
+            return Err(zero_chunk_size());
+        }
+
+        let mut buffer = vec![0; chunk_size];
+        let mut leaves = Vec::new();
+        loop {
+            let read = fill_async(&mut reader, &mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            leaves.push(hash_single(&buffer[..read]));
+            reporter.leaf_hashed().map_err(io::Error::other)?;
+            if read < chunk_size {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        MerkleTree::from_reported_leaf_hashes(MemStore::default(), leaves, reporter)
+            .map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleError;
+
+    /// Returns `len` bytes of deterministic test data.
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|byte| (byte * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn chunks_become_leaves() {
+        let bytes = data(10);
+        let tree = MerkleTree::build_from_read(&bytes[..], 4).unwrap();
+        let expected = MerkleTree::build(&[&bytes[..4], &bytes[4..8], &bytes[8..]]);
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.root(), expected.root());
+        assert!(tree.get_proof(2).verify(&bytes[8..]));
+    }
+
+    #[test]
+    fn exact_multiple_and_empty_input() {
+        let bytes = data(8);
+        let tree = MerkleTree::build_from_read(&bytes[..], 4).unwrap();
+        assert_eq!(tree.len(), 2);
+        assert!(tree.get_proof(1).verify(&bytes[4..]));
+
+        assert!(MerkleTree::build_from_read(&[][..], 4).unwrap().is_empty());
+    }
+
+    #[test]
+    fn chunk_builds_report_progress() {
+        let bytes = data(40_000);
+        let mut last = None;
+        let (tree, _) = FileMerkle::build_with_progress(&bytes[..], 1, |progress| {
+            assert!(last.is_none_or(|last: Progress| last.leaves_hashed <= progress.leaves_hashed));
+            last = Some(progress);
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        assert_eq!(
+            tree.root(),
+            FileMerkle::build(&bytes[..], 1).unwrap().0.root()
+        );
+        let last = last.unwrap();
+        assert_eq!(last.leaves_hashed, 40_000);
+        assert_eq!(last.level, tree.height() - 1);
+
+        // Cancelling while the chunks are read stops reading them.
+        let mut reports = 0;
+        let error = MerkleTree::build_from_read_with_progress(&bytes[..], 1, |_| {
+            reports += 1;
+            ControlFlow::Break(())
+        })
+        .unwrap_err();
+        assert_eq!(reports, 1);
+        assert_eq!(error.kind(), ErrorKind::Other);
+        let cancelled = error.get_ref().unwrap().downcast_ref::<MerkleError>();
+        assert_eq!(cancelled, Some(&MerkleError::Cancelled));
+    }
+
+    #[test]
+    fn zero_chunk_size_rejected() {
+        let error = MerkleTree::build_from_read(&[1, 2, 3][..], 0)
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn file_chunks_round_trip() {
+        let bytes = data(10_000);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("download");
+        std::fs::write(&path, &bytes).unwrap();
+
+        let (tree, meta) = FileMerkle::build_path(&path, 1024).unwrap();
+        assert_eq!(meta.size, 10_000);
+        assert_eq!(meta.chunk_count(), 10);
+        let root = tree.root().unwrap();
+        for (index, chunk) in bytes.chunks(1024).enumerate() {
+            let proof = tree.get_proof(index);
+            assert!(FileMerkle::verify_chunk(
+                index as u64,
+                chunk,
+                &proof,
+                root,
+                &meta
+            ));
+        }
+    }
+
+    #[test]
+    fn file_builds_report_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("download");
+        std::fs::write(&path, data(10_000)).unwrap();
+
+        let mut last = None;
+        let (tree, meta) = FileMerkle::build_path_with_progress(&path, 1024, |progress| {
+            last = Some(progress);
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        let (expected, expected_meta) = FileMerkle::build_path(&path, 1024).unwrap();
+        assert_eq!(tree.root(), expected.root());
+        assert_eq!(meta, expected_meta);
+        assert_eq!(last.map(|last| last.leaves_hashed), Some(10));
+
+        let cancelled =
+            FileMerkle::build_path_with_progress(&path, 1024, |_| ControlFlow::Break(()));
+        assert_eq!(
+            cancelled.err().map(|error| error.kind()),
+            Some(ErrorKind::Other)
+        );
+    }
+
+    #[test]
+    fn corrupted_chunk_detected() {
+        let bytes = data(4096);
+        let (tree, meta) = FileMerkle::build(&bytes[..], 1024).unwrap();
+        let root = tree.root().unwrap();
+
+        let mut chunk = bytes[1024..2048].to_vec();
+        chunk[512] ^= 1;
+        assert!(!FileMerkle::verify_chunk(
+            1,
+            &chunk,
+            &tree.get_proof(1),
+            root,
+            &meta
+        ));
+        // A valid chunk at the wrong position.
+        assert!(!FileMerkle::verify_chunk(
+            2,
+            &bytes[1024..2048],
+            &tree.get_proof(1),
+            root,
+            &meta
+        ));
+    }
+
+    #[test]
+    fn short_final_chunk() {
+        let bytes = data(2500);
+        let (tree, meta) = FileMerkle::build(&bytes[..], 1000).unwrap();
+        let root = tree.root().unwrap();
+        assert_eq!(meta.chunk_len(2), Some(500));
+        assert_eq!(meta.chunk_len(3), None);
+
+        let proof = tree.get_proof(2);
+        assert!(FileMerkle::verify_chunk(
+            2,
+            &bytes[2000..],
+            &proof,
+            root,
+            &meta
+        ));
+        // Padding the last chunk to the full size changes its length.
+        let padded = [&bytes[2000..], &[0; 500][..]].concat();
+        assert!(!FileMerkle::verify_chunk(2, &padded, &proof, root, &meta));
+    }
+
+    #[test]
+    fn empty_file_and_zero_chunk_size() {
+        let (tree, meta) = FileMerkle::build(&[][..], 1024).unwrap();
+        assert_eq!(tree.root(), None);
+        assert_eq!(meta.chunk_count(), 0);
+        assert!(!FileMerkle::verify_chunk(
+            0,
+            &[],
+            &tree.get_proof(0),
+            0,
+            &meta
+        ));
+
+        // Layouts are public, so a zero chunk size can be forged: it has no chunks.
+        let forged = FileMeta {
+            size: 10,
+            chunk_size: 0,
+        };
+        assert_eq!(forged.chunk_count(), 0);
+        assert_eq!(forged.chunk_len(0), None);
+
+        let error = FileMerkle::build(&[1][..], 0).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[cfg(feature = "tokio")]
+    mod tokio_tests {
+        use std::pin::Pin;
+        use std::task::{Context, Poll};
+
+        use tokio::io::ReadBuf;
+
+        use super::*;
+
+        /// Reader handing out its data in reads of the given lengths, cycling through
+        /// them, and returning `Pending` before every other read.
+        struct SplitReader {
+            data: Vec<u8>,
+            position: usize,
+            splits: Vec<usize>,
+            reads: usize,
+            pending: bool,
+        }
+
+        impl SplitReader {
+            fn new(data: Vec<u8>, splits: Vec<usize>) -> SplitReader {
+                SplitReader {
+                    data,
+                    position: 0,
+                    splits,
+                    reads: 0,
+                    pending: false,
+                }
+            }
+        }
+
+        impl AsyncRead for SplitReader {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                context: &mut Context<'_>,
+                buffer: &mut ReadBuf<'_>,
+            ) -> Poll<io::Result<()>> {
+                self.pending = !self.pending;
+                if self.pending {
+                    context.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+
+                let split = self.splits[self.reads % self.splits.len()];
+                let len = split
+                    .min(buffer.remaining())
+                    .min(self.data.len() - self.position);
+                let start = self.position;
+                buffer.put_slice(&self.data[start..start + len]);
+                self.position += len;
+                self.reads += 1;
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        #[tokio::test]
+        async fn matches_synchronous_build() {
+            let bytes = data(1000);
+            for chunk_size in [1, 7, 64, 1000, 4096] {
+                let expected = MerkleTree::build_from_read(&bytes[..], chunk_size).unwrap();
+                // Single bytes, and reads straddling chunk boundaries.
+                for splits in [vec![1], vec![3, 11], vec![chunk_size + 1], vec![5000]] {
+                    let reader = SplitReader::new(bytes.clone(), splits);
+                    let tree = MerkleTree::build_from_async_read(reader, chunk_size)
+                        .await
+                        .unwrap();
+                    assert_eq!(tree.len(), expected.len());
+                    assert_eq!(tree.root(), expected.root());
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn async_builds_report_progress() {
+            let bytes = data(40_000);
+            let expected = MerkleTree::build_from_read(&bytes[..], 1).unwrap();
+            // Builds holding a callback can still be spawned.
+            let (tree, last) = tokio::spawn(async move {
+                let mut last = None;
+                let reader = SplitReader::new(bytes, vec![3, 11]);
+                let tree = MerkleTree::build_from_async_read_with_progress(reader, 1, |progress| {
+                    last = Some(progress);
+                    ControlFlow::Continue(())
+                })
+                .await;
+                (tree, last)
+            })
+            .await
+            .unwrap();
+            let tree = tree.unwrap();
+            assert_eq!(tree.root(), expected.root());
+            let last = last.unwrap();
+            assert_eq!(last.leaves_hashed, 40_000);
+            assert_eq!(last.level, tree.height() - 1);
+
+            let mut reports = 0;
+            let reader = SplitReader::new(data(40_000), vec![3, 11]);
+            let error = MerkleTree::build_from_async_read_with_progress(reader, 1, |_| {
+                reports += 1;
+                ControlFlow::Break(())
+            })
+            .await
+            .unwrap_err();
+            assert_eq!(reports, 1);
+            let cancelled = error.get_ref().unwrap().downcast_ref::<MerkleError>();
+            assert_eq!(cancelled, Some(&MerkleError::Cancelled));
+        }
+
+        #[tokio::test]
+        async fn empty_input_and_zero_chunk_size() {
+            let reader = SplitReader::new(Vec::new(), vec![1]);
+            let tree = MerkleTree::build_from_async_read(reader, 4).await.unwrap();
+            assert!(tree.is_empty());
+
+            let reader = SplitReader::new(data(4), vec![1]);
+            let error = MerkleTree::build_from_async_read(reader, 0).await.err();
+            assert_eq!(error.unwrap().kind(), ErrorKind::InvalidInput);
+        }
+    }
+}
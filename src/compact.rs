@@ -0,0 +1,523 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use crate::wire::{Format, HEADER_LEN, WireError};
+use crate::{DecodeOptions, LimitError, MerkleProof, is_consistent_path};
+
+/// Version of the compact proof encoding written by `encode_into`.
+pub const COMPACT_VERSION: u8 = 1;
+
+/// Compact proofs define no flags yet.
+const COMPACT_FORMAT: Format = Format {
+    magic: *b"MRKP",
+    version: COMPACT_VERSION,
+    known_flags: 0,
+};
+
+/// Maximum length of a varint encoded `u64`.
+const MAX_VARINT_LEN: usize = 10;
+
+/// Tag preceding an invalid proof.
+const INVALID_TAG: u8 = 0;
+
+/// Tag preceding a valid proof.
+const PROOF_TAG: u8 = 1;
+
+/// Error returned when encoding a proof fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncodeError {
+    /// The buffer cannot hold the encoded proof, which needs the given amount of bytes.
+    BufferTooSmall { needed: usize },
+}
+
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::BufferTooSmall { needed } => {
+                write!(f, "buffer too small, {needed} bytes needed")
+            }
+        }
+    }
+}
+
+impl Error for EncodeError {}
+
+/// Error returned when decoding a proof fails.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The header was rejected. Truncated headers are reported as `Truncated`.
+    Header(WireError),
+    /// The data ended before the proof did.
+    Truncated,
+    /// A varint does not fit in the integer it encodes.
+    Overflow,
+    /// The proof's tag is neither the invalid nor the valid proof's.
+    InvalidTag(u8),
+    /// The input exceeds a limit of the `DecodeOptions`.
+    Limit(LimitError),
+    /// The proof's index is not below its length.
+    IndexOutOfRange { index: usize, len: usize },
+    /// Bytes remain after the proof.
+    TrailingBytes(usize),
+    /// The proof's nodes do not form a path in a tree of its length (see
+    /// `MerkleProof::verify`).
+    InconsistentPath { len: usize, nodes: usize },
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Header(error) => write!(f, "invalid header: {error}"),
+            DecodeError::Truncated => f.write_str("truncated proof"),
+            DecodeError::Overflow => f.write_str("varint overflow"),
+            DecodeError::InvalidTag(tag) => write!(f, "invalid proof tag {tag}"),
+            DecodeError::Limit(error) => write!(f, "limit exceeded: {error}"),
+            DecodeError::IndexOutOfRange { index, len } => {
+                write!(f, "index {index} out of range for length {len}")
+            }
+            DecodeError::TrailingBytes(count) => write!(f, "{count} trailing bytes"),
+            DecodeError::InconsistentPath { len, nodes } => {
+                write!(
+                    f,
+                    "a path of {nodes} nodes does not fit a tree of length {len}"
+                )
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DecodeError::Header(error) => Some(error),
+            DecodeError::Limit(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<LimitError> for DecodeError {
+    fn from(error: LimitError) -> DecodeError {
+        DecodeError::Limit(error)
+    }
+}
+
+impl From<WireError> for DecodeError {
+    fn from(error: WireError) -> DecodeError {
+        match error {
+            WireError::Io(_) => DecodeError::Truncated,
+            error => DecodeError::Header(error),
+        }
+    }
+}
+
+/// Returns the length of the varint encoding of a value.
+fn varint_len(mut value: u64) -> usize {
+    let mut len = 1;
+    while value >= 0x80 {
+        value >>= 7;
+        len += 1;
+    }
+    len
+}
+
+/// Writes the varint encoding of a value at the start of the buffer, returning the
+/// amount of bytes written. The buffer must be large enough.
+fn write_varint(buffer: &mut [u8], mut value: u64) -> usize {
+    let mut position = 0;
+    while value >= 0x80 {
+        buffer[position] = (value as u8) | 0x80;
+        value >>= 7;
+        position += 1;
+    }
+    buffer[position] = value as u8;
+    position + 1
+}
+
+/// Reads values out of an encoded proof, failing instead of reading past its end.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    /// Reads a single byte.
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let (&byte, rest) = self.bytes.split_first().ok_or(DecodeError::Truncated)?;
+        self.bytes = rest;
+        Ok(byte)
+    }
+
+    /// Reads a little-endian `u64`.
+    fn fixed(&mut self) -> Result<u64, DecodeError> {
+        let (bytes, rest) = self
+            .bytes
+            .split_first_chunk::<8>()
+            .ok_or(DecodeError::Truncated)?;
+        self.bytes = rest;
+        Ok(u64::from_le_bytes(*bytes))
+    }
+
+    /// Reads a LEB128 varint encoded `u64`.
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0_u64;
+        for position in 0..MAX_VARINT_LEN {
+            let byte = self.byte()?;
+            let bits = u64::from(byte & 0x7f);
+            let shift = 7 * position as u32;
+            if shift == 63 && bits > 1 {
+                return Err(DecodeError::Overflow);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::Overflow)
+    }
+
+    /// Reads a LEB128 varint encoded `usize`.
+    fn usize(&mut self) -> Result<usize, DecodeError> {
+        usize::try_from(self.varint()?).map_err(|_| DecodeError::Overflow)
+    }
+}
+
+impl MerkleProof {
+    /// Returns the length of the compact encoding of the proof.
+    pub fn compact_len(&self) -> usize {
+        match self {
+            MerkleProof::Invalid => HEADER_LEN + 1,
+            MerkleProof::Proof {
+                index,
+                nodes,
+                len,
+                epoch,
+                ..
+            } => {
+                HEADER_LEN
+                    + 1
+                    + varint_len(*index as u64)
+                    + varint_len(*len as u64)
+                    + varint_len(*epoch)
+                    + varint_len(nodes.len() as u64)
+                    + 8 * (nodes.len() + 1)
+            }
+        }
+    }
+
+    /// Writes the compact encoding of the proof at the start of the buffer, returning
+    /// the amount of bytes written. Nothing is allocated.
+    /// The encoding is a wire header followed by a tag byte, `0` for `Invalid` and `1`
+    /// for valid proofs. Valid proofs then hold the index, the length, the epoch and the
+    /// amount of nodes as LEB128 varints, followed by every node and the root as
+    /// little-endian `u64` values.
+    /// * `buffer` - Where the proof is written.
+    pub fn encode_into(&self, buffer: &mut [u8]) -> Result<usize, EncodeError> {
+        let needed = self.compact_len();
+        if buffer.len() < needed {
+            return Err(EncodeError::BufferTooSmall { needed });
+        }
+
+        let mut header = &mut buffer[..HEADER_LEN];
+        COMPACT_FORMAT
+            .write_header(&mut header, 0)
+            .expect("The buffer holds the header");
+        let mut position = HEADER_LEN;
+
+        match self {
+            MerkleProof::Invalid => buffer[position] = INVALID_TAG,
+            MerkleProof::Proof {
+                index,
+                nodes,
+                root,
+                len,
+                epoch,
+            } => {
+                buffer[position] = PROOF_TAG;
+                position += 1;
+                for value in [*index as u64, *len as u64, *epoch, nodes.len() as u64] {
+                    position += write_varint(&mut buffer[position..], value);
+                }
+                for node in nodes.iter().chain([root]) {
+                    buffer[position..position + 8].copy_from_slice(&node.to_le_bytes());
+                    position += 8;
+                }
+            }
+        }
+
+        Ok(needed)
+    }
+
+    /// Decodes a proof out of its compact encoding, written by `encode_into`, within the
+    /// default `DecodeOptions`.
+    /// Fails without panicking on any malformed input, including truncated data,
+    /// proofs of more than 64 nodes and trailing bytes.
+    /// * `bytes` - The encoded proof.
+    pub fn decode_compact(bytes: &[u8]) -> Result<MerkleProof, DecodeError> {
+        MerkleProof::decode_compact_with(bytes, &DecodeOptions::DEFAULT)
+    }
+
+    /// Decodes a proof out of its compact encoding, written by `encode_into`. The input
+    /// length and the declared node count are checked against the limits before any
+    /// node is read.
+    /// * `bytes` - The encoded proof.
+    /// * `options` - The limits enforced.
+    pub fn decode_compact_with(
+        bytes: &[u8],
+        options: &DecodeOptions,
+    ) -> Result<MerkleProof, DecodeError> {
+        options.check_len(bytes.len())?;
+        let mut reader = Reader { bytes };
+        COMPACT_FORMAT.read_header(&mut reader.bytes)?;
+
+        let proof = match reader.byte()? {
+            INVALID_TAG => MerkleProof::Invalid,
+            PROOF_TAG => {
+                let index = reader.usize()?;
+                let len = reader.usize()?;
+                let epoch = reader.varint()?;
+                let node_count = reader.varint()?;
+                options.check_node_count(node_count)?;
+                if index >= len {
+                    return Err(DecodeError::IndexOutOfRange { index, len });
+                }
+                let nodes = (0..node_count)
+                    .map(|_| reader.fixed())
+                    .collect::<Result<Vec<u64>, DecodeError>>()?;
+                if !is_consistent_path(index, len, &nodes) {
+                    return Err(DecodeError::InconsistentPath {
+                        len,
+                        nodes: nodes.len(),
+                    });
+                }
+                MerkleProof::Proof {
+                    index,
+                    nodes,
+                    root: reader.fixed()?,
+                    len,
+                    epoch,
+                }
+            }
+            tag => return Err(DecodeError::InvalidTag(tag)),
+        };
+
+        if !reader.bytes.is_empty() {
+            return Err(DecodeError::TrailingBytes(reader.bytes.len()));
+        }
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    /// Returns the compact encoding of the proof.
+    fn encode(proof: &MerkleProof) -> Vec<u8> {
+        let mut buffer = vec![0; proof.compact_len()];
+        assert_eq!(proof.encode_into(&mut buffer), Ok(buffer.len()));
+        buffer
+    }
+
+    #[test]
+    fn round_trip() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        for index in 0..tree.len() {
+            let proof = tree.get_proof(index);
+            let decoded = MerkleProof::decode_compact(&encode(&proof)).unwrap();
+            assert!(decoded == proof);
+            assert!(decoded.verify(index as i32 + 1));
+        }
+
+        let bytes = encode(&MerkleProof::Invalid);
+        assert_eq!(bytes.len(), HEADER_LEN + 1);
+        assert!(MerkleProof::decode_compact(&bytes).unwrap() == MerkleProof::Invalid);
+    }
+
+    #[test]
+    fn encoded_sizes() {
+        // Depth 20: index and length take 3 varint bytes, epoch and node count 1 each.
+        let tree = MerkleTree::from_leaf_hashes(vec![7; 1 << 20]);
+        let proof = tree.get_proof((1 << 20) - 1);
+        assert_eq!(
+            encode(&proof).len(),
+            HEADER_LEN + 1 + 3 + 3 + 1 + 1 + 21 * 8
+        );
+
+        // Depth 2.
+        let proof = MerkleTree::build(&[1, 2, 3, 4]).get_proof(0);
+        assert_eq!(encode(&proof).len(), HEADER_LEN + 1 + 4 + 3 * 8);
+    }
+
+    #[test]
+    fn small_buffer_rejected() {
+        let proof = MerkleTree::build(&[1, 2, 3, 4]).get_proof(0);
+        let mut buffer = [0; 16];
+        assert_eq!(
+            proof.encode_into(&mut buffer),
+            Err(EncodeError::BufferTooSmall {
+                needed: proof.compact_len()
+            })
+        );
+    }
+
+    #[test]
+    fn truncated_input_rejected() {
+        let bytes = encode(&MerkleTree::build(&[1, 2, 3]).get_proof(2));
+        for len in 0..bytes.len() {
+            assert!(matches!(
+                MerkleProof::decode_compact(&bytes[..len]),
+                Err(DecodeError::Truncated)
+            ));
+        }
+
+        let mut bytes = bytes;
+        bytes.push(0);
+        assert!(matches!(
+            MerkleProof::decode_compact(&bytes),
+            Err(DecodeError::TrailingBytes(1))
+        ));
+    }
+
+    #[test]
+    fn malformed_input_rejected() {
+        let header = &encode(&MerkleProof::Invalid)[..HEADER_LEN];
+        let decode = |body: &[u8]| MerkleProof::decode_compact(&[header, body].concat());
+
+        assert!(matches!(decode(&[2]), Err(DecodeError::InvalidTag(2))));
+        assert!(matches!(
+            decode(&[
+                1, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f
+            ]),
+            Err(DecodeError::Overflow)
+        ));
+        assert!(matches!(
+            decode(&[1, 0, 1, 0, 65]),
+            Err(DecodeError::Limit(LimitError::Depth { found: 65, max: 64 }))
+        ));
+        assert!(matches!(
+            decode(&[1, 1, 1, 0, 0]),
+            Err(DecodeError::IndexOutOfRange { index: 1, len: 1 })
+        ));
+    }
+
+    #[test]
+    fn declared_node_count_checked_before_reading() {
+        let header = &encode(&MerkleProof::Invalid)[..HEADER_LEN];
+        // Node count of u32::MAX, followed by 10 bytes.
+        let body = [&[1, 0, 1, 0, 0xff, 0xff, 0xff, 0xff, 0x0f][..], &[0; 10]].concat();
+        assert!(matches!(
+            MerkleProof::decode_compact(&[header, &body].concat()),
+            Err(DecodeError::Limit(LimitError::Depth {
+                found: 0xffff_ffff,
+                max: 64
+            }))
+        ));
+    }
+
+    #[test]
+    fn inconsistent_paths_rejected() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        let MerkleProof::Proof {
+            mut nodes, root, ..
+        } = tree.get_proof(4)
+        else {
+            panic!("The leaf is occupied");
+        };
+        nodes.pop();
+        let truncated = MerkleProof::Proof {
+            index: 4,
+            nodes,
+            root,
+            len: 5,
+            epoch: 0,
+        };
+        assert!(matches!(
+            MerkleProof::decode_compact(&encode(&truncated)),
+            Err(DecodeError::InconsistentPath { len: 5, nodes: 2 })
+        ));
+    }
+
+    #[test]
+    fn limits_enforced() {
+        let deep = MerkleProof::Proof {
+            index: 5,
+            nodes: vec![1; 1000],
+            root: 2,
+            len: 6,
+            epoch: 0,
+        };
+        let bytes = encode(&deep);
+        assert!(matches!(
+            MerkleProof::decode_compact(&bytes),
+            Err(DecodeError::Limit(LimitError::TotalBytes { .. }))
+        ));
+
+        let options = DecodeOptions {
+            max_total_bytes: usize::MAX,
+            ..DecodeOptions::DEFAULT
+        };
+        assert!(matches!(
+            MerkleProof::decode_compact_with(&bytes, &options),
+            Err(DecodeError::Limit(LimitError::Depth {
+                found: 1000,
+                max: 64
+            }))
+        ));
+
+        let options = DecodeOptions {
+            max_depth: 1000,
+            max_nodes: 1001,
+            max_total_bytes: bytes.len(),
+        };
+        // Within the limits, the path is still too long for the proof's length.
+        assert!(matches!(
+            MerkleProof::decode_compact_with(&bytes, &options),
+            Err(DecodeError::InconsistentPath {
+                len: 6,
+                nodes: 1000
+            })
+        ));
+
+        let options = DecodeOptions {
+            max_nodes: 1000,
+            ..options
+        };
+        assert!(matches!(
+            MerkleProof::decode_compact_with(&bytes, &options),
+            Err(DecodeError::Limit(LimitError::Nodes {
+                found: 1001,
+                max: 1000
+            }))
+        ));
+    }
+
+    #[test]
+    fn random_input_never_panics() {
+        let mut state = 0x9e3779b97f4a7c15_u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let valid = encode(&MerkleTree::build(&[1, 2, 3, 4, 5]).get_proof(3));
+        for _ in 0..10_000 {
+            // Random bytes behind a valid header, and valid encodings with a byte changed.
+            let len = HEADER_LEN + (next() % 64) as usize;
+            let mut bytes: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            bytes[..HEADER_LEN].copy_from_slice(&valid[..HEADER_LEN]);
+            if let Ok(proof) = MerkleProof::decode_compact(&bytes) {
+                proof.verify(4);
+            }
+
+            let mut bytes = valid.clone();
+            let position = (next() % bytes.len() as u64) as usize;
+            bytes[position] = next() as u8;
+            if let Ok(proof) = MerkleProof::decode_compact(&bytes) {
+                proof.verify(4);
+            }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::hash::Hash;
+
+use crate::diff::same_node;
+use crate::{MerkleTree, hash_pair, hash_single};
+
+/// Items of external data which do not match a tree, as returned by
+/// `MerkleTree::find_corrupted`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CorruptionReport {
+    /// Indices of the items whose hashes differ from the tree's leaves, in index order.
+    pub corrupted: Vec<usize>,
+    /// Amount of trailing leaves with no matching item.
+    pub missing: usize,
+    /// Amount of trailing items beyond the tree's length.
+    pub extra: usize,
+}
+
+impl CorruptionReport {
+    /// Returns whether the data matches the tree.
+    pub fn is_clean(&self) -> bool {
+        self.corrupted.is_empty() && self.missing == 0 && self.extra == 0
+    }
+}
+
+impl MerkleTree {
+    /// Re-hashes external data the tree was built from and returns the items which no
+    /// longer match its leaves. Every item is compared with its leaf, except the ones
+    /// pruned from the tree (see `from_frontier`), which cannot be checked.
+    /// * `data` - The items the leaves are expected to be hashes of, in index order.
+    pub fn find_corrupted<H: Hash>(&self, data: &[H]) -> CorruptionReport {
+        self.find_corrupted_by_subtree(data, 0)
+    }
+
+    /// Same as `find_corrupted`, but compares the data one subtree at a time: the root of
+    /// each run of `2^level` items is recomputed and compared with the stored node, and
+    /// the items are only compared one by one if the roots differ. Clean regions then
+    /// take a comparison per subtree rather than one per item.
+    /// Levels above the root are clamped to it.
+    /// * `data` - The items the leaves are expected to be hashes of, in index order.
+    /// * `level` - Level of the subtrees whose roots are compared first.
+    pub fn find_corrupted_by_subtree<H: Hash>(&self, data: &[H], level: usize) -> CorruptionReport {
+        let shared = self.len().min(data.len());
+        let level = level.min(self.height() - 1);
+        let mut corrupted = Vec::new();
+        let mut hashes = Vec::with_capacity(1 << level);
+        let mut start = self.pruned;
+        while start < shared {
+            let end = (((start >> level) + 1) << level).min(shared);
+            hashes.clear();
+            hashes.extend(data[start..end].iter().map(hash_single));
+
+            // Only whole subtrees have a stored root to compare with.
+            let whole = hashes.len() == 1 << level;
+            let clean = level > 0
+                && whole
+                && same_node(subtree_root(&hashes), self.levels[(level, start >> level)]);
+            if !clean {
+                for (offset, &hash) in hashes.iter().enumerate() {
+                    if !same_node(hash, self.levels[(0, start + offset)]) {
+                        corrupted.push(start + offset);
+                    }
+                }
+            }
+            start = end;
+        }
+
+        CorruptionReport {
+            corrupted,
+            missing: self.len() - shared,
+            extra: data.len() - shared,
+        }
+    }
+}
+
+/// Returns the root of a perfect subtree out of its leaves.
+/// * `leaves` - The leaves, whose amount is a power of two.
+fn subtree_root(leaves: &[u64]) -> u64 {
+    let mut nodes = leaves.to_vec();
+    while nodes.len() > 1 {
+        nodes = nodes
+            .chunks(2)
+            .map(|pair| hash_pair(pair[0], pair[1]))
+            .collect();
+    }
+    nodes[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrupted_items_reported() {
+        let mut data: Vec<u32> = (0..10_000).collect();
+        let tree = MerkleTree::build(&data);
+        assert!(tree.find_corrupted(&data).is_clean());
+
+        for index in [0, 5_123, 9_999] {
+            data[index] += 1_000_000;
+        }
+        let expected = [0, 5_123, 9_999];
+        assert_eq!(tree.find_corrupted(&data).corrupted, expected);
+        for level in [1, 4, 7, 13, 40] {
+            let report = tree.find_corrupted_by_subtree(&data, level);
+            assert_eq!(report.corrupted, expected, "level {level}");
+            assert_eq!((report.missing, report.extra), (0, 0));
+        }
+    }
+
+    #[test]
+    fn length_differences_reported() {
+        let data: Vec<u32> = (0..100).collect();
+        let tree = MerkleTree::build(&data);
+
+        let report = tree.find_corrupted_by_subtree(&data[..90], 3);
+        assert!(report.corrupted.is_empty());
+        assert_eq!((report.missing, report.extra), (10, 0));
+
+        let mut longer: Vec<u32> = (0..130).collect();
+        longer[99] = 0;
+        let report = tree.find_corrupted_by_subtree(&longer, 3);
+        assert_eq!(report.corrupted, [99]);
+        assert_eq!((report.missing, report.extra), (0, 30));
+        assert!(!report.is_clean());
+
+        let report = MerkleTree::build::<u32>(&[]).find_corrupted(&data);
+        assert_eq!((report.corrupted.len(), report.extra), (0, 100));
+    }
+
+    #[test]
+    fn pruned_leaves_skipped() {
+        let mut data: Vec<u32> = (0..130).collect();
+        let tree = MerkleTree::build(&data[..100]);
+        let mut pruned = MerkleTree::from_frontier(tree.export_frontier()).unwrap();
+        for value in &data[100..] {
+            pruned.push(value);
+        }
+        data[3] = 0;
+        data[120] = 0;
+        assert_eq!(pruned.find_corrupted(&data).corrupted, [120]);
+        assert_eq!(pruned.find_corrupted_by_subtree(&data, 3).corrupted, [120]);
+    }
+
+    #[cfg(feature = "instrumentation")]
+    #[test]
+    fn clean_subtrees_take_a_comparison() {
+        let mut data: Vec<u32> = (0..4096).collect();
+        let tree = MerkleTree::build(&data);
+        data[100] = 0;
+
+        crate::reset_counters();
+        tree.find_corrupted(&data);
+        assert_eq!(crate::node_comparisons(), 4096);
+
+        crate::reset_counters();
+        assert_eq!(tree.find_corrupted_by_subtree(&data, 6).corrupted, [100]);
+        assert_eq!(crate::node_comparisons(), 64 + 64);
+    }
+}
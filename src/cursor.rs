@@ -0,0 +1,173 @@
+use crate::{MerkleError, MerkleTree};
+
+/// Position on a node of a `MerkleTree`, from which the tree can be walked one step at
+/// a time: down to either child, up to the parent, or straight to a leaf. Moves return
+/// a new cursor, leaving this one in place, and fail rather than leave the tree.
+/// A cursor only holds the tree and the node's coordinates, so it is `Copy`.
+/// Coordinates follow `MerkleTree::node`: level 0 holds the leaves, padding included.
+#[derive(Clone, Copy, Debug)]
+pub struct Cursor<'a> {
+    tree: &'a MerkleTree,
+    level: usize,
+    index: usize,
+}
+
+impl MerkleTree {
+    /// Returns a cursor on the root of the tree.
+    pub fn cursor(&self) -> Cursor<'_> {
+        Cursor {
+            tree: self,
+            level: self.height() - 1,
+            index: 0,
+        }
+    }
+}
+
+impl<'a> Cursor<'a> {
+    /// Returns the `(level, index)` coordinates of the node.
+    pub fn coord(&self) -> (usize, usize) {
+        (self.level, self.index)
+    }
+
+    /// Returns the hash of the node.
+    pub fn hash(&self) -> u64 {
+        self.tree
+            .node(self.level, self.index)
+            .expect("Cursors stay within the tree")
+    }
+
+    /// Returns whether the node is a leaf.
+    pub fn is_leaf(&self) -> bool {
+        self.level == 0
+    }
+
+    /// Returns whether the node is the root.
+    pub fn is_root(&self) -> bool {
+        self.level == self.tree.height() - 1
+    }
+
+    /// Returns a cursor on the node's left child, or `None` if it is a leaf.
+    pub fn left(&self) -> Option<Cursor<'a>> {
+        self.child(2 * self.index)
+    }
+
+    /// Returns a cursor on the node's right child, or `None` if it is a leaf.
+    pub fn right(&self) -> Option<Cursor<'a>> {
+        self.child(2 * self.index + 1)
+    }
+
+    /// Returns a cursor on the node's parent, or `None` if it is the root.
+    pub fn parent(&self) -> Option<Cursor<'a>> {
+        (!self.is_root()).then(|| Cursor {
+            level: self.level + 1,
+            index: self.index / 2,
+            ..*self
+        })
+    }
+
+    /// Returns a cursor on a leaf, padding leaves included, wherever this one is.
+    /// Fails if the index is beyond the tree's capacity.
+    /// * `index` - Index of the leaf.
+    pub fn to_leaf(&self, index: usize) -> Result<Cursor<'a>, MerkleError> {
+        let capacity = self.tree.capacity();
+        if index >= capacity {
+            return Err(MerkleError::IndexOutOfRange {
+                index,
+                len: capacity,
+            });
+        }
+        Ok(Cursor {
+            level: 0,
+            index,
+            ..*self
+        })
+    }
+
+    /// Returns a cursor on one of the node's children, or `None` if it is a leaf.
+    /// * `index` - Index of the child within its level.
+    fn child(&self, index: usize) -> Option<Cursor<'a>> {
+        (!self.is_leaf()).then(|| Cursor {
+            level: self.level - 1,
+            index,
+            ..*self
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walks_read_the_visited_nodes() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        let root = tree.cursor();
+        assert!(root.is_root());
+        assert_eq!(root.coord(), (3, 0));
+        assert_eq!(Some(root.hash()), tree.root());
+
+        let mut visited = vec![root];
+        let mut cursor = root;
+        for step in [Cursor::right, Cursor::left, Cursor::right] {
+            cursor = step(&cursor).unwrap();
+            visited.push(cursor);
+        }
+        assert!(cursor.is_leaf());
+        assert_eq!(cursor.coord(), (0, 5));
+        let coords: Vec<_> = visited.iter().map(Cursor::coord).collect();
+        assert_eq!(coords, [(3, 0), (2, 1), (1, 2), (0, 5)]);
+        for cursor in &visited {
+            let (level, index) = cursor.coord();
+            assert_eq!(Some(cursor.hash()), tree.node(level, index));
+        }
+
+        // Going back up retraces the path.
+        let mut path = vec![cursor.coord()];
+        while let Some(parent) = cursor.parent() {
+            cursor = parent;
+            path.push(cursor.coord());
+        }
+        path.reverse();
+        assert_eq!(path, coords);
+    }
+
+    #[test]
+    fn every_leaf_reachable_from_the_root() {
+        let tree = MerkleTree::build(&(0..13).collect::<Vec<u32>>());
+        for index in 0..tree.capacity() {
+            let mut cursor = tree.cursor();
+            for level in (0..tree.height() - 1).rev() {
+                cursor = if index >> level & 1 == 0 {
+                    cursor.left().unwrap()
+                } else {
+                    cursor.right().unwrap()
+                };
+                assert_eq!(Some(cursor.hash()), tree.node(level, index >> level));
+            }
+            assert_eq!(
+                cursor.coord(),
+                tree.cursor().to_leaf(index).unwrap().coord()
+            );
+        }
+    }
+
+    #[test]
+    fn edges_are_not_crossed() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let root = tree.cursor();
+        assert!(root.parent().is_none());
+        let leaf = root.to_leaf(3).unwrap();
+        assert!(leaf.left().is_none());
+        assert!(leaf.right().is_none());
+        assert_eq!(leaf.hash(), MerkleTree::PAD_HASH);
+        assert_eq!(
+            leaf.to_leaf(4).err(),
+            Some(MerkleError::IndexOutOfRange { index: 4, len: 4 })
+        );
+
+        let single = MerkleTree::build(&[1]);
+        let cursor = single.cursor();
+        assert!(cursor.is_root() && cursor.is_leaf());
+        assert!(cursor.left().is_none() && cursor.parent().is_none());
+    }
+}
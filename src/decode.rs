@@ -0,0 +1,140 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// Limits enforced by every proof decoder, so hostile input is rejected before anything
+/// large is allocated or read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeOptions {
+    /// Maximum length of a proof's sibling path, which is the height of the tree it was
+    /// generated for.
+    pub max_depth: usize,
+    /// Maximum amount of hashes held by a proof, its root included.
+    pub max_nodes: usize,
+    /// Maximum length of the encoded input, in bytes.
+    pub max_total_bytes: usize,
+}
+
+impl DecodeOptions {
+    /// Limits used by decoders which take no options: paths of up to 64 nodes, one per
+    /// level of the largest tree whose leaves can be indexed by a `u64`, and 4 KiB of
+    /// input, enough for such a proof in every encoding of the crate.
+    pub const DEFAULT: DecodeOptions = DecodeOptions {
+        max_depth: 64,
+        max_nodes: 65,
+        max_total_bytes: 4096,
+    };
+}
+
+/// Checks shared by the decoders, which are all feature gated.
+#[cfg(any(
+    feature = "borsh",
+    feature = "cbor",
+    feature = "compact",
+    feature = "proto",
+    feature = "serde",
+    test
+))]
+impl DecodeOptions {
+    /// Checks the length of an encoded input.
+    /// * `len` - Length of the input, in bytes.
+    #[cfg(any(
+        feature = "borsh",
+        feature = "cbor",
+        feature = "compact",
+        feature = "proto",
+        test
+    ))]
+    pub(crate) fn check_len(&self, len: usize) -> Result<(), LimitError> {
+        if len > self.max_total_bytes {
+            return Err(LimitError::TotalBytes {
+                found: len as u64,
+                max: self.max_total_bytes,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks the amount of sibling nodes declared by a proof, before they are read.
+    /// * `count` - Declared amount of nodes, the root excluded.
+    pub(crate) fn check_node_count(&self, count: u64) -> Result<(), LimitError> {
+        if count > self.max_depth as u64 {
+            return Err(LimitError::Depth {
+                found: count,
+                max: self.max_depth,
+            });
+        }
+        if count.saturating_add(1) > self.max_nodes as u64 {
+            return Err(LimitError::Nodes {
+                found: count.saturating_add(1),
+                max: self.max_nodes,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for DecodeOptions {
+    fn default() -> DecodeOptions {
+        DecodeOptions::DEFAULT
+    }
+}
+
+/// Limit of a `DecodeOptions` exceeded by an input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimitError {
+    /// The proof's path is longer than `max_depth`.
+    Depth { found: u64, max: usize },
+    /// The proof holds more hashes than `max_nodes`.
+    Nodes { found: u64, max: usize },
+    /// The input is longer than `max_total_bytes`.
+    TotalBytes { found: u64, max: usize },
+}
+
+impl Display for LimitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::Depth { found, max } => {
+                write!(f, "proof depth {found} exceeds {max}")
+            }
+            LimitError::Nodes { found, max } => {
+                write!(f, "proof of {found} nodes exceeds {max}")
+            }
+            LimitError::TotalBytes { found, max } => {
+                write!(f, "input of {found} bytes exceeds {max}")
+            }
+        }
+    }
+}
+
+impl Error for LimitError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_enforced() {
+        let options = DecodeOptions {
+            max_depth: 4,
+            max_nodes: 3,
+            max_total_bytes: 10,
+        };
+        assert_eq!(options.check_len(10), Ok(()));
+        assert_eq!(
+            options.check_len(11),
+            Err(LimitError::TotalBytes { found: 11, max: 10 })
+        );
+        assert_eq!(options.check_node_count(2), Ok(()));
+        assert_eq!(
+            options.check_node_count(3),
+            Err(LimitError::Nodes { found: 4, max: 3 })
+        );
+        assert_eq!(
+            DecodeOptions::default().check_node_count(u64::MAX),
+            Err(LimitError::Depth {
+                found: u64::MAX,
+                max: 64
+            })
+        );
+    }
+}
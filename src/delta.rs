@@ -0,0 +1,486 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, ErrorKind, Read, Write};
+
+use crate::wire::{Format, WireError, read_u64, read_usize};
+use crate::{MerkleSnapshot, MerkleTree, TreeOp, empty_node, hash_pair};
+
+/// Deltas define no flags yet.
+const DELTA_FORMAT: Format = Format {
+    magic: *b"MRDT",
+    version: 1,
+    known_flags: 0,
+};
+
+/// Error returned when applying a delta fails. The tree is left untouched.
+#[derive(Debug)]
+pub enum DeltaError {
+    /// Reading failed, including reaching the end of the input before the delta did.
+    Io(io::Error),
+    /// The delta's header was rejected.
+    Header(WireError),
+    /// The delta was written against another state than the tree's: its length and top
+    /// node (`PAD_HASH` for empty trees) do not match the tree's.
+    BaseMismatch {
+        expected_len: usize,
+        expected_root: u64,
+        len: usize,
+        root: u64,
+    },
+    /// The delta's target length and capacity do not describe a tree grown out of the
+    /// tree's.
+    Shape { len: usize, capacity: usize },
+    /// A node of the delta lies outside of its target tree.
+    OutOfRange { level: usize, index: usize },
+    /// A node of the delta differs from the one recomputed out of its leaves.
+    NodeMismatch { level: usize, index: usize },
+    /// The root recomputed out of the delta differs from its declared target root.
+    RootMismatch { expected: u64, computed: u64 },
+}
+
+impl Display for DeltaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DeltaError::Io(error) => write!(f, "failed to read delta: {error}"),
+            DeltaError::Header(error) => write!(f, "invalid delta header: {error}"),
+            DeltaError::BaseMismatch {
+                expected_len,
+                expected_root,
+                len,
+                root,
+            } => write!(
+                f,
+                "delta applies to a tree of length {expected_len} and root {expected_root:#x}, \
+                 not of length {len} and root {root:#x}"
+            ),
+            DeltaError::Shape { len, capacity } => {
+                write!(f, "invalid target length {len} for capacity {capacity}")
+            }
+            DeltaError::OutOfRange { level, index } => {
+                write!(f, "node ({level}, {index}) is outside of the target tree")
+            }
+            DeltaError::NodeMismatch { level, index } => {
+                write!(f, "node ({level}, {index}) does not match its children")
+            }
+            DeltaError::RootMismatch { expected, computed } => write!(
+                f,
+                "computed root {computed:#x} does not match target root {expected:#x}"
+            ),
+        }
+    }
+}
+
+impl Error for DeltaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DeltaError::Io(error) => Some(error),
+            DeltaError::Header(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DeltaError {
+    fn from(error: io::Error) -> DeltaError {
+        DeltaError::Io(error)
+    }
+}
+
+/// Header read failures are reported as `Io`, like the rest of the delta's.
+impl From<WireError> for DeltaError {
+    fn from(error: WireError) -> DeltaError {
+        match error {
+            WireError::Io(error) => DeltaError::Io(error),
+            error => DeltaError::Header(error),
+        }
+    }
+}
+
+/// Changes between a snapshot and the tree it was frozen from, found by descending the
+/// subtrees whose roots differ.
+struct Changes {
+    /// Changed leaves, in index order.
+    leaves: Vec<(usize, u64)>,
+    /// Changed nodes above the leaves, as `(level, index, node)`.
+    nodes: Vec<(usize, usize, u64)>,
+}
+
+impl MerkleSnapshot {
+    /// Returns the node at the given coordinates as the tree frozen in the snapshot
+    /// would hold it had it grown that high: the nodes beyond the frozen ones are empty,
+    /// except for the ones above the frozen root, which pair it with them.
+    /// * `level` - Level of the node.
+    /// * `index` - Index of the node within its level.
+    fn grown_node(&self, level: usize, index: usize) -> u64 {
+        if index == 0 && level >= self.height() && level > 0 {
+            return hash_pair(self.grown_node(level - 1, 0), empty_node(level - 1));
+        }
+        self.node(level, index).unwrap_or_else(|| empty_node(level))
+    }
+}
+
+impl MerkleTree {
+    /// Writes the changes made to the tree since a snapshot was frozen from it, returning
+    /// the amount of bytes written: the leaves which changed, and their ancestors. Only
+    /// the subtrees whose roots changed are visited, so `d` changed leaves take
+    /// O(d log n) time and space. `apply_delta` patches a tree holding the snapshot's
+    /// state, such as one restored by `read_snapshot`, into this one.
+    /// The delta starts with a wire header, followed by the snapshot's length and top
+    /// node, the tree's length, capacity, epoch and top node, the changed leaves as runs
+    /// of consecutive ones (each its first index, its length and its leaves), and the
+    /// changed nodes above them (each its level, its index and the node), every value
+    /// as a little-endian `u64`.
+    /// Fails with `InvalidInput` if the snapshot is longer than the tree, or either one
+    /// is pruned (see `from_frontier`).
+    /// * `since` - The snapshot the changes are relative to.
+    /// * `writer` - Where the delta is written.
+    pub fn write_delta<W: Write>(&self, since: &MerkleSnapshot, mut writer: W) -> io::Result<u64> {
+        if since.len() > self.len() || since.pruned() > 0 || self.pruned > 0 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "deltas need an unpruned snapshot no longer than the tree",
+            ));
+        }
+        let changes = self.changes_since(since);
+
+        let mut written = DELTA_FORMAT.write_header(&mut writer, 0)? as u64;
+        let mut write = |value: u64| {
+            written += 8;
+            writer.write_all(&value.to_le_bytes())
+        };
+        write(since.len() as u64)?;
+        write(since.root().unwrap_or(MerkleTree::PAD_HASH))?;
+        write(self.len() as u64)?;
+        write(self.capacity as u64)?;
+        write(self.epoch)?;
+        write(self.top_node())?;
+
+        let runs: Vec<_> = changes
+            .leaves
+            .chunk_by(|(first, _), (second, _)| first + 1 == *second)
+            .collect();
+        write(runs.len() as u64)?;
+        for run in runs {
+            write(run[0].0 as u64)?;
+            write(run.len() as u64)?;
+            for &(_, leaf) in run {
+                write(leaf)?;
+            }
+        }
+        write(changes.nodes.len() as u64)?;
+        for &(level, index, node) in &changes.nodes {
+            write(level as u64)?;
+            write(index as u64)?;
+            write(node)?;
+        }
+
+        writer.flush()?;
+        Ok(written)
+    }
+
+    /// Applies a delta written by `write_delta`, turning a tree holding the state of the
+    /// delta's snapshot into the tree the delta was written from, epoch included.
+    /// The whole delta is read and checked before the tree is changed: the tree must
+    /// hold the snapshot's length and root, every ancestor of the changed leaves is
+    /// recomputed and must match the delta's nodes, and the resulting root must match
+    /// its target root. If any check fails, the tree is left untouched.
+    /// Values are read one at a time, so the reader should be buffered.
+    /// * `reader` - Where the delta is read from.
+    pub fn apply_delta<R: Read>(&mut self, mut reader: R) -> Result<(), DeltaError> {
+        DELTA_FORMAT.read_header(&mut reader)?;
+        let expected_len = read_usize(&mut reader)?;
+        let expected_root = read_u64(&mut reader)?;
+        if expected_len != self.len() || expected_root != self.top_node() {
+            return Err(DeltaError::BaseMismatch {
+                expected_len,
+                expected_root,
+                len: self.len(),
+                root: self.top_node(),
+            });
+        }
+        let len = read_usize(&mut reader)?;
+        let capacity = read_usize(&mut reader)?;
+        let epoch = read_u64(&mut reader)?;
+        let root = read_u64(&mut reader)?;
+        // Trees only grow as their leaves outnumber their capacity, so a larger capacity
+        // than the tree's must be the least one holding the target length.
+        if !capacity.is_power_of_two()
+            || capacity < self.capacity
+            || (capacity > self.capacity && capacity > len.next_power_of_two())
+            || len > capacity
+            || len < self.len()
+        {
+            return Err(DeltaError::Shape { len, capacity });
+        }
+        let height = capacity.ilog2() as usize + 1;
+
+        // Changes are collected as values are read, so forged counts cannot trigger huge
+        // allocations before the input runs out.
+        let mut changed = BTreeMap::new();
+        for _ in 0..read_u64(&mut reader)? {
+            let first = read_usize(&mut reader)?;
+            let run = read_usize(&mut reader)?;
+            for index in first..first.saturating_add(run) {
+                if index >= len {
+                    return Err(DeltaError::OutOfRange { level: 0, index });
+                }
+                changed.insert((0, index), read_u64(&mut reader)?);
+            }
+        }
+        let mut declared = Vec::new();
+        for _ in 0..read_u64(&mut reader)? {
+            let level = read_usize(&mut reader)?;
+            let index = read_usize(&mut reader)?;
+            if level == 0 || level >= height || index >= capacity >> level {
+                return Err(DeltaError::OutOfRange { level, index });
+            }
+            declared.push((level, index, read_u64(&mut reader)?));
+        }
+
+        // Recomputes the ancestors of the changed leaves out of the tree, as if it had
+        // grown to the target capacity.
+        let base = self.freeze();
+        let node = |changed: &BTreeMap<(usize, usize), u64>, level, index| {
+            changed
+                .get(&(level, index))
+                .copied()
+                .unwrap_or_else(|| base.grown_node(level, index))
+        };
+        for level_n in 1..height {
+            let parents: BTreeSet<usize> = changed
+                .range((level_n - 1, 0)..(level_n, 0))
+                .map(|(&(_, index), _)| index / 2)
+                .collect();
+            for parent in parents {
+                let hash = hash_pair(
+                    node(&changed, level_n - 1, 2 * parent),
+                    node(&changed, level_n - 1, 2 * parent + 1),
+                );
+                changed.insert((level_n, parent), hash);
+            }
+        }
+        for &(level, index, declared) in &declared {
+            if node(&changed, level, index) != declared {
+                return Err(DeltaError::NodeMismatch { level, index });
+            }
+        }
+        let computed = node(&changed, height - 1, 0);
+        if computed != root {
+            return Err(DeltaError::RootMismatch {
+                expected: root,
+                computed,
+            });
+        }
+        // Writing to the tree while the snapshot shares its storage would copy it.
+        drop(base);
+
+        let old_root = self.watched_root();
+        while self.capacity < capacity {
+            self.duplicate_capacity();
+        }
+        self.levels.forget_materialized();
+        // The occupied leaves are always stored, so that `leaves` can borrow them, even
+        // the new ones holding `PAD_HASH`, which the delta does not carry.
+        if let Some(last) = len.checked_sub(1) {
+            let leaf = self.levels[(0, last)];
+            self.levels.set(0, last, leaf);
+        }
+        let mut leaves = Vec::new();
+        for ((level, index), node) in changed {
+            self.levels.set(level, index, node);
+            if level == 0 {
+                leaves.push((index, node));
+            }
+        }
+        for index in self.len()..len {
+            self.occupancy.set(index);
+        }
+        self.padding = capacity - len;
+        self.epoch = epoch;
+        if self.leaf_index.is_some() {
+            self.enable_index();
+        }
+        self.invalidate_proof_cache();
+        self.notify_root_change(old_root);
+        self.record_op(TreeOp::Rewrite {
+            len,
+            capacity,
+            epoch,
+            leaves,
+        });
+        Ok(())
+    }
+
+    /// Collects the nodes which differ from the ones of a snapshot frozen from the tree,
+    /// descending from the root.
+    /// * `since` - The snapshot the changes are relative to.
+    fn changes_since(&self, since: &MerkleSnapshot) -> Changes {
+        let mut changes = Changes {
+            leaves: Vec::new(),
+            nodes: Vec::new(),
+        };
+        let mut pending = vec![(self.height() - 1, 0)];
+        while let Some((level, index)) = pending.pop() {
+            let node = self.levels[(level, index)];
+            if node == since.grown_node(level, index) {
+                continue;
+            }
+            if level == 0 {
+                changes.leaves.push((index, node));
+                continue;
+            }
+            changes.nodes.push((level, index, node));
+            // The right child first, so that leaves are found in index order.
+            pending.push((level - 1, 2 * index + 1));
+            pending.push((level - 1, 2 * index));
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::wire::HEADER_LEN;
+    use crate::{LevelCheck, hash_single};
+
+    /// Returns the delta of the given tree since a snapshot.
+    fn delta(tree: &MerkleTree, since: &MerkleSnapshot) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let written = tree.write_delta(since, &mut bytes).unwrap();
+        assert_eq!(written, bytes.len() as u64);
+        bytes
+    }
+
+    /// Checks that both trees hold the same state.
+    fn assert_same(tree: &MerkleTree, expected: &MerkleTree) {
+        assert!(tree == expected);
+        assert_eq!(tree.capacity(), expected.capacity());
+        assert_eq!(tree.head(), expected.head());
+        assert_eq!(tree.validate(), Ok(()));
+    }
+
+    #[test]
+    fn deltas_patch_reloaded_snapshots() {
+        let elements: Vec<u32> = (0..100_000).collect();
+        let mut tree = MerkleTree::build(&elements);
+        let mut snapshot = Vec::new();
+        tree.write_snapshot(&mut snapshot).unwrap();
+        let base = tree.freeze();
+
+        for index in (0..10).map(|n| n * 9_973) {
+            tree.replace_leaf_hash(index, hash_single(index + 1_000_000));
+        }
+        let bytes = delta(&tree, &base);
+        assert!(bytes.len() * 100 < snapshot.len(), "{} bytes", bytes.len());
+
+        let mut restored =
+            MerkleTree::read_snapshot(Cursor::new(&snapshot), LevelCheck::ShapeOnly).unwrap();
+        restored.apply_delta(Cursor::new(&bytes)).unwrap();
+        assert_same(&restored, &tree);
+        assert!(restored.get_proof(9_973).verify(1_009_973_usize));
+    }
+
+    #[test]
+    fn deltas_carry_growth() {
+        for (base_len, len) in [(0, 0), (0, 1), (1, 2), (5, 9), (8, 8), (3, 100)] {
+            let elements: Vec<u32> = (0..len).collect();
+            let mut tree = MerkleTree::build(&elements[..base_len as usize]);
+            let mut restored = MerkleTree::build(&elements[..base_len as usize]);
+            let base = tree.freeze();
+            tree.extend(&elements[base_len as usize..]);
+
+            restored
+                .apply_delta(Cursor::new(delta(&tree, &base)))
+                .unwrap();
+            assert_same(&restored, &tree);
+        }
+
+        // Pushing padding values only changes the length and, when growing, the root.
+        let mut tree = MerkleTree::build(&[1, 2]);
+        let mut restored = MerkleTree::build(&[1, 2]);
+        let base = tree.freeze();
+        tree.push_hash(MerkleTree::PAD_HASH);
+        restored
+            .apply_delta(Cursor::new(delta(&tree, &base)))
+            .unwrap();
+        assert_same(&restored, &tree);
+    }
+
+    #[test]
+    fn wrong_base_detected() {
+        let mut tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        let base = tree.freeze();
+        tree.push(6);
+        let bytes = delta(&tree, &base);
+
+        for elements in [[1, 2, 3, 4, 0], [1, 2, 3, 4, 5]] {
+            let mut other = MerkleTree::build(&elements);
+            other.push(7);
+            let root = other.root();
+            assert!(matches!(
+                other.apply_delta(Cursor::new(&bytes)),
+                Err(DeltaError::BaseMismatch {
+                    expected_len: 5,
+                    ..
+                })
+            ));
+            assert_eq!(other.root(), root);
+        }
+        assert!(matches!(
+            MerkleTree::build(&[1, 2, 3, 4, 0]).apply_delta(Cursor::new(&bytes)),
+            Err(DeltaError::BaseMismatch { len: 5, .. })
+        ));
+    }
+
+    #[test]
+    fn forged_capacity_rejected() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        let base = tree.freeze();
+        tree.push(4);
+        let mut bytes = delta(&tree, &base);
+        // The target capacity follows the header, the base length and root, and the
+        // target length.
+        let offset = HEADER_LEN + 24;
+        for capacity in [8, 1 << 44] {
+            bytes[offset..offset + 8].copy_from_slice(&u64::to_le_bytes(capacity));
+            let mut patched = MerkleTree::build(&[1, 2, 3]);
+            assert!(matches!(
+                patched.apply_delta(Cursor::new(&bytes)),
+                Err(DeltaError::Shape { len: 4, capacity: found }) if found as u64 == capacity
+            ));
+            assert_eq!(patched.capacity(), 4);
+        }
+    }
+
+    #[test]
+    fn tampered_deltas_leave_the_tree_untouched() {
+        let mut tree = MerkleTree::build(&(0..50).collect::<Vec<u32>>());
+        let base = tree.freeze();
+        tree.replace_leaf_hash(7, hash_single(700));
+        tree.extend(&[50, 51]);
+        let bytes = delta(&tree, &base);
+
+        let original = MerkleTree::build(&(0..50).collect::<Vec<u32>>());
+        for position in 0..bytes.len() {
+            let mut bytes = bytes.clone();
+            bytes[position] ^= 0x04;
+            let mut patched = MerkleTree::build(&(0..50).collect::<Vec<u32>>());
+            if patched.apply_delta(Cursor::new(&bytes)).is_err() {
+                assert_same(&patched, &original);
+                assert_eq!(patched.epoch(), 0);
+            } else {
+                // Only the target epoch is not covered by the checks.
+                assert!(patched == tree, "flipped byte {position}");
+            }
+        }
+        for len in 0..bytes.len() {
+            let mut patched = MerkleTree::build(&(0..50).collect::<Vec<u32>>());
+            assert!(patched.apply_delta(Cursor::new(&bytes[..len])).is_err());
+            assert_same(&patched, &original);
+        }
+    }
+}
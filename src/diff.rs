@@ -0,0 +1,175 @@
+use std::ops::Range;
+
+use crate::MerkleTree;
+
+/// Differences between two trees, as returned by `MerkleTree::diff`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffReport {
+    /// Indices of the leaves held by both trees with different hashes, in index order.
+    pub differing: Vec<usize>,
+    /// Indices of the leaves only held by the tree `diff` was called on.
+    pub only_in_self: Range<usize>,
+    /// Indices of the leaves only held by the other tree.
+    pub only_in_other: Range<usize>,
+}
+
+impl DiffReport {
+    /// Returns whether both trees hold the same leaves.
+    pub fn is_empty(&self) -> bool {
+        self.differing.is_empty() && self.only_in_self.is_empty() && self.only_in_other.is_empty()
+    }
+}
+
+/// Returns whether two nodes hold the same hash, counting the comparison.
+pub(crate) fn same_node(first: u64, second: u64) -> bool {
+    #[cfg(feature = "instrumentation")]
+    crate::instrument::count_comparison();
+    first == second
+}
+
+impl MerkleTree {
+    /// Returns the leaves which differ from the ones of another tree. Only subtrees
+    /// whose hashes differ are descended into, so `d` differing leaves take O(d log n)
+    /// node comparisons rather than one per leaf.
+    /// Trees of different capacities are compared from the largest level they share:
+    /// a node covers the same leaf slots in both trees whatever their heights. Leaves
+    /// held by a single tree are reported as ranges and not compared, and neither are
+    /// the leaves pruned from either tree (see `from_frontier`).
+    /// * `other` - The tree to be compared with.
+    pub fn diff(&self, other: &MerkleTree) -> DiffReport {
+        let shared = self.len().min(other.len());
+        let first = self.pruned.max(other.pruned);
+        let mut differing = Vec::new();
+        if first < shared {
+            let top = self.height().min(other.height()) - 1;
+            self.diff_subtree(other, top, 0, first..shared, &mut differing);
+        }
+
+        DiffReport {
+            differing,
+            only_in_self: shared..self.len(),
+            only_in_other: shared..other.len(),
+        }
+    }
+
+    /// Collects the differing leaves of a subtree, within a range of leaf indices.
+    /// * `level` - Level of the subtree's root.
+    /// * `index` - Index of the subtree's root within its level.
+    /// * `range` - Leaf indices to be compared.
+    /// * `differing` - Where the differing indices are collected.
+    fn diff_subtree(
+        &self,
+        other: &MerkleTree,
+        level: usize,
+        index: usize,
+        range: Range<usize>,
+        differing: &mut Vec<usize>,
+    ) {
+        let first_leaf = index << level;
+        let end_leaf = (index + 1) << level;
+        if end_leaf <= range.start || first_leaf >= range.end {
+            return;
+        }
+        if same_node(self.levels[(level, index)], other.levels[(level, index)]) {
+            return;
+        }
+        if level == 0 {
+            differing.push(index);
+            return;
+        }
+        self.diff_subtree(other, level - 1, 2 * index, range.clone(), differing);
+        self.diff_subtree(other, level - 1, 2 * index + 1, range, differing);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a tree over `0..len` with the given indices overwritten.
+    fn tree_with(len: u32, changed: &[u32]) -> MerkleTree {
+        let values: Vec<u32> = (0..len)
+            .map(|value| {
+                if changed.contains(&value) {
+                    value + 1_000_000
+                } else {
+                    value
+                }
+            })
+            .collect();
+        MerkleTree::build(&values)
+    }
+
+    #[test]
+    fn identical_trees_have_empty_report() {
+        let report = tree_with(1000, &[]).diff(&tree_with(1000, &[]));
+        assert!(report.is_empty());
+        assert_eq!(report.only_in_self, 1000..1000);
+        assert!(
+            MerkleTree::build::<u8>(&[])
+                .diff(&MerkleTree::build::<u8>(&[]))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn differing_leaves_reported() {
+        let base = tree_with(1000, &[]);
+        assert_eq!(base.diff(&tree_with(1000, &[637])).differing, [637]);
+
+        let run: Vec<u32> = (400..420).collect();
+        let report = tree_with(1000, &run).diff(&base);
+        assert_eq!(report.differing, (400..420).collect::<Vec<usize>>());
+        assert!(report.only_in_self.is_empty() && report.only_in_other.is_empty());
+    }
+
+    #[test]
+    fn length_differences_reported() {
+        let short = tree_with(100, &[]);
+        let long = tree_with(150, &[]);
+        let report = short.diff(&long);
+        assert!(report.differing.is_empty());
+        assert_eq!(report.only_in_self, 100..100);
+        assert_eq!(report.only_in_other, 100..150);
+        assert_eq!(long.diff(&short).only_in_self, 100..150);
+
+        let report = tree_with(150, &[3]).diff(&short);
+        assert_eq!(report.differing, [3]);
+        assert_eq!(report.only_in_self, 100..150);
+        assert!(
+            short
+                .diff(&MerkleTree::build::<u8>(&[]))
+                .differing
+                .is_empty()
+        );
+    }
+
+    #[cfg(feature = "instrumentation")]
+    #[test]
+    fn comparisons_are_logarithmic() {
+        use crate::{node_comparisons, reset_counters};
+
+        // 1024 leaves: 11 levels.
+        let base = tree_with(1024, &[]);
+        let changed = tree_with(1024, &[637]);
+        reset_counters();
+        base.diff(&changed);
+        // The root, then both children of every differing node above the leaves.
+        assert_eq!(node_comparisons(), 1 + 2 * 10);
+
+        reset_counters();
+        base.diff(&tree_with(1024, &[]));
+        assert_eq!(node_comparisons(), 1);
+
+        let run: Vec<u32> = (400..416).collect();
+        reset_counters();
+        base.diff(&tree_with(1024, &run));
+        // The 16 leaves share a subtree of height 4: its 31 nodes plus 2 per level above.
+        assert_eq!(node_comparisons(), 1 + 2 * 6 + 30);
+
+        // Only the nodes along the boundary of the shared leaves differ.
+        reset_counters();
+        tree_with(100, &[]).diff(&tree_with(150, &[]));
+        assert!(node_comparisons() <= 1 + 2 * 7);
+    }
+}
@@ -0,0 +1,488 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::hash::Hash;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::wire::{Format, HEADER_LEN, WireError};
+use crate::{
+    MerkleProof, MerkleTree, ancestor_index, empty_nodes, hash_pair, hash_single, sibling_index,
+};
+
+/// Maximum amount of nodes kept in memory by a `DiskMerkleTree` before its cache is
+/// written back and emptied.
+pub const DISK_CACHE_ENTRIES: usize = 4096;
+
+/// Disk trees define no flags yet.
+const DISK_FORMAT: Format = Format {
+    magic: *b"MRKD",
+    version: 1,
+    known_flags: 0,
+};
+
+/// Name of the file holding the length, capacity and epoch of a disk tree.
+const META_FILE: &str = "meta";
+
+/// Returns the path of the file holding a level of a disk tree.
+fn level_path(dir: &Path, level: usize) -> PathBuf {
+    dir.join(format!("level-{level}"))
+}
+
+/// Returns an `InvalidData` error with the given message.
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, error)
+}
+
+/// Opens a level file for reading and writing, creating it if needed.
+pub(crate) fn open_level(dir: &Path, level: usize) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(level_path(dir, level))
+}
+
+/// A node held by the cache, and whether it must still be written to its file.
+struct CachedNode {
+    hash: u64,
+    dirty: bool,
+}
+
+/// The level files of a disk tree, in front of which recently used nodes are cached.
+/// Writes only reach the files when the cache is flushed.
+struct Storage {
+    files: Vec<File>,
+    cache: HashMap<(usize, usize), CachedNode>,
+    max_entries: usize,
+}
+
+impl Storage {
+    /// Returns the node at the given coordinates.
+    fn read(&mut self, level: usize, index: usize) -> io::Result<u64> {
+        if let Some(node) = self.cache.get(&(level, index)) {
+            return Ok(node.hash);
+        }
+
+        let mut file = &self.files[level];
+        file.seek(SeekFrom::Start(8 * index as u64))?;
+        let mut bytes = [0; 8];
+        file.read_exact(&mut bytes)?;
+        let hash = u64::from_le_bytes(bytes);
+
+        self.make_room()?;
+        self.cache
+            .insert((level, index), CachedNode { hash, dirty: false });
+        Ok(hash)
+    }
+
+    /// Replaces the node at the given coordinates. The file is updated on the next flush.
+    fn write(&mut self, level: usize, index: usize, hash: u64) -> io::Result<()> {
+        if !self.cache.contains_key(&(level, index)) {
+            self.make_room()?;
+        }
+        self.cache
+            .insert((level, index), CachedNode { hash, dirty: true });
+        Ok(())
+    }
+
+    /// Empties the cache, writing it back first, if it cannot take another node.
+    fn make_room(&mut self) -> io::Result<()> {
+        if self.cache.len() >= self.max_entries {
+            self.flush()?;
+            self.cache.clear();
+        }
+        Ok(())
+    }
+
+    /// Writes every dirty cached node to its file.
+    fn flush(&mut self) -> io::Result<()> {
+        for (&(level, index), node) in self.cache.iter_mut().filter(|(_, node)| node.dirty) {
+            let mut file = &self.files[level];
+            file.seek(SeekFrom::Start(8 * index as u64))?;
+            file.write_all(&node.hash.to_le_bytes())?;
+            node.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+/// Merkle tree whose levels are stored in files, one per level, inside a directory.
+/// Only the nodes touched by recent operations are kept in memory, so trees too large
+/// for memory can still be proven and extended: proofs and pushes touch a node per level.
+/// Changes are written to disk by `sync` and when the tree is dropped. Writes are not
+/// atomic: a crash may leave the directory inconsistent, while reopening a cleanly
+/// closed tree always yields an identical one.
+/// Leaves are hashed like the ones of `MerkleTree`, so both produce identical roots and
+/// proofs for the same elements.
+pub struct DiskMerkleTree {
+    dir: PathBuf,
+    storage: Mutex<Storage>,
+    len: usize,
+    capacity: usize,
+    epoch: u64,
+}
+
+impl DiskMerkleTree {
+    /// Creates a tree in the given directory, which is created if needed, out of the
+    /// provided elements. Files of a previous tree in the directory are overwritten.
+    /// Levels are computed one at a time out of the previous level's file, so only a
+    /// constant amount of nodes is held in memory.
+    /// * `dir` - The directory holding the tree's files.
+    /// * `elements` - array of `Hash` elements used to populate the tree.
+    pub fn create<H: Hash>(dir: impl AsRef<Path>, elements: &[H]) -> io::Result<DiskMerkleTree> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        let capacity = elements.len().next_power_of_two();
+        let mut writer = BufWriter::new(File::create(level_path(dir, 0))?);
+        for element in elements {
+            writer.write_all(&hash_single(element).to_le_bytes())?;
+        }
+        for _ in elements.len()..capacity {
+            writer.write_all(&MerkleTree::PAD_HASH.to_le_bytes())?;
+        }
+        writer
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+
+        let height = capacity.ilog2() as usize + 1;
+        for level_n in 1..height {
+            let mut reader = BufReader::new(File::open(level_path(dir, level_n - 1))?);
+            let mut writer = BufWriter::new(File::create(level_path(dir, level_n))?);
+            let mut pair = [0; 16];
+            for _ in 0..capacity >> level_n {
+                reader.read_exact(&mut pair)?;
+                let (left, right) = pair.split_at(8);
+                let hash = hash_pair(
+                    u64::from_le_bytes(left.try_into().expect("Halves hold 8 bytes")),
+                    u64::from_le_bytes(right.try_into().expect("Halves hold 8 bytes")),
+                );
+                writer.write_all(&hash.to_le_bytes())?;
+            }
+            writer
+                .into_inner()
+                .map_err(io::IntoInnerError::into_error)?;
+        }
+
+        let tree = DiskMerkleTree::open_levels(dir, elements.len(), capacity, 0)?;
+        tree.sync()?;
+        Ok(tree)
+    }
+
+    /// Opens a tree previously created in the given directory.
+    /// Fails if the directory does not hold a tree, or its files do not match the
+    /// recorded capacity.
+    /// * `dir` - The directory holding the tree's files.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<DiskMerkleTree> {
+        let dir = dir.as_ref();
+        let mut meta = BufReader::new(File::open(dir.join(META_FILE))?);
+        DISK_FORMAT
+            .read_header(&mut meta)
+            .map_err(|error| match error {
+                WireError::Io(error) => error,
+                error => invalid_data(error),
+            })?;
+
+        let mut values = [0_u64; 3];
+        for value in &mut values {
+            let mut bytes = [0; 8];
+            meta.read_exact(&mut bytes)?;
+            *value = u64::from_le_bytes(bytes);
+        }
+        let [len, capacity, epoch] = values;
+        let len = usize::try_from(len).map_err(invalid_data)?;
+        let capacity = usize::try_from(capacity).map_err(invalid_data)?;
+        if !capacity.is_power_of_two() || len > capacity {
+            return Err(invalid_data(format!(
+                "invalid length {len} for capacity {capacity}"
+            )));
+        }
+
+        DiskMerkleTree::open_levels(dir, len, capacity, epoch)
+    }
+
+    /// Opens the level files of a tree, checking their lengths against the capacity.
+    fn open_levels(
+        dir: &Path,
+        len: usize,
+        capacity: usize,
+        epoch: u64,
+    ) -> io::Result<DiskMerkleTree> {
+        let height = capacity.ilog2() as usize + 1;
+        let mut files = Vec::with_capacity(height);
+        for level_n in 0..height {
+            let file = open_level(dir, level_n)?;
+            let expected = 8 * (capacity >> level_n) as u64;
+            let found = file.metadata()?.len();
+            if found != expected {
+                return Err(invalid_data(format!(
+                    "level {level_n} holds {found} bytes, expected {expected}"
+                )));
+            }
+            files.push(file);
+        }
+
+        Ok(DiskMerkleTree {
+            dir: dir.to_path_buf(),
+            storage: Mutex::new(Storage {
+                files,
+                cache: HashMap::new(),
+                max_entries: DISK_CACHE_ENTRIES,
+            }),
+            len,
+            capacity,
+            epoch,
+        })
+    }
+
+    /// Returns the storage, ignoring poisoning: it is only modified through methods
+    /// that leave it consistent.
+    fn storage(&self) -> MutexGuard<'_, Storage> {
+        self.storage.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the length of the tree.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns wether a tree has no elements or not.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the capacity of the tree.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the height of the tree.
+    pub fn height(&self) -> usize {
+        self.capacity.ilog2() as usize + 1
+    }
+
+    /// Returns the epoch of the tree, incremented by every push.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Returns the root of the tree. If the tree is empty, the root will be `None`.
+    pub fn root(&self) -> io::Result<Option<u64>> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        self.storage().read(self.height() - 1, 0).map(Some)
+    }
+
+    /// Creates a `MerkleProof` for a given index, identical to the one returned by a
+    /// `MerkleTree` holding the same elements. Indices which do not correspond to an
+    /// element return a `MerkleProof::Invalid` value.
+    /// * `index` - index value to generate the proof for.
+    pub fn get_proof(&self, index: usize) -> io::Result<MerkleProof> {
+        if index >= self.len {
+            return Ok(MerkleProof::Invalid);
+        }
+
+        let mut storage = self.storage();
+        let nodes = (0..self.height() - 1)
+            .map(|level_n| storage.read(level_n, sibling_index(ancestor_index(index, level_n))))
+            .collect::<io::Result<Vec<u64>>>()?;
+
+        Ok(MerkleProof::Proof {
+            index,
+            nodes,
+            root: storage.read(self.height() - 1, 0)?,
+            len: self.len,
+            epoch: self.epoch,
+        })
+    }
+
+    /// Pushes an `Hash` element into the tree, doubling its capacity if it is full.
+    /// * `value` - The `Hash` value to be added to the tree.
+    pub fn push<H: Hash>(&mut self, value: H) -> io::Result<()> {
+        if self.len == self.capacity {
+            self.duplicate_capacity()?;
+        }
+
+        let height = self.height();
+        let storage = self
+            .storage
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut index = self.len;
+        let mut node = hash_single(value);
+        storage.write(0, index, node)?;
+        for level_n in 1..height {
+            let sibling = storage.read(level_n - 1, sibling_index(index))?;
+            node = if index.is_multiple_of(2) {
+                hash_pair(node, sibling)
+            } else {
+                hash_pair(sibling, node)
+            };
+            index = ancestor_index(index, 1);
+            storage.write(level_n, index, node)?;
+        }
+
+        self.len += 1;
+        self.epoch += 1;
+        Ok(())
+    }
+
+    /// Doubles the capacity of the tree, appending padding subtrees to every level and
+    /// adding a new root level.
+    fn duplicate_capacity(&mut self) -> io::Result<()> {
+        let height = self.height();
+        let storage = self
+            .storage
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        storage.flush()?;
+
+        for (level_n, empty_node) in empty_nodes(height).into_iter().enumerate() {
+            let mut writer = BufWriter::new(&storage.files[level_n]);
+            writer.seek(SeekFrom::End(0))?;
+            for _ in 0..self.capacity >> level_n {
+                writer.write_all(&empty_node.to_le_bytes())?;
+            }
+            writer.flush()?;
+        }
+
+        let top = storage.read(height - 1, 0)?;
+        let empty_top = empty_nodes(height)[height - 1];
+        let mut file = open_level(&self.dir, height)?;
+        file.set_len(0)?;
+        file.write_all(&hash_pair(top, empty_top).to_le_bytes())?;
+        storage.files.push(file);
+
+        self.capacity *= 2;
+        Ok(())
+    }
+
+    /// Writes every pending change to disk: the cached nodes and the length, capacity
+    /// and epoch of the tree.
+    pub fn sync(&self) -> io::Result<()> {
+        let mut storage = self.storage();
+        storage.flush()?;
+        for file in &storage.files {
+            file.sync_all()?;
+        }
+
+        let mut meta = Vec::with_capacity(HEADER_LEN + 3 * 8);
+        DISK_FORMAT.write_header(&mut meta, 0)?;
+        for value in [self.len as u64, self.capacity as u64, self.epoch] {
+            meta.extend_from_slice(&value.to_le_bytes());
+        }
+        let mut file = File::create(self.dir.join(META_FILE))?;
+        file.write_all(&meta)?;
+        file.sync_all()
+    }
+}
+
+/// Writes pending changes to disk. Errors are ignored: call `sync` to handle them.
+impl Drop for DiskMerkleTree {
+    fn drop(&mut self) {
+        let _ = self.sync();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a deterministic pseudo random generator.
+    fn generator(mut state: u64) -> impl FnMut() -> u64 {
+        move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        }
+    }
+
+    /// Checks that both trees have the same root and proofs for random leaves.
+    fn assert_same(disk: &DiskMerkleTree, tree: &MerkleTree) {
+        assert_eq!(disk.len(), tree.len());
+        assert_eq!(disk.capacity(), tree.capacity());
+        assert_eq!(disk.root().unwrap(), tree.root());
+
+        let mut next = generator(0x9e3779b97f4a7c15);
+        for _ in 0..50 {
+            let index = (next() % (tree.len() as u64 + 2)) as usize;
+            assert!(disk.get_proof(index).unwrap() == tree.get_proof(index));
+        }
+    }
+
+    #[test]
+    fn create_matches_in_memory_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let elements: Vec<u32> = (0..1000).collect();
+        let disk = DiskMerkleTree::create(dir.path(), &elements).unwrap();
+        assert_same(&disk, &MerkleTree::build(&elements));
+        assert!(disk.get_proof(999).unwrap().verify(999));
+    }
+
+    #[test]
+    fn push_matches_in_memory_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut disk = DiskMerkleTree::create(dir.path(), &[1, 2, 3]).unwrap();
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        for value in 4..40 {
+            disk.push(value).unwrap();
+            tree.push(value);
+            assert_same(&disk, &tree);
+        }
+        assert_eq!(disk.epoch(), tree.epoch());
+    }
+
+    #[test]
+    fn reopen_yields_identical_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tree = MerkleTree::build(&[0; 0]);
+        {
+            let mut disk = DiskMerkleTree::create(dir.path(), &[0; 0]).unwrap();
+            assert_eq!(disk.root().unwrap(), None);
+            for value in 0..300 {
+                disk.push(value).unwrap();
+                tree.push(value);
+            }
+        }
+
+        let disk = DiskMerkleTree::open(dir.path()).unwrap();
+        assert_same(&disk, &tree);
+        assert_eq!(disk.epoch(), 300);
+    }
+
+    #[test]
+    fn small_cache_writes_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut disk = DiskMerkleTree::create(dir.path(), &[1, 2, 3]).unwrap();
+        disk.storage().max_entries = 4;
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        for value in 4..100 {
+            disk.push(value).unwrap();
+            tree.push(value);
+        }
+        assert_same(&disk, &tree);
+
+        disk.sync().unwrap();
+        drop(disk);
+        assert_same(&DiskMerkleTree::open(dir.path()).unwrap(), &tree);
+    }
+
+    #[test]
+    fn corrupted_directory_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(DiskMerkleTree::open(dir.path()).is_err());
+
+        drop(DiskMerkleTree::create(dir.path(), &[1, 2, 3]).unwrap());
+        File::create(level_path(dir.path(), 1)).unwrap();
+        let error = DiskMerkleTree::open(dir.path()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        fs::write(dir.path().join(META_FILE), b"MRKL\x01\x00\x00").unwrap();
+        let error = DiskMerkleTree::open(dir.path()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,63 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// Error returned by the fallible operations of `MerkleTree` and its variants.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MerkleError {
+    /// The leaf index is not below the length it was checked against.
+    IndexOutOfRange { index: usize, len: usize },
+    /// The requested length exceeds the tree's length.
+    LengthOutOfRange { len: usize, tree_len: usize },
+    /// The leaf's hash is not held, since it is among the leaves pruned when the tree was
+    /// built out of a frontier.
+    Pruned { index: usize, pruned: usize },
+    /// The amount of frontier nodes does not match the length, which needs one per set bit.
+    FrontierMismatch { expected: usize, found: usize },
+    /// The tree cannot grow past its fixed capacity.
+    Full { capacity: usize },
+    /// The leaves on the right do not form subtrees aligned with the ones on the left:
+    /// the left length must be a multiple of the largest complete subtree on the right.
+    UnalignedMerge { left_len: usize, right_len: usize },
+    /// A node the tree's structure requires is missing, so the tree is corrupted.
+    Corrupted { level: usize, index: usize },
+    /// The progress callback of a build asked for it to stop.
+    Cancelled,
+}
+
+impl Display for MerkleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            MerkleError::IndexOutOfRange { index, len } => {
+                write!(f, "index {index} out of range for length {len}")
+            }
+            MerkleError::LengthOutOfRange { len, tree_len } => {
+                write!(f, "length {len} exceeds the tree's length {tree_len}")
+            }
+            MerkleError::Pruned { index, pruned } => {
+                write!(f, "leaf {index} is among the {pruned} pruned leaves")
+            }
+            MerkleError::FrontierMismatch { expected, found } => {
+                write!(f, "expected {expected} frontier nodes, found {found}")
+            }
+            MerkleError::Full { capacity } => {
+                write!(f, "the tree is full at its capacity of {capacity} leaves")
+            }
+            MerkleError::UnalignedMerge {
+                left_len,
+                right_len,
+            } => write!(
+                f,
+                "cannot append {right_len} leaves to {left_len} without their hashes"
+            ),
+            MerkleError::Corrupted { level, index } => {
+                write!(
+                    f,
+                    "node ({level}, {index}) is missing from a corrupted tree"
+                )
+            }
+            MerkleError::Cancelled => write!(f, "the build was cancelled"),
+        }
+    }
+}
+
+impl Error for MerkleError {}
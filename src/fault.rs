@@ -0,0 +1,174 @@
+//! Fault injection for testing what detects corrupted trees, such as `validate` or
+//! proof verification. The methods overwrite stored nodes without recomputing their
+//! ancestors, and only exist with the `testing` feature: normal builds cannot corrupt a
+//! tree.
+//!
+#![cfg_attr(not(feature = "testing"), doc = "```compile_fail")]
+#![cfg_attr(feature = "testing", doc = "```")]
+//! let mut tree = merkle_tree::MerkleTree::build(&[1, 2, 3]);
+//! tree.corrupt_node(0, 1, 7);
+//! assert!(tree.validate().is_err());
+//! ```
+
+#[cfg(any(test, feature = "testing"))]
+use std::ops::Range;
+
+#[cfg(any(test, feature = "testing"))]
+use rand::{Rng, RngCore};
+
+#[cfg(any(test, feature = "testing"))]
+use crate::MerkleTree;
+
+#[cfg(any(test, feature = "testing"))]
+impl MerkleTree {
+    /// Overwrites the hash of a node without updating anything else, leaving the tree
+    /// inconsistent. Proofs are created out of the corrupted nodes from then on. Trees
+    /// keeping only their leaves (see `keep_leaves_only`) ignore writes to the levels
+    /// they do not store.
+    /// Panics if the coordinates are out of range.
+    /// * `level` - Level of the node.
+    /// * `index` - Index of the node within its level.
+    /// * `hash` - The hash to be written.
+    pub fn corrupt_node(&mut self, level: usize, index: usize, hash: u64) {
+        self.levels.set(level, index, hash);
+        self.invalidate_proof_cache();
+    }
+
+    /// Flips a single bit of a node, like `corrupt_node`.
+    /// Panics if the coordinates are out of range or `bit` is not below 64.
+    /// * `level` - Level of the node.
+    /// * `index` - Index of the node within its level.
+    /// * `bit` - Position of the bit, from the least significant one.
+    pub fn flip_bit(&mut self, level: usize, index: usize, bit: u32) {
+        assert!(bit < u64::BITS, "bit {bit} is out of range for a node");
+        let node = self.levels[(level, index)];
+        self.corrupt_node(level, index, node ^ (1 << bit));
+    }
+
+    /// Overwrites a uniformly random node, of any level, with a different hash, like
+    /// `corrupt_node`, and returns its level and index. Nodes covering only pruned
+    /// leaves (see `from_frontier`) are never picked, and neither are the levels which
+    /// trees keeping only their leaves do not store. `validate` detects the corruption of
+    /// every tree of more than one level.
+    /// Returns `None` if the tree holds no node which can be corrupted.
+    /// * `rng` - The random number generator used to pick the node and its new hash.
+    pub fn corrupt_random_node(&mut self, rng: &mut impl RngCore) -> Option<(usize, usize)> {
+        let lowest_kept = self.levels.lowest_kept();
+        let held: Vec<(usize, Range<usize>)> = (0..self.height())
+            .filter(|&level_n| level_n == 0 || level_n >= lowest_kept)
+            .map(|level_n| (level_n, self.pruned >> level_n..self.levels.width(level_n)))
+            .filter(|(_, indices)| !indices.is_empty())
+            .collect();
+        let total: usize = held.iter().map(|(_, indices)| indices.len()).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut pick = rng.random_range(0..total);
+        let (level, indices) = held
+            .into_iter()
+            .find(|(_, indices)| {
+                let found = pick < indices.len();
+                if !found {
+                    pick -= indices.len();
+                }
+                found
+            })
+            .expect("The pick is below the total");
+        let index = indices.start + pick;
+        let mask = rng.random_range(1..=u64::MAX);
+        let node = self.levels[(level, index)];
+        self.corrupt_node(level, index, node ^ mask);
+        Some((level, index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use crate::{MerkleTree, ValidationError};
+
+    /// Returns the level of the node `validate` reports as corrupted.
+    fn reported_level(tree: &MerkleTree) -> usize {
+        match tree.validate() {
+            Err(ValidationError::Hash { level, .. }) => level,
+            other => panic!("expected a hash mismatch, found {other:?}"),
+        }
+    }
+
+    #[test]
+    fn validation_catches_every_fault() {
+        let tree = MerkleTree::build(&(0..13).collect::<Vec<u32>>());
+
+        // A corrupted leaf is reported through its parent.
+        let mut corrupted = tree.fork();
+        corrupted.corrupt_node(0, 5, 7);
+        assert_eq!(reported_level(&corrupted), 1);
+
+        let mut corrupted = tree.fork();
+        corrupted.corrupt_node(0, 14, 7);
+        assert_eq!(reported_level(&corrupted), 0);
+
+        let mut corrupted = tree.fork();
+        corrupted.corrupt_node(2, 1, 7);
+        assert_eq!(reported_level(&corrupted), 2);
+
+        for bit in [0, 17, 63] {
+            let mut corrupted = tree.fork();
+            corrupted.flip_bit(tree.height() - 1, 0, bit);
+            assert_eq!(reported_level(&corrupted), tree.height() - 1);
+            assert_ne!(corrupted.root(), tree.root());
+        }
+
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..200 {
+            let mut corrupted = tree.fork();
+            let (level, index) = corrupted.corrupt_random_node(&mut rng).unwrap();
+            assert_ne!(corrupted.node(level, index), tree.node(level, index));
+            assert!(corrupted.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn proofs_through_corrupted_paths_fail() {
+        let values: Vec<u32> = (0..8).collect();
+        let mut tree = MerkleTree::build(&values);
+        // Caches the proofs, which must not survive the corruption.
+        tree.enable_proof_cache(8);
+        values
+            .iter()
+            .for_each(|&value| assert!(tree.get_proof(value as usize).verify(value)));
+
+        // The node above leaves 2 and 3 is a sibling along the paths of leaves 0 and 1.
+        tree.flip_bit(1, 1, 5);
+        for value in values {
+            let verified = tree.get_proof(value as usize).verify(value);
+            assert_eq!(verified, value >= 2, "leaf {value}");
+        }
+    }
+
+    #[test]
+    fn random_corruption_skips_what_is_not_held() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let tree = MerkleTree::build(&[1_u32]);
+        let mut corrupted = tree.fork();
+        assert_eq!(corrupted.corrupt_random_node(&mut rng), Some((0, 0)));
+
+        let mut tree = MerkleTree::build(&(0..64).collect::<Vec<u32>>());
+        tree.keep_leaves_only(1).unwrap();
+        for _ in 0..100 {
+            let mut corrupted = tree.fork();
+            let (level, _) = corrupted.corrupt_random_node(&mut rng).unwrap();
+            assert!(level == 0 || level == tree.height() - 1);
+            assert!(corrupted.validate().is_err());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "bit 64 is out of range for a node")]
+    fn out_of_range_bits_panic() {
+        MerkleTree::build(&[1, 2]).flip_bit(0, 0, 64);
+    }
+}
@@ -0,0 +1,313 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+
+use crate::{EncodeError, MerkleProof, MerkleTree};
+
+/// The operation succeeded.
+pub const MT_OK: i32 = 0;
+/// A required pointer was null.
+pub const MT_ERR_NULL: i32 = 1;
+/// The tree is empty, so it has no root.
+pub const MT_ERR_EMPTY: i32 = 2;
+/// The index does not correspond to an occupied leaf.
+pub const MT_ERR_INDEX: i32 = 3;
+/// The buffer cannot hold the encoded proof. The needed length is written to `out_len`.
+pub const MT_ERR_BUFFER_TOO_SMALL: i32 = 4;
+/// The proof could not be decoded.
+pub const MT_ERR_DECODE: i32 = 5;
+/// The call panicked. The panic was caught before reaching the caller.
+pub const MT_ERR_PANIC: i32 = 6;
+
+/// Opaque handle to a tree owned by foreign code, created by `mt_build` and released
+/// by `mt_free`.
+pub struct MerkleTreeHandle {
+    tree: MerkleTree,
+}
+
+/// Runs a call, turning a panic into `MT_ERR_PANIC` instead of unwinding into foreign code.
+fn guard<F: FnOnce() -> i32>(call: F) -> i32 {
+    panic::catch_unwind(AssertUnwindSafe(call)).unwrap_or(MT_ERR_PANIC)
+}
+
+/// Returns a slice over foreign memory, treating a zero length as empty whatever the
+/// pointer. Returns `None` if the pointer is null but the length is not zero.
+/// # Safety
+/// Unless null, `data` must point to `len` readable values.
+unsafe fn foreign_slice<'a, T>(data: *const T, len: usize) -> Option<&'a [T]> {
+    if len == 0 {
+        Some(&[])
+    } else if data.is_null() {
+        None
+    } else {
+        // SAFETY: The caller guarantees `data` points to `len` readable values.
+        Some(unsafe { slice::from_raw_parts(data, len) })
+    }
+}
+
+/// Builds a tree out of already hashed leaves (see `MerkleTree::from_leaf_hashes`).
+/// Returns null if `leaf_hashes` is null while `n` is not zero, or if the build panics.
+/// # Safety
+/// Unless null, `leaf_hashes` must point to `n` readable `uint64_t` values.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mt_build(leaf_hashes: *const u64, n: usize) -> *mut MerkleTreeHandle {
+    panic::catch_unwind(|| {
+        // SAFETY: Guaranteed by the caller.
+        let Some(leaves) = (unsafe { foreign_slice(leaf_hashes, n) }) else {
+            return std::ptr::null_mut();
+        };
+        let tree = MerkleTree::from_leaf_hashes(leaves.to_vec());
+        Box::into_raw(Box::new(MerkleTreeHandle { tree }))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Writes the root of the tree to `out_root`.
+/// Returns `MT_ERR_EMPTY` if the tree has no leaves.
+/// # Safety
+/// `handle` must be null or returned by `mt_build` and not yet freed. `out_root` must
+/// be null or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mt_root(handle: *const MerkleTreeHandle, out_root: *mut u64) -> i32 {
+    guard(|| {
+        if out_root.is_null() {
+            return MT_ERR_NULL;
+        }
+        // SAFETY: Guaranteed by the caller.
+        let Some(handle) = (unsafe { handle.as_ref() }) else {
+            return MT_ERR_NULL;
+        };
+        match handle.tree.root() {
+            None => MT_ERR_EMPTY,
+            Some(root) => {
+                // SAFETY: Guaranteed by the caller.
+                unsafe { out_root.write(root) };
+                MT_OK
+            }
+        }
+    })
+}
+
+/// Writes the compact encoding (see `MerkleProof::encode_into`) of the proof for a
+/// leaf into `buffer`, and its length to `out_len`.
+/// If the buffer is too small, nothing is written to it, `out_len` holds the needed
+/// length and `MT_ERR_BUFFER_TOO_SMALL` is returned, so callers can retry. Passing a
+/// null buffer with a zero length queries the needed length this way.
+/// Returns `MT_ERR_INDEX` if the index does not correspond to an occupied leaf.
+/// # Safety
+/// `handle` must be null or returned by `mt_build` and not yet freed. Unless null,
+/// `buffer` must point to `buffer_len` writable bytes. `out_len` must be null or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mt_get_proof(
+    handle: *const MerkleTreeHandle,
+    index: usize,
+    buffer: *mut u8,
+    buffer_len: usize,
+    out_len: *mut usize,
+) -> i32 {
+    guard(|| {
+        if out_len.is_null() || (buffer.is_null() && buffer_len != 0) {
+            return MT_ERR_NULL;
+        }
+        // SAFETY: Guaranteed by the caller.
+        let Some(handle) = (unsafe { handle.as_ref() }) else {
+            return MT_ERR_NULL;
+        };
+
+        let proof = handle.tree.get_proof(index);
+        if proof == MerkleProof::Invalid {
+            return MT_ERR_INDEX;
+        }
+
+        let buffer: &mut [u8] = if buffer_len == 0 {
+            &mut []
+        } else {
+            // SAFETY: Guaranteed by the caller.
+            unsafe { slice::from_raw_parts_mut(buffer, buffer_len) }
+        };
+        let (code, len) = match proof.encode_into(buffer) {
+            Ok(written) => (MT_OK, written),
+            Err(EncodeError::BufferTooSmall { needed }) => (MT_ERR_BUFFER_TOO_SMALL, needed),
+        };
+        // SAFETY: Guaranteed by the caller.
+        unsafe { out_len.write(len) };
+        code
+    })
+}
+
+/// Decodes a proof out of its compact encoding and writes to `out_valid` whether the
+/// leaf hash verifies it against the expected root.
+/// Returns `MT_ERR_DECODE` if the proof is malformed.
+/// # Safety
+/// Unless null, `proof` must point to `proof_len` readable bytes. `out_valid` must be
+/// null or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mt_proof_verify(
+    proof: *const u8,
+    proof_len: usize,
+    leaf_hash: u64,
+    root: u64,
+    out_valid: *mut bool,
+) -> i32 {
+    guard(|| {
+        if out_valid.is_null() {
+            return MT_ERR_NULL;
+        }
+        // SAFETY: Guaranteed by the caller.
+        let Some(bytes) = (unsafe { foreign_slice(proof, proof_len) }) else {
+            return MT_ERR_NULL;
+        };
+        let Ok(proof) = MerkleProof::decode_compact(bytes) else {
+            return MT_ERR_DECODE;
+        };
+
+        let valid = match &proof {
+            MerkleProof::Proof { root: proven, .. } => {
+                *proven == root && proof.verify_leaf(leaf_hash)
+            }
+            MerkleProof::Invalid => false,
+        };
+        // SAFETY: Guaranteed by the caller.
+        unsafe { out_valid.write(valid) };
+        MT_OK
+    })
+}
+
+/// Releases a tree returned by `mt_build`. Null handles are ignored.
+/// # Safety
+/// `handle` must be null or returned by `mt_build` and not yet freed. It must not be
+/// used after this call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mt_free(handle: *mut MerkleTreeHandle) {
+    if !handle.is_null() {
+        // SAFETY: Guaranteed by the caller. Dropping a tree never panics.
+        drop(unsafe { Box::from_raw(handle) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    const HEADER: &str = include_str!("../include/merkle_tree.h");
+
+    /// Returns the compact encoding of the proof for a leaf, going through `mt_get_proof`
+    /// the way foreign callers do: querying the length first.
+    fn get_proof(handle: *const MerkleTreeHandle, index: usize) -> Vec<u8> {
+        let mut len = 0;
+        let code = unsafe { mt_get_proof(handle, index, ptr::null_mut(), 0, &mut len) };
+        assert_eq!(code, MT_ERR_BUFFER_TOO_SMALL);
+
+        let mut buffer = vec![0; len];
+        let code = unsafe { mt_get_proof(handle, index, buffer.as_mut_ptr(), len, &mut len) };
+        assert_eq!(code, MT_OK);
+        assert_eq!(len, buffer.len());
+        buffer
+    }
+
+    /// Returns the result of `mt_proof_verify`, panicking on errors.
+    fn verify(proof: &[u8], leaf_hash: u64, root: u64) -> bool {
+        let mut valid = false;
+        let code =
+            unsafe { mt_proof_verify(proof.as_ptr(), proof.len(), leaf_hash, root, &mut valid) };
+        assert_eq!(code, MT_OK);
+        valid
+    }
+
+    #[test]
+    fn build_prove_verify() {
+        let leaves = [11_u64, 22, 33, 44, 55];
+        let handle = unsafe { mt_build(leaves.as_ptr(), leaves.len()) };
+        assert!(!handle.is_null());
+
+        let mut root = 0;
+        assert_eq!(unsafe { mt_root(handle, &mut root) }, MT_OK);
+        assert_eq!(
+            Some(root),
+            MerkleTree::from_leaf_hashes(leaves.to_vec()).root()
+        );
+
+        for (index, &leaf) in leaves.iter().enumerate() {
+            let proof = get_proof(handle, index);
+            assert!(verify(&proof, leaf, root));
+            assert!(!verify(&proof, leaf + 1, root));
+            assert!(!verify(&proof, leaf, root + 1));
+        }
+
+        unsafe { mt_free(handle) };
+    }
+
+    #[test]
+    fn empty_tree() {
+        let handle = unsafe { mt_build(ptr::null(), 0) };
+        assert!(!handle.is_null());
+
+        let mut root = 0;
+        assert_eq!(unsafe { mt_root(handle, &mut root) }, MT_ERR_EMPTY);
+        let mut len = 0;
+        let code = unsafe { mt_get_proof(handle, 0, ptr::null_mut(), 0, &mut len) };
+        assert_eq!(code, MT_ERR_INDEX);
+
+        unsafe { mt_free(handle) };
+    }
+
+    #[test]
+    fn errors_reported() {
+        assert!(unsafe { mt_build(ptr::null(), 3) }.is_null());
+
+        let mut root = 0;
+        assert_eq!(unsafe { mt_root(ptr::null(), &mut root) }, MT_ERR_NULL);
+
+        let handle = unsafe { mt_build([1_u64, 2, 3].as_ptr(), 3) };
+        assert_eq!(unsafe { mt_root(handle, ptr::null_mut()) }, MT_ERR_NULL);
+        let mut len = 0;
+        let code = unsafe { mt_get_proof(handle, 3, ptr::null_mut(), 0, &mut len) };
+        assert_eq!(code, MT_ERR_INDEX);
+        let code = unsafe { mt_get_proof(handle, 0, ptr::null_mut(), 8, &mut len) };
+        assert_eq!(code, MT_ERR_NULL);
+
+        let mut proof = get_proof(handle, 0);
+        proof.push(0);
+        let mut valid = true;
+        let code = unsafe { mt_proof_verify(proof.as_ptr(), proof.len(), 1, 0, &mut valid) };
+        assert_eq!(code, MT_ERR_DECODE);
+        assert!(valid);
+
+        unsafe {
+            mt_free(handle);
+            mt_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn panics_caught() {
+        assert_eq!(guard(|| panic!("boom")), MT_ERR_PANIC);
+    }
+
+    #[test]
+    fn header_declares_every_function() {
+        for declaration in [
+            "MerkleTreeHandle *mt_build(const uint64_t *leaf_hashes, size_t n);",
+            "int32_t mt_root(const MerkleTreeHandle *handle, uint64_t *out_root);",
+            "int32_t mt_get_proof(const MerkleTreeHandle *handle,",
+            "int32_t mt_proof_verify(const uint8_t *proof,",
+            "void mt_free(MerkleTreeHandle *handle);",
+            "typedef struct MerkleTreeHandle MerkleTreeHandle;",
+        ] {
+            assert!(HEADER.contains(declaration), "missing {declaration:?}");
+        }
+
+        for (name, value) in [
+            ("MT_OK", MT_OK),
+            ("MT_ERR_NULL", MT_ERR_NULL),
+            ("MT_ERR_EMPTY", MT_ERR_EMPTY),
+            ("MT_ERR_INDEX", MT_ERR_INDEX),
+            ("MT_ERR_BUFFER_TOO_SMALL", MT_ERR_BUFFER_TOO_SMALL),
+            ("MT_ERR_DECODE", MT_ERR_DECODE),
+            ("MT_ERR_PANIC", MT_ERR_PANIC),
+        ] {
+            let define = format!("#define {name} {value}\n");
+            assert!(HEADER.contains(&define), "missing {define:?}");
+        }
+    }
+}
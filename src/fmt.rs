@@ -0,0 +1,267 @@
+use std::error::Error;
+use std::fmt::{self, Debug, Display, Formatter, LowerHex, UpperHex};
+
+use crate::{MerkleProof, MerkleTree, TreeHead};
+
+/// Length of the canonical hex representation of a root: two digits per byte of digest.
+pub const ROOT_HEX_LEN: usize = 2 * size_of::<u64>();
+
+/// Error returned when parsing a root out of its hex representation fails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// The string (without its `0x` prefix) does not have `ROOT_HEX_LEN` digits.
+    InvalidLength(usize),
+    /// The string contains a character that is not a hex digit.
+    InvalidDigit(char),
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidLength(len) => {
+                write!(f, "expected {ROOT_HEX_LEN} hex digits, found {len}")
+            }
+            ParseError::InvalidDigit(digit) => write!(f, "invalid hex digit {digit:?}"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Returns the canonical hex representation of a root: `ROOT_HEX_LEN` lowercase,
+/// zero-padded digits, without prefix.
+/// * `root` - The root to be formatted.
+pub fn root_to_hex(root: u64) -> String {
+    format!("{root:0width$x}", width = ROOT_HEX_LEN)
+}
+
+/// Parses a root out of its hex representation. Exactly `ROOT_HEX_LEN` digits are
+/// expected, optionally preceded by a `0x` prefix. Both lowercase and uppercase digits
+/// are accepted.
+/// * `hex` - The string to be parsed.
+pub fn parse_root(hex: &str) -> Result<u64, ParseError> {
+    let digits = hex.strip_prefix("0x").unwrap_or(hex);
+    if let Some(digit) = digits.chars().find(|digit| !digit.is_ascii_hexdigit()) {
+        return Err(ParseError::InvalidDigit(digit));
+    }
+    if digits.len() != ROOT_HEX_LEN {
+        return Err(ParseError::InvalidLength(digits.len()));
+    }
+    Ok(u64::from_str_radix(digits, 16).expect("Validated hex digits always parse"))
+}
+
+impl MerkleTree {
+    /// Returns the canonical hex representation of the root (see `root_to_hex`).
+    /// If the tree is empty, there is no root and `None` is returned.
+    pub fn root_hex(&self) -> Option<String> {
+        self.root().map(root_to_hex)
+    }
+}
+
+/// Formats the head's root as zero-padded lowercase hex digits.
+impl LowerHex for TreeHead {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            f.write_str("0x")?;
+        }
+        write!(f, "{:0width$x}", self.root, width = ROOT_HEX_LEN)
+    }
+}
+
+/// Formats the head's root as zero-padded uppercase hex digits.
+impl UpperHex for TreeHead {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            f.write_str("0x")?;
+        }
+        write!(f, "{:0width$X}", self.root, width = ROOT_HEX_LEN)
+    }
+}
+
+/// Maximum amount of nodes shown per level by the `Debug` output.
+const MAX_DEBUG_NODES: usize = 8;
+
+/// Trees up to this capacity show their levels in the `Debug` output even without
+/// the alternate flag.
+const SMALL_TREE_CAPACITY: usize = 8;
+
+/// Formats a hash as 16 lowercase hex digits.
+struct HexHash(u64);
+
+impl Debug for HexHash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&root_to_hex(self.0))
+    }
+}
+
+/// Formats a hash as its first 8 hex digits.
+struct ShortHash(u64);
+
+impl Debug for ShortHash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x}", self.0 >> 32)
+    }
+}
+
+/// Formats a level of a tree, eliding the nodes beyond `MAX_DEBUG_NODES`.
+struct LevelDebug<'a>(&'a MerkleTree, usize);
+
+impl Debug for LevelDebug<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let LevelDebug(tree, level_n) = *self;
+        let width = tree.levels.width(level_n);
+        let mut list = f.debug_list();
+        list.entries(
+            tree.levels
+                .iter_level(level_n)
+                .take(MAX_DEBUG_NODES)
+                .map(ShortHash),
+        );
+        if width > MAX_DEBUG_NODES {
+            list.entry(&format_args!("... {} more", width - MAX_DEBUG_NODES));
+        }
+        list.finish()
+    }
+}
+
+/// Shows the tree's shape and root. Levels are shown too, from the root down and with
+/// their hashes abbreviated, for small trees or when using the alternate `{:#?}` flag.
+impl Debug for MerkleTree {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let show_levels = f.alternate() || self.capacity() <= SMALL_TREE_CAPACITY;
+        let mut debug = f.debug_struct("MerkleTree");
+        debug
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .field("padding", &self.padding)
+            .field("height", &self.height())
+            .field("root", &self.root().map(HexHash));
+
+        if show_levels {
+            let levels: Vec<LevelDebug> = (0..self.height())
+                .rev()
+                .map(|level_n| LevelDebug(self, level_n))
+                .collect();
+            debug.field("levels", &levels);
+        }
+
+        debug.finish()
+    }
+}
+
+/// Shows the variant, the proven index, the length of the path and the root.
+impl Debug for MerkleProof {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            MerkleProof::Invalid => f.write_str("Invalid"),
+            MerkleProof::Proof {
+                index,
+                nodes,
+                root,
+                len,
+                epoch,
+            } => f
+                .debug_struct("Proof")
+                .field("index", index)
+                .field("path_len", &nodes.len())
+                .field("root", &HexHash(*root))
+                .field("len", len)
+                .field("epoch", epoch)
+                .finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_small_tree() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4]);
+        assert_eq!(
+            format!("{tree:?}"),
+            "MerkleTree { len: 4, capacity: 4, padding: 0, height: 3, root: Some(dee641bc2d541152), \
+             levels: [[dee641bc], [b89651ba, 48ad259c], [53de5ce8, aaf8fa43, b93fa199, 6b36c285]] }"
+        );
+    }
+
+    #[test]
+    fn debug_large_tree_elides_levels() {
+        let tree = MerkleTree::build(&[0; 20]);
+        let output = format!("{tree:?}");
+        assert!(output.starts_with("MerkleTree { len: 20, capacity: 32, padding: 12, height: 6"));
+        assert!(!output.contains("levels"));
+
+        let output = format!("{tree:#?}");
+        assert!(output.contains("levels"));
+        assert!(output.contains("... 24 more"));
+        assert!(output.contains("... 8 more"));
+    }
+
+    #[test]
+    fn debug_empty_tree() {
+        let tree = MerkleTree::build::<u8>(&[]);
+        assert!(
+            format!("{tree:?}").contains("len: 0, capacity: 1, padding: 1, height: 1, root: None")
+        );
+    }
+
+    #[test]
+    fn debug_proof() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4]);
+        assert_eq!(
+            format!("{:?}", tree.get_proof(2)),
+            "Proof { index: 2, path_len: 2, root: dee641bc2d541152, len: 4, epoch: 0 }"
+        );
+        assert_eq!(format!("{:?}", tree.get_proof(4)), "Invalid");
+    }
+
+    #[test]
+    fn root_hex_round_trip() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4]);
+        let hex = tree.root_hex().unwrap();
+        assert_eq!(hex, "dee641bc2d541152");
+        assert_eq!(parse_root(&hex), Ok(tree.root().unwrap()));
+        assert_eq!(parse_root(&format!("0x{hex}")), Ok(tree.root().unwrap()));
+        assert_eq!(parse_root(&hex.to_uppercase()), Ok(tree.root().unwrap()));
+        assert_eq!(MerkleTree::build::<u8>(&[]).root_hex(), None);
+    }
+
+    #[test]
+    fn root_hex_is_zero_padded() {
+        assert_eq!(root_to_hex(0xab), "00000000000000ab");
+        assert_eq!(parse_root("00000000000000ab"), Ok(0xab));
+    }
+
+    #[test]
+    fn parse_root_rejects_malformed_strings() {
+        assert_eq!(parse_root("ab"), Err(ParseError::InvalidLength(2)));
+        assert_eq!(parse_root("0x"), Err(ParseError::InvalidLength(0)));
+        assert_eq!(
+            parse_root("00000000000000abc"),
+            Err(ParseError::InvalidLength(17))
+        );
+        assert_eq!(
+            parse_root("00000000000000ag"),
+            Err(ParseError::InvalidDigit('g'))
+        );
+        assert_eq!(
+            parse_root("+0000000000000ab"),
+            Err(ParseError::InvalidDigit('+'))
+        );
+        assert_eq!(
+            parse_root("0X00000000000000ab"),
+            Err(ParseError::InvalidDigit('X'))
+        );
+    }
+
+    #[test]
+    fn head_hex_formatting() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4]);
+        let head = tree.head().unwrap();
+        assert_eq!(format!("{head:x}"), "dee641bc2d541152");
+        assert_eq!(format!("{head:#x}"), "0xdee641bc2d541152");
+        assert_eq!(format!("{head:X}"), "DEE641BC2D541152");
+    }
+}
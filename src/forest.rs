@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+
+use crate::{MerkleProof, MerkleTree, hash_single};
+
+/// Leaf left behind by a removed tenant, until its slot is reused.
+const TOMBSTONE: u64 = MerkleTree::PAD_HASH;
+
+/// Returns the forest leaf committing to a tenant's tree: the hash of the tenant id's
+/// hash and the tree's root, `PAD_HASH` standing for the root of an empty tree.
+fn tenant_leaf(tenant: &str, member_root: u64) -> u64 {
+    hash_single((hash_single(tenant), member_root))
+}
+
+/// Returns the root committed for a member tree.
+fn member_root(tree: &MerkleTree) -> u64 {
+    tree.root().unwrap_or(MerkleTree::PAD_HASH)
+}
+
+/// Collection of named member trees, one per tenant, committed to by a single
+/// super-root: the root of a tree with a leaf per tenant, binding its id to its tree's
+/// root.
+/// Updating a member only recomputes the path of its forest leaf. Removed tenants leave
+/// a tombstone leaf, which is reused by the next added tenant, like `MerkleMap` does.
+#[derive(Debug)]
+pub struct MerkleForest {
+    forest: MerkleTree,
+    /// Forest leaf index and tree of every tenant.
+    members: HashMap<String, (usize, MerkleTree)>,
+    /// Indices of the tombstone leaves.
+    free: Vec<usize>,
+}
+
+/// Proof that a value is a leaf of a tenant's tree, itself committed by a super-root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForestProof {
+    member: MerkleProof,
+    forest: MerkleProof,
+}
+
+impl ForestProof {
+    /// Returns the proof of the value within the tenant's tree.
+    pub fn member(&self) -> &MerkleProof {
+        &self.member
+    }
+
+    /// Returns the proof of the tenant's leaf within the forest.
+    pub fn forest(&self) -> &MerkleProof {
+        &self.forest
+    }
+
+    /// Returns whether the proof shows that the value belongs to the tenant's tree under
+    /// the super-root.
+    /// * `tenant` - The id of the tenant.
+    /// * `value` - The `Hash` value to be tested.
+    /// * `super_root` - The trusted super-root of the forest.
+    pub fn verify<H: std::hash::Hash>(&self, tenant: &str, value: H, super_root: u64) -> bool {
+        let (
+            MerkleProof::Proof {
+                root: member_root, ..
+            },
+            MerkleProof::Proof {
+                root: forest_root, ..
+            },
+        ) = (&self.member, &self.forest)
+        else {
+            return false;
+        };
+        *forest_root == super_root
+            && self.member.verify(value)
+            && self.forest.verify_leaf(tenant_leaf(tenant, *member_root))
+    }
+}
+
+impl MerkleForest {
+    /// Creates a forest without tenants.
+    pub fn new() -> MerkleForest {
+        MerkleForest {
+            forest: MerkleTree::build::<u8>(&[]),
+            members: HashMap::new(),
+            free: Vec::new(),
+        }
+    }
+
+    /// Returns the amount of tenants.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Returns whether the forest holds no tenants.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Returns the super-root committing to every tenant's tree, or `None` if no tenant
+    /// was ever added.
+    pub fn super_root(&self) -> Option<u64> {
+        self.forest.root()
+    }
+
+    /// Returns the tree of a tenant.
+    /// * `tenant` - The id of the tenant.
+    pub fn tenant(&self, tenant: &str) -> Option<&MerkleTree> {
+        self.members.get(tenant).map(|(_, tree)| tree)
+    }
+
+    /// Returns an iterator over the ids of the tenants, in arbitrary order.
+    pub fn tenants(&self) -> impl Iterator<Item = &str> {
+        self.members.keys().map(String::as_str)
+    }
+
+    /// Adds a tenant with its tree, returning the tree it replaced if the tenant was
+    /// already present. New tenants take the slot of a removed one if there is one.
+    /// * `tenant` - The id of the tenant.
+    /// * `tree` - The tenant's tree.
+    pub fn insert(&mut self, tenant: impl Into<String>, tree: MerkleTree) -> Option<MerkleTree> {
+        let tenant = tenant.into();
+        let leaf = tenant_leaf(&tenant, member_root(&tree));
+        if let Some((index, old)) = self.members.get_mut(&tenant) {
+            self.forest.replace_leaf_hash(*index, leaf);
+            return Some(std::mem::replace(old, tree));
+        }
+
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.forest.replace_leaf_hash(index, leaf);
+                index
+            }
+            None => {
+                self.forest.push_hash(leaf);
+                self.forest.len() - 1
+            }
+        };
+        self.members.insert(tenant, (index, tree));
+        None
+    }
+
+    /// Removes a tenant, returning its tree. Its leaf becomes a tombstone, so proofs of
+    /// its tree no longer verify against the new super-root.
+    /// * `tenant` - The id of the tenant.
+    pub fn remove(&mut self, tenant: &str) -> Option<MerkleTree> {
+        let (index, tree) = self.members.remove(tenant)?;
+        self.forest.replace_leaf_hash(index, TOMBSTONE);
+        self.free.push(index);
+        Some(tree)
+    }
+
+    /// Applies a change to a tenant's tree and returns its result, or `None` if the
+    /// tenant is not present. If the tree's root changed, only the path of the tenant's
+    /// forest leaf is recomputed.
+    /// * `tenant` - The id of the tenant.
+    /// * `change` - The change to be applied to the tree.
+    pub fn update<R>(
+        &mut self,
+        tenant: &str,
+        change: impl FnOnce(&mut MerkleTree) -> R,
+    ) -> Option<R> {
+        let (index, tree) = self.members.get_mut(tenant)?;
+        let old_root = tree.root();
+        let result = change(tree);
+        if tree.root() != old_root {
+            let leaf = tenant_leaf(tenant, member_root(tree));
+            self.forest.replace_leaf_hash(*index, leaf);
+        }
+        Some(result)
+    }
+
+    /// Returns the proof of a tenant's leaf, composed of its proof within the tenant's
+    /// tree and the proof of the tenant within the forest, or `None` if the tenant is
+    /// not present or the index does not hold a provable leaf.
+    /// * `tenant` - The id of the tenant.
+    /// * `index` - The index of the leaf within the tenant's tree.
+    pub fn prove(&self, tenant: &str, index: usize) -> Option<ForestProof> {
+        let (forest_index, tree) = self.members.get(tenant)?;
+        let member = tree.get_proof(index);
+        if member == MerkleProof::Invalid {
+            return None;
+        }
+        Some(ForestProof {
+            member,
+            forest: self.forest.get_proof(*forest_index),
+        })
+    }
+}
+
+impl Default for MerkleForest {
+    fn default() -> MerkleForest {
+        MerkleForest::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a forest of the given amount of tenants, each with a few leaves.
+    fn build_forest(tenants: usize) -> MerkleForest {
+        let mut forest = MerkleForest::new();
+        for tenant in 0..tenants {
+            let values: Vec<usize> = (tenant..tenant + 5).collect();
+            forest.insert(format!("tenant-{tenant}"), MerkleTree::build(&values));
+        }
+        forest
+    }
+
+    #[test]
+    fn composed_proofs_verify() {
+        let forest = build_forest(100);
+        let super_root = forest.super_root().unwrap();
+
+        let proof = forest.prove("tenant-42", 3).unwrap();
+        assert!(proof.verify("tenant-42", 45_usize, super_root));
+        assert!(!proof.verify("tenant-42", 46_usize, super_root));
+        assert!(!proof.verify("tenant-43", 45_usize, super_root));
+        assert!(!proof.verify("tenant-42", 45_usize, super_root ^ 1));
+
+        assert!(forest.prove("tenant-42", 5).is_none());
+        assert!(forest.prove("tenant-100", 0).is_none());
+    }
+
+    #[test]
+    fn updates_refresh_the_super_root() {
+        let mut forest = build_forest(100);
+        let old_root = forest.super_root().unwrap();
+        let stale = forest.prove("tenant-7", 0).unwrap();
+
+        assert_eq!(
+            forest.update("tenant-7", |tree| tree.push(99_usize)),
+            Some(())
+        );
+        let super_root = forest.super_root().unwrap();
+        assert_ne!(super_root, old_root);
+        assert!(!stale.verify("tenant-7", 7_usize, super_root));
+        assert!(
+            forest
+                .prove("tenant-7", 5)
+                .unwrap()
+                .verify("tenant-7", 99_usize, super_root)
+        );
+        assert!(
+            forest
+                .prove("tenant-8", 0)
+                .unwrap()
+                .verify("tenant-8", 8_usize, super_root)
+        );
+
+        // Replacing the tree outright commits to the same super-root.
+        let mut replaced = build_forest(100);
+        let values: Vec<usize> = vec![7, 8, 9, 10, 11, 99];
+        replaced.insert("tenant-7", MerkleTree::build(&values));
+        assert_eq!(replaced.super_root(), Some(super_root));
+        assert_eq!(replaced.update("tenant-100", |_| ()), None);
+    }
+
+    #[test]
+    fn tenants_added_and_removed() {
+        let mut forest = build_forest(3);
+        let removed = forest.remove("tenant-1").unwrap();
+        assert_eq!(removed.len(), 5);
+        assert_eq!(forest.len(), 2);
+        assert!(forest.tenant("tenant-1").is_none());
+        assert!(forest.prove("tenant-1", 0).is_none());
+
+        // The freed slot is reused instead of growing the forest.
+        forest.insert("tenant-9", MerkleTree::build::<u8>(&[]));
+        assert_eq!(forest.forest.len(), 3);
+        assert_eq!(forest.tenants().count(), 3);
+        let super_root = forest.super_root().unwrap();
+        assert!(
+            forest
+                .prove("tenant-2", 1)
+                .unwrap()
+                .verify("tenant-2", 3_usize, super_root)
+        );
+
+        // Replacing a tenant's tree returns the old one.
+        let old = forest.insert("tenant-9", MerkleTree::build(&[1]));
+        assert!(old.unwrap().is_empty());
+        assert_ne!(forest.super_root(), Some(super_root));
+    }
+
+    #[cfg(feature = "instrumentation")]
+    #[test]
+    fn updates_hash_one_forest_path() {
+        use crate::{hash_ops, reset_counters};
+
+        let mut forest = build_forest(100);
+        let forest_height = forest.forest.height() as u64;
+        assert_eq!(forest_height, 8);
+
+        reset_counters();
+        forest.update("tenant-50", |tree| tree.push(0_usize));
+        let member_height = forest.tenant("tenant-50").unwrap().height() as u64;
+        // The member's own path, then one hash per level of the forest below its root;
+        // the 99 other tenants are left untouched.
+        let ops = hash_ops();
+        assert_eq!(ops.pair, (member_height - 1) + (forest_height - 1));
+        assert_eq!(ops.leaf, 1 + 2);
+    }
+}
@@ -0,0 +1,91 @@
+use crate::MerkleTree;
+
+impl MerkleTree {
+    /// Returns a logically independent copy of the tree, which initially shares its
+    /// storage: forking only copies a handle to it, so it is O(height), and a fork
+    /// discarded before being written to costs nothing else.
+    /// Storage is copied on write: the first write to shared storage, by either tree,
+    /// copies it. Both trees keep working independently afterwards.
+    /// The fork keeps the tree's epoch. Options such as the proof cache, the lookup index,
+    /// the root observer, the root history and the operation log are not carried over, so
+    /// they are disabled.
+    pub fn fork(&self) -> MerkleTree {
+        MerkleTree {
+            levels: self.levels.clone(),
+            capacity: self.capacity,
+            padding: self.padding,
+            occupancy: self.occupancy.clone(),
+            epoch: self.epoch,
+            growth_events: self.growth_events,
+            proof_cache: None,
+            leaf_index: None,
+            root_observer: None,
+            root_history: None,
+            op_log: None,
+            pruned: self.pruned,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns whether both trees share the same storage.
+    fn shares_levels(first: &MerkleTree, second: &MerkleTree) -> bool {
+        first.levels.shares_storage(&second.levels)
+    }
+
+    #[test]
+    fn forks_diverge_independently() {
+        let values: Vec<u32> = (0..100).collect();
+        let mut parent = MerkleTree::build(&values);
+        let mut fork = parent.fork();
+        assert!(shares_levels(&parent, &fork));
+        assert_eq!(fork.root(), parent.root());
+        assert_eq!(fork.epoch(), parent.epoch());
+
+        let root = parent.root();
+        let proof = parent.get_proof(42);
+        for value in 100..1000_u32 {
+            fork.push(value);
+        }
+        assert_eq!(fork.len(), 1000);
+        assert_eq!(
+            fork.root(),
+            MerkleTree::build(&(0..1000).collect::<Vec<u32>>()).root()
+        );
+        assert_eq!(parent.root(), root);
+        assert_eq!(parent.len(), 100);
+        assert_eq!(parent.get_proof(42), proof);
+        parent.validate().unwrap();
+
+        let fork_root = fork.root();
+        let fork_proof = fork.get_proof(500);
+        parent.push(7_u32);
+        parent.push(8_u32);
+        assert_eq!(fork.root(), fork_root);
+        assert_eq!(fork.get_proof(500), fork_proof);
+        assert!(fork_proof.verify(500_u32));
+        assert!(parent.get_proof(101).verify(8_u32));
+        assert!(!fork.get_proof(101).verify(8_u32));
+        fork.validate().unwrap();
+    }
+
+    #[test]
+    fn writes_copy_shared_storage() {
+        // A full tree: the fork's push grows it, leaving the parent's storage shared with
+        // nothing but itself.
+        let parent = MerkleTree::build(&[1, 2, 3, 4]);
+        let mut fork = parent.fork();
+        fork.push(5);
+        assert!(!shares_levels(&parent, &fork));
+        assert_eq!(parent.occupancy().filter(|&occupied| occupied).count(), 4);
+        assert_eq!(fork.occupancy().filter(|&occupied| occupied).count(), 5);
+
+        // Discarding an untouched fork leaves the parent as it was.
+        let fork = parent.fork();
+        drop(fork);
+        assert_eq!(parent.root(), MerkleTree::build(&[1, 2, 3, 4]).root());
+    }
+}
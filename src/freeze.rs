@@ -0,0 +1,169 @@
+use crate::{MemStore, MerkleProof, MerkleTree, ancestor_index, sibling_index};
+
+/// Immutable view of a `MerkleTree` as of when it was frozen (see `MerkleTree::freeze`).
+/// It shares the tree's storage copy-on-write, so it is cheap to take and to hold, and
+/// it is unaffected by anything done to the tree afterwards: pushes, rewrites or the
+/// tree being dropped. It never reflects a tree shorter than the frozen one: if the
+/// tree is replaced or shrunk, the snapshot keeps answering as of the freeze point.
+#[derive(Clone, Debug)]
+pub struct MerkleSnapshot {
+    levels: MemStore,
+    len: usize,
+    epoch: u64,
+    pruned: usize,
+}
+
+impl MerkleTree {
+    /// Returns an immutable view of the tree as it is now, sharing its storage: freezing
+    /// only copies a handle to it. The first write to the tree after a freeze copies its
+    /// storage, as long as a snapshot holding it is alive.
+    pub fn freeze(&self) -> MerkleSnapshot {
+        MerkleSnapshot {
+            levels: self.levels.clone(),
+            len: self.len(),
+            epoch: self.epoch,
+            pruned: self.pruned,
+        }
+    }
+}
+
+impl MerkleSnapshot {
+    /// Returns the length of the tree when it was frozen.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the tree was empty when it was frozen.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the epoch of the tree when it was frozen.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Returns the root of the tree when it was frozen. If it was empty, the root will be
+    /// `None`.
+    pub fn root(&self) -> Option<u64> {
+        if self.is_empty() {
+            return None;
+        }
+        self.levels.get(self.levels.height() - 1, 0)
+    }
+
+    /// Returns the hash stored at an occupied leaf, or `None` for padding, indices beyond
+    /// the frozen length and pruned leaves.
+    /// * `index` - Index of the leaf.
+    pub fn leaf(&self, index: usize) -> Option<u64> {
+        if index >= self.len || index < self.pruned {
+            return None;
+        }
+        self.levels.get(0, index)
+    }
+
+    /// Returns the height of the tree when it was frozen.
+    pub(crate) fn height(&self) -> usize {
+        self.levels.height()
+    }
+
+    /// Returns the node at the given coordinates, or `None` if they are out of range.
+    /// * `level` - Level of the node.
+    /// * `index` - Index of the node within its level.
+    pub(crate) fn node(&self, level: usize, index: usize) -> Option<u64> {
+        self.levels.get(level, index)
+    }
+
+    /// Returns the amount of leading leaves which were pruned when it was frozen.
+    pub(crate) fn pruned(&self) -> usize {
+        self.pruned
+    }
+
+    /// Creates a `MerkleProof` for a given index, the same as the one the tree returned
+    /// when it was frozen. Invalid indices return a `MerkleProof::Invalid` value.
+    /// * `index` - index value to generate the proof for.
+    pub fn get_proof(&self, index: usize) -> MerkleProof {
+        let Some(root) = self.root() else {
+            return MerkleProof::Invalid;
+        };
+        if self.leaf(index).is_none() {
+            return MerkleProof::Invalid;
+        }
+
+        let nodes = (0..self.levels.height() - 1)
+            .map(|level_n| self.levels[(level_n, sibling_index(ancestor_index(index, level_n)))])
+            .collect();
+        MerkleProof::Proof {
+            index,
+            nodes,
+            root,
+            len: self.len,
+            epoch: self.epoch,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_survive_growth() {
+        let values: Vec<u32> = (0..200).collect();
+        let mut tree = MerkleTree::build(&values[..13]);
+        let snapshot = tree.freeze();
+        let frozen_proof = tree.get_proof(5);
+
+        for &value in &values[13..] {
+            tree.push(value);
+        }
+        assert_eq!(tree.len(), 200);
+
+        assert_eq!(snapshot.len(), 13);
+        assert_eq!(snapshot.epoch(), 0);
+        assert_eq!(snapshot.root(), MerkleTree::build(&values[..13]).root());
+        assert_eq!(snapshot.get_proof(5), frozen_proof);
+        for (index, value) in values[..13].iter().enumerate() {
+            assert!(snapshot.get_proof(index).verify(value));
+        }
+        assert_eq!(snapshot.get_proof(13), MerkleProof::Invalid);
+        assert_eq!(snapshot.leaf(13), None);
+    }
+
+    #[test]
+    fn storage_is_shared_until_written() {
+        let mut tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        let snapshot = tree.freeze();
+        assert!(tree.levels.shares_storage(&snapshot.levels));
+
+        tree.push(6);
+        assert!(!tree.levels.shares_storage(&snapshot.levels));
+        let other = tree.freeze();
+        drop(tree);
+        assert_eq!(other.len(), 6);
+        assert_eq!(snapshot.root(), MerkleTree::build(&[1, 2, 3, 4, 5]).root());
+    }
+
+    #[test]
+    fn snapshots_ignore_rewrites_and_replacement() {
+        let mut tree = MerkleTree::build(&[1, 2, 3, 4]);
+        let snapshot = tree.freeze();
+        let root = snapshot.root();
+
+        // Rewriting a leaf in place, as the tree's wrappers do, leaves the view intact.
+        tree.replace_leaf_hash(2, MerkleTree::PAD_HASH);
+        assert_ne!(tree.root(), root);
+        assert_eq!(snapshot.root(), root);
+        assert!(snapshot.get_proof(2).verify(3));
+
+        // A shorter tree taking its place does not shrink the view either.
+        tree = MerkleTree::build(&[1]);
+        assert_eq!(tree.len(), 1);
+        assert_eq!(snapshot.len(), 4);
+        assert!(snapshot.get_proof(3).verify(4));
+
+        let empty = MerkleTree::build::<u8>(&[]).freeze();
+        assert_eq!(empty.root(), None);
+        assert_eq!(empty.get_proof(0), MerkleProof::Invalid);
+    }
+}
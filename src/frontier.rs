@@ -0,0 +1,220 @@
+use crate::{MemStore, MerkleError, MerkleTree, hash_pair};
+
+/// The roots of the maximal complete subtrees formed by a tree's leaves, together with
+/// its length: the `O(log n)` state needed to keep appending to the tree elsewhere.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frontier {
+    /// Amount of leaves covered by the frontier.
+    pub len: usize,
+    /// Roots of the complete subtrees, one per set bit of `len`, from the largest
+    /// (leftmost) to the smallest.
+    pub nodes: Vec<u64>,
+}
+
+impl MerkleTree {
+    /// Returns the frontier of the tree, from which `from_frontier` builds a tree that
+    /// keeps producing the same roots as this one when the same elements are pushed.
+    pub fn export_frontier(&self) -> Frontier {
+        let len = self.len();
+        let nodes = (0..usize::BITS as usize)
+            .rev()
+            .filter(|&level| len & (1 << level) != 0)
+            .map(|level| self.levels[(level, (len >> level) - 1)])
+            .collect();
+        Frontier { len, nodes }
+    }
+
+    /// Builds a tree out of a frontier, as returned by `export_frontier`. The tree has
+    /// the length and root of the one the frontier was exported from, and pushing
+    /// elements into it produces the same roots as pushing them into the original tree.
+    /// Only the frontier and the nodes along the tree's right edge are held, in
+    /// `O(log n)` memory and time, so the leaves the frontier covers are pruned: `leaf`
+    /// and `node` return `None` for them and the nodes covering only them, `leaves` starts
+    /// after them, `get_proof` returns `MerkleProof::Invalid` and `get_proof_at` fails
+    /// with `MerkleError::Pruned`. Pruned trees can be neither serialized nor snapshotted.
+    /// Fails if the frontier does not hold a node per set bit of its length.
+    /// * `frontier` - The frontier to continue from.
+    pub fn from_frontier(frontier: Frontier) -> Result<MerkleTree, MerkleError> {
+        let Frontier { len, nodes } = frontier;
+        let expected = len.count_ones() as usize;
+        if nodes.len() != expected {
+            return Err(MerkleError::FrontierMismatch {
+                expected,
+                found: nodes.len(),
+            });
+        }
+
+        let capacity = len.next_power_of_two();
+        let height = capacity.ilog2() as usize + 1;
+        let mut levels = MemStore::empty(capacity);
+        levels.prune(len);
+        let mut frontier_nodes = nodes.into_iter();
+        let mut level_nodes = vec![None; height];
+        for level_n in (0..height)
+            .rev()
+            .filter(|&level_n| len & (1 << level_n) != 0)
+        {
+            level_nodes[level_n] = frontier_nodes.next();
+        }
+
+        // The frontier nodes stand for the pruned leaves, and only the nodes to their
+        // right which cover some of those leaves are hashed out of them: every other node
+        // roots padding only.
+        for (level_n, node) in level_nodes.into_iter().enumerate() {
+            if let Some(node) = node {
+                levels.set(level_n, (len >> level_n) - 1, node);
+            }
+            if level_n > 0 && len % (1 << level_n) != 0 {
+                let index = len >> level_n;
+                let node = hash_pair(
+                    levels[(level_n - 1, 2 * index)],
+                    levels[(level_n - 1, 2 * index + 1)],
+                );
+                levels.set(level_n, index, node);
+            }
+        }
+
+        let mut tree = MerkleTree::from_store(levels, capacity, capacity - len);
+        tree.pruned = len;
+        Ok(tree)
+    }
+
+    /// Returns the amount of leading leaves whose hashes are not held by the tree, since
+    /// it was built out of a frontier. It is zero for every other tree.
+    pub fn pruned_len(&self) -> usize {
+        self.pruned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleProof;
+
+    #[test]
+    fn split_appends_match_single_build() {
+        let values: Vec<u32> = (0..1000).collect();
+        let expected = MerkleTree::build(&values);
+
+        for split in [0, 1, 2, 3, 8, 100, 511, 512, 513, 999, 1000] {
+            let first = MerkleTree::build(&values[..split]);
+            let frontier = first.export_frontier();
+            assert_eq!(frontier.nodes.len(), split.count_ones() as usize);
+
+            let mut second = MerkleTree::from_frontier(frontier).unwrap();
+            assert_eq!(second.len(), split);
+            assert_eq!(second.root(), first.root());
+
+            second.extend(&values[split..]);
+            assert_eq!(second.len(), 1000);
+            assert_eq!(second.root(), expected.root(), "split at {split}");
+            assert_eq!(second.pruned_len(), split);
+            second.validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn proofs_only_for_appended_leaves() {
+        let values: Vec<u32> = (0..20).collect();
+        let mut tree =
+            MerkleTree::from_frontier(MerkleTree::build(&values[..13]).export_frontier()).unwrap();
+        tree.extend(&values[13..]);
+        let mut expected = MerkleTree::build(&values[..13]);
+        expected.extend(&values[13..]);
+
+        for index in 0..13 {
+            assert!(tree.get_proof(index) == MerkleProof::Invalid);
+            assert_eq!(tree.leaf(index), None);
+            assert_eq!(
+                tree.get_proof_at(index, 20),
+                Err(MerkleError::Pruned { index, pruned: 13 })
+            );
+        }
+        for (index, value) in values.iter().enumerate().skip(13) {
+            assert!(tree.get_proof(index) == expected.get_proof(index));
+            assert!(tree.get_proof(index).verify(value));
+            assert!(tree.get_proof_at(index, index + 1) == expected.get_proof_at(index, index + 1));
+        }
+    }
+
+    #[test]
+    fn only_the_right_edge_held() {
+        let values: Vec<u32> = (0..100_000).collect();
+        let full = MerkleTree::build(&values);
+        let tree = MerkleTree::from_frontier(full.export_frontier()).unwrap();
+        assert_eq!(tree.root(), full.root());
+
+        // Of the nodes covering the pruned leaves, at most two per level are held.
+        let held = (0..tree.height())
+            .flat_map(|level| (0..tree.level_len(level).unwrap()).map(move |index| (level, index)))
+            .filter(|&(level, index)| index << level < tree.len())
+            .filter(|&(level, index)| tree.node(level, index).is_some())
+            .count();
+        assert!(held <= 2 * tree.height(), "{held} nodes held");
+        assert!(tree.leaves().is_empty());
+        for (level, index) in [(0, 0), (0, 99_999), (1, 0), (15, 0)] {
+            assert_eq!(tree.node(level, index), None, "node ({level}, {index})");
+        }
+        assert_eq!(tree.raw_leaf(100_000), Some(MerkleTree::PAD_HASH));
+    }
+
+    #[test]
+    fn huge_frontiers_stay_small() {
+        let len = (1 << 40) + 5;
+        let frontier = Frontier {
+            len,
+            nodes: vec![1, 2, 3],
+        };
+        let mut tree = MerkleTree::from_frontier(frontier).unwrap();
+        assert_eq!(tree.capacity(), 1 << 41);
+        let stats = tree.stats();
+        assert!(stats.heap_bytes < 64 * 1024, "{stats}");
+        assert_eq!(tree.is_padded(len - 1), Some(false));
+        assert_eq!(tree.is_padded(len), Some(true));
+
+        tree.extend(&[1, 2, 3]);
+        assert!(tree.get_proof(len + 2).verify(3));
+        assert!(tree.stats().heap_bytes < 64 * 1024, "{}", tree.stats());
+    }
+
+    #[test]
+    fn pruned_leaves_not_exposed() {
+        let values: Vec<u32> = (0..20).collect();
+        let continued = |prefix: &[u32]| {
+            let mut tree =
+                MerkleTree::from_frontier(MerkleTree::build(prefix).export_frontier()).unwrap();
+            tree.extend(&values[13..]);
+            tree
+        };
+        let tree = continued(&values[..13]);
+        let full = MerkleTree::build(&values);
+
+        assert_eq!(tree.leaves(), &full.leaves()[13..]);
+        assert_eq!(tree.fork().into_leaf_hashes(), tree.leaves());
+        assert_eq!(tree.get(12), None);
+        assert_eq!(tree.get(13), full.get(13));
+        assert_eq!(tree.get_range(13..20), full.get_range(13..20));
+        assert!(tree.iter_occupied().map(|(index, _)| index).eq(13..20));
+
+        assert!(tree == continued(&values[..13]));
+        assert!(tree != full);
+        let other = continued(&[7; 13]);
+        assert_eq!(other.leaves(), tree.leaves());
+        assert!(tree != other);
+    }
+
+    #[test]
+    fn mismatched_frontier_rejected() {
+        let frontier = Frontier {
+            len: 3,
+            nodes: vec![1],
+        };
+        assert_eq!(
+            MerkleTree::from_frontier(frontier).err(),
+            Some(MerkleError::FrontierMismatch {
+                expected: 2,
+                found: 1
+            })
+        );
+    }
+}
@@ -0,0 +1,109 @@
+/// Identifies the hash function used to build a tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    /// The standard library's `DefaultHasher`.
+    DefaultHasher,
+}
+
+impl HashAlgorithm {
+    /// Returns the byte used to identify the algorithm in canonical encodings.
+    pub fn id(&self) -> u8 {
+        match self {
+            HashAlgorithm::DefaultHasher => 0,
+        }
+    }
+
+    /// Returns the algorithm identified by the given byte, or `None` if it is unknown.
+    /// * `id` - The byte returned by `id`.
+    pub fn from_id(id: u8) -> Option<HashAlgorithm> {
+        match id {
+            0 => Some(HashAlgorithm::DefaultHasher),
+            _ => None,
+        }
+    }
+}
+
+/// Bundles a tree's root together with the context needed to safely verify
+/// proofs against it: the amount of elements the tree held and the algorithm
+/// used to hash them.
+/// The head also records the tree's epoch, which identifies the mutation that
+/// produced it but plays no part in proof verification.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TreeHead {
+    pub root: u64,
+    pub len: usize,
+    pub algo: HashAlgorithm,
+    pub epoch: u64,
+}
+
+impl TreeHead {
+    /// Returns the canonical byte encoding of the head, suitable for signing
+    /// or logging.
+    /// The layout is the algorithm id (1 byte), followed by the length, the
+    /// root and the epoch, all as big-endian `u64` values.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(25);
+        bytes.push(self.algo.id());
+        bytes.extend_from_slice(&(self.len as u64).to_be_bytes());
+        bytes.extend_from_slice(&self.root.to_be_bytes());
+        bytes.extend_from_slice(&self.epoch.to_be_bytes());
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{HashAlgorithm, MerkleTree};
+
+    #[test]
+    fn head_of_populated_tree() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let head = tree.head().unwrap();
+        assert_eq!(head.root, tree.root().unwrap());
+        assert_eq!(head.len, 3);
+    }
+
+    #[test]
+    fn algorithm_id_round_trip() {
+        let algo = HashAlgorithm::DefaultHasher;
+        assert_eq!(HashAlgorithm::from_id(algo.id()), Some(algo));
+        assert_eq!(HashAlgorithm::from_id(1), None);
+    }
+
+    #[test]
+    fn head_of_empty_tree() {
+        assert!(MerkleTree::build::<u8>(&[]).head().is_none());
+    }
+
+    #[test]
+    fn proof_verifies_against_head() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        let head = tree.head().unwrap();
+        assert!(tree.get_proof(4).verify_against(&head, 5));
+        assert!(!tree.get_proof(4).verify_against(&head, 4));
+    }
+
+    #[test]
+    fn proof_not_verifies_against_head_with_wrong_len() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        let mut head = tree.head().unwrap();
+
+        // Same root, different length.
+        head.len = 6;
+        assert!(!tree.get_proof(4).verify_against(&head, 5));
+    }
+
+    #[test]
+    fn canonical_encoding() {
+        let mut tree = MerkleTree::build(&[1, 2]);
+        tree.push(3);
+        let head = tree.head().unwrap();
+        let bytes = head.to_bytes();
+
+        assert_eq!(bytes.len(), 25);
+        assert_eq!(bytes[0], 0);
+        assert_eq!(bytes[1..9], 3_u64.to_be_bytes());
+        assert_eq!(bytes[9..17], head.root.to_be_bytes());
+        assert_eq!(bytes[17..25], 1_u64.to_be_bytes());
+    }
+}
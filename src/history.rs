@@ -0,0 +1,333 @@
+use std::collections::VecDeque;
+
+use crate::{
+    MerkleError, MerkleProof, MerkleTree, NodeStore, ancestor_index, empty_nodes, hash_pair,
+    sibling_index,
+};
+
+/// Ring buffer of the most recent roots of a tree, oldest first.
+pub(crate) struct RootHistory {
+    max_entries: usize,
+    entries: VecDeque<(u64, u64)>,
+}
+
+impl RootHistory {
+    /// Records the root produced by the mutation at the given epoch, evicting the oldest
+    /// entry if the history is full.
+    fn record(&mut self, epoch: u64, root: u64) {
+        if self.max_entries == 0 {
+            return;
+        }
+        if self.entries.len() == self.max_entries {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((epoch, root));
+    }
+}
+
+impl MerkleTree {
+    /// Enables a history of the last `max_entries` roots of the tree, each with the epoch
+    /// of the mutation that produced it. Mutations leaving the root untouched are not
+    /// recorded. The current root, if any, is recorded right away.
+    /// Enabling the history again replaces the previous one.
+    /// * `max_entries` - The maximum amount of roots to be kept.
+    pub fn keep_root_history(&mut self, max_entries: usize) {
+        let mut history = RootHistory {
+            max_entries,
+            entries: VecDeque::with_capacity(max_entries),
+        };
+        if let Some(root) = self.root() {
+            history.record(self.epoch, root);
+        }
+        self.root_history = Some(history);
+    }
+
+    /// Disables the root history, releasing its memory.
+    pub fn disable_root_history(&mut self) {
+        self.root_history = None;
+    }
+
+    /// Returns the recorded `(epoch, root)` pairs, newest first. The iterator is empty if
+    /// the history is disabled.
+    pub fn root_history(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.root_history
+            .iter()
+            .flat_map(|history| history.entries.iter().rev().copied())
+    }
+
+    /// Returns the root the tree had at the given epoch, if it is still in the history.
+    /// Epochs whose mutation left the root untouched resolve to the root recorded before
+    /// them, while epochs older than the oldest recorded entry resolve to `None`.
+    /// * `epoch` - The epoch whose root is returned.
+    pub fn root_at_epoch(&self, epoch: u64) -> Option<u64> {
+        let history = self.root_history.as_ref()?;
+        let position = history
+            .entries
+            .partition_point(|&(recorded, _)| recorded <= epoch);
+        let (_, root) = history.entries.get(position.checked_sub(1)?)?;
+        Some(*root)
+    }
+
+    /// Returns the root the tree had when it only held its first `len` elements, that is,
+    /// the root of `MerkleTree::build` over those elements. Stored nodes are reused for
+    /// every subtree fully included in the prefix, so only the nodes along the prefix's
+    /// boundary are recomputed, in O(log n).
+    /// Returns `None` if `len` is 0 (empty trees have no root), greater than the tree's
+    /// length or lower than the amount of pruned leaves (see `from_frontier`).
+    /// * `len` - Length of the prefix.
+    pub fn root_at(&self, len: usize) -> Option<u64> {
+        if len == 0 || len > self.len() {
+            return None;
+        }
+        let height = len.next_power_of_two().ilog2() as usize + 1;
+        let empty = empty_nodes(height);
+        self.prefix_node(height - 1, 0, len, &empty)
+    }
+
+    /// Returns the proof of inclusion of a leaf as of when the tree only held its first
+    /// `len` elements: its sibling path and root are the ones of `MerkleTree::build` over
+    /// those elements, so it verifies against `root_at(len)`. The proof's epoch is the
+    /// tree's current one.
+    /// Fails if `len` exceeds the tree's length, if the index is not below `len` or if the
+    /// leaf was pruned (see `from_frontier`).
+    /// * `index` - Index of the leaf.
+    /// * `len` - Length of the prefix.
+    pub fn get_proof_at(&self, index: usize, len: usize) -> Result<MerkleProof, MerkleError> {
+        if len > self.len() {
+            return Err(MerkleError::LengthOutOfRange {
+                len,
+                tree_len: self.len(),
+            });
+        }
+        if index >= len {
+            return Err(MerkleError::IndexOutOfRange { index, len });
+        }
+        if index < self.pruned {
+            return Err(MerkleError::Pruned {
+                index,
+                pruned: self.pruned,
+            });
+        }
+
+        let height = len.next_power_of_two().ilog2() as usize + 1;
+        let empty = empty_nodes(height);
+        // Prefixes holding a leaf whose hash is held never reach into the pruned nodes.
+        let node = |level_n, index| {
+            self.prefix_node(level_n, index, len, &empty)
+                .expect("Prefixes past the pruned leaves are held")
+        };
+        let nodes = (0..height - 1)
+            .map(|level_n| node(level_n, sibling_index(ancestor_index(index, level_n))))
+            .collect();
+
+        Ok(MerkleProof::Proof {
+            index,
+            nodes,
+            root: node(height - 1, 0),
+            len,
+            epoch: self.epoch,
+        })
+    }
+
+    /// Returns the node at the given coordinates of the tree built over the first `len`
+    /// elements, or `None` if it depends on pruned nodes (see `from_frontier`), which is
+    /// the case whenever `len` is lower than the amount of pruned leaves.
+    /// * `level` - Level of the node.
+    /// * `index` - Index of the node within its level.
+    /// * `len` - Length of the prefix.
+    /// * `empty` - Hashes of padding only subtrees, per level.
+    fn prefix_node(&self, level: usize, index: usize, len: usize, empty: &[u64]) -> Option<u64> {
+        let first_leaf = index << level;
+        let end_leaf = (index + 1) << level;
+        if len < self.pruned {
+            None
+        } else if end_leaf <= len {
+            (index >= self.levels.first(level)).then(|| self.levels[(level, index)])
+        } else if first_leaf >= len {
+            Some(empty[level])
+        } else {
+            Some(hash_pair(
+                self.prefix_node(level - 1, 2 * index, len, empty)?,
+                self.prefix_node(level - 1, 2 * index + 1, len, empty)?,
+            ))
+        }
+    }
+}
+
+impl<S: NodeStore> MerkleTree<S> {
+    /// Records the current root in the history, if enabled. Called through
+    /// `notify_root_change` whenever the root changes.
+    pub(crate) fn record_root(&mut self) {
+        let epoch = self.epoch;
+        let root = self.top_node();
+        if let Some(history) = &mut self.root_history {
+            history.record(epoch, root);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{MerkleError, MerkleTree};
+
+    #[test]
+    fn root_at_matches_built_prefixes() {
+        let elements: Vec<u32> = (0..37).collect();
+        let tree = MerkleTree::build(&elements);
+        for len in 1..=elements.len() {
+            assert_eq!(
+                tree.root_at(len),
+                MerkleTree::build(&elements[..len]).root(),
+                "prefix of {len} elements"
+            );
+        }
+        assert_eq!(tree.root_at(37), tree.root());
+    }
+
+    #[test]
+    fn root_at_after_pushes() {
+        let mut tree = MerkleTree::build(&[0]);
+        let mut roots = vec![tree.root()];
+        for value in 1..20 {
+            tree.push(value);
+            roots.push(tree.root());
+        }
+        for len in 1..=20 {
+            assert_eq!(tree.root_at(len), roots[len - 1]);
+        }
+    }
+
+    #[test]
+    fn root_at_out_of_range() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        assert_eq!(tree.root_at(0), None);
+        assert_eq!(tree.root_at(4), None);
+    }
+
+    #[test]
+    fn root_at_pruned_prefixes() {
+        let elements: Vec<u32> = (0..40).collect();
+        let mut tree =
+            MerkleTree::from_frontier(MerkleTree::build(&elements[..13]).export_frontier())
+                .unwrap();
+        tree.extend(&elements[13..]);
+        for len in 1..13 {
+            assert_eq!(tree.root_at(len), None, "prefix of {len} elements");
+        }
+        for len in 13..=elements.len() {
+            assert_eq!(
+                tree.root_at(len),
+                MerkleTree::build(&elements[..len]).root(),
+                "prefix of {len} elements"
+            );
+        }
+    }
+
+    #[test]
+    fn historical_proofs_verify() {
+        let elements: Vec<u32> = (100..113).collect();
+        let tree = MerkleTree::build(&elements);
+
+        for len in [5, 8, 13] {
+            let prefix = MerkleTree::build(&elements[..len]);
+            for (index, &element) in elements[..len].iter().enumerate() {
+                let proof = tree.get_proof_at(index, len).unwrap();
+                assert!(proof.verify(element));
+                assert!(!proof.verify(element + 1));
+                assert!(proof == prefix.get_proof(index));
+            }
+        }
+    }
+
+    #[test]
+    fn historical_proofs_out_of_range() {
+        let elements: Vec<u32> = (100..113).collect();
+        let tree = MerkleTree::build(&elements);
+
+        assert_eq!(
+            tree.get_proof_at(7, 5),
+            Err(MerkleError::IndexOutOfRange { index: 7, len: 5 })
+        );
+        assert_eq!(
+            tree.get_proof_at(0, 0),
+            Err(MerkleError::IndexOutOfRange { index: 0, len: 0 })
+        );
+        assert_eq!(
+            tree.get_proof_at(0, 14),
+            Err(MerkleError::LengthOutOfRange {
+                len: 14,
+                tree_len: 13
+            })
+        );
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut tree = MerkleTree::build(&[0]);
+        tree.keep_root_history(4);
+
+        let mut roots = vec![(tree.epoch(), tree.root().unwrap())];
+        for value in 1..10 {
+            tree.push(value);
+            roots.push((tree.epoch(), tree.root().unwrap()));
+        }
+
+        let expected: Vec<(u64, u64)> = roots.iter().rev().take(4).copied().collect();
+        assert_eq!(tree.root_history().collect::<Vec<_>>(), expected);
+        assert_eq!(expected[0].0, 9);
+    }
+
+    #[test]
+    fn root_at_epoch() {
+        let mut tree = MerkleTree::build::<u8>(&[]);
+        tree.keep_root_history(8);
+        assert_eq!(tree.root_history().count(), 0);
+
+        let mut roots = Vec::new();
+        for value in 0..5 {
+            tree.push(value);
+            roots.push(tree.root().unwrap());
+        }
+
+        for (epoch, root) in roots.iter().enumerate() {
+            assert_eq!(tree.root_at_epoch(epoch as u64 + 1), Some(*root));
+        }
+        assert_eq!(tree.root_at_epoch(0), None);
+        assert_eq!(tree.root_at_epoch(6), Some(roots[4]));
+    }
+
+    #[test]
+    fn evicted_epochs_not_resolved() {
+        let mut tree = MerkleTree::build::<u8>(&[]);
+        tree.keep_root_history(2);
+        for value in 0..5 {
+            tree.push(value);
+        }
+
+        assert_eq!(tree.root_at_epoch(3), None);
+        assert!(tree.root_at_epoch(4).is_some());
+        assert_eq!(tree.root_at_epoch(5), tree.root());
+    }
+
+    #[test]
+    fn unchanged_root_not_recorded() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        tree.keep_root_history(4);
+        tree.push_hash(MerkleTree::PAD_HASH);
+
+        assert_eq!(tree.root_history().count(), 1);
+        assert_eq!(tree.root_at_epoch(1), tree.root());
+    }
+
+    #[test]
+    fn disabling_frees_history() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        tree.keep_root_history(4);
+        tree.push(4);
+        tree.disable_root_history();
+
+        assert!(tree.root_history.is_none());
+        assert_eq!(tree.root_history().count(), 0);
+        assert_eq!(tree.root_at_epoch(1), None);
+    }
+}
@@ -0,0 +1,107 @@
+use std::cell::Cell;
+
+/// Amount of hashes computed, split by kind.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HashOps {
+    /// Hashes of single values, i.e. leaves.
+    pub leaf: u64,
+    /// Hashes combining two nodes.
+    pub pair: u64,
+}
+
+thread_local! {
+    static COUNTERS: Cell<HashOps> = const { Cell::new(HashOps { leaf: 0, pair: 0 }) };
+    static COMPARISONS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Records the computation of a leaf hash.
+pub(crate) fn count_leaf() {
+    COUNTERS.with(|counters| {
+        let mut ops = counters.get();
+        ops.leaf += 1;
+        counters.set(ops);
+    });
+}
+
+/// Records the computation of a pair hash.
+pub(crate) fn count_pair() {
+    COUNTERS.with(|counters| {
+        let mut ops = counters.get();
+        ops.pair += 1;
+        counters.set(ops);
+    });
+}
+
+/// Records the comparison of two nodes' hashes.
+pub(crate) fn count_comparison() {
+    COMPARISONS.with(|comparisons| comparisons.set(comparisons.get() + 1));
+}
+
+/// Returns the amount of hashes computed by the crate on the current thread since it
+/// started or since the last call to `reset_counters`.
+pub fn hash_ops() -> HashOps {
+    COUNTERS.with(Cell::get)
+}
+
+/// Returns the amount of node hashes compared by the crate on the current thread (see
+/// `MerkleTree::diff`) since it started or since the last call to `reset_counters`.
+pub fn node_comparisons() -> u64 {
+    COMPARISONS.with(Cell::get)
+}
+
+/// Resets the hash and comparison counters of the current thread to zero.
+pub fn reset_counters() {
+    COUNTERS.with(|counters| counters.set(HashOps::default()));
+    COMPARISONS.with(|comparisons| comparisons.set(0));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn build_hashes_every_node_once() {
+        reset_counters();
+        MerkleTree::build(&[1, 2, 3, 4, 5]);
+        // Nodes rooting padding only are read from the empty nodes instead.
+        assert_eq!(hash_ops(), HashOps { leaf: 5, pair: 6 });
+
+        reset_counters();
+        assert_eq!(hash_ops(), HashOps::default());
+    }
+
+    #[test]
+    fn push_performs_one_pair_hash_per_level() {
+        // Trees of capacity 2^k with room for one more leaf.
+        for k in 2..10 {
+            let mut tree = MerkleTree::build(&vec![0; (1 << k) - 1]);
+            assert_eq!(tree.capacity(), 1 << k);
+            reset_counters();
+            tree.push(1);
+            assert_eq!(hash_ops(), HashOps { leaf: 1, pair: k });
+        }
+    }
+
+    #[test]
+    fn duplicate_capacity_hashes_only_the_root() {
+        for k in 0..10 {
+            let mut tree = MerkleTree::build(&vec![0; 1 << k]);
+            reset_counters();
+            tree.duplicate_capacity();
+            // Only the new root is hashed: the empty subtree is read from the table of
+            // empty nodes.
+            assert_eq!(hash_ops(), HashOps { leaf: 0, pair: 1 });
+            assert_eq!(tree.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn verify_hashes_value_and_path() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4]);
+        let proof = tree.get_proof(1);
+        reset_counters();
+        assert!(proof.verify(2));
+        assert_eq!(hash_ops(), HashOps { leaf: 1, pair: 2 });
+    }
+}
@@ -0,0 +1,133 @@
+use std::iter::{Copied, FusedIterator};
+use std::slice;
+
+use crate::MerkleTree;
+
+/// Iterator over the hashes of a tree's occupied leaves, in index order.
+/// Created by `MerkleTree::iter` or by iterating over a `&MerkleTree`.
+pub struct LeafHashes<'a> {
+    inner: Copied<slice::Iter<'a, u64>>,
+}
+
+impl Iterator for LeafHashes<'_> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for LeafHashes<'_> {
+    fn next_back(&mut self) -> Option<u64> {
+        self.inner.next_back()
+    }
+}
+
+impl ExactSizeIterator for LeafHashes<'_> {}
+
+impl FusedIterator for LeafHashes<'_> {}
+
+impl MerkleTree {
+    /// Returns an iterator over the hashes of the occupied leaves, in index order.
+    /// Padding is never yielded.
+    pub fn iter(&self) -> LeafHashes<'_> {
+        LeafHashes {
+            inner: self.leaves().iter().copied(),
+        }
+    }
+}
+
+impl MerkleTree {
+    /// Returns an iterator over the occupied leaves as `(index, hash)` pairs, in
+    /// ascending index order. Pruned leaves (see `from_frontier`) are skipped.
+    /// Unlike `iter`, it follows the occupancy bitmap instead of assuming the occupied
+    /// leaves form a prefix of the first level, so it is the iterator to rely on whenever
+    /// the index of each leaf matters, and the one serialization and proof generation
+    /// over every leaf are built on. Only the occupied slots are visited, never the
+    /// padding.
+    pub fn iter_occupied(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.occupancy
+            .iter_from(self.pruned)
+            .map(|index| (index, self.levels[(0, index)]))
+    }
+}
+
+impl<'a> IntoIterator for &'a MerkleTree {
+    type Item = u64;
+    type IntoIter = LeafHashes<'a>;
+
+    fn into_iter(self) -> LeafHashes<'a> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Frontier, MerkleTree};
+
+    #[test]
+    fn iterates_over_leaves() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let collected: Vec<u64> = (&tree).into_iter().collect();
+        assert_eq!(collected, tree.leaves());
+
+        let mut reversed: Vec<u64> = tree.iter().rev().collect();
+        reversed.reverse();
+        assert_eq!(reversed, tree.leaves());
+    }
+
+    #[test]
+    fn iteration_after_growth_skips_padding() {
+        let mut tree = MerkleTree::build(&[1, 2, 3, 4]);
+        tree.push(5);
+        assert_eq!(tree.capacity(), 8);
+
+        let mut iter = tree.iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.next_back(), tree.leaf(4));
+
+        let mut count = 0;
+        for leaf in &tree {
+            assert_eq!(Some(leaf), tree.leaf(count));
+            count += 1;
+        }
+        assert_eq!(count, 5);
+    }
+
+    #[test]
+    fn empty_tree_yields_nothing() {
+        let tree = MerkleTree::build::<u8>(&[]);
+        assert_eq!(tree.iter().len(), 0);
+        assert_eq!((&tree).into_iter().next(), None);
+        assert_eq!(tree.iter_occupied().next(), None);
+    }
+
+    #[test]
+    fn iter_occupied_yields_indices_in_order() {
+        let mut tree = MerkleTree::build(&[1, 2, 3, 4]);
+        tree.push(5);
+
+        let occupied: Vec<(usize, u64)> = tree.iter_occupied().collect();
+        let expected: Vec<(usize, u64)> = tree.iter().enumerate().collect();
+        assert_eq!(occupied, expected);
+        assert_eq!(occupied.last().map(|&(index, _)| index), Some(4));
+    }
+
+    #[test]
+    fn iter_occupied_skips_pruned_leaves_and_padding() {
+        let len = (1 << 40) + 5;
+        let frontier = Frontier {
+            len,
+            nodes: vec![1, 2, 3],
+        };
+        let mut tree = MerkleTree::from_frontier(frontier).unwrap();
+        tree.extend(&[1, 2, 3]);
+        let occupied: Vec<(usize, u64)> = tree.iter_occupied().collect();
+        let expected: Vec<(usize, u64)> = (len..).zip(tree.iter()).collect();
+        assert_eq!(occupied, expected);
+    }
+}
@@ -0,0 +1,142 @@
+use std::borrow::Cow;
+use std::ops::{Index, IndexMut};
+use std::sync::Arc;
+
+/// Storage of a level of a `MerkleTree` in its own allocation, as trees stored their
+/// levels before `MemStore` laid them out in a single one. Only kept as the reference the
+/// tests of `MemStore` compare it with.
+/// Only the nodes up to the frontier of real data are stored: every node beyond them
+/// roots a subtree filled with padding only, so it holds the level's empty node and is
+/// read from it instead. Writing beyond the stored nodes materializes the ones up to the
+/// written index.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Level {
+    /// The stored nodes, a prefix of the level.
+    nodes: Arc<Vec<u64>>,
+    /// Amount of nodes of the level, stored or not.
+    width: usize,
+    /// Node rooting a subtree of padding only at this level.
+    empty: u64,
+}
+
+impl Level {
+    /// Creates a level out of its nodes, dropping the trailing ones equal to the empty
+    /// node of the level.
+    /// * `nodes` - Every node of the level.
+    /// * `empty` - The empty node of the level.
+    /// * `keep` - Amount of leading nodes to be stored anyway.
+    pub(crate) fn new(mut nodes: Vec<u64>, empty: u64, keep: usize) -> Level {
+        let width = nodes.len();
+        let stored = nodes
+            .iter()
+            .rposition(|&node| node != empty)
+            .map_or(0, |last| last + 1)
+            .max(keep.min(width));
+        nodes.truncate(stored);
+        nodes.shrink_to_fit();
+        Level {
+            nodes: Arc::new(nodes),
+            width,
+            empty,
+        }
+    }
+
+    /// Returns the amount of nodes of the level, stored or not.
+    pub(crate) fn len(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the node at an index, or `None` if it is out of range.
+    pub(crate) fn get(&self, index: usize) -> Option<u64> {
+        (index < self.width).then(|| self[index])
+    }
+
+    /// Returns the stored nodes, a prefix of the level.
+    pub(crate) fn stored(&self) -> &[u64] {
+        &self.nodes
+    }
+
+    /// Returns every node of the level, only copying them if some are not stored.
+    pub(crate) fn to_cow(&self) -> Cow<'_, [u64]> {
+        if self.nodes.len() == self.width {
+            Cow::Borrowed(&self.nodes)
+        } else {
+            Cow::Owned(self.iter().collect())
+        }
+    }
+
+    /// Returns an iterator over every node of the level.
+    pub(crate) fn iter(&self) -> impl DoubleEndedIterator<Item = u64> + ExactSizeIterator + '_ {
+        (0..self.width).map(|index| self[index])
+    }
+
+    /// Changes the amount of nodes of the level. New nodes are empty, and nodes beyond
+    /// the new width are dropped.
+    /// * `width` - The new amount of nodes.
+    pub(crate) fn resize(&mut self, width: usize) {
+        if width < self.nodes.len() {
+            Arc::make_mut(&mut self.nodes).truncate(width);
+        }
+        self.width = width;
+    }
+}
+
+impl Index<usize> for Level {
+    type Output = u64;
+
+    /// Panics if the index is out of range.
+    #[track_caller]
+    fn index(&self, index: usize) -> &u64 {
+        assert!(
+            index < self.width,
+            "index {index} is out of range for a level of {} nodes",
+            self.width
+        );
+        self.nodes.get(index).unwrap_or(&self.empty)
+    }
+}
+
+impl IndexMut<usize> for Level {
+    /// Materializes the nodes up to the index, and copies them first if the level is
+    /// shared. Panics if the index is out of range.
+    #[track_caller]
+    fn index_mut(&mut self, index: usize) -> &mut u64 {
+        assert!(
+            index < self.width,
+            "index {index} is out of range for a level of {} nodes",
+            self.width
+        );
+        let nodes = Arc::make_mut(&mut self.nodes);
+        if index >= nodes.len() {
+            nodes.resize(index + 1, self.empty);
+        }
+        &mut nodes[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_nodes_not_stored() {
+        let mut level = Level::new(vec![1, 2, 0, 3, 0, 0, 0, 0], 0, 0);
+        assert_eq!(level.stored(), [1, 2, 0, 3]);
+        assert_eq!(level.len(), 8);
+        assert_eq!(level.get(6), Some(0));
+        assert_eq!(level.get(8), None);
+        assert!(matches!(level.to_cow(), Cow::Owned(_)));
+
+        level[5] = 4;
+        assert_eq!(level.stored(), [1, 2, 0, 3, 0, 4]);
+        assert_eq!(level.iter().collect::<Vec<_>>(), [1, 2, 0, 3, 0, 4, 0, 0]);
+        level.resize(16);
+        assert_eq!(level.stored().len(), 6);
+        level.resize(2);
+        assert_eq!(level.iter().collect::<Vec<_>>(), [1, 2]);
+        assert!(matches!(level.to_cow(), Cow::Borrowed([1, 2])));
+
+        // Leading nodes are kept on request, even if empty.
+        assert_eq!(Level::new(vec![0, 0, 0], 0, 2).stored(), [0, 0]);
+    }
+}
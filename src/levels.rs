@@ -0,0 +1,853 @@
+use std::borrow::Cow;
+use std::ops::{Index, Range};
+use std::sync::{Arc, OnceLock};
+
+use crate::{NodeStore, empty_node, empty_node_table, hash_pair};
+
+/// Region of `MemStore::nodes` holding the stored nodes of a level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Span {
+    /// Position of the level's first held node.
+    offset: usize,
+    /// Index of the level's first held node. The nodes before it only cover pruned
+    /// leaves (see `MerkleTree::from_frontier`), so they are not held at all.
+    first: usize,
+    /// Index past the level's last stored node: the nodes from `first` up to it are
+    /// stored.
+    stored: usize,
+    /// Amount of positions reserved for the level, so that it can store more nodes
+    /// without moving the other levels.
+    reserved: usize,
+    /// Amount of nodes of the level, stored or not.
+    width: usize,
+}
+
+impl Span {
+    /// Returns the position of a held node within `MemStore::nodes`.
+    /// * `index` - Index of the node within its level, at least `first`.
+    fn position(&self, index: usize) -> usize {
+        self.offset + index - self.first
+    }
+
+    /// Returns the positions of the stored nodes within `MemStore::nodes`.
+    fn held(&self) -> Range<usize> {
+        self.offset..self.position(self.stored)
+    }
+}
+
+/// The default `NodeStore` of a `MerkleTree`, holding every level in memory in a
+/// single allocation, the leaves first and the root last. Each level takes a region of
+/// it, with room for more nodes, so filling a level rarely moves the others: when one
+/// runs out of room, every region is given twice the room it needs and the allocation
+/// is laid out again.
+/// Only the nodes up to the frontier of real data are stored: every node beyond them
+/// roots a subtree filled with padding only, so it holds the level's empty node (see
+/// `empty_node`) and is read from it instead. Writing beyond the stored nodes
+/// materializes the ones up to the written index. Likewise, the nodes covering only
+/// the leaves pruned from a tree (see `MerkleTree::from_frontier`) are not held at all,
+/// except for the ones the others are hashed out of.
+/// The allocation is shared copy-on-write between the tree and the snapshots taken from
+/// it (see `freeze`): cloning the levels only clones a handle, and the nodes are only
+/// copied when shared levels are written to.
+/// In leaves-only mode (see `MerkleTree::keep_leaves_only`), the levels between the
+/// leaves and the top cached ones are not stored at all. Their nodes are hashed out of
+/// the leaves when read by value, and the first time one is borrowed every level is
+/// computed and kept until the tree forgets it.
+#[derive(Clone, Debug, Default)]
+pub struct MemStore {
+    nodes: Arc<Vec<u64>>,
+    /// Region of each level, bottom-up.
+    spans: Vec<Span>,
+    /// Amount of top levels stored in leaves-only mode, or `None` if every level is.
+    cached: Option<usize>,
+    /// Every level, stored or not, in leaves-only mode once a node which is not stored
+    /// has been borrowed. Writes keep it up to date, until pushes forget it.
+    materialized: OnceLock<Box<MemStore>>,
+}
+
+impl MemStore {
+    /// Stores levels, dropping the trailing nodes of each one that equal the empty node
+    /// of the level.
+    /// * `levels` - Every node of every level, bottom-up.
+    /// * `keep` - Amount of leading leaves to be stored anyway.
+    pub(crate) fn new(levels: Vec<Vec<u64>>, keep: usize) -> MemStore {
+        let widths = levels.iter().map(Vec::len).collect();
+        MemStore::from_prefixes(levels, widths, keep)
+    }
+
+    /// Stores the leading nodes of levels, like `new`: every node beyond them holds the
+    /// empty node of its level, so the padding is never materialized.
+    /// Panics if a level holds more nodes than its width.
+    /// * `prefixes` - The leading nodes of every level, bottom-up.
+    /// * `widths` - The amount of nodes of every level, stored or not.
+    /// * `keep` - Amount of leading leaves to be stored anyway, if given.
+    pub(crate) fn from_prefixes(
+        prefixes: Vec<Vec<u64>>,
+        widths: Vec<usize>,
+        keep: usize,
+    ) -> MemStore {
+        let mut nodes = Vec::new();
+        let mut spans = Vec::with_capacity(prefixes.len());
+        for (level_n, (level, width)) in prefixes.iter().zip(widths).enumerate() {
+            assert!(level.len() <= width, "level {level_n} exceeds its width");
+            let empty = empty_node(level_n);
+            let keep = if level_n == 0 {
+                keep.min(level.len())
+            } else {
+                0
+            };
+            let stored = level
+                .iter()
+                .rposition(|&node| node != empty)
+                .map_or(0, |last| last + 1)
+                .max(keep);
+            spans.push(Span {
+                offset: nodes.len(),
+                first: 0,
+                stored,
+                reserved: stored,
+                width,
+            });
+            nodes.extend_from_slice(&level[..stored]);
+        }
+        nodes.shrink_to_fit();
+        MemStore {
+            nodes: Arc::new(nodes),
+            spans,
+            cached: None,
+            materialized: OnceLock::new(),
+        }
+    }
+
+    /// Lays out the levels of a tree with every node empty, storing none.
+    /// * `capacity` - The capacity of the tree, a power of two.
+    pub(crate) fn empty(capacity: usize) -> MemStore {
+        let height = capacity.trailing_zeros() as usize + 1;
+        MemStore {
+            nodes: Arc::new(Vec::new()),
+            spans: (0..height)
+                .map(|level_n| Span {
+                    offset: 0,
+                    first: 0,
+                    stored: 0,
+                    reserved: 0,
+                    width: capacity >> level_n,
+                })
+                .collect(),
+            cached: None,
+            materialized: OnceLock::new(),
+        }
+    }
+
+    /// Returns the amount of levels.
+    pub(crate) fn height(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// Returns the amount of nodes of a level, stored or not.
+    /// Panics if the level is out of range.
+    pub(crate) fn width(&self, level: usize) -> usize {
+        self.spans[level].width
+    }
+
+    /// Returns the node at the given coordinates, or `None` if they are out of range or
+    /// the node is pruned. Nodes which are not stored in leaves-only mode are hashed out
+    /// of the leaves below them, unless every level has been materialized.
+    pub(crate) fn get(&self, level: usize, index: usize) -> Option<u64> {
+        let span = self.spans.get(level)?;
+        if index >= span.width || index < span.first {
+            return None;
+        }
+        match self.materialized.get() {
+            Some(materialized) => materialized.get(level, index),
+            None => Some(self.compute(level, index)),
+        }
+    }
+
+    /// Returns the stored nodes of a level, from its first held one (see `first`). Levels
+    /// which are not stored in leaves-only mode hold none.
+    /// Panics if the level is out of range.
+    pub(crate) fn stored(&self, level: usize) -> &[u64] {
+        &self.nodes[self.spans[level].held()]
+    }
+
+    /// Returns the index of the first held node of a level: the ones before it are
+    /// pruned.
+    /// Panics if the level is out of range.
+    pub(crate) fn first(&self, level: usize) -> usize {
+        self.spans[level].first
+    }
+
+    /// Returns every node of a level, or `None` if it is out of range. The nodes are
+    /// only copied if some are not stored, and pruned ones read as the level's empty node.
+    pub(crate) fn level(&self, level: usize) -> Option<Cow<'_, [u64]>> {
+        let span = self.spans.get(level)?;
+        Some(if span.first == 0 && span.stored == span.width {
+            Cow::Borrowed(self.stored(level))
+        } else {
+            Cow::Owned(self.iter_level(level).collect())
+        })
+    }
+
+    /// Returns an iterator over every node of a level.
+    /// Panics if the level is out of range.
+    pub(crate) fn iter_level(
+        &self,
+        level: usize,
+    ) -> impl DoubleEndedIterator<Item = u64> + ExactSizeIterator + '_ {
+        (0..self.spans[level].width).map(move |index| self[(level, index)])
+    }
+
+    /// Doubles the width of every level and adds a level on top, holding a new root.
+    /// In leaves-only mode, the level leaving the cached ones stops being stored.
+    /// * `root` - The new root.
+    pub(crate) fn grow(&mut self, root: u64) {
+        let uncached = self.uncached();
+        for span in &mut self.spans {
+            span.width *= 2;
+        }
+        let nodes = Arc::make_mut(&mut self.nodes);
+        self.spans.push(Span {
+            offset: nodes.len(),
+            first: 0,
+            stored: 1,
+            reserved: 1,
+            width: 1,
+        });
+        nodes.push(root);
+        for level_n in uncached.end..self.uncached().end {
+            self.spans[level_n].stored = 0;
+        }
+        if let Some(materialized) = self.materialized.get_mut() {
+            materialized.grow(root);
+        }
+    }
+
+    /// Keeps the lowest levels, with the widths of a tree of that height, and drops the
+    /// others. In leaves-only mode, the levels joining the cached ones are hashed out of
+    /// the leaves.
+    /// * `height` - The amount of levels to be kept.
+    pub(crate) fn shrink(&mut self, height: usize) {
+        let uncached = self.uncached();
+        self.spans.truncate(height);
+        for (level_n, span) in self.spans.iter_mut().enumerate() {
+            span.width = span.width.min(1 << (height - 1 - level_n));
+            span.stored = span.stored.min(span.width);
+            span.first = span.first.min(span.stored);
+        }
+        let end = self
+            .spans
+            .last()
+            .map_or(0, |span| span.offset + span.reserved);
+        Arc::make_mut(&mut self.nodes).truncate(end);
+        if let Some(materialized) = self.materialized.get_mut() {
+            materialized.shrink(height);
+        }
+
+        // Only the levels below them are read, so they are filled bottom-up.
+        for level_n in self.uncached().end..uncached.end.min(height) {
+            let stored = self.spans[0].stored.div_ceil(1 << level_n);
+            for index in 0..stored {
+                let node = hash_pair(
+                    self.compute(level_n - 1, 2 * index),
+                    self.compute(level_n - 1, 2 * index + 1),
+                );
+                self.set(level_n, index, node);
+            }
+        }
+    }
+
+    /// Returns the amount of top levels stored in leaves-only mode, or `None` if every
+    /// level is.
+    pub(crate) fn cached(&self) -> Option<usize> {
+        self.cached
+    }
+
+    /// Switches between storing every level and leaves-only mode, computing the levels
+    /// which become stored out of the leaves and dropping the others.
+    /// * `cached` - Amount of top levels to be stored in leaves-only mode, or `None` to
+    ///   store every level.
+    pub(crate) fn set_cached(&mut self, cached: Option<usize>) {
+        let mut levels = match self.materialized.take() {
+            Some(materialized) => *materialized,
+            None => self.materialize(),
+        };
+        levels.cached = cached;
+        let uncached = levels.uncached();
+        let mut nodes = Vec::new();
+        for (level_n, span) in levels.spans.iter_mut().enumerate() {
+            let offset = nodes.len();
+            if uncached.contains(&level_n) {
+                span.stored = span.first;
+            } else {
+                nodes.extend_from_slice(&levels.nodes[span.held()]);
+            }
+            *span = Span {
+                offset,
+                reserved: span.stored - span.first,
+                ..*span
+            };
+        }
+        levels.nodes = Arc::new(nodes);
+        *self = levels;
+    }
+
+    /// Drops the materialized levels, if any, so that writes stop keeping them up to
+    /// date.
+    pub(crate) fn forget_materialized(&mut self) {
+        self.materialized.take();
+    }
+
+    /// Returns the lowest level above the leaves whose nodes must be rewritten when a
+    /// leaf changes. In leaves-only mode, the levels below the cached ones are only
+    /// hashed when read, unless they have been materialized.
+    pub(crate) fn lowest_kept(&self) -> usize {
+        match self.materialized.get() {
+            Some(_) => 1,
+            None => self.uncached().end,
+        }
+    }
+
+    /// Writes the node at the given coordinates. Writes to levels which are not stored
+    /// in leaves-only mode only reach the materialized levels, if any. Writing a pruned
+    /// node holds the level's nodes from it on again, the ones in between reading as
+    /// the level's empty node until written.
+    /// Panics if the coordinates are out of range.
+    #[track_caller]
+    pub(crate) fn set(&mut self, level: usize, index: usize, node: u64) {
+        let span = self.spans[level];
+        assert!(
+            index < span.width,
+            "index {index} is out of range for a level of {} nodes",
+            span.width
+        );
+        if let Some(materialized) = self.materialized.get_mut() {
+            materialized.set(level, index, node);
+        }
+        if self.uncached().contains(&level) {
+            return;
+        }
+
+        if index < span.first || index >= span.first + span.reserved {
+            // Room for twice the held nodes, which only start at the first one.
+            let first = span.first.min(index);
+            self.reserve(level, index, first + 2 * (index + 1 - first));
+        }
+        let span = &mut self.spans[level];
+        let nodes = Arc::make_mut(&mut self.nodes);
+        if index >= span.stored {
+            let empty = empty_node(level);
+            nodes[span.position(span.stored)..span.position(index)].fill(empty);
+            span.stored = index + 1;
+        }
+        nodes[span.position(index)] = node;
+    }
+
+    /// Writes consecutive nodes of a level, laying the levels out again at most once.
+    /// Panics if the nodes do not fit in the level.
+    /// * `level` - Level of the nodes.
+    /// * `first` - Index of the first node within its level.
+    /// * `nodes` - The nodes to be written.
+    #[track_caller]
+    fn fill(&mut self, level: usize, first: usize, nodes: &[u64]) {
+        let span = self.spans[level];
+        let end = first + nodes.len();
+        assert!(
+            end <= span.width,
+            "nodes up to {end} are out of range for a level of {} nodes",
+            span.width
+        );
+        if let Some(materialized) = self.materialized.get_mut() {
+            materialized.fill(level, first, nodes);
+        }
+        if self.uncached().contains(&level) || nodes.is_empty() {
+            return;
+        }
+
+        if first < span.first || end > span.first + span.reserved {
+            self.reserve(level, first, end);
+        }
+        let span = &mut self.spans[level];
+        let stored = Arc::make_mut(&mut self.nodes);
+        if first > span.stored {
+            stored[span.position(span.stored)..span.position(first)].fill(empty_node(level));
+        }
+        stored[span.position(first)..span.position(end)].copy_from_slice(nodes);
+        span.stored = span.stored.max(end);
+    }
+
+    /// Returns the leaves in a range of indices. Panics if they are not stored.
+    /// * `range` - Indices of the leaves to be returned.
+    pub(crate) fn into_leaves(self, range: Range<usize>) -> Vec<u64> {
+        let first = self.spans.first().map_or(0, |span| {
+            assert!(span.first <= range.start && span.stored >= range.end);
+            span.first
+        });
+        // The leaves come first.
+        let mut leaves = Arc::unwrap_or_clone(self.nodes);
+        leaves.truncate(range.end - first);
+        leaves.drain(..range.start - first);
+        leaves
+    }
+
+    /// Drops the nodes covering only leading leaves, keeping the ones which the other
+    /// nodes are hashed out of: the left siblings of the nodes covering later leaves.
+    /// Nodes which are already pruned stay so.
+    /// * `pruned` - Amount of leading leaves to be pruned.
+    pub(crate) fn prune(&mut self, pruned: usize) {
+        self.forget_materialized();
+        let mut nodes = Vec::new();
+        for (level_n, span) in self.spans.iter_mut().enumerate() {
+            let first = ((pruned >> level_n) & !1).max(span.first);
+            let stored = span.stored.max(first);
+            let offset = nodes.len();
+            if first < span.stored {
+                nodes.extend_from_slice(&self.nodes[span.position(first)..span.position(stored)]);
+            }
+            *span = Span {
+                offset,
+                first,
+                stored,
+                reserved: stored - first,
+                width: span.width,
+            };
+        }
+        self.nodes = Arc::new(nodes);
+    }
+
+    /// Returns the heap memory allocated by the levels: the shared allocation holding
+    /// the reference counts and the vector, plus the vector's nodes and the regions.
+    /// Materialized levels count as well.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        2 * size_of::<usize>()
+            + size_of::<Vec<u64>>()
+            + self.nodes.capacity() * size_of::<u64>()
+            + self.spans.capacity() * size_of::<Span>()
+            + self.materialized.get().map_or(0, |materialized| {
+                size_of::<MemStore>() + materialized.heap_bytes()
+            })
+    }
+
+    /// Returns whether both trees share the same storage.
+    #[cfg(test)]
+    pub(crate) fn shares_storage(&self, other: &MemStore) -> bool {
+        Arc::ptr_eq(&self.nodes, &other.nodes)
+    }
+
+    /// Changes the amount of nodes of a level, regardless of the others, so that tests
+    /// can lay out malformed trees.
+    #[cfg(test)]
+    pub(crate) fn set_width(&mut self, level: usize, width: usize) {
+        let span = &mut self.spans[level];
+        span.width = width;
+        span.stored = span.stored.min(width);
+        span.first = span.first.min(span.stored);
+    }
+
+    /// Returns the levels which are not stored in leaves-only mode: the ones between the
+    /// leaves and the cached ones.
+    fn uncached(&self) -> Range<usize> {
+        match self.cached {
+            Some(cached) => 1..self.height().saturating_sub(cached).max(1),
+            None => 1..1,
+        }
+    }
+
+    /// Returns the node at the given coordinates, hashing it out of the stored nodes
+    /// below it if it is not stored itself.
+    fn compute(&self, level: usize, index: usize) -> u64 {
+        if !self.uncached().contains(&level) {
+            return self[(level, index)];
+        }
+        // Subtrees beyond the stored leaves hold padding only.
+        if index << level >= self.spans[0].stored {
+            return empty_node(level);
+        }
+        hash_pair(
+            self.compute(level - 1, 2 * index),
+            self.compute(level - 1, 2 * index + 1),
+        )
+    }
+
+    /// Returns every level, stored or not, out of the stored ones.
+    fn materialize(&self) -> MemStore {
+        let uncached = self.uncached();
+        let mut nodes: Vec<u64> = Vec::new();
+        let mut spans: Vec<Span> = Vec::with_capacity(self.height());
+        for (level_n, span) in self.spans.iter().enumerate() {
+            let offset = nodes.len();
+            // Leaves-only mode is never used on pruned trees, so the levels which are not
+            // stored start at their first node.
+            let (first, stored) = if uncached.contains(&level_n) {
+                let below = spans[level_n - 1];
+                let stored = below.stored.div_ceil(2);
+                for index in 0..stored {
+                    let left = nodes[below.offset + 2 * index];
+                    let right = if 2 * index + 1 < below.stored {
+                        nodes[below.offset + 2 * index + 1]
+                    } else {
+                        empty_node(level_n - 1)
+                    };
+                    nodes.push(hash_pair(left, right));
+                }
+                (0, stored)
+            } else {
+                nodes.extend_from_slice(self.stored(level_n));
+                (span.first, span.stored)
+            };
+            spans.push(Span {
+                offset,
+                first,
+                stored,
+                reserved: stored - first,
+                width: span.width,
+            });
+        }
+        MemStore {
+            nodes: Arc::new(nodes),
+            spans,
+            cached: None,
+            materialized: OnceLock::new(),
+        }
+    }
+
+    /// Lays the levels out again, so that a level has room for its nodes between two
+    /// indices. Every other level is given room for twice the nodes it stores, except
+    /// the ones which are not stored.
+    /// * `level` - The level running out of room.
+    /// * `from` - The first node it holds from then on, if it is currently pruned.
+    /// * `room` - The index up to which it is given room.
+    fn reserve(&mut self, level: usize, from: usize, room: usize) {
+        let uncached = self.uncached();
+        let first = |level_n: usize, span: &Span| {
+            if level_n == level {
+                span.first.min(from)
+            } else {
+                span.first
+            }
+        };
+        let room = |level_n: usize, span: &Span| {
+            if uncached.contains(&level_n) {
+                return 0;
+            }
+            let first = first(level_n, span);
+            let room = if level_n == level {
+                room.max(span.stored) - first
+            } else {
+                2 * (span.stored - first)
+            };
+            room.clamp(1, (span.width - first).max(1))
+        };
+        let total: usize = self
+            .spans
+            .iter()
+            .enumerate()
+            .map(|(level_n, span)| room(level_n, span))
+            .sum();
+        let mut nodes = Vec::with_capacity(total);
+        for (level_n, span) in self.spans.iter_mut().enumerate() {
+            let offset = nodes.len();
+            let first = first(level_n, span);
+            nodes.resize(offset + span.first - first, empty_node(level_n));
+            nodes.extend_from_slice(&self.nodes[span.held()]);
+            span.reserved = room(level_n, span);
+            nodes.resize(offset + span.reserved, 0);
+            span.offset = offset;
+            span.first = first;
+        }
+        self.nodes = Arc::new(nodes);
+    }
+}
+
+impl Index<(usize, usize)> for MemStore {
+    type Output = u64;
+
+    /// Borrowing a node which is not stored in leaves-only mode materializes every level,
+    /// while pruned nodes read as the level's empty node.
+    /// Panics if the coordinates are out of range.
+    #[track_caller]
+    fn index(&self, (level, index): (usize, usize)) -> &u64 {
+        let span = self.spans[level];
+        assert!(
+            index < span.width,
+            "index {index} is out of range for a level of {} nodes",
+            span.width
+        );
+        if self.uncached().contains(&level) {
+            let materialized = self
+                .materialized
+                .get_or_init(|| Box::new(self.materialize()));
+            &materialized[(level, index)]
+        } else if (span.first..span.stored).contains(&index) {
+            &self.nodes[span.position(index)]
+        } else {
+            &empty_node_table()[level]
+        }
+    }
+}
+
+impl NodeStore for MemStore {
+    fn height(&self) -> usize {
+        MemStore::height(self)
+    }
+
+    fn len(&self, level: usize) -> Option<usize> {
+        (level < self.height()).then(|| self.width(level))
+    }
+
+    fn get(&self, level: usize, index: usize) -> Option<u64> {
+        MemStore::get(self, level, index)
+    }
+
+    #[track_caller]
+    fn put(&mut self, level: usize, index: usize, node: u64) {
+        self.set(level, index, node);
+    }
+
+    #[track_caller]
+    fn put_batch(&mut self, level: usize, first: usize, nodes: &[u64]) {
+        self.fill(level, first, nodes);
+    }
+
+    fn reset(&mut self, capacity: usize) {
+        *self = MemStore::empty(capacity);
+    }
+
+    fn grow(&mut self, root: u64) {
+        MemStore::grow(self, root);
+    }
+
+    fn lowest_written(&self) -> usize {
+        self.lowest_kept()
+    }
+
+    fn release_reads(&mut self) {
+        self.forget_materialized();
+    }
+}
+
+/// Compares the nodes of the levels and which ones are pruned, however they are laid
+/// out.
+impl PartialEq for MemStore {
+    fn eq(&self, other: &MemStore) -> bool {
+        self.height() == other.height()
+            && (0..self.height()).all(|level| {
+                self.width(level) == other.width(level)
+                    && self.first(level) == other.first(level)
+                    && self.iter_level(level).eq(other.iter_level(level))
+            })
+    }
+}
+
+impl Eq for MemStore {}
+
+#[cfg(test)]
+mod tests {
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::level::Level;
+    use crate::{LevelCheck, MerkleProof, MerkleTree, generate_tree_levels};
+
+    /// Returns every level of a tree holding the leaves, padded up to the capacity.
+    fn dense_levels(leaves: &[u64], capacity: usize) -> Vec<Vec<u64>> {
+        let mut padded = leaves.to_vec();
+        padded.resize(capacity, MerkleTree::PAD_HASH);
+        let mut levels = Vec::new();
+        generate_tree_levels(&padded, &mut levels);
+        levels
+    }
+
+    /// Checks that every accessor of the tree matches the dense levels.
+    fn check_against_dense(tree: &MerkleTree, dense: &[Vec<u64>]) -> Result<(), TestCaseError> {
+        prop_assert_eq!(tree.height(), dense.len());
+        for (level_n, nodes) in dense.iter().enumerate() {
+            let level = tree.level(level_n).map(Cow::into_owned);
+            prop_assert_eq!(level.as_ref(), Some(nodes));
+            prop_assert_eq!(tree.level_len(level_n), Some(nodes.len()));
+            for (index, &node) in nodes.iter().enumerate() {
+                prop_assert_eq!(tree.node(level_n, index), Some(node));
+            }
+            prop_assert_eq!(tree.node(level_n, nodes.len()), None);
+        }
+        let levels: Vec<Vec<u64>> = tree.levels_iter().map(Cow::into_owned).collect();
+        prop_assert_eq!(&levels[..], dense);
+        prop_assert_eq!(tree.leaves(), &dense[0][..tree.len()]);
+
+        let top = dense.last().unwrap()[0];
+        prop_assert_eq!(tree.root(), (!tree.is_empty()).then_some(top));
+        for index in 0..tree.len() {
+            let MerkleProof::Proof { nodes, .. } = tree.get_proof(index) else {
+                return Err(TestCaseError::fail(format!("leaf {index} is not provable")));
+            };
+            let siblings: Vec<u64> = (0..dense.len() - 1)
+                .map(|level_n| dense[level_n][(index >> level_n) ^ 1])
+                .collect();
+            prop_assert_eq!(nodes, siblings);
+        }
+        Ok(())
+    }
+
+    /// Operation on the storage of a tree's levels.
+    #[derive(Clone, Debug)]
+    enum Op {
+        /// Writes a node, at coordinates taken modulo the shape of the levels.
+        Write {
+            level: usize,
+            index: usize,
+            node: u64,
+        },
+        /// Doubles the capacity, adding the given root.
+        Grow(u64),
+        /// Keeps a height taken modulo the current one.
+        Shrink(usize),
+    }
+
+    fn arb_op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            8 => (any::<usize>(), any::<usize>(), any::<u64>())
+                .prop_map(|(level, index, node)| Op::Write { level, index, node }),
+            1 => any::<u64>().prop_map(Op::Grow),
+            1 => any::<usize>().prop_map(Op::Shrink),
+        ]
+    }
+
+    /// Applies an operation to both layouts.
+    fn apply(op: &Op, flat: &mut MemStore, reference: &mut Vec<Level>) {
+        match *op {
+            Op::Write { level, index, node } => {
+                let level = level % reference.len();
+                let index = index % reference[level].len();
+                flat.set(level, index, node);
+                reference[level][index] = node;
+            }
+            Op::Grow(root) => {
+                flat.grow(root);
+                for level in reference.iter_mut() {
+                    let width = level.len();
+                    level.resize(2 * width);
+                }
+                reference.push(Level::new(vec![root], empty_node(reference.len()), 1));
+            }
+            Op::Shrink(height) => {
+                let height = 1 + height % reference.len();
+                flat.shrink(height);
+                reference.truncate(height);
+                for (level_n, level) in reference.iter_mut().enumerate() {
+                    level.resize(1 << (height - 1 - level_n));
+                }
+            }
+        }
+    }
+
+    /// Checks that both layouts hold the same nodes.
+    fn check_layouts(flat: &MemStore, reference: &[Level]) -> Result<(), TestCaseError> {
+        prop_assert_eq!(flat.height(), reference.len());
+        for (level_n, level) in reference.iter().enumerate() {
+            prop_assert_eq!(flat.width(level_n), level.len());
+            prop_assert!(flat.iter_level(level_n).eq(level.iter()));
+            prop_assert_eq!(flat.level(level_n), Some(level.to_cow()));
+            prop_assert_eq!(flat.get(level_n, level.len()), None);
+        }
+        prop_assert_eq!(flat.get(reference.len(), 0), None);
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn flat_layout_matches_separate_levels(
+            leaves in vec(any::<u64>(), 1..=40),
+            ops in vec(arb_op(), 0..=60),
+        ) {
+            let dense = dense_levels(&leaves, leaves.len().next_power_of_two());
+            let mut flat = MemStore::new(dense.clone(), leaves.len());
+            let mut reference: Vec<Level> = dense
+                .into_iter()
+                .enumerate()
+                .map(|(level_n, nodes)| {
+                    let keep = if level_n == 0 { leaves.len() } else { 0 };
+                    Level::new(nodes, empty_node(level_n), keep)
+                })
+                .collect();
+            check_layouts(&flat, &reference)?;
+            for op in &ops {
+                apply(op, &mut flat, &mut reference);
+                check_layouts(&flat, &reference)?;
+            }
+        }
+
+        #[test]
+        fn reads_match_dense_levels(
+            built in vec(any::<u64>(), 0..=70),
+            pushed in vec(any::<u64>(), 0..=70),
+            cached_levels in proptest::option::of(0_usize..5),
+        ) {
+            let mut leaves = built.clone();
+            let mut tree = MerkleTree::from_leaf_hashes(built);
+            if let Some(cached_levels) = cached_levels {
+                tree.keep_leaves_only(cached_levels).unwrap();
+            }
+            check_against_dense(&tree, &dense_levels(&leaves, tree.capacity()))?;
+            for leaf in pushed {
+                tree.push_hash(leaf);
+                leaves.push(leaf);
+                check_against_dense(&tree, &dense_levels(&leaves, tree.capacity()))?;
+            }
+        }
+
+        #[test]
+        fn restored_sparse_levels_match(
+            leaves in vec(1..=u64::MAX, 1..=40),
+            extra_height in 0_usize..4,
+        ) {
+            let capacity = leaves.len().next_power_of_two() << extra_height;
+            let dense = dense_levels(&leaves, capacity);
+            let mut tree = MerkleTree::from_levels(dense, LevelCheck::Strict).unwrap();
+            prop_assert_eq!(tree.len(), leaves.len());
+            check_against_dense(&tree, &dense_levels(&leaves, capacity))?;
+
+            let mut leaves = leaves;
+            leaves.push(7);
+            tree.push_hash(7);
+            check_against_dense(&tree, &dense_levels(&leaves, tree.capacity()))?;
+        }
+    }
+
+    #[test]
+    fn sparse_trees_store_little() {
+        let leaves: Vec<u64> = (1..=100).collect();
+        let sparse =
+            MerkleTree::from_levels(dense_levels(&leaves, 1 << 16), LevelCheck::Strict).unwrap();
+        assert_eq!(sparse.capacity(), 1 << 16);
+        assert!(sparse.get_proof(99).verify_leaf(100));
+
+        // A dense tree of this capacity takes over a megabyte. Only the paths to the
+        // frontier of the 100 leaves are stored.
+        let stats = sparse.stats();
+        assert_eq!(stats.node_count, (1 << 17) - 1);
+        assert!(stats.heap_bytes < 4096, "{} bytes", stats.heap_bytes);
+    }
+
+    #[test]
+    fn padding_is_not_materialized() {
+        // Five leaves at a capacity of 2^20: dense levels hold about 2M nodes.
+        let mut leaves: Vec<u64> = (1..=5).collect();
+        let dense = dense_levels(&leaves, 1 << 20);
+        let mut tree = MerkleTree::from_levels_unchecked(dense.clone());
+        let stats = tree.stats();
+        assert_eq!((stats.len, stats.padding), (5, (1 << 20) - 5));
+        assert_eq!(stats.node_count, (1 << 21) - 1);
+        assert_eq!(tree.root(), Some(dense[20][0]));
+        assert!(tree.get_proof(4).verify_leaf(5));
+        // Neither the stored nodes nor the occupancy bitmap grow with the padding.
+        assert!(stats.heap_bytes < 4096, "{stats}");
+
+        for leaf in 6..=100 {
+            tree.push_hash(leaf);
+            leaves.push(leaf);
+        }
+        assert_eq!(tree.root(), Some(dense_levels(&leaves, 1 << 20)[20][0]));
+        assert!(tree.stats().heap_bytes < 8192);
+    }
+}
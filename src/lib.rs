@@ -1,9 +1,218 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Mutex, OnceLock};
+
+mod accumulator;
+mod agg;
+#[cfg(feature = "allocator-api2")]
+mod alloc_store;
+mod ancestor;
+#[cfg(feature = "audit")]
+mod audit;
+#[cfg(feature = "borsh")]
+mod borsh_impl;
+mod builder;
+mod cache;
+mod cached_store;
+mod cas;
+#[cfg(feature = "cbor")]
+mod cbor;
+#[cfg(feature = "cdc")]
+mod cdc;
+#[cfg(feature = "rand")]
+mod challenge;
+mod chunk;
+#[cfg(feature = "compact")]
+mod compact;
+mod corrupt;
+mod cursor;
+mod decode;
+mod delta;
+mod diff;
+mod disk;
+mod error;
+mod fault;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod fmt;
+mod forest;
+mod fork;
+mod freeze;
+mod frontier;
+mod head;
+mod history;
+#[cfg(feature = "instrumentation")]
+mod instrument;
+mod iter;
+#[cfg(test)]
+mod level;
+mod levels;
+mod lookup;
+#[cfg(feature = "manifest")]
+mod manifest;
+mod map;
+mod mmr;
+mod observe;
+mod occupancy;
+mod oplog;
+mod ops;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod partial;
+mod persistent;
+mod progress;
+mod proof_ref;
+#[cfg(feature = "proto")]
+mod proto;
+mod protocol;
+#[cfg(feature = "python")]
+mod python;
+mod range;
+#[cfg(feature = "async")]
+mod remote;
+mod render;
+mod restore;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod signing;
+mod snapshot;
+mod sparse;
+mod static_tree;
+mod stats;
+mod storage;
+mod store;
+mod sync;
+#[cfg(any(test, feature = "testing"))]
+mod testing;
+#[cfg(any(test, feature = "testutil"))]
+mod testutil;
+mod trace;
+mod transfer;
+mod trusted;
+mod unordered;
+mod validate;
+#[cfg(feature = "vectors")]
+mod vectors;
+mod verified;
+mod versioned;
+mod visit;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod wire;
+mod writer;
+
+use cache::ProofCache;
+use history::RootHistory;
+use observe::RootObserver;
+use occupancy::Occupancy;
+use oplog::OpLog;
+use progress::Reporter;
+
+pub use accumulator::RootAccumulator;
+pub use agg::{AggMerkleTree, AggProof, Aggregate};
+#[cfg(feature = "allocator-api2")]
+pub use alloc_store::AllocStore;
+pub use ancestor::PathStep;
+#[cfg(feature = "audit")]
+pub use audit::AuditError;
+#[cfg(feature = "borsh")]
+pub use borsh_impl::MAX_PROOF_NODES;
+pub use builder::{BuilderCheckpoint, CheckpointError, MerkleTreeBuilder};
+pub use cache::ProofCacheStats;
+pub use cached_store::{CachedStore, NodeCacheStats};
+pub use cas::CasError;
+#[cfg(feature = "cbor")]
+pub use cbor::CborError;
+#[cfg(feature = "cdc")]
+pub use cdc::{CdcParams, ChunkInfo};
+pub use chunk::{FileMerkle, FileMeta};
+#[cfg(feature = "compact")]
+pub use compact::{COMPACT_VERSION, DecodeError, EncodeError};
+pub use corrupt::CorruptionReport;
+pub use cursor::Cursor;
+pub use decode::{DecodeOptions, LimitError};
+pub use delta::DeltaError;
+pub use diff::DiffReport;
+pub use disk::{DISK_CACHE_ENTRIES, DiskMerkleTree};
+pub use error::MerkleError;
+#[cfg(feature = "ffi")]
+pub use ffi::{
+    MT_ERR_BUFFER_TOO_SMALL, MT_ERR_DECODE, MT_ERR_EMPTY, MT_ERR_INDEX, MT_ERR_NULL, MT_ERR_PANIC,
+    MT_OK, MerkleTreeHandle, mt_build, mt_free, mt_get_proof, mt_proof_verify, mt_root,
+};
+pub use fmt::{ParseError, ROOT_HEX_LEN, parse_root, root_to_hex};
+pub use forest::{ForestProof, MerkleForest};
+pub use freeze::MerkleSnapshot;
+pub use frontier::Frontier;
+pub use head::{HashAlgorithm, TreeHead};
+#[cfg(feature = "instrumentation")]
+pub use instrument::{HashOps, hash_ops, node_comparisons, reset_counters};
+pub use iter::LeafHashes;
+pub use levels::MemStore;
+#[cfg(feature = "manifest")]
+pub use manifest::{Manifest, ManifestEntry, build_manifest};
+pub use map::{KvProof, MerkleMap};
+pub use mmr::{Mmr, MmrProof, mmr_peak_positions, mmr_size};
+pub use oplog::{ReplayError, TreeOp};
+pub use partial::{BitVec, ExtractError, PartialBlock};
+pub use persistent::PersistentMerkleTree;
+pub use progress::{PROGRESS_INTERVAL, Progress};
+pub use proof_ref::ProofRef;
+#[cfg(feature = "proto")]
+pub use proto::{PROTO_SCHEMA, ProtoError, ProtoMerkleProof, ProtoTreeHead};
+pub use protocol::{MAX_SYNC_BATCH, NodeCoord, SyncError, SyncRequest, SyncResponse, SyncSession};
+#[cfg(feature = "python")]
+pub use python::{PyMerkleProof, PyMerkleTree, python_module};
+#[cfg(feature = "async")]
+pub use remote::{AsyncMerkleReader, AsyncNodeStore};
+pub use render::DotOptions;
+pub use restore::LevelCheck;
+pub use signing::{SignedTreeHead, Signer, Verifier};
+pub use snapshot::{MAX_SNAPSHOT_CAPACITY, SNAPSHOT_MAGIC, SNAPSHOT_VERSION, SnapshotError};
+pub use sparse::{MAX_SPARSE_DEPTH, SparseMerkleTree, SparseProof};
+pub use static_tree::StaticMerkleTree;
+pub use stats::TreeStats;
+pub use store::{FileStore, NodeStore};
+pub use sync::SyncStats;
+#[cfg(any(test, feature = "testing"))]
+pub use testing::{arb_proof_for, arb_tampered_proof_for, arb_tree, check_invariants};
+#[cfg(any(test, feature = "testutil"))]
+pub use testutil::{
+    NaivePadding, assert_leaves_match_reference, assert_tree_matches_reference, naive_proof,
+    naive_root, naive_verify,
+};
+pub use transfer::{RemoteNodes, TransferPlan, compute_transfer_plan};
+pub use trusted::{RootMeta, TrustedRoots};
+pub use unordered::UnorderedCommitment;
+pub use validate::ValidationError;
+#[cfg(feature = "vectors")]
+pub use vectors::{
+    TestVector, VECTOR_SIZES, VectorError, VectorProof, generate_vectors, vector_input,
+    vectors_to_json, verify_vectors,
+};
+pub use verified::{ChunkCheck, ChunkMismatch, VerifiedWriter};
+pub use versioned::VersionedMerkleTree;
+pub use visit::{Control, NodeRef, Traversal};
+#[cfg(feature = "wasm")]
+pub use wasm::{WasmMerkleProof, build_tree};
+pub use wire::{HEADER_LEN, Header, OPTIONAL_FLAGS, WireError};
+pub use writer::TreeWriter;
+
+/// Domain separation tag hashed before every leaf value, so that a leaf hash
+/// can never be produced by combining two nodes (and vice versa).
+const LEAF_TAG: u8 = 0;
+
+/// Domain separation tag hashed before every pair of child nodes.
+const NODE_TAG: u8 = 1;
 
 /// Returns the hash of a single value. The value's type must implement
 /// the `Hash` trait.
 fn hash_single<H: Hash>(value: H) -> u64 {
+    #[cfg(feature = "instrumentation")]
+    instrument::count_leaf();
     let mut hasher = DefaultHasher::new();
+    LEAF_TAG.hash(&mut hasher);
     value.hash(&mut hasher);
     hasher.finish()
 }
@@ -11,7 +220,15 @@ fn hash_single<H: Hash>(value: H) -> u64 {
 /// Returns the hash resulting of combining two values.
 /// Both values must implement the `Hash` trait.
 fn hash_pair<H: Hash>(first: H, second: H) -> u64 {
+    #[cfg(feature = "instrumentation")]
+    instrument::count_pair();
+    combine(first, second)
+}
+
+/// Combines two values like `hash_pair`, without counting the hash.
+fn combine<H: Hash>(first: H, second: H) -> u64 {
     let mut hasher = DefaultHasher::new();
+    NODE_TAG.hash(&mut hasher);
     first.hash(&mut hasher);
     second.hash(&mut hasher);
     hasher.finish()
@@ -21,10 +238,14 @@ fn hash_pair<H: Hash>(first: H, second: H) -> u64 {
 /// relative to the child node's level.
 /// Each level of the tree can be thought as a vector of nodes, in this context, an index for
 /// a given level represents the index within this vector.
+/// Levels beyond the bits of the index yield `0` instead of overflowing.
 /// * `index` - The child index.
 /// * `level` - The relative upwards level of the target ancestor.
 fn ancestor_index(index: usize, level: usize) -> usize {
-    index / (2_usize.pow(level as u32)) // Integer division.
+    u32::try_from(level)
+        .ok()
+        .and_then(|level| index.checked_shr(level))
+        .unwrap_or(0)
 }
 
 /// Given a node's index. Returns the index of its sibling node.
@@ -33,42 +254,181 @@ fn ancestor_index(index: usize, level: usize) -> usize {
 /// The sibling node is the node on the same level that shares the same parent.
 /// * `index` - The target node's index.
 fn sibling_index(index: usize) -> usize {
-    if index % 2 == 0 { index + 1 } else { index - 1 }
+    index ^ 1
+}
+
+/// Given a node's index. Returns the indices of its children, in the level below it.
+/// * `index` - The parent node's index.
+fn child_indices(index: usize) -> [usize; 2] {
+    [2 * index, 2 * index + 1]
+}
+
+/// Given a node's index. Returns the index of the node and of each of its ancestors,
+/// level after level, carrying the index upwards with a single shift per level. The
+/// `n`-th item is `ancestor_index(index, n)`, and items past the root are `0`.
+/// * `index` - The starting node's index.
+fn path_indices(index: usize) -> impl Iterator<Item = usize> {
+    std::iter::successors(Some(index), |&index| Some(index >> 1))
+}
+
+/// Returns the amount of nodes in the proofs of a tree of a given length, one per level
+/// below the root of its capacity, the next power of two.
+/// * `len` - The tree's length.
+fn proof_depth(len: usize) -> usize {
+    len.checked_next_power_of_two()
+        .map_or(usize::BITS, usize::ilog2) as usize
+}
+
+/// Returns whether a path of sibling nodes has the shape of the proof of a leaf in a
+/// tree of a given length: the index is below the length, and there is a node per
+/// level below the root of the capacity the length needs.
+/// Trees restored with more capacity than that (see `from_levels`) have longer paths,
+/// whose nodes past that capacity can only be the empty nodes of their levels, so
+/// nothing else can be appended to a path.
+/// * `index` - The leaf's index.
+/// * `len` - The tree's length.
+/// * `nodes` - The siblings along the path, from the leaves' level upwards.
+fn is_consistent_path(index: usize, len: usize, nodes: &[u64]) -> bool {
+    let depth = proof_depth(len);
+    index < len
+        && (depth..=usize::BITS as usize).contains(&nodes.len())
+        && nodes[depth..]
+            .iter()
+            .zip(&empty_node_table()[depth..])
+            .all(|(node, empty)| node == empty)
+}
+
+/// Hashes a leaf up its path, combining it with the sibling of each of its ancestors.
+/// Returns the root the path leads to.
+/// * `index` - The leaf's index.
+/// * `leaf` - The leaf hash.
+/// * `siblings` - The siblings along the path, from the leaves' level upwards.
+fn path_root<I: IntoIterator<Item = u64>>(index: usize, leaf: u64, siblings: I) -> u64 {
+    let mut computed_root = leaf;
+
+    for (ancestor, node) in path_indices(index).zip(siblings) {
+        computed_root = if ancestor.is_multiple_of(2) {
+            hash_pair(computed_root, node)
+        } else {
+            hash_pair(node, computed_root)
+        };
+    }
+
+    computed_root
 }
 
 /// Given the leaves of a tree (the first level of the tree), generates all
 /// its upper levels (ancestors) by computing the hashes of each pair iteratively.
+/// The last node of a level holding an odd amount of them is paired with the empty node
+/// of its level, as if the leaves had been padded with `PAD_HASH` up to a power of two.
 /// * `leaves` - Level 0, the starting leaves.
 /// * `levels` - Vector where the generated levels will be stored.
-fn generate_tree_levels(leaves: &Vec<u64>, levels: &mut Vec<Vec<u64>>) {
+#[cfg(test)]
+fn generate_tree_levels(leaves: &[u64], levels: &mut Vec<Vec<u64>>) {
+    generate_reported_levels(leaves, levels, &mut Reporter::silent())
+        .expect("Silent reporters never cancel");
+}
+
+/// Generates the upper levels of a tree like `generate_tree_levels`, reporting every
+/// node combined.
+/// Fails with `MerkleError::Cancelled` if the reporter's callback cancels the build.
+/// * `leaves` - Level 0, the starting leaves.
+/// * `levels` - Vector where the generated levels will be stored.
+/// * `reporter` - Where the progress is reported.
+fn generate_reported_levels(
+    leaves: &[u64],
+    levels: &mut Vec<Vec<u64>>,
+    reporter: &mut Reporter,
+) -> Result<(), MerkleError> {
     let mut current: Vec<u64> = leaves.to_owned();
     levels.push(current.clone());
 
     while current.len() > 1 {
         let current_len = current.len();
+        let level_n = levels.len();
         let mut next_level = Vec::new();
-        for index in (0..current_len).step_by(2) {
-            let hash = hash_pair(current[index], current[index + 1]);
-            next_level.push(hash);
+        for parent in 0..current_len / 2 {
+            let [left, right] = child_indices(parent);
+            next_level.push(hash_pair(current[left], current[right]));
+            reporter.node_combined(level_n)?;
+        }
+        if !current_len.is_multiple_of(2) {
+            let last = current[current_len - 1];
+            next_level.push(hash_pair(last, empty_node(level_n - 1)));
+            reporter.node_combined(level_n)?;
         }
         current = next_level;
         levels.push(current.clone());
     }
+    Ok(())
+}
+
+/// Returns the hash of a subtree filled with padding only at a level, out of a table
+/// computed once per process. Reading it is not counted as hashing.
+/// * `level` - Level of the subtree's root.
+fn empty_node(level: usize) -> u64 {
+    empty_node_table()[level]
+}
+
+/// Returns the table read by `empty_node`, computing it on the first call.
+fn empty_node_table() -> &'static [u64] {
+    static EMPTY_NODES: OnceLock<Vec<u64>> = OnceLock::new();
+    EMPTY_NODES.get_or_init(|| {
+        let mut nodes = vec![MerkleTree::PAD_HASH];
+        for level_n in 1..=usize::BITS as usize {
+            nodes.push(combine(nodes[level_n - 1], nodes[level_n - 1]));
+        }
+        nodes
+    })
+}
+
+/// Returns the hash of a subtree filled with padding only, for each level from the
+/// leaves up to `height - 1`.
+/// * `height` - Amount of levels to be computed.
+fn empty_nodes(height: usize) -> Vec<u64> {
+    let mut nodes = Vec::with_capacity(height);
+    let mut node = MerkleTree::PAD_HASH;
+    for level_n in 0..height {
+        if level_n > 0 {
+            node = hash_pair(node, node);
+        }
+        nodes.push(node);
+    }
+    nodes
 }
 
 /// Base structure were merkle tree data is stored.
-pub struct MerkleTree {
-    levels: Vec<Vec<u64>>,
+/// Occupancy is tracked explicitly, through the `padding` count and a bitmap of occupied
+/// leaf slots maintained by every mutation, regardless of the hashes the slots hold.
+/// Nothing is ever inferred from a leaf being equal to `PAD_HASH`.
+/// Nodes are held by a `NodeStore`, in memory by default (see `build_in`).
+pub struct MerkleTree<S = MemStore> {
+    levels: S,
     capacity: usize,
     padding: usize,
+    occupancy: Occupancy,
+    epoch: u64,
+    growth_events: u64,
+    proof_cache: Option<Mutex<ProofCache>>,
+    leaf_index: Option<HashMap<u64, usize>>,
+    root_observer: Option<RootObserver>,
+    root_history: Option<RootHistory>,
+    op_log: Option<OpLog>,
+    /// Amount of leading leaves whose hashes are not held, see `from_frontier`.
+    pruned: usize,
 }
 
 /// Contains merkle proof information for later validation.
+#[derive(Clone, PartialEq, Eq)]
 pub enum MerkleProof {
     Proof {
         index: usize,
         nodes: Vec<u64>,
         root: u64,
+        /// Length of the tree at the time the proof was generated.
+        len: usize,
+        /// Epoch of the tree at the time the proof was generated.
+        epoch: u64,
     },
 
     /// Invalid proofs always return false for `proof.verify(value)`.
@@ -77,7 +437,7 @@ pub enum MerkleProof {
 
 impl MerkleTree {
     /// The default `Hash` value that is used as padding.
-    const PAD_HASH: u64 = 0;
+    pub const PAD_HASH: u64 = 0;
 
     /// Constructs a `MerkleTree` and populates it with the provided elements as leaf nodes.
     /// Each leaf node is hashed and stored in the frist level of the tree. Then, each
@@ -85,56 +445,169 @@ impl MerkleTree {
     /// the root node.
     /// * `elements` - array of `Hash` elements used to populate the tree.
     pub fn build<H: Hash>(elements: &[H]) -> MerkleTree {
-        let capacity = elements.len().next_power_of_two();
-        let padding = capacity - elements.len();
-        let padding_vec = vec![MerkleTree::PAD_HASH; padding];
+        MerkleTree::from_leaf_hashes(elements.iter().map(hash_single).collect())
+    }
 
-        // Level 0 hashes
-        let leaves = elements
-            .iter()
-            .map(hash_single)
-            .chain(padding_vec)
-            .collect();
+    /// Constructs a `MerkleTree` out of already hashed leaves, such as the ones returned
+    /// by `leaves()`. The resulting tree is identical to the one built from the original
+    /// elements.
+    /// * `leaves` - The leaf hashes used to populate the tree.
+    pub fn from_leaf_hashes(leaves: Vec<u64>) -> MerkleTree {
+        MerkleTree::from_leaf_hashes_in(MemStore::default(), leaves)
+    }
 
-        let mut levels = Vec::new();
-        generate_tree_levels(&leaves, &mut levels);
+    /// Assembles a tree out of already generated levels, with every other piece of
+    /// state (epoch, caches, etc.) at its initial value.
+    fn from_parts(levels: Vec<Vec<u64>>, capacity: usize, padding: usize) -> MerkleTree {
+        // The occupied leaves are always stored, so that `leaves` can borrow them.
+        let keep = capacity - padding;
+        MerkleTree::from_store(MemStore::new(levels, keep), capacity, padding)
+    }
 
+    /// Returns the hashes of the occupied leaves, in index order. Padding is never included,
+    /// and neither are the leaves pruned from the tree (see `from_frontier`): the hashes
+    /// start at leaf `pruned_len()`.
+    pub fn leaves(&self) -> &[u64] {
+        if self.levels.height() == 0 {
+            return &[];
+        }
+        let first = self.levels.first(0);
+        &self.levels.stored(0)[self.pruned - first..self.len() - first]
+    }
+
+    /// Consumes the tree, returning the hashes of its occupied leaves in index order,
+    /// like `leaves` does.
+    pub fn into_leaf_hashes(mut self) -> Vec<u64> {
+        let held = self.pruned..self.len();
+        std::mem::take(&mut self.levels).into_leaves(held)
+    }
+
+    /// Returns every node of a level (padding included), or `None` if the level is out
+    /// of range. See `node` for the level orientation.
+    /// Nodes rooting subtrees of padding only are not stored, so the level is only
+    /// borrowed if it holds none: otherwise it is copied with them filled in.
+    /// * `level` - The level to be returned.
+    pub fn level(&self, level: usize) -> Option<Cow<'_, [u64]>> {
+        self.levels.level(level)
+    }
+
+    /// Returns an iterator over the levels of the tree, bottom-up: from the leaves
+    /// (padding included) to the root. Levels are borrowed or copied as in `level`.
+    pub fn levels_iter(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = Cow<'_, [u64]>> + ExactSizeIterator {
+        (0..self.height()).map(|level_n| {
+            self.levels
+                .level(level_n)
+                .expect("Levels below the height exist")
+        })
+    }
+}
+
+impl<S: NodeStore> MerkleTree<S> {
+    /// Assembles a tree out of a store already holding its levels, with every other
+    /// piece of state (epoch, caches, etc.) at its initial value.
+    fn from_store(levels: S, capacity: usize, padding: usize) -> MerkleTree<S> {
         MerkleTree {
             levels,
             capacity,
             padding,
+            occupancy: Occupancy::new(capacity, capacity - padding),
+            epoch: 0,
+            growth_events: 0,
+            proof_cache: None,
+            leaf_index: None,
+            root_observer: None,
+            root_history: None,
+            op_log: None,
+            pruned: 0,
         }
     }
 
     /// Returns the height of the tree.
     pub fn height(&self) -> usize {
-        self.levels.len()
+        self.levels.height()
     }
 
     /// Creates a `MerkleProof` for a given index.
     /// Attempting to create a proof for an invalid node (i.e. using an index which
     /// does not correspond to a valid leaf) will return a `MerkleProof::Invalid`
     /// value.
+    /// If the proof cache is enabled, the proof may be served from it.
+    /// Trees missing nodes of the path, which can only be corrupted ones, return a
+    /// `MerkleProof::Invalid` value as well. Use `try_get_proof` to tell the cases apart.
     /// * `index` - index value to generate the proof for.
     pub fn get_proof(&self, index: usize) -> MerkleProof {
-        let is_invalid_index = index >= self.len();
-        if is_invalid_index || self.is_empty() {
-            return MerkleProof::Invalid;
+        self.try_get_proof(index).unwrap_or(MerkleProof::Invalid)
+    }
+
+    /// Creates a `MerkleProof` for a given index, like `get_proof`, failing instead of
+    /// returning a `MerkleProof::Invalid` value. Fails if the index does not hold an
+    /// element, if it is pruned (see `from_frontier`), or if a node of its path is
+    /// missing from a corrupted tree.
+    /// * `index` - index value to generate the proof for.
+    pub fn try_get_proof(&self, index: usize) -> Result<MerkleProof, MerkleError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("get_proof", index).entered();
+        self.get_proof_cached(index)
+    }
+
+    /// Walks the levels of the tree to generate the `MerkleProof` for a given index.
+    /// * `index` - index value to generate the proof for.
+    fn generate_proof(&self, index: usize) -> Result<MerkleProof, MerkleError> {
+        if !self.is_occupied(index) {
+            return Err(MerkleError::IndexOutOfRange {
+                index,
+                len: self.len(),
+            });
+        }
+        if index < self.pruned {
+            return Err(MerkleError::Pruned {
+                index,
+                pruned: self.pruned,
+            });
         }
 
         let mut nodes: Vec<u64> = Vec::new();
 
-        for level_n in 0..self.levels.len() - 1 {
-            let ancestor = ancestor_index(index, level_n);
-            let proof_node_index = sibling_index(ancestor);
-            nodes.push(self.levels[level_n][proof_node_index]);
+        let ancestors = path_indices(index).take(self.height().saturating_sub(1));
+        for (level_n, ancestor) in ancestors.enumerate() {
+            let sibling = sibling_index(ancestor);
+            let node = self.levels.get(level_n, sibling);
+            nodes.push(node.ok_or(MerkleError::Corrupted {
+                level: level_n,
+                index: sibling,
+            })?);
         }
 
-        MerkleProof::Proof {
+        Ok(MerkleProof::Proof {
             nodes,
             index,
-            root: self.root().expect("Non-empty trees always have a root"),
-        }
+            root: self.root().ok_or(MerkleError::Corrupted {
+                level: self.height(),
+                index: 0,
+            })?,
+            len: self.len(),
+            epoch: self.epoch,
+        })
+    }
+
+    /// Returns the `TreeHead` of the tree, bundling its root, length and hash algorithm.
+    /// If the tree is empty, the head will be `None`.
+    pub fn head(&self) -> Option<TreeHead> {
+        Some(TreeHead {
+            root: self.root()?,
+            len: self.len(),
+            algo: HashAlgorithm::DefaultHasher,
+            epoch: self.epoch,
+        })
+    }
+
+    /// Returns the epoch of the tree.
+    /// The epoch starts at 0 and is incremented by every mutating operation, so
+    /// it can be used to cheaply detect whether the tree changed.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
     }
 
     /// Returns the root of the tree. If the tree is empty, the root will be `None`.
@@ -142,7 +615,45 @@ impl MerkleTree {
         if self.is_empty() {
             return None;
         }
-        self.levels.get(self.height() - 1)?.first().copied()
+        self.levels.get(self.height().checked_sub(1)?, 0)
+    }
+
+    /// Returns the hash stored at an occupied leaf.
+    /// Padded slots are not considered leaves, so both them and indices beyond the
+    /// tree's capacity return `None`. Use `raw_leaf` to read padded slots. Pruned leaves
+    /// (see `from_frontier`) return `None` too.
+    /// * `index` - Index of the leaf.
+    pub fn leaf(&self, index: usize) -> Option<u64> {
+        if !self.is_occupied(index) || index < self.pruned {
+            return None;
+        }
+        self.raw_leaf(index)
+    }
+
+    /// Returns the hash stored at any slot of the first level, including padded ones
+    /// (which hold `PAD_HASH`). Indices beyond the tree's capacity return `None`.
+    /// * `index` - Index of the slot.
+    pub fn raw_leaf(&self, index: usize) -> Option<u64> {
+        self.node(0, index)
+    }
+
+    /// Returns the hash of the node at the given coordinates, or `None` if they are out
+    /// of range.
+    /// Levels are numbered bottom-up: level 0 holds the leaves (padding included) and
+    /// level `height() - 1` holds the root. Within a level, nodes are indexed from left
+    /// to right, so the children of node `(level, index)` are `(level - 1, 2 * index)`
+    /// and `(level - 1, 2 * index + 1)`.
+    /// * `level` - Level of the node.
+    /// * `index` - Index of the node within its level.
+    pub fn node(&self, level: usize, index: usize) -> Option<u64> {
+        self.levels.get(level, index)
+    }
+
+    /// Returns the amount of nodes in a level, or `None` if the level is out of range.
+    /// See `node` for the level orientation.
+    /// * `level` - The level to be measured.
+    pub fn level_len(&self, level: usize) -> Option<usize> {
+        self.levels.len(level)
     }
 
     /// Returns the capacity of the tree.
@@ -156,10 +667,14 @@ impl MerkleTree {
     /// The length of the tree is the amount of elements it contains.
     /// It may be different from the tree's capacity.
     pub fn len(&self) -> usize {
-        match self.levels.first() {
-            Option::None => 0_usize,
-            Option::Some(level) => level.len() - self.padding,
-        }
+        self.levels.len(0).map_or(0, |width| width - self.padding)
+    }
+
+    /// Returns whether the leaf slot at the given index holds an element rather than
+    /// padding. Every query about individual slots must go through this method.
+    /// * `index` - Index of the leaf slot.
+    fn is_occupied(&self, index: usize) -> bool {
+        self.occupancy.get(index) == Some(true)
     }
 
     /// Returns wether a tree has no elements or not.
@@ -178,81 +693,193 @@ impl MerkleTree {
     /// subtree of the same height of the current, filled with padding values.
     /// This operation also results in the tree increasing its height by 1 level.
     fn duplicate_capacity(&mut self) {
-        // Generate new nodes.
-        let new_leaves = vec![MerkleTree::PAD_HASH; self.capacity];
-        let mut new_levels = Vec::new();
-        generate_tree_levels(&new_leaves, &mut new_levels);
-
-        // Append new nodes to each level of the tree.
-        for (level_n, level) in new_levels.iter_mut().enumerate() {
-            self.levels[level_n].append(level);
-        }
-
-        // Re-compute root node;
-        let last_level = &self.levels[self.height() - 1];
-        let new_root = hash_pair(last_level[0], last_level[1]);
-        self.levels.push(vec![new_root]);
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!(
+            "duplicate_capacity",
+            old_capacity = self.capacity,
+            new_capacity = self.capacity * 2
+        )
+        .entered();
+        // Every node of a subtree filled with padding only depends on its level, so the
+        // new subtree is not stored: each level reads its nodes from its empty node.
+        let top = self.height() - 1;
+        let new_root = hash_pair(self.top_node(), empty_node(top));
+        self.levels.grow(new_root);
 
         // Update padding;
         self.padding += self.capacity;
 
         // Update capacity;
         self.capacity *= 2;
+        self.occupancy.grow(self.capacity);
+        self.growth_events += 1;
     }
 
     /// Pushes an `Hash` element into the tree.
     /// This will only trigger the update of new node's ancestors.
     /// If the tree does not have enough capacity, more space will be
     /// allocated and its capacity will be doubled.
+    /// Panics if a node of the new leaf's path is missing from a corrupted tree, which
+    /// `try_push` reports instead.
     /// * `value` - The `Hash` value to be added to the tree.
+    #[track_caller]
     pub fn push<H: Hash>(&mut self, value: H) {
+        if let Err(error) = self.try_push(value) {
+            panic!("cannot push into the tree: {error}");
+        }
+    }
+
+    /// Pushes an `Hash` element into the tree, like `push`. Fails without changing the
+    /// tree if a node of the new leaf's path is missing from a corrupted tree.
+    /// * `value` - The `Hash` value to be added to the tree.
+    pub fn try_push<H: Hash>(&mut self, value: H) -> Result<(), MerkleError> {
+        // Growing reads the root through the path of the last leaf, and the new leaf's
+        // path is then made of new nodes.
+        let index = if self.is_full() {
+            self.len() - 1
+        } else {
+            self.len()
+        };
+        self.check_path(index)?;
+        self.push_hash(hash_single(value));
+        Ok(())
+    }
+
+    /// Checks that the nodes read and written along the path of a leaf exist: its
+    /// ancestors, up to the root, and their siblings.
+    /// * `index` - The index of the leaf.
+    fn check_path(&self, index: usize) -> Result<(), MerkleError> {
+        let height = self.height();
+        if height == 0 {
+            return Err(MerkleError::Corrupted { level: 0, index });
+        }
+        let check = |level, index| match self.levels.len(level) {
+            Some(width) if index < width => Ok(()),
+            _ => Err(MerkleError::Corrupted { level, index }),
+        };
+        for (level_n, ancestor) in path_indices(index).enumerate().take(height) {
+            check(level_n, ancestor)?;
+            if level_n + 1 < height {
+                check(level_n, sibling_index(ancestor))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Pushes an already hashed leaf into the tree.
+    /// The leaf is considered occupied even if its hash equals `PAD_HASH`.
+    /// * `leaf` - The leaf hash to be added to the tree.
+    fn push_hash(&mut self, leaf: u64) {
+        let old_root = self.watched_root();
         if self.is_full() {
             self.duplicate_capacity();
         }
 
-        let mut index = self.len();
-        self.levels[0][index] = hash_single(value);
-
-        for level_n in 1..self.levels.len() {
-            let previous_level = &self.levels[level_n - 1];
-            let node = previous_level[index];
-            let sibling_node = previous_level[sibling_index(index)]; // Previous index's sibling.
+        let index = self.len();
+        // Pushes are the write-heavy path of leaves-only mode, which must not pay for
+        // keeping every level up to date.
+        self.levels.release_reads();
+        self.levels.put(0, index, leaf);
+        self.occupancy.set(index);
+        self.index_leaf(leaf, index);
+        self.rehash_path(index);
 
-            let parent_index = ancestor_index(index, 1);
+        self.padding -= 1;
+        self.epoch += 1;
+        self.invalidate_proof_cache();
+        self.notify_root_change(old_root);
+        self.record_op(TreeOp::Push { leaf });
+    }
 
-            self.levels[level_n][parent_index] = if index % 2 == 0 {
-                hash_pair(node, sibling_node)
-            } else {
-                hash_pair(sibling_node, node)
+    /// Recomputes every ancestor of a leaf, after the leaf changed. In leaves-only mode,
+    /// only the cached levels are written, and the lowest of them is hashed out of the
+    /// leaves (see `keep_leaves_only`).
+    /// * `index` - The index of the leaf.
+    fn rehash_path(&mut self, index: usize) {
+        let parents = path_indices(index)
+            .enumerate()
+            .take(self.levels.height())
+            .skip(self.levels.lowest_written());
+        for (level_n, parent_index) in parents {
+            let child = |index| {
+                self.levels
+                    .get(level_n - 1, index)
+                    .expect("Children are within their level")
             };
-
-            index = parent_index;
+            let [left, right] = child_indices(parent_index);
+            let parent = hash_pair(child(left), child(right));
+            self.levels.put(level_n, parent_index, parent);
         }
-
-        self.padding -= 1;
     }
 }
 
 impl MerkleProof {
+    /// Returns the epoch of the tree at the time the proof was generated.
+    /// Invalid proofs have no epoch.
+    pub fn epoch(&self) -> Option<u64> {
+        match self {
+            MerkleProof::Invalid => None,
+            MerkleProof::Proof { epoch, .. } => Some(*epoch),
+        }
+    }
+
     /// Returns whether a given `Hash` value verifies the proof.
+    /// Proofs whose index does not correspond to an occupied leaf of the tree they
+    /// were generated for never verify, nor do proofs holding more or fewer nodes than
+    /// the depth of a tree of their length.
     /// * `value` - The `Hash` value to be tested.
     pub fn verify<H: Hash>(&self, value: H) -> bool {
+        self.verify_leaf(hash_single(value))
+    }
+
+    /// Returns whether an already hashed leaf verifies the proof.
+    /// * `leaf` - The leaf hash to be tested.
+    pub(crate) fn verify_leaf(&self, leaf: u64) -> bool {
         match self {
             MerkleProof::Invalid => false,
-            MerkleProof::Proof { index, nodes, root } => {
-                let mut computed_root = hash_single(value);
+            MerkleProof::Proof {
+                index,
+                nodes,
+                root,
+                len,
+                ..
+            } => {
+                // The path must lead from the leaf up to the root of a tree of the
+                // proof's length: shorter or longer paths, and indices beyond the leaves
+                // the path can address, never verify, whatever their nodes hash to.
+                if !is_consistent_path(*index, *len, nodes) {
+                    return false;
+                }
 
-                for (node_n, &node) in nodes.iter().enumerate() {
-                    let ancestor = ancestor_index(*index, node_n);
+                path_root(*index, leaf, nodes.iter().copied()) == *root
+            }
+        }
+    }
 
-                    computed_root = if ancestor % 2 == 0 {
-                        hash_pair(computed_root, node)
-                    } else {
-                        hash_pair(node, computed_root)
-                    };
-                }
+    /// Returns whether a given `Hash` value verifies the proof, and the proof leads to a
+    /// trusted root.
+    /// * `root` - The trusted root.
+    /// * `value` - The `Hash` value to be tested.
+    pub fn verify_with_root<H: Hash>(&self, root: u64, value: H) -> bool {
+        match self {
+            MerkleProof::Invalid => false,
+            MerkleProof::Proof { root: proven, .. } => *proven == root && self.verify(value),
+        }
+    }
 
-                computed_root == *root
+    /// Returns whether a given `Hash` value verifies the proof against a trusted `TreeHead`.
+    /// Besides the root, the proof must have been generated for a tree of the same length
+    /// and hash algorithm as the one described by the head.
+    /// * `head` - The trusted head of the tree.
+    /// * `value` - The `Hash` value to be tested.
+    pub fn verify_against<H: Hash>(&self, head: &TreeHead, value: H) -> bool {
+        match self {
+            MerkleProof::Invalid => false,
+            MerkleProof::Proof { root, len, .. } => {
+                *root == head.root
+                    && *len == head.len
+                    && head.algo == HashAlgorithm::DefaultHasher
+                    && self.verify(value)
             }
         }
     }
@@ -262,6 +889,173 @@ impl MerkleProof {
 mod tests {
     use super::*;
 
+    #[test]
+    fn ancestor_index_never_overflows() {
+        assert_eq!(ancestor_index(13, 2), 3);
+        assert_eq!(ancestor_index(usize::MAX, 63), 1);
+        assert_eq!(ancestor_index(usize::MAX, 64), 0);
+        assert_eq!(ancestor_index(usize::MAX, usize::MAX), 0);
+    }
+
+    /// Index of the ancestor at a level, dividing by the amount of leaves under it.
+    fn reference_ancestor(index: usize, level: usize) -> usize {
+        2_usize
+            .checked_pow(level as u32)
+            .map_or(0, |width| index / width)
+    }
+
+    /// Index of the other node of the level sharing a parent with the given one.
+    fn reference_sibling(index: usize) -> usize {
+        [index.wrapping_sub(1), index.wrapping_add(1)]
+            .into_iter()
+            .find(|&other| other / 2 == index / 2)
+            .expect("Every node has a sibling")
+    }
+
+    #[test]
+    fn paths_must_match_the_depth() {
+        let values: Vec<u32> = (0..8).collect();
+        let tree = MerkleTree::build(&values);
+        let MerkleProof::Proof { nodes, .. } = tree.get_proof(1) else {
+            panic!("The leaf is occupied");
+        };
+        let proof = |index, nodes: &[u64], len| MerkleProof::Proof {
+            index,
+            nodes: nodes.to_vec(),
+            root: path_root(index, hash_single(1), nodes.iter().copied()),
+            len,
+            epoch: 0,
+        };
+        assert!(proof(1, &nodes, 8).verify(1));
+
+        // Each forged path folds into the root it claims.
+        assert!(!proof(1, &nodes[..2], 8).verify(1));
+        assert!(!proof(1, &[&nodes[..], &[7]].concat(), 8).verify(1));
+        assert!(!proof(9, &nodes, 8).verify(1));
+        assert!(!proof(9, &nodes, 10).verify(1));
+
+        // Only the empty nodes of the levels past the depth may extend a path.
+        let padded = [&nodes[..], &[empty_node(3)]].concat();
+        assert!(proof(1, &padded, 8).verify(1));
+    }
+
+    #[test]
+    fn odd_levels_padded_on_the_fly() {
+        let leaves: Vec<u64> = (1..=3).map(hash_single).collect();
+        let mut levels = Vec::new();
+        generate_tree_levels(&leaves, &mut levels);
+        let pair = hash_pair(leaves[0], leaves[1]);
+        assert_eq!(
+            levels[1],
+            [pair, hash_pair(leaves[2], MerkleTree::PAD_HASH)]
+        );
+        assert_eq!(
+            levels[2],
+            [MerkleTree::from_leaf_hashes(leaves.clone()).root().unwrap()]
+        );
+
+        // Even levels may still lead to odd ones.
+        let six: Vec<u64> = (0..6).map(hash_single).collect();
+        let mut levels = Vec::new();
+        generate_tree_levels(&six, &mut levels);
+        assert_eq!(levels[1].len(), 3);
+        assert_eq!(levels[2][1], hash_pair(levels[1][2], empty_node(1)));
+        assert_eq!(
+            levels[3],
+            [MerkleTree::from_leaf_hashes(six).root().unwrap()]
+        );
+    }
+
+    #[test]
+    fn padding_on_the_fly_matches_padded_leaves() {
+        for len in 1..40 {
+            let leaves: Vec<u64> = (0..len).map(hash_single).collect();
+            let mut levels = Vec::new();
+            generate_tree_levels(&leaves, &mut levels);
+            let tree = MerkleTree::from_leaf_hashes(leaves);
+            assert_eq!(levels.len(), tree.height());
+            assert_eq!(levels.last().unwrap().first().copied(), tree.root());
+        }
+    }
+
+    #[test]
+    fn five_leaf_hashes_build_a_tree() {
+        let leaves: Vec<u64> = (1..=5).map(hash_single).collect();
+        let tree = MerkleTree::from_leaf_hashes(leaves);
+        assert_eq!(tree.root(), MerkleTree::build(&[1, 2, 3, 4, 5]).root());
+    }
+
+    proptest::proptest! {
+        #![proptest_config(proptest::prelude::ProptestConfig::with_cases(4096))]
+
+        #[test]
+        fn index_math_matches_reference(index: usize, level in 0..70_usize) {
+            let path: Vec<usize> = path_indices(index).take(level + 1).collect();
+            for (level_n, &ancestor) in path.iter().enumerate() {
+                proptest::prop_assert_eq!(ancestor, reference_ancestor(index, level_n));
+                proptest::prop_assert_eq!(ancestor, ancestor_index(index, level_n));
+            }
+            proptest::prop_assert_eq!(sibling_index(index), reference_sibling(index));
+            proptest::prop_assert_eq!(sibling_index(sibling_index(index)), index);
+
+            let parent = index / 2;
+            let children: Vec<usize> = (2 * parent..=(2 * parent).saturating_add(2))
+                .filter(|&child| reference_ancestor(child, 1) == parent)
+                .collect();
+            proptest::prop_assert_eq!(&child_indices(parent)[..], &children[..]);
+        }
+
+        #[test]
+        fn proofs_follow_reference_paths(len in 1..300_usize, index: usize) {
+            let values: Vec<usize> = (0..len).collect();
+            let tree = MerkleTree::build(&values);
+            let index = index % len;
+            let MerkleProof::Proof { nodes, .. } = tree.get_proof(index) else {
+                return Err(proptest::test_runner::TestCaseError::fail("leaves are provable"));
+            };
+            let expected: Vec<u64> = (0..tree.height() - 1)
+                .map(|level| {
+                    let sibling = reference_sibling(reference_ancestor(index, level));
+                    tree.node(level, sibling).unwrap()
+                })
+                .collect();
+            proptest::prop_assert_eq!(nodes, expected);
+
+            let mut pushed = MerkleTree::build::<usize>(&[]);
+            values.iter().for_each(|value| pushed.push(value));
+            proptest::prop_assert_eq!(pushed.root(), tree.root());
+            proptest::prop_assert!(tree.get_proof(index).verify(index));
+            assert_tree_matches_reference(&pushed, &values);
+        }
+    }
+
+    #[test]
+    fn absurd_proofs_never_verify() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        let MerkleProof::Proof { nodes, root, .. } = tree.get_proof(4) else {
+            panic!("The leaf is occupied");
+        };
+
+        // An index beyond the leaves the path can address, whose lower bits are genuine.
+        let forged = MerkleProof::Proof {
+            index: usize::MAX - 3,
+            nodes: nodes.clone(),
+            root,
+            len: usize::MAX,
+            epoch: 0,
+        };
+        assert!(!forged.verify(5));
+
+        let deep = MerkleProof::Proof {
+            index: usize::MAX - 1,
+            nodes: vec![7; 1000],
+            root,
+            len: usize::MAX,
+            epoch: 0,
+        };
+        assert!(!deep.verify(5));
+    }
+
     #[test]
     fn build_with_power_of_2_elements() {
         MerkleTree::build(&[1; 1]);
@@ -376,4 +1170,198 @@ mod tests {
         tree.push(3);
         tree.get_proof(9).verify(3);
     }
+
+    #[test]
+    fn push_leaf_equal_to_pad_hash() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        tree.push_hash(MerkleTree::PAD_HASH);
+
+        assert_eq!(tree.len(), 4);
+        assert!(tree.is_full());
+        assert!(matches!(tree.get_proof(3), MerkleProof::Proof { .. }));
+        assert!(tree.get_proof(2).verify(3));
+
+        // The next push must not overwrite the leaf that looks like padding.
+        tree.push(5);
+        assert_eq!(tree.len(), 5);
+        assert_eq!(tree.capacity(), 8);
+        assert!(tree.get_proof(4).verify(5));
+        assert!(tree.get_proof(2).verify(3));
+        assert!(matches!(tree.get_proof(5), MerkleProof::Invalid));
+    }
+
+    #[test]
+    fn proof_for_padded_slot_not_verifies() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let MerkleProof::Proof { nodes, root, .. } = tree.get_proof(2) else {
+            panic!("Expected a valid proof");
+        };
+
+        // A proof claiming a slot past the tree length is rejected outright.
+        let forged = MerkleProof::Proof {
+            index: 3,
+            nodes,
+            root,
+            len: 3,
+            epoch: 0,
+        };
+        assert!(!forged.verify(3));
+    }
+
+    #[test]
+    fn epoch_increments_on_push() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        assert_eq!(tree.epoch(), 0);
+
+        tree.push(4);
+        assert_eq!(tree.epoch(), 1);
+
+        // Pushing beyond capacity is still a single mutation.
+        tree.push(5);
+        assert_eq!(tree.epoch(), 2);
+    }
+
+    #[test]
+    fn epoch_unchanged_by_reads() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        tree.get_proof(1);
+        tree.root();
+        tree.head();
+        tree.len();
+        assert_eq!(tree.epoch(), 0);
+    }
+
+    #[test]
+    fn proof_records_epoch() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        assert_eq!(tree.get_proof(1).epoch(), Some(0));
+
+        tree.push(4);
+        let proof = tree.get_proof(1);
+        assert_eq!(proof.epoch(), Some(tree.epoch()));
+        assert_eq!(tree.head().unwrap().epoch, tree.epoch());
+
+        assert_eq!(tree.get_proof(10).epoch(), None);
+    }
+
+    #[test]
+    fn leaf_matches_element_hash() {
+        let elements = [4, 8, 15, 16, 23];
+        let tree = MerkleTree::build(&elements);
+        for (index, element) in elements.iter().enumerate() {
+            assert_eq!(tree.leaf(index), Some(hash_single(element)));
+        }
+    }
+
+    #[test]
+    fn leaf_of_padded_and_out_of_range_slots() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        assert_eq!(tree.leaf(3), None);
+        assert_eq!(tree.raw_leaf(3), Some(MerkleTree::PAD_HASH));
+        assert_eq!(tree.leaf(4), None);
+        assert_eq!(tree.raw_leaf(4), None);
+        assert_eq!(MerkleTree::build::<u8>(&[]).leaf(0), None);
+    }
+
+    #[test]
+    fn leaf_after_push() {
+        let mut tree = MerkleTree::build(&[1, 2]);
+        tree.push(3);
+        tree.push(4);
+        tree.push(5);
+
+        for (index, element) in [1, 2, 3, 4, 5].iter().enumerate() {
+            assert_eq!(tree.leaf(index), Some(hash_single(element)));
+        }
+        assert_eq!(tree.leaf(5), None);
+        assert_eq!(tree.raw_leaf(7), Some(MerkleTree::PAD_HASH));
+    }
+
+    #[test]
+    fn top_node_is_root() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        assert_eq!(tree.node(tree.height() - 1, 0), tree.root());
+        assert_eq!(tree.level_len(tree.height() - 1), Some(1));
+        assert_eq!(tree.level_len(0), Some(8));
+    }
+
+    #[test]
+    fn node_is_hash_of_children() {
+        let mut tree = MerkleTree::build(&[1; 13]);
+        tree.push(2);
+
+        for level in 0..tree.height() - 1 {
+            for index in 0..tree.level_len(level + 1).unwrap() {
+                let left = tree.node(level, 2 * index).unwrap();
+                let right = tree.node(level, 2 * index + 1).unwrap();
+                assert_eq!(tree.node(level + 1, index), Some(hash_pair(left, right)));
+            }
+        }
+    }
+
+    #[test]
+    fn node_out_of_range() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        assert_eq!(tree.node(0, 4), None);
+        assert_eq!(tree.node(2, 1), None);
+        assert_eq!(tree.node(3, 0), None);
+        assert_eq!(tree.level_len(3), None);
+    }
+
+    #[test]
+    fn leaves_exclude_padding() {
+        let elements = [1, 2, 3, 4, 5];
+        let tree = MerkleTree::build(&elements);
+        let expected: Vec<u64> = elements.iter().map(hash_single).collect();
+
+        assert_eq!(tree.leaves().len(), tree.len());
+        assert_eq!(tree.leaves(), expected);
+        assert_eq!(tree.into_leaf_hashes(), expected);
+        assert!(MerkleTree::build::<u8>(&[]).leaves().is_empty());
+    }
+
+    #[test]
+    fn leaves_after_capacity_growth() {
+        let mut tree = MerkleTree::build(&[1, 2, 3, 4]);
+        tree.push(5);
+        let expected: Vec<u64> = [1, 2, 3, 4, 5].iter().map(hash_single).collect();
+        assert_eq!(tree.leaves(), expected);
+    }
+
+    #[test]
+    fn from_leaf_hashes_round_trip() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        tree.push(4);
+        tree.push(5);
+
+        let rebuilt = MerkleTree::from_leaf_hashes(tree.leaves().to_vec());
+        assert_eq!(rebuilt.root(), tree.root());
+        assert_eq!(rebuilt.capacity(), tree.capacity());
+        assert!(rebuilt.get_proof(4).verify(5));
+    }
+
+    #[test]
+    fn level_accessor() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        assert_eq!(tree.level(0).unwrap().len(), 4);
+        assert_eq!(tree.level(2).as_deref(), Some(&[tree.root().unwrap()][..]));
+        assert_eq!(tree.level(3), None);
+        assert_eq!(tree.levels_iter().count(), tree.height());
+    }
+
+    #[test]
+    fn rebuild_from_iterated_levels() {
+        let elements = [1, 2, 3, 4, 5, 6];
+        let tree = MerkleTree::build(&elements);
+
+        let levels: Vec<Vec<u64>> = tree.levels_iter().map(Cow::into_owned).collect();
+        let capacity = levels[0].len();
+        let rebuilt = MerkleTree::from_parts(levels, capacity, capacity - tree.len());
+
+        assert_eq!(rebuilt.root(), tree.root());
+        for (index, element) in elements.iter().enumerate() {
+            assert!(rebuilt.get_proof(index) == tree.get_proof(index));
+            assert!(rebuilt.get_proof(index).verify(element));
+        }
+    }
 }
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::{MerkleTree, NodeStore, hash_single};
+
+impl MerkleTree {
+    /// Constructs a `MerkleTree` like `build`, with the leaf lookup index enabled.
+    /// * `elements` - array of `Hash` elements used to populate the tree.
+    pub fn build_indexed<H: Hash>(elements: &[H]) -> MerkleTree {
+        let mut tree = MerkleTree::build(elements);
+        tree.enable_index();
+        tree
+    }
+
+    /// Enables a lookup index mapping each leaf hash to the first index holding it, so
+    /// that `contains` and `index_of` run in constant time. The index is kept up to date
+    /// by every mutation, and its memory is only paid for while it is enabled.
+    pub fn enable_index(&mut self) {
+        let mut index = HashMap::with_capacity(self.len());
+        for (position, leaf) in self.iter_occupied() {
+            index.entry(leaf).or_insert(position);
+        }
+        self.leaf_index = Some(index);
+    }
+
+    /// Disables the lookup index, releasing its memory.
+    pub fn disable_index(&mut self) {
+        self.leaf_index = None;
+    }
+
+    /// Returns whether the lookup index is enabled.
+    pub fn is_indexed(&self) -> bool {
+        self.leaf_index.is_some()
+    }
+
+    /// Returns whether a value is committed in the tree.
+    /// Uses the lookup index if enabled, or falls back to a linear scan otherwise.
+    /// * `value` - The `Hash` value to look for.
+    pub fn contains<H: Hash>(&self, value: &H) -> bool {
+        self.index_of(value).is_some()
+    }
+
+    /// Returns the index of the leaf holding a value. If the value was committed more
+    /// than once, the lowest index is returned.
+    /// Uses the lookup index if enabled, or falls back to a linear scan otherwise.
+    /// * `value` - The `Hash` value to look for.
+    pub fn index_of<H: Hash>(&self, value: &H) -> Option<usize> {
+        self.index_of_hash(hash_single(value))
+    }
+
+    /// Ensures a value is committed in the tree, returning the index of its leaf and
+    /// whether it was pushed. If the value is already committed, the tree is left
+    /// untouched, root and epoch included, and the index `index_of` returns is given:
+    /// with duplicates committed before this method was used, such as by `push` or
+    /// `build`, the lowest index holding the value. Otherwise the value is pushed like
+    /// `push` does, and its new index is given.
+    /// Uses the lookup index if enabled, which makes it run in constant time, or falls
+    /// back to a linear scan otherwise. Values held only by pruned leaves (see
+    /// `from_frontier`) are not found, so they are pushed again.
+    /// Panics if the value must be pushed into a corrupted tree, like `push`.
+    /// * `value` - The `Hash` value to be committed.
+    #[track_caller]
+    pub fn get_or_push<H: Hash>(&mut self, value: H) -> (usize, bool) {
+        if let Some(index) = self.index_of_hash(hash_single(&value)) {
+            return (index, false);
+        }
+        self.push(value);
+        (self.len() - 1, true)
+    }
+
+    /// Returns the lowest index of the leaf holding the given hash.
+    /// * `leaf` - The leaf hash to look for.
+    pub(crate) fn index_of_hash(&self, leaf: u64) -> Option<usize> {
+        match &self.leaf_index {
+            Option::Some(index) => index.get(&leaf).copied(),
+            Option::None => self
+                .iter_occupied()
+                .find(|&(_, hash)| hash == leaf)
+                .map(|(position, _)| position),
+        }
+    }
+}
+
+impl<S: NodeStore> MerkleTree<S> {
+    /// Records a newly occupied leaf in the lookup index, if enabled.
+    /// * `leaf` - The leaf hash.
+    /// * `position` - The index of the leaf.
+    pub(crate) fn index_leaf(&mut self, leaf: u64, position: usize) {
+        if let Some(index) = &mut self.leaf_index {
+            index.entry(leaf).or_insert(position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::MerkleTree;
+
+    #[test]
+    fn lookups_with_index() {
+        let mut tree = MerkleTree::build_indexed(&["a", "b", "c"]);
+        assert!(tree.is_indexed());
+        assert!(tree.contains(&"b"));
+        assert_eq!(tree.index_of(&"c"), Some(2));
+        assert!(!tree.contains(&"d"));
+
+        tree.push("d");
+        tree.push("e");
+        assert_eq!(tree.index_of(&"d"), Some(3));
+        assert_eq!(tree.index_of(&"e"), Some(4));
+    }
+
+    #[test]
+    fn duplicates_return_lowest_index() {
+        let mut tree = MerkleTree::build_indexed(&[7, 8, 7]);
+        tree.push(8);
+        assert_eq!(tree.index_of(&7), Some(0));
+        assert_eq!(tree.index_of(&8), Some(1));
+    }
+
+    #[test]
+    fn lookups_without_index_scan_linearly() {
+        let mut tree = MerkleTree::build(&[7, 8, 7]);
+        assert!(!tree.is_indexed());
+        assert_eq!(tree.index_of(&7), Some(0));
+        assert!(!tree.contains(&9));
+
+        tree.push(9);
+        assert_eq!(tree.index_of(&9), Some(3));
+    }
+
+    #[test]
+    fn padding_is_never_found() {
+        let tree = MerkleTree::build_indexed(&[1, 2, 3]);
+        assert_eq!(tree.index_of_hash(MerkleTree::PAD_HASH), None);
+
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        assert_eq!(tree.index_of_hash(MerkleTree::PAD_HASH), None);
+    }
+
+    #[test]
+    fn get_or_push_is_idempotent() {
+        for mut tree in [
+            MerkleTree::build_indexed(&["a", "b"]),
+            MerkleTree::build(&["a", "b"]),
+        ] {
+            assert_eq!(tree.get_or_push("c"), (2, true));
+            let (root, epoch) = (tree.root(), tree.epoch());
+            for _ in 0..3 {
+                assert_eq!(tree.get_or_push("c"), (2, false));
+                assert_eq!(tree.get_or_push("a"), (0, false));
+            }
+            assert_eq!((tree.root(), tree.epoch()), (root, epoch));
+
+            // Novel values still append, interleaved with known ones.
+            for (value, expected) in [("d", (3, true)), ("b", (1, false)), ("e", (4, true))] {
+                assert_eq!(tree.get_or_push(value), expected);
+            }
+            assert_eq!(tree, MerkleTree::build(&["a", "b", "c", "d", "e"]));
+            assert!(tree.get_proof(4).verify("e"));
+        }
+    }
+
+    #[test]
+    fn get_or_push_finds_earlier_duplicates() {
+        let mut tree = MerkleTree::build(&[7, 8, 7]);
+        tree.push(8);
+        tree.enable_index();
+        let root = tree.root();
+        assert_eq!(tree.get_or_push(7), (0, false));
+        assert_eq!(tree.get_or_push(8), (1, false));
+        assert_eq!(tree.root(), root);
+        assert_eq!(tree.get_or_push(9), (4, true));
+        assert_eq!(tree.index_of(&9), Some(4));
+    }
+
+    #[test]
+    fn index_can_be_enabled_later_and_disabled() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        tree.push(4);
+        tree.enable_index();
+        assert_eq!(tree.index_of(&4), Some(3));
+
+        tree.disable_index();
+        assert!(!tree.is_indexed());
+        assert_eq!(tree.index_of(&4), Some(3));
+    }
+}
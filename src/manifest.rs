@@ -0,0 +1,216 @@
+use std::fs::{self, File};
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+use crate::chunk::fill;
+use crate::{MerkleProof, MerkleTree, MerkleTreeBuilder};
+
+/// Length of the chunks file contents are hashed in.
+const CONTENT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// A file committed by a manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Path of the file relative to the manifest's directory, with `/` separators.
+    pub path: String,
+    /// Size of the file, in bytes.
+    pub size: u64,
+    /// Root of the file's contents hashed in 64 KiB chunks, or `None` for empty files.
+    pub content_hash: Option<u64>,
+    /// Index of the file's leaf in the manifest's tree.
+    pub leaf_index: usize,
+}
+
+impl ManifestEntry {
+    /// Returns whether the proof shows that the file is committed by a manifest: the
+    /// leaf is derived from the path, the size and the content hash.
+    /// * `proof` - The proof of the entry's leaf.
+    pub fn verify(&self, proof: &MerkleProof) -> bool {
+        proof.verify((&self.path, self.size, self.content_hash))
+    }
+}
+
+/// Tree committing to every file below a directory, together with the files' entries.
+/// Leaves are sorted by path, so the same directory yields the same root on every machine.
+pub struct Manifest {
+    /// Tree holding a leaf per file, in the order of `entries`.
+    pub tree: MerkleTree,
+    /// The entries of the files, sorted by path.
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Returns the entry of a file and the proof of its leaf, or `None` if the manifest
+    /// does not hold the file.
+    /// * `path` - Path of the file relative to the manifest's directory, with `/`
+    ///   separators.
+    pub fn prove_file(&self, path: &str) -> Option<(&ManifestEntry, MerkleProof)> {
+        let position = self
+            .entries
+            .binary_search_by(|entry| entry.path.as_str().cmp(path))
+            .ok()?;
+        let entry = &self.entries[position];
+        Some((entry, self.tree.get_proof(entry.leaf_index)))
+    }
+}
+
+/// Returns the size and content hash of a file.
+fn hash_contents(path: &Path) -> io::Result<(u64, Option<u64>)> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; CONTENT_CHUNK_SIZE];
+    let mut builder = MerkleTreeBuilder::new();
+    let mut size = 0;
+    loop {
+        let filled = fill(&mut file, &mut buffer)?;
+        if filled == 0 {
+            break;
+        }
+        builder.push(&buffer[..filled]);
+        size += filled as u64;
+        if filled < buffer.len() {
+            break;
+        }
+    }
+    Ok((size, builder.root()))
+}
+
+/// Collects the relative paths of every file below a directory.
+/// * `dir` - The directory being walked.
+/// * `prefix` - The relative path of `dir`, empty for the root.
+/// * `files` - Where the paths are stored.
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().into_string().map_err(|name| {
+            io::Error::new(
+                ErrorKind::InvalidData,
+                format!("file name {name:?} is not valid UTF-8"),
+            )
+        })?;
+        let path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{prefix}/{name}")
+        };
+
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(&entry.path(), &path, files)?;
+        } else if file_type.is_file() {
+            files.push(path);
+        } else {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{path} is neither a regular file nor a directory"),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Builds a manifest committing to every regular file below a directory. Each file's
+/// leaf is derived from its relative path, its size and its content hash, and leaves
+/// are sorted by path. Empty directories are not committed.
+/// Fails instead of skipping anything that could make machines disagree: symlinks and
+/// other special files, names which are not valid UTF-8, and unreadable files or
+/// directories.
+/// * `root_dir` - The directory to be committed.
+pub fn build_manifest<P: AsRef<Path>>(root_dir: P) -> io::Result<Manifest> {
+    let root_dir = root_dir.as_ref();
+    let mut paths = Vec::new();
+    collect_files(root_dir, "", &mut paths)?;
+    paths.sort();
+
+    let mut entries = Vec::with_capacity(paths.len());
+    for (leaf_index, path) in paths.into_iter().enumerate() {
+        let (size, content_hash) = hash_contents(&root_dir.join(&path))?;
+        entries.push(ManifestEntry {
+            path,
+            size,
+            content_hash,
+            leaf_index,
+        });
+    }
+
+    let leaves: Vec<_> = entries
+        .iter()
+        .map(|entry| (&entry.path, entry.size, entry.content_hash))
+        .collect();
+    let tree = MerkleTree::build(&leaves);
+    Ok(Manifest { tree, entries })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates the fixture directory.
+    fn fixture() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("docs/api")).unwrap();
+        fs::create_dir(dir.path().join("empty")).unwrap();
+        fs::write(dir.path().join("README"), b"read me").unwrap();
+        fs::write(dir.path().join("docs/guide.md"), b"# Guide").unwrap();
+        fs::write(dir.path().join("docs/api/index.html"), vec![7; 200_000]).unwrap();
+        fs::write(dir.path().join("docs/api/blank"), b"").unwrap();
+        dir
+    }
+
+    #[test]
+    fn entries_sorted_and_proven() {
+        let dir = fixture();
+        let manifest = build_manifest(dir.path()).unwrap();
+
+        let paths: Vec<&str> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "README",
+                "docs/api/blank",
+                "docs/api/index.html",
+                "docs/guide.md"
+            ]
+        );
+
+        let (entry, proof) = manifest.prove_file("docs/api/index.html").unwrap();
+        assert_eq!(entry.size, 200_000);
+        assert_eq!(entry.leaf_index, 2);
+        assert!(entry.verify(&proof));
+
+        let (blank, proof) = manifest.prove_file("docs/api/blank").unwrap();
+        assert_eq!(blank.content_hash, None);
+        assert!(blank.verify(&proof));
+        assert!(!entry.verify(&proof));
+
+        assert!(manifest.prove_file("docs").is_none());
+    }
+
+    #[test]
+    fn root_tracks_names_and_contents() {
+        let dir = fixture();
+        let root = build_manifest(dir.path()).unwrap().tree.root();
+        assert_eq!(build_manifest(dir.path()).unwrap().tree.root(), root);
+
+        fs::rename(dir.path().join("README"), dir.path().join("README.txt")).unwrap();
+        let renamed = build_manifest(dir.path()).unwrap().tree.root();
+        assert_ne!(renamed, root);
+
+        fs::write(dir.path().join("docs/guide.md"), b"# Guide!").unwrap();
+        assert_ne!(build_manifest(dir.path()).unwrap().tree.root(), renamed);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_rejected() {
+        let dir = fixture();
+        std::os::unix::fs::symlink(dir.path().join("README"), dir.path().join("link")).unwrap();
+        let error = build_manifest(dir.path()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn missing_directory_rejected() {
+        let dir = fixture();
+        assert!(build_manifest(dir.path().join("missing")).is_err());
+    }
+}
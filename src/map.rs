@@ -0,0 +1,252 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::{MerkleProof, MerkleTree, hash_single};
+
+/// Leaf left behind by a removed entry, until its slot is reused.
+const TOMBSTONE: u64 = MerkleTree::PAD_HASH;
+
+/// Returns the leaf binding a key to a value: the hash of the key's hash and the
+/// value's hash.
+fn entry_leaf<K: Hash + ?Sized, V: Hash + ?Sized>(key: &K, value: &V) -> u64 {
+    hash_single((hash_single(key), hash_single(value)))
+}
+
+impl MerkleTree {
+    /// Replaces an occupied leaf and recomputes its ancestors.
+    /// Unlike every other mutation, this rewrites history: it must only be used on trees
+    /// which keep no root history and have no lookup index.
+    /// * `index` - The index of the leaf.
+    /// * `leaf` - The new leaf hash.
+    pub(crate) fn replace_leaf_hash(&mut self, index: usize, leaf: u64) {
+        let old_root = self.watched_root();
+        self.levels.set(0, index, leaf);
+        self.rehash_path(index);
+        self.epoch += 1;
+        self.invalidate_proof_cache();
+        self.notify_root_change(old_root);
+    }
+}
+
+/// Authenticated key-value map: every entry is committed by a leaf binding its key to
+/// its value, so a `KvProof` shows that a key maps to a value under a root.
+/// Entries keep their leaf while they are overwritten. Removed entries leave a tombstone
+/// leaf, which is reused by the next inserted key, so the tree only grows with the
+/// amount of entries held at once. The root depends on the order of the operations,
+/// not only on the entries.
+#[derive(Debug)]
+pub struct MerkleMap<K, V> {
+    tree: MerkleTree,
+    /// Leaf index and value of every entry.
+    entries: HashMap<K, (usize, V)>,
+    /// Indices of the tombstone leaves.
+    free: Vec<usize>,
+}
+
+/// Proof that a key maps to a value in a `MerkleMap`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KvProof {
+    proof: MerkleProof,
+}
+
+impl KvProof {
+    /// Returns the proof of the entry's leaf.
+    pub fn proof(&self) -> &MerkleProof {
+        &self.proof
+    }
+
+    /// Returns whether the proof shows that the key maps to the value under the root.
+    /// * `key` - The `Hash` key of the entry.
+    /// * `value` - The `Hash` value of the entry.
+    /// * `root` - The trusted root of the map.
+    pub fn verify<K: Hash + ?Sized, V: Hash + ?Sized>(
+        &self,
+        key: &K,
+        value: &V,
+        root: u64,
+    ) -> bool {
+        match &self.proof {
+            MerkleProof::Invalid => false,
+            MerkleProof::Proof {
+                root: proof_root, ..
+            } => *proof_root == root && self.proof.verify_leaf(entry_leaf(key, value)),
+        }
+    }
+}
+
+impl<K: Hash + Eq, V: Hash> MerkleMap<K, V> {
+    /// Creates an empty map.
+    pub fn new() -> MerkleMap<K, V> {
+        MerkleMap {
+            tree: MerkleTree::build::<u8>(&[]),
+            entries: HashMap::new(),
+            free: Vec::new(),
+        }
+    }
+
+    /// Returns the amount of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the map holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the root committing to every entry, or `None` if nothing was ever
+    /// inserted.
+    pub fn root(&self) -> Option<u64> {
+        self.tree.root()
+    }
+
+    /// Returns the value of a key.
+    /// * `key` - The key of the entry.
+    pub fn get<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        self.entries.get(key).map(|(_, value)| value)
+    }
+
+    /// Returns whether the map holds a key.
+    /// * `key` - The key of the entry.
+    pub fn contains_key<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.entries.contains_key(key)
+    }
+
+    /// Maps a key to a value, returning the value it replaced. Overwritten entries keep
+    /// their leaf, and new keys take the slot of a removed entry if there is one.
+    /// * `key` - The `Hash` key of the entry.
+    /// * `value` - The `Hash` value of the entry.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let leaf = entry_leaf(&key, &value);
+        if let Some((index, old)) = self.entries.get_mut(&key) {
+            self.tree.replace_leaf_hash(*index, leaf);
+            return Some(std::mem::replace(old, value));
+        }
+
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.tree.replace_leaf_hash(index, leaf);
+                index
+            }
+            None => {
+                self.tree.push_hash(leaf);
+                self.tree.len() - 1
+            }
+        };
+        self.entries.insert(key, (index, value));
+        None
+    }
+
+    /// Removes a key, returning its value. Its leaf becomes a tombstone, so proofs of
+    /// the entry no longer verify against the new root.
+    /// * `key` - The key of the entry.
+    pub fn remove<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+    {
+        let (index, value) = self.entries.remove(key)?;
+        self.tree.replace_leaf_hash(index, TOMBSTONE);
+        self.free.push(index);
+        Some(value)
+    }
+
+    /// Returns the proof that the key maps to its current value, or `None` if the map
+    /// does not hold the key.
+    /// * `key` - The key of the entry.
+    pub fn prove<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<KvProof>
+    where
+        K: Borrow<Q>,
+    {
+        let (index, _) = self.entries.get(key)?;
+        Some(KvProof {
+            proof: self.tree.get_proof(*index),
+        })
+    }
+}
+
+impl<K: Hash + Eq, V: Hash> Default for MerkleMap<K, V> {
+    fn default() -> MerkleMap<K, V> {
+        MerkleMap::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proofs_bind_keys_and_values() {
+        let mut map = MerkleMap::new();
+        map.insert("alice".to_string(), 10);
+        map.insert("bob".to_string(), 20);
+        let root = map.root().unwrap();
+
+        let proof = map.prove("alice").unwrap();
+        assert!(proof.verify("alice", &10, root));
+        assert!(!proof.verify("alice", &20, root));
+        assert!(!proof.verify("bob", &10, root));
+        assert!(!proof.verify("alice", &10, root ^ 1));
+        assert!(map.prove("carol").is_none());
+    }
+
+    #[test]
+    fn overwrites_invalidate_old_proofs() {
+        let mut map = MerkleMap::new();
+        map.insert("alice", 10);
+        map.insert("bob", 20);
+        let old_proof = map.prove("alice").unwrap();
+
+        assert_eq!(map.insert("alice", 11), Some(10));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get("alice"), Some(&11));
+
+        let root = map.root().unwrap();
+        assert!(!old_proof.verify("alice", &10, root));
+        assert!(map.prove("alice").unwrap().verify("alice", &11, root));
+        assert!(map.prove("bob").unwrap().verify("bob", &20, root));
+    }
+
+    #[test]
+    fn removed_keys_are_not_provable() {
+        let mut map = MerkleMap::new();
+        map.insert(1, "one");
+        map.insert(2, "two");
+        let old_proof = map.prove(&1).unwrap();
+
+        assert_eq!(map.remove(&1), Some("one"));
+        assert_eq!(map.remove(&1), None);
+        assert!(!map.contains_key(&1));
+        assert!(map.prove(&1).is_none());
+        let root = map.root().unwrap();
+        assert!(!old_proof.verify(&1, &"one", root));
+
+        // The freed slot is reused instead of growing the tree.
+        map.insert(3, "three");
+        assert_eq!(map.tree.len(), 2);
+        assert!(
+            map.prove(&3)
+                .unwrap()
+                .verify(&3, &"three", map.root().unwrap())
+        );
+    }
+
+    #[test]
+    fn proofs_survive_unrelated_inserts() {
+        let mut map = MerkleMap::new();
+        map.insert(0_u32, 0_u32);
+        for key in 1..1000 {
+            map.insert(key, key * 2);
+        }
+        let root = map.root().unwrap();
+        assert!(map.prove(&0).unwrap().verify(&0, &0, root));
+        assert!(map.prove(&777).unwrap().verify(&777, &1554, root));
+        map.tree.validate().unwrap();
+    }
+}
@@ -0,0 +1,307 @@
+use std::hash::Hash;
+
+use crate::{hash_pair, hash_single};
+
+/// Returns the amount of positions taken by a perfect subtree of the given height.
+fn subtree_size(height: u32) -> u64 {
+    (2 << height) - 1
+}
+
+/// Returns the position and height of every peak of an MMR of the given size, from the
+/// leftmost (highest) to the rightmost, or `None` if no MMR has that size.
+/// * `mmr_size` - Amount of positions of the MMR.
+pub fn mmr_peak_positions(mmr_size: u64) -> Option<Vec<(u64, u32)>> {
+    let mut peaks = Vec::new();
+    let mut start = 0_u64;
+    for height in (0..u64::BITS - 1).rev() {
+        let size = subtree_size(height);
+        if mmr_size - start >= size {
+            peaks.push((start + size - 1, height));
+            start += size;
+        }
+    }
+    (start == mmr_size).then_some(peaks)
+}
+
+/// Returns the size of an MMR holding the given amount of leaves, or `None` if it does
+/// not fit in a `u64`, which is the case above `u64::MAX / 2` leaves.
+/// * `leaves` - Amount of leaves.
+pub fn mmr_size(leaves: u64) -> Option<u64> {
+    Some(leaves.checked_mul(2)? - u64::from(leaves.count_ones()))
+}
+
+/// Returns the siblings' positions of the path from a leaf up to its peak, bottom-up,
+/// or `None` if the position is not a leaf within the peak.
+/// * `position` - Position of the leaf.
+/// * `peak` - Position of the peak holding the leaf.
+/// * `height` - Height of the peak.
+fn sibling_positions(position: u64, peak: u64, height: u32) -> Option<Vec<u64>> {
+    let mut siblings = Vec::with_capacity(height as usize);
+    let mut node = peak;
+    for level in (1..=height).rev() {
+        let left = node - (1 << level);
+        let right = node - 1;
+        if position <= left {
+            siblings.push(right);
+            node = left;
+        } else {
+            siblings.push(left);
+            node = right;
+        }
+    }
+    siblings.reverse();
+    (node == position).then_some(siblings)
+}
+
+/// Returns the root of an MMR out of its peaks, by bagging them from the right: each
+/// peak is combined with the bag of the peaks to its right.
+fn bag_peaks(peaks: &[u64]) -> Option<u64> {
+    let (&last, rest) = peaks.split_last()?;
+    Some(
+        rest.iter()
+            .rev()
+            .fold(last, |bag, &peak| hash_pair(peak, bag)),
+    )
+}
+
+/// Merkle Mountain Range: an append-only list of perfect trees, one per set bit of the
+/// amount of leaves, whose roots (the peaks) are bagged into a single root. Nothing is
+/// ever padded, and each push hashes only the subtrees it completes.
+/// Nodes are numbered by the standard MMR positions: in post-order, starting at `0`, so
+/// each parent directly follows its right child.
+#[derive(Clone, Debug, Default)]
+pub struct Mmr {
+    /// Every node, by position.
+    nodes: Vec<u64>,
+    leaves: u64,
+}
+
+/// Proof that a leaf is part of an MMR, verified against the bagged root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MmrProof {
+    /// Position of the leaf.
+    pub position: u64,
+    /// Size of the MMR the proof was generated for.
+    pub mmr_size: u64,
+    /// Siblings of the path from the leaf up to its peak, bottom-up.
+    pub siblings: Vec<u64>,
+    /// Every other peak, from left to right.
+    pub peaks: Vec<u64>,
+}
+
+impl Mmr {
+    /// Creates an empty MMR.
+    pub fn new() -> Mmr {
+        Mmr::default()
+    }
+
+    /// Returns the amount of leaves.
+    pub fn leaves(&self) -> u64 {
+        self.leaves
+    }
+
+    /// Returns the amount of positions, leaves and parents included.
+    pub fn size(&self) -> u64 {
+        self.nodes.len() as u64
+    }
+
+    /// Returns whether the MMR holds no leaves.
+    pub fn is_empty(&self) -> bool {
+        self.leaves == 0
+    }
+
+    /// Returns the node at a position.
+    /// * `position` - The node's position.
+    pub fn node(&self, position: u64) -> Option<u64> {
+        self.nodes.get(usize::try_from(position).ok()?).copied()
+    }
+
+    /// Appends an `Hash` element as the next leaf, returning its position. The new leaf
+    /// merges with every peak of its height, the way a binary counter carries.
+    /// * `value` - The `Hash` value to be added.
+    pub fn push<H: Hash>(&mut self, value: H) -> u64 {
+        let position = self.size();
+        let mut node = hash_single(value);
+        self.nodes.push(node);
+        for height in 0..self.leaves.trailing_ones() {
+            let left = self.nodes[self.nodes.len() - 1 - subtree_size(height) as usize];
+            node = hash_pair(left, node);
+            self.nodes.push(node);
+        }
+        self.leaves += 1;
+        position
+    }
+
+    /// Returns the hashes of the peaks, from the leftmost (highest) to the rightmost.
+    pub fn peaks(&self) -> Vec<u64> {
+        mmr_peak_positions(self.size())
+            .expect("The size is always valid")
+            .into_iter()
+            .map(|(position, _)| self.nodes[position as usize])
+            .collect()
+    }
+
+    /// Returns the root, obtained by bagging the peaks from the right. If the MMR is
+    /// empty, there is no root and `None` is returned.
+    pub fn root(&self) -> Option<u64> {
+        bag_peaks(&self.peaks())
+    }
+
+    /// Returns the proof of the leaf at a position, or `None` if the position does not
+    /// hold a leaf.
+    /// * `position` - Position of the leaf, as returned by `push`.
+    pub fn prove(&self, position: u64) -> Option<MmrProof> {
+        let peaks = mmr_peak_positions(self.size()).expect("The size is always valid");
+        let &(peak, height) = peaks.iter().find(|&&(peak, _)| position <= peak)?;
+        let siblings = sibling_positions(position, peak, height)?;
+        Some(MmrProof {
+            position,
+            mmr_size: self.size(),
+            siblings: siblings
+                .into_iter()
+                .map(|sibling| self.nodes[sibling as usize])
+                .collect(),
+            peaks: peaks
+                .into_iter()
+                .filter(|&(other, _)| other != peak)
+                .map(|(other, _)| self.nodes[other as usize])
+                .collect(),
+        })
+    }
+}
+
+impl MmrProof {
+    /// Returns whether a given `Hash` value verifies the proof against a trusted root.
+    /// * `value` - The `Hash` value to be tested.
+    /// * `root` - The trusted bagged root.
+    pub fn verify<H: Hash>(&self, value: H, root: u64) -> bool {
+        let Some(peaks) = mmr_peak_positions(self.mmr_size) else {
+            return false;
+        };
+        let Some(slot) = peaks.iter().position(|&(peak, _)| self.position <= peak) else {
+            return false;
+        };
+        let (peak, height) = peaks[slot];
+        let Some(siblings) = sibling_positions(self.position, peak, height) else {
+            return false;
+        };
+        if self.siblings.len() != siblings.len() || self.peaks.len() != peaks.len() - 1 {
+            return false;
+        }
+
+        // Subtrees left of the path precede the leaf, so their roots have lower positions.
+        let mut node = hash_single(value);
+        for (&sibling, &sibling_position) in self.siblings.iter().zip(&siblings) {
+            node = if sibling_position < self.position {
+                hash_pair(sibling, node)
+            } else {
+                hash_pair(node, sibling)
+            };
+        }
+
+        let mut all_peaks = self.peaks.clone();
+        all_peaks.insert(slot, node);
+        bag_peaks(&all_peaks) == Some(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standard_shapes() {
+        // Size and peak positions after each of the first 11 leaves.
+        let shapes: [(u64, &[u64]); 11] = [
+            (1, &[0]),
+            (3, &[2]),
+            (4, &[2, 3]),
+            (7, &[6]),
+            (8, &[6, 7]),
+            (10, &[6, 9]),
+            (11, &[6, 9, 10]),
+            (15, &[14]),
+            (16, &[14, 15]),
+            (18, &[14, 17]),
+            (19, &[14, 17, 18]),
+        ];
+        let leaf_positions = [0, 1, 3, 4, 7, 8, 10, 11, 15, 16, 18];
+
+        let mut mmr = Mmr::new();
+        for (leaf, (size, peaks)) in shapes.into_iter().enumerate() {
+            assert_eq!(mmr.push(leaf), leaf_positions[leaf]);
+            assert_eq!(mmr.size(), size);
+            assert_eq!(mmr_size(leaf as u64 + 1), Some(size));
+            let positions: Vec<u64> = mmr_peak_positions(size)
+                .unwrap()
+                .into_iter()
+                .map(|(position, _)| position)
+                .collect();
+            assert_eq!(positions, peaks);
+        }
+        for invalid in [2, 5, 6, 9, 12] {
+            assert_eq!(mmr_peak_positions(invalid), None);
+        }
+
+        assert_eq!(mmr_size(u64::MAX / 2), Some(u64::MAX - 1 - 63));
+        assert_eq!(mmr_size(u64::MAX / 2 + 1), None);
+        assert_eq!(mmr_size(u64::MAX), None);
+    }
+
+    #[test]
+    fn parents_follow_their_children() {
+        let mut mmr = Mmr::new();
+        (0..4).for_each(|value| {
+            mmr.push(value);
+        });
+        let leaf = |value: i32| hash_single(value);
+        assert_eq!(mmr.node(2), Some(hash_pair(leaf(0), leaf(1))));
+        assert_eq!(mmr.node(5), Some(hash_pair(leaf(2), leaf(3))));
+        assert_eq!(mmr.node(6), Some(hash_pair(mmr.nodes[2], mmr.nodes[5])));
+        assert_eq!(mmr.root(), mmr.node(6));
+        assert_eq!(Mmr::new().root(), None);
+    }
+
+    #[test]
+    fn every_leaf_of_20_proves() {
+        let mut mmr = Mmr::new();
+        let positions: Vec<u64> = (0..20_usize).map(|value| mmr.push(value)).collect();
+        let root = mmr.root().unwrap();
+        // 20 leaves form peaks of 16 and 4 leaves.
+        assert_eq!(mmr.peaks(), [mmr.nodes[30], mmr.nodes[37]]);
+        assert_eq!(root, hash_pair(mmr.nodes[30], mmr.nodes[37]));
+
+        for (value, &position) in positions.iter().enumerate() {
+            let proof = mmr.prove(position).unwrap();
+            assert!(proof.verify(value, root), "leaf {value}");
+            assert!(!proof.verify(value + 1, root));
+            assert!(!proof.verify(value, root ^ 1));
+        }
+
+        // Parents hold no leaf.
+        assert!(mmr.prove(2).is_none());
+        assert!(mmr.prove(mmr.size()).is_none());
+    }
+
+    #[test]
+    fn tampered_proofs_rejected() {
+        let mut mmr = Mmr::new();
+        (0..11_i32).for_each(|value| {
+            mmr.push(value);
+        });
+        let root = mmr.root().unwrap();
+        let proof = mmr.prove(8).unwrap();
+
+        let mut moved = proof.clone();
+        moved.position = 7;
+        assert!(!moved.verify(5, root));
+
+        let mut resized = proof.clone();
+        resized.mmr_size = 12;
+        assert!(!resized.verify(5, root));
+
+        let mut truncated = proof;
+        truncated.peaks.pop();
+        assert!(!truncated.verify(5, root));
+    }
+}
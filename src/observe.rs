@@ -0,0 +1,130 @@
+use crate::{MerkleTree, NodeStore};
+
+/// Callback notified of root changes, with the old and the new root.
+pub(crate) type RootObserver = Box<dyn FnMut(u64, u64) + Send + Sync>;
+
+impl<S: NodeStore> MerkleTree<S> {
+    /// Registers a callback invoked after every mutation that changes the root, with the
+    /// old and the new root. Mutations leaving the root untouched, such as pushing a leaf
+    /// equal to `PAD_HASH` into an existing padded slot, are not notified. Capacity growth
+    /// is part of the mutation that triggered it, so a single notification is sent.
+    /// The root of an empty tree is reported as `PAD_HASH`, which is what it stores.
+    /// Registering a callback replaces the previous one.
+    /// The callback runs while the tree is mutably borrowed and must be `'static`, so it
+    /// cannot access the tree: re-entrant reads or mutations are rejected at compile time.
+    /// * `callback` - The function called with the old and the new root.
+    pub fn on_root_change<F: FnMut(u64, u64) + Send + Sync + 'static>(&mut self, callback: F) {
+        self.root_observer = Some(Box::new(callback));
+    }
+
+    /// Removes the root change callback, if any.
+    pub fn remove_root_observer(&mut self) {
+        self.root_observer = None;
+    }
+
+    /// Returns the node stored at the top of the tree, which is the root of non-empty
+    /// trees and `PAD_HASH` for empty ones.
+    pub(crate) fn top_node(&self) -> u64 {
+        self.levels
+            .height()
+            .checked_sub(1)
+            .and_then(|top| self.levels.get(top, 0))
+            .unwrap_or(MerkleTree::PAD_HASH)
+    }
+
+    /// Returns the top node if root changes are watched, by a callback or the root
+    /// history. Otherwise it is not read, since it may have to be hashed out of the leaves
+    /// (see `keep_leaves_only`).
+    pub(crate) fn watched_root(&self) -> Option<u64> {
+        (self.root_observer.is_some() || self.root_history.is_some()).then(|| self.top_node())
+    }
+
+    /// Notifies the root change callback and records the new root in the history if the
+    /// root changed. Must be called by every mutating operation once it is complete.
+    /// * `old_root` - The top node before the mutation, as returned by `watched_root`.
+    pub(crate) fn notify_root_change(&mut self, old_root: Option<u64>) {
+        let Some(old_root) = old_root else {
+            return;
+        };
+        let new_root = self.top_node();
+        if new_root == old_root {
+            return;
+        }
+        self.record_root();
+        if let Some(observer) = &mut self.root_observer {
+            observer(old_root, new_root);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::MerkleTree;
+
+    /// Registers a callback recording every notification into the returned vector.
+    fn record(tree: &mut MerkleTree) -> Arc<Mutex<Vec<(u64, u64)>>> {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Arc::clone(&calls);
+        tree.on_root_change(move |old, new| recorder.lock().unwrap().push((old, new)));
+        calls
+    }
+
+    #[test]
+    fn notifies_every_push() {
+        let mut tree = MerkleTree::build::<u8>(&[]);
+        let calls = record(&mut tree);
+
+        let mut expected = Vec::new();
+        for value in 0..10 {
+            let old = tree.root().unwrap_or(MerkleTree::PAD_HASH);
+            tree.push(value);
+            expected.push((old, tree.root().unwrap()));
+        }
+
+        assert_eq!(*calls.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn unchanged_root_not_notified() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        let calls = record(&mut tree);
+
+        // Writing PAD_HASH into the padded slot holding it leaves every node untouched.
+        tree.push_hash(MerkleTree::PAD_HASH);
+        assert!(calls.lock().unwrap().is_empty());
+
+        // Growth changes the root even when pushing PAD_HASH.
+        let old = tree.root().unwrap();
+        tree.push_hash(MerkleTree::PAD_HASH);
+        assert_eq!(*calls.lock().unwrap(), [(old, tree.root().unwrap())]);
+
+        // Reads are never notified.
+        tree.get_proof(0);
+        tree.validate().unwrap();
+        assert_eq!(calls.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn removed_observer_not_notified() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        let calls = record(&mut tree);
+        tree.push(4);
+        tree.remove_root_observer();
+        tree.push(5);
+
+        assert_eq!(calls.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn registering_replaces_observer() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        let first = record(&mut tree);
+        let second = record(&mut tree);
+        tree.push(4);
+
+        assert!(first.lock().unwrap().is_empty());
+        assert_eq!(second.lock().unwrap().len(), 1);
+    }
+}
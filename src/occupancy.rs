@@ -0,0 +1,222 @@
+use std::borrow::Cow;
+use std::iter;
+use std::sync::Arc;
+
+use crate::MerkleTree;
+
+/// Bitmap recording which leaf slots hold elements rather than padding.
+/// Trees fill their slots from the left, so the run of occupied slots at the start is
+/// only counted, and bits are stored from the end of that run on: bit `i % 64` of word
+/// `i / 64` corresponds to slot `prefix + i`, and slots past the words are unoccupied.
+/// This keeps the bitmap small however large the capacity of the tree, pruned trees
+/// included. The words are shared copy-on-write, like the levels of the tree.
+#[derive(Clone)]
+pub(crate) struct Occupancy {
+    /// Amount of occupied slots at the start, a multiple of 64.
+    prefix: usize,
+    words: Arc<Vec<u64>>,
+    slots: usize,
+}
+
+impl Occupancy {
+    /// Creates a bitmap of `slots` slots where only the first `occupied` are set.
+    pub(crate) fn new(slots: usize, occupied: usize) -> Occupancy {
+        let mut words = Vec::new();
+        if !occupied.is_multiple_of(64) {
+            words.push((1 << (occupied % 64)) - 1);
+        }
+        Occupancy {
+            prefix: occupied - occupied % 64,
+            words: Arc::new(words),
+            slots,
+        }
+    }
+
+    /// Returns whether the slot is occupied, or `None` if it is out of range.
+    pub(crate) fn get(&self, index: usize) -> Option<bool> {
+        if index >= self.slots {
+            return None;
+        }
+        let Some(offset) = index.checked_sub(self.prefix) else {
+            return Some(true);
+        };
+        let word = self.words.get(offset / 64).copied().unwrap_or(0);
+        Some(word & (1 << (offset % 64)) != 0)
+    }
+
+    /// Marks a slot as occupied.
+    pub(crate) fn set(&mut self, index: usize) {
+        let Some(offset) = index.checked_sub(self.prefix) else {
+            return;
+        };
+        let words = Arc::make_mut(&mut self.words);
+        if words.len() <= offset / 64 {
+            words.resize(offset / 64 + 1, 0);
+        }
+        words[offset / 64] |= 1 << (offset % 64);
+
+        // Fold the words which became full into the run of occupied slots.
+        let full = words.iter().take_while(|&&word| word == u64::MAX).count();
+        if full > 0 {
+            words.drain(..full);
+            self.prefix += full * 64;
+        }
+    }
+
+    /// Returns the heap memory allocated by the bitmap: the shared allocation holding the
+    /// reference counts and the vector, plus the words.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        2 * size_of::<usize>() + size_of::<Vec<u64>>() + self.words.capacity() * size_of::<u64>()
+    }
+
+    /// Extends the bitmap with unoccupied slots until it holds `slots` of them.
+    pub(crate) fn grow(&mut self, slots: usize) {
+        self.slots = slots;
+    }
+
+    /// Returns an iterator over the indices of the occupied slots from the given one on,
+    /// in ascending order. The run of occupied slots at the start is counted through, and
+    /// only the set bits of the stored words are visited, so padding is never scanned.
+    /// * `start` - Index of the first slot considered.
+    pub(crate) fn iter_from(&self, start: usize) -> impl Iterator<Item = usize> + '_ {
+        let words = self
+            .words
+            .iter()
+            .enumerate()
+            .skip(start.saturating_sub(self.prefix) / 64)
+            .flat_map(move |(word_n, &word)| {
+                let first = self.prefix + 64 * word_n;
+                let mut word = word;
+                iter::from_fn(move || {
+                    (word != 0).then(|| {
+                        let bit = word.trailing_zeros() as usize;
+                        word &= word - 1;
+                        first + bit
+                    })
+                })
+            })
+            .skip_while(move |&index| index < start);
+        (start.min(self.prefix)..self.prefix).chain(words)
+    }
+
+    /// Returns the words of the whole bitmap, where bit `i % 64` of word `i / 64`
+    /// corresponds to slot `i`. They are only borrowed if no run of occupied slots is
+    /// counted and every word is stored.
+    fn packed(&self) -> Cow<'_, [u64]> {
+        let len = self.slots.div_ceil(64);
+        if self.prefix == 0 && self.words.len() == len {
+            return Cow::Borrowed(self.words.as_slice());
+        }
+        let mut words = vec![u64::MAX; self.prefix / 64];
+        words.extend_from_slice(&self.words);
+        words.resize(len, 0);
+        Cow::Owned(words)
+    }
+}
+
+impl MerkleTree {
+    /// Returns whether the slot at the given index is padding, or `None` if the index
+    /// is beyond the tree's capacity.
+    /// * `index` - Index of the leaf slot.
+    pub fn is_padded(&self, index: usize) -> Option<bool> {
+        self.occupancy.get(index).map(|occupied| !occupied)
+    }
+
+    /// Returns an iterator over the occupancy of every leaf slot, up to the tree's
+    /// capacity: `true` for slots holding elements and `false` for padding.
+    pub fn occupancy(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.capacity).map(|index| self.occupancy.get(index) == Some(true))
+    }
+
+    /// Returns the occupancy of every leaf slot as a packed bitmap, where bit `i % 64`
+    /// of word `i / 64` is set if slot `i` holds an element.
+    /// The bitmap is stored compactly, so it is usually built on the fly, taking a bit per
+    /// slot of the tree's capacity.
+    pub fn occupancy_bitmap(&self) -> Cow<'_, [u64]> {
+        self.occupancy.packed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Occupancy;
+    use crate::MerkleTree;
+
+    /// Asserts that exactly the first `len` slots of the tree are occupied.
+    fn assert_occupancy(tree: &MerkleTree, len: usize) {
+        let expected: Vec<bool> = (0..tree.capacity()).map(|index| index < len).collect();
+        assert_eq!(tree.occupancy().collect::<Vec<bool>>(), expected);
+        for index in 0..tree.capacity() {
+            assert_eq!(tree.is_padded(index), Some(index >= len));
+        }
+        assert_eq!(tree.is_padded(tree.capacity()), None);
+    }
+
+    #[test]
+    fn occupancy_of_built_tree() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        assert_occupancy(&tree, 5);
+        assert_eq!(*tree.occupancy_bitmap(), [0b11111]);
+
+        let tree = MerkleTree::build::<u8>(&[]);
+        assert_occupancy(&tree, 0);
+    }
+
+    #[test]
+    fn occupancy_across_pushes() {
+        let mut tree = MerkleTree::build::<u8>(&[]);
+        for len in 1..=130 {
+            tree.push(len as u8);
+            assert_occupancy(&tree, len);
+        }
+        assert_eq!(*tree.occupancy_bitmap(), [u64::MAX, u64::MAX, 0b11, 0]);
+    }
+
+    #[test]
+    fn occupancy_of_word_boundaries() {
+        for len in [63, 64, 65, 128, 200] {
+            let tree = MerkleTree::build(&(0..len).collect::<Vec<u32>>());
+            assert_occupancy(&tree, len as usize);
+        }
+    }
+
+    #[test]
+    fn scattered_slots_fold_into_the_prefix() {
+        let mut occupancy = Occupancy::new(1 << 40, 70);
+        for index in (130..192).chain([200, 70]).chain(71..130) {
+            occupancy.set(index);
+        }
+        assert_eq!(occupancy.prefix, 192);
+        assert_eq!(*occupancy.words, [1 << 8]);
+        assert_eq!(occupancy.get(199), Some(false));
+        assert_eq!(occupancy.get(200), Some(true));
+        assert_eq!(occupancy.get((1 << 40) - 1), Some(false));
+        assert_eq!(occupancy.get(1 << 40), None);
+    }
+
+    #[test]
+    fn occupied_slots_iterated() {
+        let mut occupancy = Occupancy::new(1 << 20, 70);
+        for index in [100, 130, 200, 1000] {
+            occupancy.set(index);
+        }
+        let expected: Vec<usize> = (0..70).chain([100, 130, 200, 1000]).collect();
+        assert!(occupancy.iter_from(0).eq(expected.iter().copied()));
+        for start in [0, 5, 63, 64, 69, 70, 100, 101, 131, 1000, 1001] {
+            let from: Vec<usize> = expected
+                .iter()
+                .copied()
+                .filter(|&index| index >= start)
+                .collect();
+            assert!(occupancy.iter_from(start).eq(from), "from {start}");
+        }
+    }
+
+    #[test]
+    fn proofs_refused_for_padded_slots() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        assert_eq!(tree.is_padded(3), Some(true));
+        assert!(!tree.get_proof(3).verify(MerkleTree::PAD_HASH));
+        assert!(tree.get_proof(3).epoch().is_none());
+    }
+}
@@ -0,0 +1,386 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::occupancy::Occupancy;
+use crate::{MerkleTree, NodeStore};
+
+/// Mutation of a `MerkleTree`, as recorded by `MerkleTree::record_ops`. Leaves are
+/// recorded by hash, so replaying never needs the original values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TreeOp {
+    /// A leaf pushed by `push`, `try_push` or `extend`.
+    Push { leaf: u64 },
+    /// Leaves rewritten in place, along with the tree's length, capacity and epoch
+    /// afterwards, by `sync_from` or `apply_delta`. Shrinking capacities drops the slots
+    /// beyond them, and the leaves cut off by a shorter length are rewritten as padding.
+    Rewrite {
+        len: usize,
+        capacity: usize,
+        epoch: u64,
+        leaves: Vec<(usize, u64)>,
+    },
+    /// The tree's state after the preceding operations. Recording starts with one and
+    /// embeds one every few operations, so that replays can be verified.
+    Checkpoint { len: usize, epoch: u64, root: u64 },
+}
+
+/// Error returned when replaying operations fails. `op` is the position of the
+/// operation among the replayed ones.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    /// A `Rewrite` does not fit in a tree: its capacity is not a power of two, or its
+    /// length or a leaf index exceed it.
+    InvalidOp { op: usize },
+    /// The tree's state differs from the one recorded by a `Checkpoint`.
+    CheckpointMismatch {
+        op: usize,
+        expected: (usize, u64, u64),
+        found: (usize, u64, u64),
+    },
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::InvalidOp { op } => write!(f, "operation {op} does not fit in a tree"),
+            ReplayError::CheckpointMismatch {
+                op,
+                expected,
+                found,
+            } => write!(
+                f,
+                "checkpoint {op} expects length, epoch and root {expected:x?}, found {found:x?}"
+            ),
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+/// Callback receiving every recorded operation.
+pub(crate) struct OpLog {
+    sink: Box<dyn FnMut(TreeOp) + Send + Sync>,
+    /// Amount of operations between checkpoints.
+    checkpoint_every: usize,
+    /// Amount of operations since the last checkpoint.
+    since_checkpoint: usize,
+}
+
+impl<S: NodeStore> MerkleTree<S> {
+    /// Records every later mutation into a sink, as `TreeOp` values, until
+    /// `stop_recording_ops` is called. Recording starts with a `TreeOp::Checkpoint` of
+    /// the current state, and embeds another after every `checkpoint_every` operations.
+    /// Replaying the operations with `replay` reproduces the tree, epoch included, as
+    /// long as recording started on an empty tree; `replay_onto` continues any tree
+    /// holding the first checkpoint's state. Recording again replaces the previous sink.
+    /// Syncs from pruned trees (see `from_frontier`) cannot be replayed: their leaves are
+    /// not known, so the next checkpoint fails.
+    /// * `checkpoint_every` - Amount of operations between checkpoints, at least 1.
+    /// * `sink` - The function called with every operation.
+    pub fn record_ops<F: FnMut(TreeOp) + Send + Sync + 'static>(
+        &mut self,
+        checkpoint_every: usize,
+        sink: F,
+    ) {
+        let mut log = OpLog {
+            sink: Box::new(sink),
+            checkpoint_every: checkpoint_every.max(1),
+            since_checkpoint: 0,
+        };
+        (log.sink)(self.checkpoint());
+        self.op_log = Some(log);
+    }
+
+    /// Stops recording operations, dropping the sink.
+    pub fn stop_recording_ops(&mut self) {
+        self.op_log = None;
+    }
+
+    /// Passes a completed operation to the sink, if recording, followed by a checkpoint
+    /// when one is due. Must be called by every mutating operation.
+    /// * `op` - The operation.
+    pub(crate) fn record_op(&mut self, op: TreeOp) {
+        if self.op_log.is_none() {
+            return;
+        }
+        let checkpoint = self.checkpoint();
+        let log = self.op_log.as_mut().expect("Recording was checked");
+        (log.sink)(op);
+        log.since_checkpoint += 1;
+        if log.since_checkpoint == log.checkpoint_every {
+            log.since_checkpoint = 0;
+            (log.sink)(checkpoint);
+        }
+    }
+
+    /// Returns a checkpoint of the tree's current state.
+    fn checkpoint(&self) -> TreeOp {
+        TreeOp::Checkpoint {
+            len: self.len(),
+            epoch: self.epoch,
+            root: self.top_node(),
+        }
+    }
+}
+
+impl MerkleTree {
+    /// Replays recorded operations onto an empty tree, returning the resulting tree.
+    /// Fails at the first operation which does not fit in the tree or checkpoint which
+    /// does not match it.
+    /// * `ops` - The operations, as recorded by `record_ops`.
+    pub fn replay<I: IntoIterator<Item = TreeOp>>(ops: I) -> Result<MerkleTree, ReplayError> {
+        let mut tree = MerkleTree::build::<u8>(&[]);
+        tree.replay_onto(ops)?;
+        Ok(tree)
+    }
+
+    /// Replays recorded operations onto the tree. Replays are deterministic: the same
+    /// operations onto the same state always yield the same tree. Every checkpoint is
+    /// compared with the tree's state when reached, failing if they differ, in which
+    /// case the tree holds the operations replayed so far.
+    /// Operations replayed while recording are recorded again.
+    /// * `ops` - The operations, as recorded by `record_ops`.
+    pub fn replay_onto<I: IntoIterator<Item = TreeOp>>(
+        &mut self,
+        ops: I,
+    ) -> Result<(), ReplayError> {
+        for (position, op) in ops.into_iter().enumerate() {
+            match op {
+                TreeOp::Push { leaf } => self.push_hash(leaf),
+                TreeOp::Rewrite {
+                    len,
+                    capacity,
+                    epoch,
+                    leaves,
+                } => {
+                    let fits = capacity.is_power_of_two()
+                        && len <= capacity
+                        && leaves.iter().all(|&(index, _)| index < capacity);
+                    if !fits {
+                        return Err(ReplayError::InvalidOp { op: position });
+                    }
+                    self.rewrite(len, capacity, epoch, leaves);
+                }
+                TreeOp::Checkpoint { len, epoch, root } => {
+                    let found = (self.len(), self.epoch, self.top_node());
+                    if found != (len, epoch, root) {
+                        return Err(ReplayError::CheckpointMismatch {
+                            op: position,
+                            expected: (len, epoch, root),
+                            found,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites leaves in place and reshapes the tree, recomputing the ancestors of the
+    /// rewritten leaves, as recorded by `TreeOp::Rewrite`.
+    /// * `len` - The new length.
+    /// * `capacity` - The new capacity, a power of two at least the length.
+    /// * `epoch` - The new epoch.
+    /// * `leaves` - The rewritten leaves, with their indices, below the capacity.
+    pub(crate) fn rewrite(
+        &mut self,
+        len: usize,
+        capacity: usize,
+        epoch: u64,
+        leaves: Vec<(usize, u64)>,
+    ) {
+        let old_root = self.watched_root();
+        self.resize_capacity(capacity);
+        self.levels.forget_materialized();
+        // The occupied leaves are always stored, so that `leaves` can borrow them.
+        if let Some(last) = len.checked_sub(1) {
+            let leaf = self.levels[(0, last)];
+            self.levels.set(0, last, leaf);
+        }
+        for &(index, leaf) in &leaves {
+            self.levels.set(0, index, leaf);
+        }
+        for &(index, _) in &leaves {
+            self.rehash_path(index);
+        }
+        self.padding = capacity - len;
+        self.occupancy = Occupancy::new(capacity, len);
+        self.epoch = epoch;
+        if self.is_indexed() {
+            self.enable_index();
+        }
+        self.invalidate_proof_cache();
+        self.notify_root_change(old_root);
+        self.record_op(TreeOp::Rewrite {
+            len,
+            capacity,
+            epoch,
+            leaves,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Push(u32),
+        Sync(Vec<u32>),
+        Delta(Vec<u32>),
+    }
+
+    fn arb_op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            20 => any::<u32>().prop_map(Op::Push),
+            1 => proptest::collection::vec(any::<u32>(), 0..40).prop_map(Op::Sync),
+            2 => proptest::collection::vec(any::<u32>(), 0..10).prop_map(Op::Delta),
+        ]
+    }
+
+    /// Starts recording a tree's operations into a shared list.
+    fn record(tree: &mut MerkleTree, checkpoint_every: usize) -> Arc<Mutex<Vec<TreeOp>>> {
+        let ops = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&ops);
+        tree.record_ops(checkpoint_every, move |op| sink.lock().unwrap().push(op));
+        ops
+    }
+
+    fn assert_same_tree(replayed: &MerkleTree, tree: &MerkleTree) {
+        assert_eq!(replayed.len(), tree.len());
+        assert_eq!(replayed.capacity(), tree.capacity());
+        assert_eq!(replayed.epoch(), tree.epoch());
+        assert!(replayed.levels_iter().eq(tree.levels_iter()));
+        assert_eq!(replayed.leaves(), tree.leaves());
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn replays_reproduce_sessions(ops in proptest::collection::vec(arb_op(), 500)) {
+            let mut tree = MerkleTree::build::<u32>(&[]);
+            let recorded = record(&mut tree, 16);
+            for op in ops {
+                match op {
+                    Op::Push(value) => tree.push(value),
+                    Op::Sync(values) => {
+                        tree.sync_from(&MerkleTree::build(&values));
+                    }
+                    Op::Delta(values) => {
+                        let mut target = tree.fork();
+                        target.extend(values);
+                        let mut delta = Vec::new();
+                        target.write_delta(&tree.freeze(), &mut delta).unwrap();
+                        tree.apply_delta(&delta[..]).unwrap();
+                    }
+                }
+            }
+
+            let ops = recorded.lock().unwrap().clone();
+            let replayed = MerkleTree::replay(ops).unwrap();
+            assert_same_tree(&replayed, &tree);
+        }
+    }
+
+    #[test]
+    fn tampered_ops_fail_at_the_next_checkpoint() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        let recorded = record(&mut tree, 4);
+        for value in 4..20 {
+            tree.push(value);
+        }
+        let mut ops = recorded.lock().unwrap().clone();
+        assert_eq!(
+            ops.iter()
+                .filter(|op| matches!(op, TreeOp::Checkpoint { .. }))
+                .count(),
+            5
+        );
+
+        // Recording started on a non-empty tree, so only that tree can be continued.
+        assert!(matches!(
+            MerkleTree::replay(ops.clone()),
+            Err(ReplayError::CheckpointMismatch { op: 0, .. })
+        ));
+        let mut replayed = MerkleTree::build(&[1, 2, 3]);
+        replayed.replay_onto(ops.clone()).unwrap();
+        assert_same_tree(&replayed, &tree);
+
+        // The 7th push is followed by the checkpoint at position 10.
+        ops[8] = TreeOp::Push { leaf: 0 };
+        let mut replayed = MerkleTree::build(&[1, 2, 3]);
+        assert_eq!(
+            replayed.replay_onto(ops).unwrap_err(),
+            ReplayError::CheckpointMismatch {
+                op: 10,
+                expected: (
+                    11,
+                    8,
+                    MerkleTree::build(&(1..12).collect::<Vec<_>>()).top_node()
+                ),
+                found: (11, 8, replayed.top_node()),
+            }
+        );
+        assert_eq!(replayed.len(), 11);
+    }
+
+    #[test]
+    fn rewrites_are_validated() {
+        let mut tree = MerkleTree::build(&[1, 2]);
+        let op = TreeOp::Rewrite {
+            len: 3,
+            capacity: 3,
+            epoch: 1,
+            leaves: vec![(2, 7)],
+        };
+        assert_eq!(
+            tree.replay_onto([op]),
+            Err(ReplayError::InvalidOp { op: 0 })
+        );
+        assert_eq!(tree.leaves().len(), 2);
+    }
+
+    #[test]
+    fn stopped_recordings_miss_later_ops() {
+        let mut tree = MerkleTree::build::<u32>(&[]);
+        let recorded = record(&mut tree, 100);
+        tree.push(1);
+        tree.stop_recording_ops();
+        tree.push(2);
+        assert_eq!(
+            *recorded.lock().unwrap(),
+            [
+                TreeOp::Checkpoint {
+                    len: 0,
+                    epoch: 0,
+                    root: MerkleTree::build::<u32>(&[]).top_node(),
+                },
+                TreeOp::Push {
+                    leaf: MerkleTree::build(&[1]).leaves()[0],
+                },
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn ops_round_trip_through_serde() {
+        let mut tree = MerkleTree::build::<u32>(&[]);
+        let recorded = record(&mut tree, 3);
+        tree.extend(0..10);
+        tree.sync_from(&MerkleTree::build(&[5, 6, 7]));
+        let json = serde_json::to_string(&*recorded.lock().unwrap()).unwrap();
+        let ops: Vec<TreeOp> = serde_json::from_str(&json).unwrap();
+        assert_same_tree(&MerkleTree::replay(ops).unwrap(), &tree);
+    }
+}
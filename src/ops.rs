@@ -0,0 +1,199 @@
+use std::hash::{Hash, Hasher};
+use std::ops::{Index, Range};
+
+use crate::MerkleTree;
+
+impl MerkleTree {
+    /// Returns a reference to the hash of an occupied leaf, or `None` if the index
+    /// reaches into the padding or beyond, or if the leaf is pruned (see `from_frontier`).
+    /// * `index` - Index of the leaf.
+    pub fn get(&self, index: usize) -> Option<&u64> {
+        self.leaves().get(index.checked_sub(self.pruned)?)
+    }
+
+    /// Returns the hashes of a range of occupied leaves, or `None` if the range reaches
+    /// into the padding or beyond, or into the pruned leaves (see `from_frontier`).
+    /// * `range` - Range of leaf indices.
+    pub fn get_range(&self, range: Range<usize>) -> Option<&[u64]> {
+        let start = range.start.checked_sub(self.pruned)?;
+        self.leaves()
+            .get(start..range.end.checked_sub(self.pruned)?)
+    }
+}
+
+/// Two trees are equal when they hold the same elements in the same order, that is,
+/// when their occupied leaf hashes are equal. Capacity, padding, epoch and any other
+/// bookkeeping state are ignored.
+/// Trees with pruned leaves (see `from_frontier`) only equal trees pruned up to the same
+/// leaf, holding the same leaves after it and having the same root.
+impl PartialEq for MerkleTree {
+    fn eq(&self, other: &MerkleTree) -> bool {
+        self.pruned == other.pruned
+            && self.leaves() == other.leaves()
+            && (self.pruned == 0 || self.root() == other.root())
+    }
+}
+
+impl Eq for MerkleTree {}
+
+/// A tree is hashed through its length and root, which is consistent with its
+/// equality: trees holding the same elements always share both. This allows trees
+/// to be nested as the elements of other trees.
+///
+/// ```
+/// use merkle_tree::MerkleTree;
+///
+/// let monday = MerkleTree::build(&["a", "b", "c"]);
+/// let tuesday = MerkleTree::build(&["d", "e"]);
+/// let week = MerkleTree::build(&[&monday, &tuesday]);
+///
+/// // "e" is the second element of tuesday, which is the second element of the week.
+/// assert!(tuesday.get_proof(1).verify("e"));
+/// assert!(week.get_proof(1).verify(&tuesday));
+/// ```
+impl Hash for MerkleTree {
+    fn hash<S: Hasher>(&self, state: &mut S) {
+        self.len().hash(state);
+        self.root().hash(state);
+    }
+}
+
+/// Pushes every element of the iterator into the tree, in order.
+impl<H: Hash> Extend<H> for MerkleTree {
+    fn extend<I: IntoIterator<Item = H>>(&mut self, elements: I) {
+        for element in elements {
+            self.push(element);
+        }
+    }
+}
+
+/// Indexes the hashes of the occupied leaves, like a `Vec` would.
+/// Panics if the index reaches into the padding or beyond.
+impl Index<usize> for MerkleTree {
+    type Output = u64;
+
+    #[track_caller]
+    fn index(&self, index: usize) -> &u64 {
+        match self.get(index) {
+            Option::Some(leaf) => leaf,
+            Option::None => panic!(
+                "index out of bounds: the len is {} but the index is {}",
+                self.len(),
+                index
+            ),
+        }
+    }
+}
+
+/// Slices the hashes of the occupied leaves, like a `Vec` would.
+/// Panics if the range reaches into the padding or beyond.
+impl Index<Range<usize>> for MerkleTree {
+    type Output = [u64];
+
+    #[track_caller]
+    fn index(&self, range: Range<usize>) -> &[u64] {
+        if range.start > range.end {
+            panic!(
+                "slice index starts at {} but ends at {}",
+                range.start, range.end
+            );
+        }
+        match self.get_range(range.clone()) {
+            Option::Some(leaves) => leaves,
+            Option::None => panic!(
+                "range end index {} out of range for tree of length {}",
+                range.end,
+                self.len()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{MerkleTree, hash_single};
+
+    #[test]
+    fn index_matches_leaf() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        for index in 0..tree.len() {
+            assert_eq!(Some(tree[index]), tree.leaf(index));
+            assert_eq!(tree.get(index).copied(), tree.leaf(index));
+        }
+        assert_eq!(tree[4], hash_single(5));
+    }
+
+    #[test]
+    fn range_index_matches_leaves() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        assert_eq!(&tree[2..5], &tree.leaves()[2..5]);
+        assert_eq!(tree.get_range(0..5), Some(tree.leaves()));
+        assert_eq!(tree[5..5].len(), 0);
+    }
+
+    #[test]
+    fn get_beyond_len() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        assert_eq!(tree.get(3), None);
+        assert_eq!(tree.get_range(2..4), None);
+        assert_eq!(tree.get_range(3..3), Some(&[][..]));
+    }
+
+    #[test]
+    fn trees_with_same_elements_are_equal() {
+        let built = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        let mut pushed = MerkleTree::build(&[1, 2]);
+        pushed.push(3);
+        pushed.push(4);
+        pushed.push(5);
+
+        assert!(built == pushed);
+        assert!(built.epoch() != pushed.epoch());
+    }
+
+    #[test]
+    fn trees_with_different_elements_are_not_equal() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        assert!(tree != MerkleTree::build(&[1, 2, 0, 4, 5]));
+        assert!(tree != MerkleTree::build(&[1, 2, 3, 4]));
+        assert!(MerkleTree::build::<u8>(&[]) == MerkleTree::build::<u16>(&[]));
+    }
+
+    #[test]
+    fn equal_trees_hash_equally() {
+        let built = MerkleTree::build(&[1, 2, 3]);
+        let mut pushed = MerkleTree::build(&[1, 2]);
+        pushed.push(3);
+
+        assert_eq!(hash_single(&built), hash_single(&pushed));
+        assert_ne!(hash_single(&built), hash_single(MerkleTree::build(&[1, 2])));
+    }
+
+    #[test]
+    fn nested_trees() {
+        let days = [
+            MerkleTree::build(&[1, 2, 3]),
+            MerkleTree::build(&[4, 5]),
+            MerkleTree::build(&[6]),
+        ];
+        let month = MerkleTree::build(&days);
+
+        assert!(days[1].get_proof(0).verify(4));
+        assert!(month.get_proof(1).verify(&days[1]));
+        assert!(!month.get_proof(1).verify(&days[2]));
+    }
+
+    #[test]
+    #[should_panic(expected = "the len is 3 but the index is 3")]
+    fn index_into_padding_panics() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let _ = tree[3];
+    }
+
+    #[test]
+    #[should_panic(expected = "range end index 4 out of range for tree of length 3")]
+    fn range_into_padding_panics() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let _ = &tree[1..4];
+    }
+}
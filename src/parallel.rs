@@ -0,0 +1,168 @@
+use std::hash::Hash;
+
+use rayon::iter::{FromParallelIterator, IntoParallelIterator, ParallelIterator};
+
+use crate::{MerkleTree, NodeStore, hash_pair, hash_single};
+
+impl<S: NodeStore> MerkleTree<S> {
+    /// Pushes every element of a parallel iterator into the tree, in the iterator's
+    /// order. Elements are hashed in parallel, and every ancestor of the new leaves is
+    /// then recomputed in a single pass over the levels, rather than once per leaf.
+    /// The tree ends up identical to the one `extend` produces over the same elements,
+    /// epoch included. While root changes or operations are watched, by a callback, the
+    /// root history or an operation log, the leaves are pushed one at a time instead,
+    /// so that every intermediate root is still seen.
+    /// * `elements` - The `Hash` values to be added to the tree.
+    pub fn par_extend<H, I>(&mut self, elements: I)
+    where
+        H: Hash + Send,
+        I: IntoParallelIterator<Item = H>,
+    {
+        let leaves: Vec<u64> = elements.into_par_iter().map(hash_single).collect();
+        if self.watched_root().is_some() || self.op_log.is_some() {
+            for leaf in leaves {
+                self.push_hash(leaf);
+            }
+            return;
+        }
+        self.append_leaf_hashes(&leaves);
+    }
+
+    /// Pushes already hashed leaves into the tree, recomputing their ancestors level by
+    /// level. Must not be called while root changes or operations are watched.
+    /// * `leaves` - The leaf hashes to be added to the tree.
+    fn append_leaf_hashes(&mut self, leaves: &[u64]) {
+        if leaves.is_empty() {
+            return;
+        }
+        let first = self.len();
+        let end = first + leaves.len();
+        while self.capacity < end {
+            self.duplicate_capacity();
+        }
+
+        self.levels.release_reads();
+        self.levels.put_batch(0, first, leaves);
+        for (index, &leaf) in (first..end).zip(leaves) {
+            self.occupancy.set(index);
+            self.index_leaf(leaf, index);
+        }
+
+        // Nodes of the level below within `start..stop` are the ones just computed, the
+        // others being read from the store.
+        let (mut start, mut stop) = (first, end);
+        let mut below = leaves.to_vec();
+        for level_n in 1..self.height() {
+            let child = |index: usize| match index.checked_sub(start) {
+                Some(offset) if index < stop => below[offset],
+                _ => self
+                    .levels
+                    .get(level_n - 1, index)
+                    .expect("Children are within their level"),
+            };
+            let parents: Vec<u64> = (start / 2..stop.div_ceil(2))
+                .map(|parent| hash_pair(child(2 * parent), child(2 * parent + 1)))
+                .collect();
+            (start, stop) = (start / 2, stop.div_ceil(2));
+            if level_n >= self.levels.lowest_written() {
+                self.levels.put_batch(level_n, start, &parents);
+            }
+            below = parents;
+        }
+
+        self.padding -= leaves.len();
+        self.epoch += leaves.len() as u64;
+        self.invalidate_proof_cache();
+    }
+}
+
+/// Builds a tree out of the elements of a parallel iterator, in the iterator's order,
+/// hashing them in parallel. The tree is identical to the one `build` produces.
+impl<H: Hash + Send> FromParallelIterator<H> for MerkleTree {
+    fn from_par_iter<I: IntoParallelIterator<Item = H>>(elements: I) -> MerkleTree {
+        let leaves = elements.into_par_iter().map(hash_single).collect();
+        MerkleTree::from_leaf_hashes(leaves)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use rayon::prelude::*;
+
+    use super::*;
+    use crate::FileStore;
+
+    /// Asserts that two trees hold the same nodes, length, capacity and epoch.
+    fn assert_identical(tree: &MerkleTree, expected: &MerkleTree) {
+        assert_eq!(tree.len(), expected.len());
+        assert_eq!(tree.capacity(), expected.capacity());
+        assert_eq!(tree.epoch(), expected.epoch());
+        assert!(tree.levels_iter().eq(expected.levels_iter()));
+    }
+
+    #[test]
+    fn parallel_extends_match_sequential_ones() {
+        let mut tree = MerkleTree::build(&(0..1000).collect::<Vec<u64>>());
+        let mut expected = MerkleTree::build(&(0..1000).collect::<Vec<u64>>());
+        tree.par_extend((1000..1_000_000_u64).into_par_iter());
+        expected.extend(1000..1_000_000_u64);
+        assert_identical(&tree, &expected);
+        assert_eq!(tree.stats().growth_events, expected.stats().growth_events);
+
+        // Unindexed iterators keep their order too.
+        tree.par_extend((0..5000_u64).into_par_iter().filter(|value| value % 3 == 0));
+        expected.extend((0..5000_u64).filter(|value| value % 3 == 0));
+        assert_identical(&tree, &expected);
+        tree.validate().unwrap();
+    }
+
+    #[test]
+    fn small_and_unusual_extends() {
+        for (len, extra) in [(0, 1), (1, 1), (3, 5), (8, 8), (13, 100), (4, 0)] {
+            let values: Vec<u32> = (0..len).collect();
+            let extra: Vec<u32> = (len..len + extra).collect();
+            let mut tree = MerkleTree::build(&values);
+            let mut expected = MerkleTree::build(&values);
+            tree.par_extend(extra.par_iter());
+            expected.extend(&extra);
+            assert_identical(&tree, &expected);
+        }
+
+        let values: Vec<u32> = (0..1000).collect();
+        let mut tree = MerkleTree::build(&values[..10]);
+        tree.keep_leaves_only(2).unwrap();
+        tree.enable_index();
+        tree.par_extend(values[10..].par_iter());
+        assert_eq!(tree.root(), MerkleTree::build(&values).root());
+        assert_eq!(tree.index_of(&999_u32), Some(999));
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::create(dir.path()).unwrap();
+        let mut tree = MerkleTree::build_in(store, &values[..10]);
+        tree.par_extend(values[10..].par_iter());
+        assert_eq!(tree.root(), MerkleTree::build(&values).root());
+        assert!(tree.get_proof(500).verify(500_u32));
+    }
+
+    #[test]
+    fn watched_trees_see_every_root() {
+        let roots = Arc::new(Mutex::new(Vec::new()));
+        let mut tree = MerkleTree::build(&[0_u32]);
+        let seen = Arc::clone(&roots);
+        tree.on_root_change(move |_, new| seen.lock().unwrap().push(new));
+        tree.par_extend((1..10_u32).into_par_iter());
+        assert_eq!(roots.lock().unwrap().len(), 9);
+        assert_eq!(roots.lock().unwrap().last(), tree.root().as_ref());
+    }
+
+    #[test]
+    fn collected_trees_match_built_ones() {
+        let tree: MerkleTree = (0..10_000_u32).into_par_iter().collect();
+        assert_identical(
+            &tree,
+            &MerkleTree::build(&(0..10_000).collect::<Vec<u32>>()),
+        );
+    }
+}
@@ -0,0 +1,411 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use crate::{MerkleTree, hash_pair};
+
+/// Growable sequence of bits, packed eight per byte, least significant bit first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BitVec {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl BitVec {
+    /// Creates an empty sequence.
+    pub fn new() -> BitVec {
+        BitVec::default()
+    }
+
+    /// Creates a sequence out of packed bytes, as returned by `as_bytes`, or returns
+    /// `None` if the amount of bytes is not the one needed by `len` bits or if the bits
+    /// past `len` are not cleared.
+    /// * `bytes` - The packed bits.
+    /// * `len` - Amount of bits.
+    pub fn from_bytes(bytes: Vec<u8>, len: usize) -> Option<BitVec> {
+        if bytes.len() != len.div_ceil(8) {
+            return None;
+        }
+        let trailing = len % 8;
+        if trailing != 0 && bytes[bytes.len() - 1] >> trailing != 0 {
+            return None;
+        }
+        Some(BitVec { bytes, len })
+    }
+
+    /// Returns the amount of bits.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the sequence holds no bits.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the bit at an index, or `None` if it is out of range.
+    /// * `index` - Index of the bit.
+    pub fn get(&self, index: usize) -> Option<bool> {
+        if index >= self.len {
+            return None;
+        }
+        Some(self.bytes[index / 8] & (1 << (index % 8)) != 0)
+    }
+
+    /// Appends a bit.
+    /// * `bit` - The bit to be added.
+    pub fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit {
+            self.bytes[self.len / 8] |= 1 << (self.len % 8);
+        }
+        self.len += 1;
+    }
+
+    /// Flips the bit at an index.
+    /// Panics if the index is out of range.
+    /// * `index` - Index of the bit.
+    pub fn toggle(&mut self, index: usize) {
+        assert!(index < self.len, "bit {index} is out of range");
+        self.bytes[index / 8] ^= 1 << (index % 8);
+    }
+
+    /// Returns the packed bits, the unused high bits of the last byte being cleared.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns an iterator over the bits, in order.
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len).map(|index| self.get(index) == Some(true))
+    }
+}
+
+/// Pruned tree in the layout of Bitcoin's "merkleblock" messages: a depth-first, left
+/// to right traversal from the root, which records a flag per visited node and a hash
+/// per node it does not descend into.
+/// A node's flag is set if it covers a matched leaf. The traversal descends into flagged
+/// nodes, except for leaves, and records the hash of every other visited node: the
+/// roots of the subtrees without matches, and the matched leaves themselves.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartialBlock {
+    /// Flag of every visited node, in traversal order.
+    pub flags: BitVec,
+    /// Hash of every node the traversal does not descend into, in traversal order.
+    pub hashes: Vec<u64>,
+}
+
+/// Error returned when a `PartialBlock` does not describe a pruned tree of the given
+/// root and length.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtractError {
+    /// No tree has this amount of leaves: it is either zero or its capacity overflows.
+    LeafCount(usize),
+    /// The traversal needs more flags than the block holds.
+    FlagsExhausted,
+    /// The traversal needs more hashes than the block holds.
+    HashesExhausted,
+    /// The traversal ended before using every flag.
+    SurplusFlags { unused: usize },
+    /// The traversal ended before using every hash.
+    SurplusHashes { unused: usize },
+    /// A flagged node covers padding slots only.
+    MatchInPadding { level: usize, index: usize },
+    /// The reconstructed root is not the trusted one.
+    RootMismatch,
+}
+
+impl Display for ExtractError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractError::LeafCount(len) => write!(f, "no tree has {len} leaves"),
+            ExtractError::FlagsExhausted => write!(f, "not enough flags"),
+            ExtractError::HashesExhausted => write!(f, "not enough hashes"),
+            ExtractError::SurplusFlags { unused } => write!(f, "{unused} flags left unused"),
+            ExtractError::SurplusHashes { unused } => write!(f, "{unused} hashes left unused"),
+            ExtractError::MatchInPadding { level, index } => {
+                write!(
+                    f,
+                    "node ({level}, {index}) is flagged but covers padding only"
+                )
+            }
+            ExtractError::RootMismatch => write!(f, "the reconstructed root does not match"),
+        }
+    }
+}
+
+impl Error for ExtractError {}
+
+impl MerkleTree {
+    /// Returns the pruned tree holding the given leaves, in the layout of Bitcoin's
+    /// "merkleblock" messages (see `PartialBlock`). Unlike a proof per leaf, the shared
+    /// parts of the paths are only encoded once.
+    /// Panics if an index does not hold a leaf whose hash is held: padding, indices
+    /// beyond the tree's length and pruned leaves (see `from_frontier`).
+    /// * `matched_indices` - Indices of the leaves to be included, in any order.
+    pub fn to_partial(&self, matched_indices: &[usize]) -> PartialBlock {
+        let mut matched = matched_indices.to_vec();
+        matched.sort_unstable();
+        matched.dedup();
+        for &index in &matched {
+            assert!(
+                self.leaf(index).is_some(),
+                "index {index} does not hold a provable leaf"
+            );
+        }
+
+        let mut block = PartialBlock {
+            flags: BitVec::new(),
+            hashes: Vec::new(),
+        };
+        self.build_partial(self.height() - 1, 0, &matched, &mut block);
+        block
+    }
+
+    /// Appends the traversal of a subtree to a partial block.
+    /// * `matched` - Sorted indices of the matched leaves.
+    fn build_partial(
+        &self,
+        level: usize,
+        index: usize,
+        matched: &[usize],
+        block: &mut PartialBlock,
+    ) {
+        let first = index << level;
+        let last = first + (1 << level);
+        let covers_match = matched.partition_point(|&leaf| leaf < first)
+            < matched.partition_point(|&leaf| leaf < last);
+
+        block.flags.push(covers_match);
+        if level == 0 || !covers_match {
+            block.hashes.push(self.levels[(level, index)]);
+        } else {
+            self.build_partial(level - 1, 2 * index, matched, block);
+            self.build_partial(level - 1, 2 * index + 1, matched, block);
+        }
+    }
+}
+
+/// Position of a traversal over the flags and hashes of a `PartialBlock`.
+struct Cursor<'a> {
+    block: &'a PartialBlock,
+    flags: usize,
+    hashes: usize,
+    len: usize,
+    matches: Vec<(usize, u64)>,
+}
+
+impl Cursor<'_> {
+    /// Returns the hash of a subtree, collecting its matched leaves.
+    fn traverse(&mut self, level: usize, index: usize) -> Result<u64, ExtractError> {
+        let flag = self
+            .block
+            .flags
+            .get(self.flags)
+            .ok_or(ExtractError::FlagsExhausted)?;
+        self.flags += 1;
+        if flag && index << level >= self.len {
+            return Err(ExtractError::MatchInPadding { level, index });
+        }
+
+        if level == 0 || !flag {
+            let hash = *self
+                .block
+                .hashes
+                .get(self.hashes)
+                .ok_or(ExtractError::HashesExhausted)?;
+            self.hashes += 1;
+            if flag {
+                self.matches.push((index, hash));
+            }
+            return Ok(hash);
+        }
+        let left = self.traverse(level - 1, 2 * index)?;
+        let right = self.traverse(level - 1, 2 * index + 1)?;
+        Ok(hash_pair(left, right))
+    }
+}
+
+impl PartialBlock {
+    /// Returns the index and hash of every matched leaf, in index order, checking that
+    /// the block is a well formed pruned tree of the given root: every flag and hash
+    /// must be used, and no flagged node may cover padding only.
+    /// * `root` - The trusted root of the tree.
+    /// * `total_leaves` - Length of the tree, which gives its shape.
+    pub fn extract(
+        &self,
+        root: u64,
+        total_leaves: usize,
+    ) -> Result<Vec<(usize, u64)>, ExtractError> {
+        let capacity = total_leaves
+            .checked_next_power_of_two()
+            .filter(|_| total_leaves > 0)
+            .ok_or(ExtractError::LeafCount(total_leaves))?;
+
+        let mut cursor = Cursor {
+            block: self,
+            flags: 0,
+            hashes: 0,
+            len: total_leaves,
+            matches: Vec::new(),
+        };
+        let computed = cursor.traverse(capacity.trailing_zeros() as usize, 0)?;
+        if cursor.flags != self.flags.len() {
+            return Err(ExtractError::SurplusFlags {
+                unused: self.flags.len() - cursor.flags,
+            });
+        }
+        if cursor.hashes != self.hashes.len() {
+            return Err(ExtractError::SurplusHashes {
+                unused: self.hashes.len() - cursor.hashes,
+            });
+        }
+        if computed != root {
+            return Err(ExtractError::RootMismatch);
+        }
+        Ok(cursor.matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the tree shared by the tests: 13 leaves, so 3 padding slots.
+    fn tree() -> MerkleTree {
+        MerkleTree::build(&(0..13).collect::<Vec<i32>>())
+    }
+
+    #[test]
+    fn match_patterns_extract() {
+        let tree = tree();
+        let root = tree.root().unwrap();
+        let patterns: [&[usize]; 6] = [
+            &[],
+            &[0],
+            &[12],
+            &[1, 4, 5, 11],
+            &[7, 6, 7],
+            &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12],
+        ];
+
+        for pattern in patterns {
+            let block = tree.to_partial(pattern);
+            let mut expected: Vec<usize> = pattern.to_vec();
+            expected.sort_unstable();
+            expected.dedup();
+            let expected: Vec<(usize, u64)> = expected
+                .into_iter()
+                .map(|index| (index, tree.leaves()[index]))
+                .collect();
+            assert_eq!(block.extract(root, 13), Ok(expected), "{pattern:?}");
+        }
+
+        // Without matches, the block is the root alone.
+        let block = tree.to_partial(&[]);
+        assert_eq!(block.flags.iter().collect::<Vec<bool>>(), [false]);
+        assert_eq!(block.hashes, [root]);
+
+        // A single match takes a flag per level, plus one per sibling.
+        let block = tree.to_partial(&[12]);
+        assert_eq!(block.flags.len(), 9);
+        assert_eq!(block.hashes.len(), 5);
+    }
+
+    #[test]
+    fn malformed_blocks_rejected() {
+        let tree = tree();
+        let root = tree.root().unwrap();
+        let block = tree.to_partial(&[1, 4, 5, 11]);
+
+        let mut surplus = block.clone();
+        surplus.hashes.push(7);
+        assert_eq!(
+            surplus.extract(root, 13),
+            Err(ExtractError::SurplusHashes { unused: 1 })
+        );
+
+        let mut surplus = block.clone();
+        surplus.flags.push(false);
+        assert_eq!(
+            surplus.extract(root, 13),
+            Err(ExtractError::SurplusFlags { unused: 1 })
+        );
+
+        let mut missing = block.clone();
+        missing.hashes.pop();
+        assert_eq!(
+            missing.extract(root, 13),
+            Err(ExtractError::HashesExhausted)
+        );
+
+        // A corrupted flag never yields a forged leaf: the block is either rejected, or
+        // a leaf whose hash it already held becomes matched.
+        let mut rejected = 0;
+        for index in 0..block.flags.len() {
+            let mut corrupted = block.clone();
+            corrupted.flags.toggle(index);
+            match corrupted.extract(root, 13) {
+                Err(_) => rejected += 1,
+                Ok(matches) => {
+                    for (leaf, hash) in matches {
+                        assert_eq!(tree.leaves()[leaf], hash, "flag {index}");
+                    }
+                }
+            }
+        }
+        assert!(rejected > block.flags.len() / 2);
+
+        let mut corrupted = block.clone();
+        corrupted.flags.toggle(0);
+        assert_eq!(
+            corrupted.extract(root, 13),
+            Err(ExtractError::SurplusFlags {
+                unused: block.flags.len() - 1
+            })
+        );
+
+        let mut corrupted = block.clone();
+        corrupted.hashes[0] ^= 1;
+        assert_eq!(corrupted.extract(root, 13), Err(ExtractError::RootMismatch));
+        assert_eq!(block.extract(root ^ 1, 13), Err(ExtractError::RootMismatch));
+        assert_eq!(block.extract(root, 17), Err(ExtractError::FlagsExhausted));
+        assert_eq!(block.extract(root, 0), Err(ExtractError::LeafCount(0)));
+    }
+
+    #[test]
+    fn padding_matches_rejected() {
+        // Flagging the padding slot of a 3 leaf tree: root, both children, and the
+        // leaves of the right one.
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let mut block = tree.to_partial(&[2]);
+        assert_eq!(
+            block.flags.iter().collect::<Vec<bool>>(),
+            [true, false, true, true, false]
+        );
+        block.flags.toggle(4);
+        assert_eq!(
+            block.extract(tree.root().unwrap(), 3),
+            Err(ExtractError::MatchInPadding { level: 0, index: 3 })
+        );
+    }
+
+    #[test]
+    fn bits_round_trip() {
+        let mut bits = BitVec::new();
+        for bit in [true, false, true, true, false, false, false, false, true] {
+            bits.push(bit);
+        }
+        assert_eq!(bits.as_bytes(), [0b0000_1101, 0b1]);
+        assert_eq!(BitVec::from_bytes(bits.as_bytes().to_vec(), 9), Some(bits));
+        assert_eq!(BitVec::from_bytes(vec![0b10], 1), None);
+        assert_eq!(BitVec::from_bytes(vec![0, 0], 8), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "index 13")]
+    fn padding_not_provable() {
+        tree().to_partial(&[13]);
+    }
+}
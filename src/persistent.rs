@@ -0,0 +1,446 @@
+use std::fmt::{self, Debug, Formatter};
+use std::hash::Hash;
+use std::sync::Arc;
+
+use crate::diff::same_node;
+use crate::{DiffReport, MerkleProof, MerkleTree, hash_pair, hash_single};
+
+/// Node of a `PersistentMerkleTree`, shared by every version holding it.
+enum Node {
+    Leaf(u64),
+    Branch {
+        hash: u64,
+        left: Arc<Node>,
+        right: Arc<Node>,
+    },
+}
+
+impl Node {
+    /// Returns the hash of the node.
+    fn hash(&self) -> u64 {
+        match self {
+            Node::Leaf(hash) | Node::Branch { hash, .. } => *hash,
+        }
+    }
+
+    /// Allocates the parent of two nodes.
+    fn branch(left: Arc<Node>, right: Arc<Node>) -> Arc<Node> {
+        Arc::new(Node::Branch {
+            hash: hash_pair(left.hash(), right.hash()),
+            left,
+            right,
+        })
+    }
+
+    /// Returns the children of a branch.
+    fn children(&self) -> (&Arc<Node>, &Arc<Node>) {
+        match self {
+            Node::Branch { left, right, .. } => (left, right),
+            Node::Leaf(_) => unreachable!("Only levels above the leaves are descended"),
+        }
+    }
+}
+
+/// Returns a subtree of the given level filled with padding only. Its levels are made of
+/// a single node each, shared by both children of the level above.
+fn empty_subtree(level: usize) -> Arc<Node> {
+    let mut node = Arc::new(Node::Leaf(MerkleTree::PAD_HASH));
+    for _ in 0..level {
+        node = Node::branch(Arc::clone(&node), node);
+    }
+    node
+}
+
+/// Returns a copy of a subtree where a leaf is replaced. Only the nodes along the
+/// leaf's path are allocated: every other node is shared with the original subtree.
+/// * `node` - Root of the subtree.
+/// * `level` - Level of the subtree's root.
+/// * `index` - Index of the leaf within the subtree.
+/// * `leaf` - The new leaf hash.
+fn with_leaf(node: &Arc<Node>, level: usize, index: usize, leaf: u64) -> Arc<Node> {
+    if level == 0 {
+        return Arc::new(Node::Leaf(leaf));
+    }
+    let (left, right) = node.children();
+    if index >> (level - 1) & 1 == 0 {
+        Node::branch(with_leaf(left, level - 1, index, leaf), Arc::clone(right))
+    } else {
+        Node::branch(Arc::clone(left), with_leaf(right, level - 1, index, leaf))
+    }
+}
+
+/// Collects the differing leaves of two subtrees covering the same leaf slots.
+/// * `first` - Root of the first subtree.
+/// * `second` - Root of the second subtree.
+/// * `level` - Level of both roots.
+/// * `first_leaf` - Index of the first leaf covered by the subtrees.
+/// * `end` - Index of the first leaf not to be compared.
+/// * `differing` - Where the differing indices are collected.
+fn diff_nodes(
+    first: &Arc<Node>,
+    second: &Arc<Node>,
+    level: usize,
+    first_leaf: usize,
+    end: usize,
+    differing: &mut Vec<usize>,
+) {
+    if first_leaf >= end || same_node(first.hash(), second.hash()) {
+        return;
+    }
+    if level == 0 {
+        differing.push(first_leaf);
+        return;
+    }
+    let (first_left, first_right) = first.children();
+    let (second_left, second_right) = second.children();
+    diff_nodes(
+        first_left,
+        second_left,
+        level - 1,
+        first_leaf,
+        end,
+        differing,
+    );
+    let middle = first_leaf + (1 << (level - 1));
+    diff_nodes(first_right, second_right, level - 1, middle, end, differing);
+}
+
+/// Immutable variant of `MerkleTree`, for keeping many versions of a tree alive at once.
+/// Updates return a new tree instead of mutating it, which shares every unchanged
+/// subtree with the original: each one allocates a node per level, not a copy of the
+/// tree. Cloning a tree only clones a handle.
+/// Roots and proofs are the same as the ones of a `MerkleTree` holding the same leaves.
+#[derive(Clone)]
+pub struct PersistentMerkleTree {
+    root: Arc<Node>,
+    height: usize,
+    len: usize,
+    epoch: u64,
+}
+
+impl PersistentMerkleTree {
+    /// Creates an empty tree, of capacity 1.
+    pub fn new() -> PersistentMerkleTree {
+        PersistentMerkleTree {
+            root: empty_subtree(0),
+            height: 1,
+            len: 0,
+            epoch: 0,
+        }
+    }
+
+    /// Constructs a tree populated with the provided elements as leaf nodes.
+    /// * `elements` - array of `Hash` elements used to populate the tree.
+    pub fn build<H: Hash>(elements: &[H]) -> PersistentMerkleTree {
+        let mut nodes: Vec<Arc<Node>> = elements
+            .iter()
+            .map(|element| Arc::new(Node::Leaf(hash_single(element))))
+            .collect();
+        let len = nodes.len();
+        nodes.resize_with(len.next_power_of_two(), || empty_subtree(0));
+
+        let mut height = 1;
+        while nodes.len() > 1 {
+            nodes = nodes
+                .chunks(2)
+                .map(|pair| Node::branch(Arc::clone(&pair[0]), Arc::clone(&pair[1])))
+                .collect();
+            height += 1;
+        }
+        PersistentMerkleTree {
+            root: nodes.swap_remove(0),
+            height,
+            len,
+            epoch: 0,
+        }
+    }
+
+    /// Returns the height of the tree.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the capacity of the tree.
+    pub fn capacity(&self) -> usize {
+        1 << (self.height - 1)
+    }
+
+    /// Returns the length of the tree.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns wether a tree has no elements or not.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the epoch of the tree: the amount of updates leading to it.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Returns the root of the tree. If the tree is empty, the root will be `None`.
+    pub fn root(&self) -> Option<u64> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(self.root.hash())
+    }
+
+    /// Returns the hash stored at an occupied leaf.
+    /// * `index` - Index of the leaf.
+    pub fn leaf(&self, index: usize) -> Option<u64> {
+        if index >= self.len {
+            return None;
+        }
+        let mut node = &self.root;
+        for level in (1..self.height).rev() {
+            let (left, right) = node.children();
+            node = if index >> (level - 1) & 1 == 0 {
+                left
+            } else {
+                right
+            };
+        }
+        Some(node.hash())
+    }
+
+    /// Returns a new tree holding the elements of this one followed by an `Hash`
+    /// element. This tree is left untouched.
+    /// If the tree is full, the new one has twice its capacity.
+    /// * `value` - The `Hash` value to be added to the tree.
+    pub fn push<H: Hash>(&self, value: H) -> PersistentMerkleTree {
+        let mut root = Arc::clone(&self.root);
+        let mut height = self.height;
+        if self.len == self.capacity() && !self.is_empty() {
+            root = Node::branch(root, empty_subtree(height - 1));
+            height += 1;
+        }
+        PersistentMerkleTree {
+            root: with_leaf(&root, height - 1, self.len, hash_single(value)),
+            height,
+            len: self.len + 1,
+            epoch: self.epoch + 1,
+        }
+    }
+
+    /// Returns a new tree where the element at an index is replaced by an `Hash`
+    /// element. This tree is left untouched.
+    /// Panics if the index does not hold an element.
+    /// * `index` - Index of the leaf.
+    /// * `value` - The new `Hash` value of the leaf.
+    pub fn set<H: Hash>(&self, index: usize, value: H) -> PersistentMerkleTree {
+        assert!(
+            index < self.len,
+            "index {index} is out of range for a tree of length {}",
+            self.len
+        );
+        PersistentMerkleTree {
+            root: with_leaf(&self.root, self.height - 1, index, hash_single(value)),
+            height: self.height,
+            len: self.len,
+            epoch: self.epoch + 1,
+        }
+    }
+
+    /// Returns the leaves which differ from the ones of another tree, like
+    /// `MerkleTree::diff`. Only subtrees whose hashes differ are descended into, so
+    /// versions of a tree sharing most of their nodes are compared in O(d log n) for `d`
+    /// differing leaves.
+    /// * `other` - The tree to be compared with.
+    pub fn diff(&self, other: &PersistentMerkleTree) -> DiffReport {
+        let shared = self.len.min(other.len);
+        let mut differing = Vec::new();
+        if shared > 0 {
+            // A node covers the same leaf slots in both trees whatever their heights.
+            let height = self.height.min(other.height);
+            let (first, second) = (self.leftmost(height), other.leftmost(height));
+            diff_nodes(first, second, height - 1, 0, shared, &mut differing);
+        }
+        DiffReport {
+            differing,
+            only_in_self: shared..self.len,
+            only_in_other: shared..other.len,
+        }
+    }
+
+    /// Returns the leftmost node of the level holding the roots of subtrees of a height.
+    /// * `height` - The height of the subtrees, at most the tree's.
+    fn leftmost(&self, height: usize) -> &Arc<Node> {
+        let mut node = &self.root;
+        for _ in height..self.height {
+            node = node.children().0;
+        }
+        node
+    }
+
+    /// Returns a handle to the root node, which is dropped along with the last version
+    /// holding it.
+    #[cfg(test)]
+    pub(crate) fn root_handle(&self) -> std::sync::Weak<impl Sized + use<>> {
+        Arc::downgrade(&self.root)
+    }
+
+    /// Creates a `MerkleProof` for a given index, the same as the one of a `MerkleTree`
+    /// holding the same leaves.
+    /// Attempting to create a proof for an index which does not hold an element will
+    /// return a `MerkleProof::Invalid` value.
+    /// * `index` - index value to generate the proof for.
+    pub fn get_proof(&self, index: usize) -> MerkleProof {
+        let Some(root) = self.root() else {
+            return MerkleProof::Invalid;
+        };
+        if index >= self.len {
+            return MerkleProof::Invalid;
+        }
+
+        let mut nodes = Vec::with_capacity(self.height - 1);
+        let mut node = &self.root;
+        for level in (1..self.height).rev() {
+            let (left, right) = node.children();
+            let (next, sibling) = if index >> (level - 1) & 1 == 0 {
+                (left, right)
+            } else {
+                (right, left)
+            };
+            nodes.push(sibling.hash());
+            node = next;
+        }
+        nodes.reverse();
+
+        MerkleProof::Proof {
+            index,
+            nodes,
+            root,
+            len: self.len,
+            epoch: self.epoch,
+        }
+    }
+}
+
+impl Default for PersistentMerkleTree {
+    fn default() -> PersistentMerkleTree {
+        PersistentMerkleTree::new()
+    }
+}
+
+impl Debug for PersistentMerkleTree {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PersistentMerkleTree")
+            .field("len", &self.len)
+            .field("capacity", &self.capacity())
+            .field("height", &self.height)
+            .field("root", &self.root())
+            .field("epoch", &self.epoch)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    /// Collects the addresses of every node reachable from a subtree.
+    fn collect_nodes(node: &Arc<Node>, seen: &mut HashSet<*const Node>) {
+        if !seen.insert(Arc::as_ptr(node)) {
+            return;
+        }
+        if let Node::Branch { left, right, .. } = node.as_ref() {
+            collect_nodes(left, seen);
+            collect_nodes(right, seen);
+        }
+    }
+
+    #[test]
+    fn versions_share_subtrees() {
+        let mut versions = vec![PersistentMerkleTree::new()];
+        for value in 0..1000 {
+            let next = versions.last().unwrap().push(value);
+            versions.push(next);
+        }
+
+        // Old versions keep answering, the same as a tree built from their leaves.
+        let values: Vec<i32> = (0..1000).collect();
+        for len in [1, 2, 5, 64, 65, 333, 1000] {
+            let version = &versions[len];
+            let tree = MerkleTree::build(&values[..len]);
+            assert_eq!(version.root(), tree.root());
+            assert_eq!(version.capacity(), tree.capacity());
+            for index in [0, len / 2, len - 1] {
+                let proof = version.get_proof(index);
+                assert!(proof.verify(index as i32), "version {len}, leaf {index}");
+                let MerkleProof::Proof { nodes, .. } = tree.get_proof(index) else {
+                    panic!("leaf {index} of the tree is provable");
+                };
+                assert!(
+                    matches!(proof, MerkleProof::Proof { nodes: ref path, .. } if *path == nodes)
+                );
+            }
+            assert_eq!(version.get_proof(len), MerkleProof::Invalid);
+        }
+        assert_eq!(versions[0].root(), None);
+
+        // Copying each version would take about a million nodes: sharing takes a path
+        // per push, plus the padding subtrees added by each growth.
+        let mut seen = HashSet::new();
+        for version in &versions {
+            collect_nodes(&version.root, &mut seen);
+        }
+        let copied: usize = versions.iter().map(|v| 2 * v.capacity() - 1).sum();
+        assert!(copied > 1_000_000);
+        assert!(seen.len() <= 1000 * 11 + 11 * 11, "{} nodes", seen.len());
+    }
+
+    #[test]
+    fn set_leaves_old_versions_untouched() {
+        let old = PersistentMerkleTree::build(&[1, 2, 3, 4, 5]);
+        let new = old.set(3, 40);
+
+        assert_eq!(old.leaf(3), Some(hash_single(4)));
+        assert_eq!(new.leaf(3), Some(hash_single(40)));
+        assert_eq!(old.root(), MerkleTree::build(&[1, 2, 3, 4, 5]).root());
+        assert_eq!(new.root(), MerkleTree::build(&[1, 2, 3, 40, 5]).root());
+        assert!(old.get_proof(3).verify(4));
+        assert!(new.get_proof(3).verify(40));
+        assert!(!new.get_proof(3).verify(4));
+
+        // Only the path of the replaced leaf differs.
+        let (mut old_nodes, mut new_nodes) = (HashSet::new(), HashSet::new());
+        collect_nodes(&old.root, &mut old_nodes);
+        collect_nodes(&new.root, &mut new_nodes);
+        assert_eq!(new_nodes.difference(&old_nodes).count(), new.height());
+    }
+
+    #[test]
+    fn diff_matches_tree_diff() {
+        let values: Vec<u32> = (0..300).collect();
+        let old = PersistentMerkleTree::build(&values[..200]);
+        let mut new = old.set(7, 1000).set(150, 1000);
+        for value in &values[200..] {
+            new = new.push(value);
+        }
+
+        let report = old.diff(&new);
+        assert_eq!(report.differing, [7, 150]);
+        assert_eq!(
+            (report.only_in_self, report.only_in_other),
+            (200..200, 200..300)
+        );
+        let mut changed = values.clone();
+        changed[7] = 1000;
+        changed[150] = 1000;
+        let expected = MerkleTree::build(&changed).diff(&MerkleTree::build(&values[..200]));
+        assert_eq!(new.diff(&old), expected);
+        assert!(new.diff(&new).is_empty());
+        assert!(PersistentMerkleTree::new().diff(&new).differing.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "index 5")]
+    fn set_beyond_length_panics() {
+        PersistentMerkleTree::build(&[1, 2, 3, 4, 5]).set(5, 0);
+    }
+}
@@ -0,0 +1,256 @@
+use std::hash::Hash;
+use std::ops::ControlFlow;
+
+use crate::{MemStore, MerkleError, MerkleTree, hash_single};
+
+/// Amount of leaves hashed or nodes combined between two reports of a build's progress,
+/// so that the callback takes a negligible share of the build.
+pub const PROGRESS_INTERVAL: usize = 1 << 14;
+
+/// Progress of a build, as reported to the callback of `MerkleTree::build_with_progress`
+/// and the other builders taking one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Amount of leaves hashed so far.
+    pub leaves_hashed: usize,
+    /// Level whose nodes are being combined out of the ones below it: 0 while the leaves
+    /// are hashed, and the level of the root once the build is done.
+    pub level: usize,
+    /// Amount of nodes above the leaves combined so far, over every level.
+    pub nodes_combined: usize,
+}
+
+/// Callback receiving the progress of a build, which cancels it by returning `Break`.
+/// It is `Send`, like the other callbacks of the crate, so that asynchronous builds
+/// holding it can move between threads.
+type ProgressCallback<'a> = &'a mut (dyn FnMut(Progress) -> ControlFlow<()> + Send);
+
+/// Counts the work done by a build, reporting it to a callback once every
+/// `PROGRESS_INTERVAL` leaves hashed or nodes combined.
+pub(crate) struct Reporter<'a> {
+    callback: Option<ProgressCallback<'a>>,
+    progress: Progress,
+    /// Amount of leaves hashed and nodes combined since the last report.
+    unreported: usize,
+}
+
+impl<'a> Reporter<'a> {
+    /// Creates a reporter calling the given callback.
+    /// * `callback` - Where the progress is reported.
+    pub(crate) fn new(callback: ProgressCallback<'a>) -> Reporter<'a> {
+        Reporter {
+            callback: Some(callback),
+            progress: Progress::default(),
+            unreported: 0,
+        }
+    }
+
+    /// Creates a reporter counting the work without reporting it, for builds which are
+    /// not watched.
+    pub(crate) fn silent() -> Reporter<'static> {
+        Reporter {
+            callback: None,
+            progress: Progress::default(),
+            unreported: 0,
+        }
+    }
+
+    /// Counts a leaf hashed, reporting the progress if it is due.
+    /// Fails with `MerkleError::Cancelled` if the callback cancels the build.
+    pub(crate) fn leaf_hashed(&mut self) -> Result<(), MerkleError> {
+        self.progress.leaves_hashed += 1;
+        self.count()
+    }
+
+    /// Counts a node combined, reporting the progress if it is due.
+    /// Fails with `MerkleError::Cancelled` if the callback cancels the build.
+    /// * `level` - Level of the node.
+    pub(crate) fn node_combined(&mut self, level: usize) -> Result<(), MerkleError> {
+        self.progress.level = level;
+        self.progress.nodes_combined += 1;
+        self.count()
+    }
+
+    /// Reports the progress whether it is due or not, as builds do once done.
+    /// Fails with `MerkleError::Cancelled` if the callback cancels the build.
+    pub(crate) fn report(&mut self) -> Result<(), MerkleError> {
+        self.unreported = 0;
+        let cancelled = self
+            .callback
+            .as_mut()
+            .is_some_and(|callback| callback(self.progress).is_break());
+        if cancelled {
+            return Err(MerkleError::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Counts a leaf hashed or a node combined, reporting the progress if it is due.
+    fn count(&mut self) -> Result<(), MerkleError> {
+        self.unreported += 1;
+        if self.unreported < PROGRESS_INTERVAL {
+            return Ok(());
+        }
+        self.report()
+    }
+}
+
+/// Hashes elements into leaves, reporting every leaf hashed.
+/// Fails with `MerkleError::Cancelled` if the reporter's callback cancels the build.
+/// * `elements` - The `Hash` values to be hashed.
+/// * `reporter` - Where the progress is reported.
+pub(crate) fn hash_reported<H: Hash>(
+    elements: &[H],
+    reporter: &mut Reporter,
+) -> Result<Vec<u64>, MerkleError> {
+    let mut leaves = Vec::with_capacity(elements.len());
+    for element in elements {
+        leaves.push(hash_single(element));
+        reporter.leaf_hashed()?;
+    }
+    Ok(leaves)
+}
+
+impl MerkleTree {
+    /// Constructs a `MerkleTree` like `build`, reporting its progress to a callback once
+    /// every `PROGRESS_INTERVAL` leaves hashed or nodes combined, and once more with the
+    /// final counts when it is done.
+    /// The callback cancels the build by returning `ControlFlow::Break`, in which case no
+    /// tree is built.
+    /// Fails with `MerkleError::Cancelled` if the callback cancels the build.
+    /// * `elements` - array of `Hash` elements used to populate the tree.
+    /// * `progress` - Callback receiving the progress.
+    pub fn build_with_progress<H: Hash>(
+        elements: &[H],
+        mut progress: impl FnMut(Progress) -> ControlFlow<()> + Send,
+    ) -> Result<MerkleTree, MerkleError> {
+        let mut reporter = Reporter::new(&mut progress);
+        let leaves = hash_reported(elements, &mut reporter)?;
+        MerkleTree::from_reported_leaf_hashes(MemStore::default(), leaves, &mut reporter)
+    }
+
+    /// Pushes every element into the tree like `extend`, reporting the progress of their
+    /// hashing as `build_with_progress` does. The ancestors of the new leaves are
+    /// rehashed as each one is pushed, so no nodes are reported as combined.
+    /// Every element is hashed before the first one is pushed, so the tree is left
+    /// untouched if the callback cancels the extension by returning `ControlFlow::Break`.
+    /// The pushes themselves are neither reported nor cancellable, since stopping them
+    /// halfway would leave only some of the elements in the tree: the report made once
+    /// every element is hashed is the last one, and the last chance to cancel.
+    /// Fails with `MerkleError::Cancelled` if the callback cancels the extension.
+    /// * `elements` - The `Hash` values to be added to the tree.
+    /// * `progress` - Callback receiving the progress.
+    pub fn extend_with_progress<H: Hash>(
+        &mut self,
+        elements: &[H],
+        mut progress: impl FnMut(Progress) -> ControlFlow<()> + Send,
+    ) -> Result<(), MerkleError> {
+        let mut reporter = Reporter::new(&mut progress);
+        let leaves = hash_reported(elements, &mut reporter)?;
+        reporter.report()?;
+        for leaf in leaves {
+            self.push_hash(leaf);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns every progress reported while building a tree over `0..len`, and the
+    /// result of the build.
+    fn watched_build(len: u32) -> (Vec<Progress>, Result<MerkleTree, MerkleError>) {
+        let values: Vec<u32> = (0..len).collect();
+        let mut reports = Vec::new();
+        let tree = MerkleTree::build_with_progress(&values, |progress| {
+            reports.push(progress);
+            ControlFlow::Continue(())
+        });
+        (reports, tree)
+    }
+
+    #[test]
+    fn counts_grow_up_to_the_totals() {
+        for len in [0, 1, 5, 100_000] {
+            let (reports, tree) = watched_build(len);
+            let tree = tree.unwrap();
+            assert!(tree == MerkleTree::build(&(0..len).collect::<Vec<u32>>()));
+
+            // Every node above the leaves covering some of them is combined once.
+            let nodes: usize = (1..tree.height())
+                .map(|level| tree.len().div_ceil(1 << level))
+                .sum();
+            let done = Progress {
+                leaves_hashed: tree.len(),
+                level: tree.height() - 1,
+                nodes_combined: nodes,
+            };
+            assert_eq!(reports.last(), Some(&done), "{len} leaves");
+            assert!(reports.len() <= (tree.len() + nodes) / PROGRESS_INTERVAL + 1);
+            for pair in reports.windows(2) {
+                assert!(pair[0].leaves_hashed <= pair[1].leaves_hashed);
+                assert!(pair[0].level <= pair[1].level);
+                assert!(pair[0].nodes_combined <= pair[1].nodes_combined);
+            }
+        }
+    }
+
+    #[test]
+    fn cancelled_builds_stop() {
+        let values: Vec<u32> = (0..100_000).collect();
+        let mut reports = 0;
+        let tree = MerkleTree::build_with_progress(&values, |progress| {
+            reports += 1;
+            if progress.level > 0 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(tree.err(), Some(MerkleError::Cancelled));
+        assert_eq!(reports, 100_000 / PROGRESS_INTERVAL + 1);
+
+        // Even the final report cancels the build.
+        let tree = MerkleTree::build_with_progress(&[1, 2, 3], |_| ControlFlow::Break(()));
+        assert_eq!(tree.err(), Some(MerkleError::Cancelled));
+    }
+
+    #[test]
+    fn cancelled_extensions_leave_the_tree_untouched() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        let root = tree.root();
+        let values: Vec<u32> = (0..50_000).collect();
+        let extended = tree.extend_with_progress(&values, |progress| {
+            if progress.leaves_hashed > PROGRESS_INTERVAL {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(extended, Err(MerkleError::Cancelled));
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.root(), root);
+        assert_eq!(tree.epoch(), 0);
+
+        let mut last = None;
+        tree.extend_with_progress(&values, |progress| {
+            last = Some(progress);
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        assert_eq!(
+            last,
+            Some(Progress {
+                leaves_hashed: values.len(),
+                level: 0,
+                nodes_combined: 0,
+            })
+        );
+        let mut expected = MerkleTree::build(&[1, 2, 3]);
+        expected.extend(&values);
+        assert!(tree == expected);
+        assert_eq!(tree.root(), expected.root());
+    }
+}
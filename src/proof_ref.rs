@@ -0,0 +1,202 @@
+use std::hash::Hash;
+
+use crate::{
+    HashAlgorithm, MemStore, MerkleProof, MerkleTree, TreeHead, ancestor_index, empty_node_table,
+    hash_single, path_root, proof_depth, sibling_index,
+};
+
+/// Proof of inclusion borrowing its nodes from the tree it was created from, see
+/// `MerkleTree::proof_ref`. It verifies exactly like the `MerkleProof` returned by
+/// `get_proof` for the same leaf, without copying the nodes anywhere: each one is read
+/// from the tree's levels when needed.
+#[derive(Clone, Copy, Debug)]
+pub struct ProofRef<'a> {
+    levels: &'a MemStore,
+    index: usize,
+    root: u64,
+    len: usize,
+    epoch: u64,
+}
+
+impl MerkleTree {
+    /// Returns the proof of inclusion of a leaf borrowing its nodes from the tree, so
+    /// that creating it never allocates, or `None` if the index does not hold an
+    /// element or if it is pruned (see `from_frontier`). The tree cannot be mutated while
+    /// the proof is alive. Unlike `get_proof`, the proof cache is not used.
+    /// Trees keeping only their leaves (see `keep_leaves_only`) hash the nodes which are
+    /// not stored whenever they are read.
+    /// * `index` - index value to generate the proof for.
+    pub fn proof_ref(&self, index: usize) -> Option<ProofRef<'_>> {
+        if !self.is_occupied(index) || index < self.pruned {
+            return None;
+        }
+        Some(ProofRef {
+            levels: &self.levels,
+            index,
+            root: self.root()?,
+            len: self.len(),
+            epoch: self.epoch,
+        })
+    }
+}
+
+impl ProofRef<'_> {
+    /// Returns the index of the proven leaf.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the root the proof leads to.
+    pub fn root(&self) -> u64 {
+        self.root
+    }
+
+    /// Returns the length of the tree the proof was created from.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the tree the proof was created from is empty, which never happens.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the epoch of the tree the proof was created from.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Returns the siblings along the path of the leaf, from the leaves' level upwards,
+    /// the same as the nodes of the `MerkleProof`.
+    pub fn nodes(&self) -> impl ExactSizeIterator<Item = u64> + '_ {
+        (0..self.levels.height() - 1).map(|level_n| {
+            self.levels[(level_n, sibling_index(ancestor_index(self.index, level_n)))]
+        })
+    }
+
+    /// Returns whether a given `Hash` value verifies the proof, like
+    /// `MerkleProof::verify`.
+    /// * `value` - The `Hash` value to be tested.
+    pub fn verify<H: Hash>(&self, value: H) -> bool {
+        // The path must have the shape the proof's length gives it, as an owned proof's.
+        let depth = proof_depth(self.len);
+        let consistent = self.nodes().len() >= depth
+            && self
+                .nodes()
+                .skip(depth)
+                .zip(&empty_node_table()[depth..])
+                .all(|(node, &empty)| node == empty);
+        consistent && path_root(self.index, hash_single(value), self.nodes()) == self.root
+    }
+
+    /// Returns whether a given `Hash` value verifies the proof, and the proof leads to
+    /// the trusted root, like `MerkleProof::verify_with_root`.
+    /// * `root` - The trusted root.
+    /// * `value` - The `Hash` value to be tested.
+    pub fn verify_with_root<H: Hash>(&self, root: u64, value: H) -> bool {
+        self.root == root && self.verify(value)
+    }
+
+    /// Returns whether a given `Hash` value verifies the proof against a trusted
+    /// `TreeHead`, like `MerkleProof::verify_against`.
+    /// * `head` - The trusted head of the tree.
+    /// * `value` - The `Hash` value to be tested.
+    pub fn verify_against<H: Hash>(&self, head: &TreeHead, value: H) -> bool {
+        self.root == head.root
+            && self.len == head.len
+            && head.algo == HashAlgorithm::DefaultHasher
+            && self.verify(value)
+    }
+
+    /// Copies the proof into a `MerkleProof`, equal to the one `get_proof` returns.
+    pub fn to_owned(&self) -> MerkleProof {
+        MerkleProof::Proof {
+            index: self.index,
+            nodes: self.nodes().collect(),
+            root: self.root,
+            len: self.len,
+            epoch: self.epoch,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    use super::*;
+
+    /// Allocator counting the allocations of the current thread, so that tests running in
+    /// parallel do not count each other's.
+    struct CountingAlloc;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.with(|allocations| allocations.set(allocations.get() + 1));
+            // SAFETY: forwarded as is to the system allocator.
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            // SAFETY: forwarded as is to the system allocator.
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+    /// Returns the amount of allocations made by the current thread while running `run`.
+    fn allocations<T>(run: impl FnOnce() -> T) -> (T, usize) {
+        let before = ALLOCATIONS.with(Cell::get);
+        let result = run();
+        (result, ALLOCATIONS.with(Cell::get) - before)
+    }
+
+    #[test]
+    fn borrowed_proofs_do_not_allocate() {
+        let values: Vec<u32> = (0..1000).collect();
+        let tree = MerkleTree::build(&values);
+        // Warms up the table of empty nodes, which is allocated once.
+        assert!(tree.proof_ref(0).unwrap().verify(0));
+
+        let (verified, count) = allocations(|| {
+            values
+                .iter()
+                .enumerate()
+                .all(|(index, value)| tree.proof_ref(index).unwrap().verify(value))
+        });
+        assert!(verified);
+        assert_eq!(count, 0);
+
+        let (_, count) = allocations(|| tree.get_proof(7));
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn borrowed_proofs_match_owned_ones() {
+        let values: Vec<u32> = (0..37).collect();
+        let mut tree = MerkleTree::build(&values);
+        tree.push(37);
+        let head = tree.head().unwrap();
+        for index in 0..tree.len() {
+            let borrowed = tree.proof_ref(index).unwrap();
+            let owned = tree.get_proof(index);
+            assert_eq!(borrowed.to_owned(), owned);
+            assert_eq!(borrowed.epoch(), 1);
+            assert!(borrowed.verify(index as u32));
+            assert!(!borrowed.verify(index as u32 + 1));
+            assert!(borrowed.verify_with_root(head.root, index as u32));
+            assert!(owned.verify_with_root(head.root, index as u32));
+            assert!(!borrowed.verify_with_root(0, index as u32));
+            assert!(borrowed.verify_against(&head, index as u32));
+        }
+        assert!(tree.proof_ref(38).is_none());
+        assert!(MerkleTree::build::<u32>(&[]).proof_ref(0).is_none());
+    }
+}
@@ -0,0 +1,330 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use prost::Message;
+
+use crate::{DecodeOptions, HashAlgorithm, LimitError, MerkleProof, TreeHead, is_consistent_path};
+
+/// Protobuf schema of `ProtoMerkleProof` and `ProtoTreeHead`, for other languages to
+/// generate their own types from the same definitions.
+pub const PROTO_SCHEMA: &str = include_str!("../proto/merkle_tree.proto");
+
+/// Width of every hash of the messages, in bytes.
+const HASH_BYTES: usize = size_of::<u64>();
+
+/// The `MerkleProof` message of `PROTO_SCHEMA`, converted from and into a `MerkleProof`.
+#[derive(Clone, PartialEq, Message)]
+pub struct ProtoMerkleProof {
+    #[prost(uint64, tag = "1")]
+    pub index: u64,
+    #[prost(bytes = "vec", repeated, tag = "2")]
+    pub nodes: Vec<Vec<u8>>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub root: Vec<u8>,
+    #[prost(uint64, tag = "4")]
+    pub len: u64,
+    #[prost(uint64, tag = "5")]
+    pub epoch: u64,
+}
+
+/// The `TreeHead` message of `PROTO_SCHEMA`, converted from and into a `TreeHead`.
+#[derive(Clone, PartialEq, Message)]
+pub struct ProtoTreeHead {
+    #[prost(bytes = "vec", tag = "1")]
+    pub root: Vec<u8>,
+    #[prost(uint64, tag = "2")]
+    pub len: u64,
+    #[prost(uint32, tag = "3")]
+    pub algo: u32,
+    #[prost(uint64, tag = "4")]
+    pub epoch: u64,
+}
+
+/// Error returned when a protobuf message cannot be converted into a crate type.
+#[derive(Debug, PartialEq)]
+pub enum ProtoError {
+    /// The input is not a valid encoding of the message.
+    Decode(prost::DecodeError),
+    /// The input exceeds a limit of the `DecodeOptions`.
+    Limit(LimitError),
+    /// A hash does not have the width of a `u64`.
+    HashWidth { found: usize },
+    /// An index or a length does not fit in a `usize`.
+    Overflow(u64),
+    /// The nodes do not form a path to the index in a tree of the length.
+    InvalidPath,
+    /// The hash algorithm identifier is unknown.
+    UnknownAlgorithm(u32),
+}
+
+impl Display for ProtoError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtoError::Decode(error) => write!(f, "invalid protobuf message: {error}"),
+            ProtoError::Limit(error) => write!(f, "{error}"),
+            ProtoError::HashWidth { found } => {
+                write!(f, "expected hashes of {HASH_BYTES} bytes, found {found}")
+            }
+            ProtoError::Overflow(value) => write!(f, "value {value} does not fit in a usize"),
+            ProtoError::InvalidPath => write!(f, "nodes do not form a path to the index"),
+            ProtoError::UnknownAlgorithm(id) => write!(f, "unknown hash algorithm {id}"),
+        }
+    }
+}
+
+impl Error for ProtoError {}
+
+impl From<prost::DecodeError> for ProtoError {
+    fn from(error: prost::DecodeError) -> Self {
+        ProtoError::Decode(error)
+    }
+}
+
+impl From<LimitError> for ProtoError {
+    fn from(error: LimitError) -> Self {
+        ProtoError::Limit(error)
+    }
+}
+
+/// Parses a hash, failing unless it has the width of a `u64`.
+/// * `bytes` - The big-endian hash.
+fn parse_hash(bytes: &[u8]) -> Result<u64, ProtoError> {
+    let bytes: [u8; HASH_BYTES] = bytes
+        .try_into()
+        .map_err(|_| ProtoError::HashWidth { found: bytes.len() })?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+/// Converts an index or a length, failing if it does not fit in a `usize`.
+fn to_usize(value: u64) -> Result<usize, ProtoError> {
+    usize::try_from(value).map_err(|_| ProtoError::Overflow(value))
+}
+
+impl ProtoMerkleProof {
+    /// Converts the message into a proof, failing if its path is longer than the limits
+    /// allow, any hash is not 8 bytes wide, or its nodes do not form the path of its
+    /// index in a tree of its length. A message with every field unset is an invalid
+    /// proof.
+    /// * `options` - The limits enforced. `max_total_bytes` is only enforced on inputs,
+    ///   by `MerkleProof::decode_proto`.
+    pub fn into_proof(self, options: &DecodeOptions) -> Result<MerkleProof, ProtoError> {
+        if self == ProtoMerkleProof::default() {
+            return Ok(MerkleProof::Invalid);
+        }
+        options.check_node_count(self.nodes.len() as u64)?;
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| parse_hash(node))
+            .collect::<Result<Vec<u64>, ProtoError>>()?;
+        let root = parse_hash(&self.root)?;
+        let index = to_usize(self.index)?;
+        let len = to_usize(self.len)?;
+        if !is_consistent_path(index, len, &nodes) {
+            return Err(ProtoError::InvalidPath);
+        }
+        Ok(MerkleProof::Proof {
+            index,
+            nodes,
+            root,
+            len,
+            epoch: self.epoch,
+        })
+    }
+}
+
+impl From<&MerkleProof> for ProtoMerkleProof {
+    fn from(proof: &MerkleProof) -> Self {
+        match proof {
+            MerkleProof::Invalid => ProtoMerkleProof::default(),
+            MerkleProof::Proof {
+                index,
+                nodes,
+                root,
+                len,
+                epoch,
+            } => ProtoMerkleProof {
+                index: *index as u64,
+                nodes: nodes
+                    .iter()
+                    .map(|node| node.to_be_bytes().to_vec())
+                    .collect(),
+                root: root.to_be_bytes().to_vec(),
+                len: *len as u64,
+                epoch: *epoch,
+            },
+        }
+    }
+}
+
+/// Converts the message within the default `DecodeOptions`, see `into_proof`.
+impl TryFrom<ProtoMerkleProof> for MerkleProof {
+    type Error = ProtoError;
+
+    fn try_from(message: ProtoMerkleProof) -> Result<Self, ProtoError> {
+        message.into_proof(&DecodeOptions::DEFAULT)
+    }
+}
+
+impl From<TreeHead> for ProtoTreeHead {
+    fn from(head: TreeHead) -> Self {
+        ProtoTreeHead {
+            root: head.root.to_be_bytes().to_vec(),
+            len: head.len as u64,
+            algo: u32::from(head.algo.id()),
+            epoch: head.epoch,
+        }
+    }
+}
+
+/// Fails if the root is not 8 bytes wide, the length does not fit in a `usize`, or the
+/// algorithm is unknown.
+impl TryFrom<ProtoTreeHead> for TreeHead {
+    type Error = ProtoError;
+
+    fn try_from(message: ProtoTreeHead) -> Result<Self, ProtoError> {
+        let algo = u8::try_from(message.algo)
+            .ok()
+            .and_then(HashAlgorithm::from_id)
+            .ok_or(ProtoError::UnknownAlgorithm(message.algo))?;
+        Ok(TreeHead {
+            root: parse_hash(&message.root)?,
+            len: to_usize(message.len)?,
+            algo,
+            epoch: message.epoch,
+        })
+    }
+}
+
+impl MerkleProof {
+    /// Encodes the proof as a `MerkleProof` protobuf message, see `PROTO_SCHEMA`.
+    pub fn encode_proto(&self) -> Vec<u8> {
+        ProtoMerkleProof::from(self).encode_to_vec()
+    }
+
+    /// Decodes a proof encoded as a `MerkleProof` protobuf message, failing if the input
+    /// exceeds any of the limits or does not hold a proof, see
+    /// `ProtoMerkleProof::into_proof`. The input's length is checked before decoding it.
+    /// * `bytes` - The encoded proof.
+    /// * `options` - The limits enforced.
+    pub fn decode_proto(bytes: &[u8], options: &DecodeOptions) -> Result<MerkleProof, ProtoError> {
+        options.check_len(bytes.len())?;
+        ProtoMerkleProof::decode(bytes)?.into_proof(options)
+    }
+}
+
+impl TreeHead {
+    /// Encodes the head as a `TreeHead` protobuf message, see `PROTO_SCHEMA`.
+    pub fn encode_proto(&self) -> Vec<u8> {
+        ProtoTreeHead::from(*self).encode_to_vec()
+    }
+
+    /// Decodes a head encoded as a `TreeHead` protobuf message.
+    /// Fails if the input is not such a message or the head is invalid.
+    /// * `bytes` - The encoded head.
+    pub fn decode_proto(bytes: &[u8]) -> Result<TreeHead, ProtoError> {
+        TreeHead::try_from(ProtoTreeHead::decode(bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn proofs_round_trip() {
+        let mut tree = MerkleTree::build(&(0..37).collect::<Vec<u32>>());
+        tree.push(37);
+        for index in [0, 1, 20, 37] {
+            let proof = tree.get_proof(index);
+            let decoded = MerkleProof::decode_proto(&proof.encode_proto(), &DecodeOptions::DEFAULT);
+            assert!(decoded.unwrap() == proof);
+            let message = ProtoMerkleProof::from(&proof);
+            assert_eq!(message.nodes.len(), 6);
+            assert!(MerkleProof::try_from(message).unwrap() == proof);
+        }
+
+        let invalid = MerkleProof::Invalid;
+        assert!(invalid.encode_proto().is_empty());
+        let decoded = MerkleProof::decode_proto(&[], &DecodeOptions::DEFAULT).unwrap();
+        assert!(decoded == MerkleProof::Invalid);
+    }
+
+    #[test]
+    fn heads_round_trip() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let head = tree.head().unwrap();
+        assert_eq!(TreeHead::decode_proto(&head.encode_proto()), Ok(head));
+        assert_eq!(TreeHead::try_from(ProtoTreeHead::from(head)), Ok(head));
+
+        let mut message = ProtoTreeHead::from(head);
+        message.algo = 256;
+        assert_eq!(
+            TreeHead::try_from(message),
+            Err(ProtoError::UnknownAlgorithm(256))
+        );
+    }
+
+    #[test]
+    fn oversized_paths_rejected() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let mut message = ProtoMerkleProof::from(&tree.get_proof(0));
+        message.nodes = vec![vec![0; 8]; 65];
+        let bytes = message.encode_to_vec();
+        assert_eq!(
+            MerkleProof::decode_proto(&bytes, &DecodeOptions::DEFAULT),
+            Err(ProtoError::Limit(LimitError::Depth { found: 65, max: 64 }))
+        );
+
+        // Inputs longer than the limit are rejected before being decoded.
+        message.nodes = vec![vec![0; 8]; 1000];
+        let bytes = message.encode_to_vec();
+        assert_eq!(
+            MerkleProof::decode_proto(&bytes, &DecodeOptions::DEFAULT),
+            Err(ProtoError::Limit(LimitError::TotalBytes {
+                found: bytes.len() as u64,
+                max: 4096
+            }))
+        );
+    }
+
+    #[test]
+    fn malformed_proofs_rejected() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let proof = ProtoMerkleProof::from(&tree.get_proof(2));
+
+        let mut message = proof.clone();
+        message.nodes[1] = vec![0; 7];
+        assert_eq!(
+            MerkleProof::try_from(message),
+            Err(ProtoError::HashWidth { found: 7 })
+        );
+
+        let mut message = proof.clone();
+        message.root = vec![0; 9];
+        assert_eq!(
+            MerkleProof::try_from(message),
+            Err(ProtoError::HashWidth { found: 9 })
+        );
+
+        let mut message = proof.clone();
+        message.index = 3;
+        assert_eq!(MerkleProof::try_from(message), Err(ProtoError::InvalidPath));
+
+        let mut message = proof;
+        message.nodes.pop();
+        assert_eq!(MerkleProof::try_from(message), Err(ProtoError::InvalidPath));
+
+        assert!(matches!(
+            MerkleProof::decode_proto(&[0xff], &DecodeOptions::DEFAULT),
+            Err(ProtoError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn schema_is_exported() {
+        assert!(PROTO_SCHEMA.contains("message MerkleProof"));
+        assert!(PROTO_SCHEMA.contains("message TreeHead"));
+    }
+}
@@ -0,0 +1,637 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::ops::Range;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::MerkleTree;
+
+/// Maximum amount of nodes carried by a message: `LevelRange` requests ask for at most
+/// this many nodes, responses never hold more, and longer ones are rejected when
+/// deserialized.
+pub const MAX_SYNC_BATCH: usize = 256;
+
+/// Coordinates of a node, see `MerkleTree::node`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NodeCoord {
+    pub level: usize,
+    pub index: usize,
+}
+
+/// Request sent by a `SyncSession` to the remote tree, which answers it with
+/// `MerkleTree::respond`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SyncRequest {
+    /// Asks for the remote tree's length and root.
+    Head,
+    /// Asks for a single node.
+    Node { level: usize, index: usize },
+    /// Asks for consecutive nodes of a level, `MAX_SYNC_BATCH` at most.
+    LevelRange {
+        level: usize,
+        start: usize,
+        count: usize,
+    },
+}
+
+/// Response of the remote tree to a `SyncRequest`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SyncResponse {
+    /// Answers `SyncRequest::Head`.
+    Head { len: usize, root: Option<u64> },
+    /// Answers `SyncRequest::Node` and `SyncRequest::LevelRange` above the leaves.
+    /// Nodes out of range are left out.
+    Nodes(
+        #[cfg_attr(feature = "serde", serde(deserialize_with = "bounded"))] Vec<(NodeCoord, u64)>,
+    ),
+    /// Answers `SyncRequest::LevelRange` over the leaves.
+    Leaves {
+        start: usize,
+        #[cfg_attr(feature = "serde", serde(deserialize_with = "bounded"))]
+        hashes: Vec<u64>,
+    },
+}
+
+/// Deserializes a sequence of at most `MAX_SYNC_BATCH` items, failing as soon as it
+/// gets longer.
+#[cfg(feature = "serde")]
+fn bounded<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    use serde::de::{Error, SeqAccess, Visitor};
+    use std::marker::PhantomData;
+
+    struct BoundedVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for BoundedVisitor<T> {
+        type Value = Vec<T>;
+
+        fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "a sequence of at most {MAX_SYNC_BATCH} items")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<T>, A::Error> {
+            let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(MAX_SYNC_BATCH));
+            while let Some(item) = seq.next_element()? {
+                if items.len() == MAX_SYNC_BATCH {
+                    return Err(A::Error::invalid_length(MAX_SYNC_BATCH + 1, &self));
+                }
+                items.push(item);
+            }
+            Ok(items)
+        }
+    }
+
+    deserializer.deserialize_seq(BoundedVisitor(PhantomData))
+}
+
+/// Error returned when a `SyncSession` receives a response it cannot use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncError {
+    /// The response was not expected: it came before `Head` was answered, after the
+    /// session completed, or without a request pending.
+    UnexpectedResponse,
+    /// The remote tree's length has no supported capacity.
+    Length(usize),
+    /// A response holds a node outside of the remote tree's shape.
+    OutOfRange(NodeCoord),
+    /// The repaired tree does not have the root announced by the remote tree.
+    RootMismatch,
+}
+
+impl Display for SyncError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncError::UnexpectedResponse => write!(f, "unexpected response"),
+            SyncError::Length(len) => write!(f, "unsupported remote length {len}"),
+            SyncError::OutOfRange(NodeCoord { level, index }) => {
+                write!(f, "node ({level}, {index}) is out of range")
+            }
+            SyncError::RootMismatch => {
+                write!(f, "the repaired tree does not match the remote root")
+            }
+        }
+    }
+}
+
+impl Error for SyncError {}
+
+impl MerkleTree {
+    /// Answers a request of a remote `SyncSession`. Ranges are clamped to the level's
+    /// length and to `MAX_SYNC_BATCH` nodes.
+    /// * `request` - The request to be answered.
+    pub fn respond(&self, request: &SyncRequest) -> SyncResponse {
+        match *request {
+            SyncRequest::Head => SyncResponse::Head {
+                len: self.len(),
+                root: self.root(),
+            },
+            SyncRequest::Node { level, index } => SyncResponse::Nodes(
+                self.node(level, index)
+                    .map(|hash| (NodeCoord { level, index }, hash))
+                    .into_iter()
+                    .collect(),
+            ),
+            SyncRequest::LevelRange {
+                level,
+                start,
+                count,
+            } => {
+                let nodes = self.level(level).unwrap_or_default();
+                let start = start.min(nodes.len());
+                let end = start + count.min(MAX_SYNC_BATCH).min(nodes.len() - start);
+                if level == 0 {
+                    return SyncResponse::Leaves {
+                        start,
+                        hashes: nodes[start..end].to_vec(),
+                    };
+                }
+                SyncResponse::Nodes(
+                    (start..end)
+                        .map(|index| (NodeCoord { level, index }, nodes[index]))
+                        .collect(),
+                )
+            }
+        }
+    }
+}
+
+/// Nodes and leaves a session needs to fetch next.
+#[derive(Default)]
+struct Wanted {
+    nodes: Vec<NodeCoord>,
+    leaves: Vec<Range<usize>>,
+}
+
+impl Wanted {
+    /// Returns the requests fetching everything wanted: runs of consecutive nodes are
+    /// merged into `LevelRange` requests of up to `MAX_SYNC_BATCH` nodes.
+    fn into_requests(mut self) -> Vec<SyncRequest> {
+        self.nodes.sort_unstable();
+        let mut runs: Vec<(usize, Range<usize>)> = Vec::new();
+        for NodeCoord { level, index } in self.nodes {
+            match runs.last_mut() {
+                Some((run_level, run)) if *run_level == level && run.end == index => {
+                    run.end += 1;
+                }
+                _ => runs.push((level, index..index + 1)),
+            }
+        }
+        runs.extend(self.leaves.into_iter().map(|range| (0, range)));
+
+        let mut requests = Vec::new();
+        for (level, run) in runs {
+            if run.len() == 1 {
+                requests.push(SyncRequest::Node {
+                    level,
+                    index: run.start,
+                });
+                continue;
+            }
+            for start in run.clone().step_by(MAX_SYNC_BATCH) {
+                requests.push(SyncRequest::LevelRange {
+                    level,
+                    start,
+                    count: MAX_SYNC_BATCH.min(run.end - start),
+                });
+            }
+        }
+        requests
+    }
+}
+
+/// Local side of the anti-entropy protocol, which repairs a copy of the local tree until
+/// it matches a remote one. Starting from the remote root, it only fetches the children
+/// of the nodes which differ from the local ones, so each round trip goes down a level
+/// and `d` differing leaves take O(d log n) nodes; leaves beyond the local tree's length
+/// are fetched in ranges instead.
+/// Fetched nodes are only used to decide what to fetch: once the leaves are, the
+/// ancestors of the changed ones are recomputed locally, and the session only completes
+/// if the result has the root announced by the remote tree.
+#[derive(Debug)]
+pub struct SyncSession {
+    tree: MerkleTree,
+    /// Leaves held by the local tree before the session, which are worth comparing.
+    local_len: usize,
+    /// Length and root of the remote tree, once known.
+    remote: Option<(usize, Option<u64>)>,
+    /// Amount of requests sent whose response was not handled yet.
+    outstanding: usize,
+    /// Rewritten leaves, by index, written to the tree when the session finishes.
+    dirty: BTreeMap<usize, u64>,
+    complete: bool,
+}
+
+impl SyncSession {
+    /// Starts repairing a copy of the local tree (see `MerkleTree::fork`), returning the
+    /// session and the requests to be sent to the remote tree.
+    /// * `local` - The tree to be repaired.
+    pub fn initiate(local: &MerkleTree) -> (SyncSession, Vec<SyncRequest>) {
+        let session = SyncSession {
+            tree: local.fork(),
+            local_len: local.len(),
+            remote: None,
+            outstanding: 1,
+            dirty: BTreeMap::new(),
+            complete: false,
+        };
+        (session, vec![SyncRequest::Head])
+    }
+
+    /// Returns whether the repaired tree matches the remote one.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Returns the repaired tree if the session completed, or `None` otherwise.
+    pub fn into_tree(self) -> Option<MerkleTree> {
+        self.complete.then_some(self.tree)
+    }
+
+    /// Handles a response of the remote tree, returning the requests to be sent next.
+    /// Responses may be handled in any order. Once every request was answered and no
+    /// more are needed, the session completes.
+    /// * `response` - The response to be handled.
+    pub fn handle(&mut self, response: SyncResponse) -> Result<Vec<SyncRequest>, SyncError> {
+        if self.complete || self.outstanding == 0 {
+            return Err(SyncError::UnexpectedResponse);
+        }
+        let mut wanted = Wanted::default();
+        match (response, self.remote) {
+            (SyncResponse::Head { len, root }, None) => self.start(len, root, &mut wanted)?,
+            (SyncResponse::Nodes(nodes), Some(_)) => {
+                for (coord, hash) in nodes {
+                    self.compare(coord, hash, &mut wanted)?;
+                }
+            }
+            (SyncResponse::Leaves { start, hashes }, Some(_)) => {
+                for (offset, hash) in hashes.into_iter().enumerate() {
+                    let index = start.saturating_add(offset);
+                    self.compare(NodeCoord { level: 0, index }, hash, &mut wanted)?;
+                }
+            }
+            _ => return Err(SyncError::UnexpectedResponse),
+        }
+
+        let requests = wanted.into_requests();
+        self.outstanding += requests.len();
+        self.outstanding -= 1;
+        if self.outstanding == 0 {
+            self.finish()?;
+        }
+        Ok(requests)
+    }
+
+    /// Shapes the tree after the remote one and starts descending from its root.
+    fn start(
+        &mut self,
+        len: usize,
+        root: Option<u64>,
+        wanted: &mut Wanted,
+    ) -> Result<(), SyncError> {
+        self.remote = Some((len, root));
+        if len == self.tree.len() && root == self.tree.root() {
+            return Ok(());
+        }
+        let capacity = len
+            .max(1)
+            .checked_next_power_of_two()
+            .ok_or(SyncError::Length(len))?;
+
+        // The nodes of the remote shape are compared with local ones, so the tree takes
+        // the remote capacity right away. Its length changes when the session finishes.
+        self.tree.resize_capacity(capacity);
+        // Leaves beyond the remote length become padding.
+        for index in len..self.local_len.min(capacity) {
+            self.dirty.insert(index, MerkleTree::PAD_HASH);
+        }
+        self.local_len = self.local_len.min(len);
+
+        self.descend(
+            NodeCoord {
+                level: self.tree.height() - 1,
+                index: 0,
+            },
+            wanted,
+        );
+        Ok(())
+    }
+
+    /// Wants what is needed to repair the subtree of a differing node: its children,
+    /// or its leaves if they are not worth comparing one level at a time.
+    fn descend(&self, NodeCoord { level, index }: NodeCoord, wanted: &mut Wanted) {
+        let (remote_len, _) = self.remote.expect("Descending starts from the remote head");
+        let first = index << level;
+        let end = ((index + 1) << level).min(remote_len);
+        if first >= end {
+            // Padding only, which is already in place.
+            return;
+        }
+        if level <= 1 || first >= self.local_len {
+            wanted.leaves.push(first..end);
+        } else {
+            wanted.nodes.push(NodeCoord {
+                level: level - 1,
+                index: 2 * index,
+            });
+            wanted.nodes.push(NodeCoord {
+                level: level - 1,
+                index: 2 * index + 1,
+            });
+        }
+    }
+
+    /// Compares a fetched node with the local one, collecting differing leaves and
+    /// descending into differing subtrees.
+    fn compare(
+        &mut self,
+        coord: NodeCoord,
+        hash: u64,
+        wanted: &mut Wanted,
+    ) -> Result<(), SyncError> {
+        let local = self
+            .tree
+            .node(coord.level, coord.index)
+            .ok_or(SyncError::OutOfRange(coord))?;
+        if local == hash {
+            return Ok(());
+        }
+        if coord.level == 0 {
+            self.dirty.insert(coord.index, hash);
+        } else {
+            self.descend(coord, wanted);
+        }
+        Ok(())
+    }
+
+    /// Rewrites the differing leaves, reshaping the tree after the remote one, and checks
+    /// the root. The rewrite goes through the same path as a replayed
+    /// `TreeOp::Rewrite`, so caches, the lookup index, observers and recordings follow it.
+    fn finish(&mut self) -> Result<(), SyncError> {
+        let (remote_len, remote_root) = self.remote.expect("Sessions finish after the remote head");
+        if !self.dirty.is_empty() || remote_len != self.tree.len() {
+            let leaves = std::mem::take(&mut self.dirty).into_iter().collect();
+            let epoch = self.tree.epoch + 1;
+            self.tree
+                .rewrite(remote_len, self.tree.capacity, epoch, leaves);
+        }
+        if self.tree.root() != remote_root {
+            return Err(SyncError::RootMismatch);
+        }
+        self.complete = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+    use crate::{MerkleProof, check_invariants};
+
+    /// Runs a session against a remote tree answering from another thread, returning
+    /// the repaired tree and the amount of round trips.
+    fn reconcile(
+        local: &MerkleTree,
+        remote: &MerkleTree,
+    ) -> Result<(MerkleTree, usize), SyncError> {
+        let (request_tx, request_rx) = mpsc::channel::<Vec<SyncRequest>>();
+        let (response_tx, response_rx) = mpsc::channel::<Vec<SyncResponse>>();
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                for batch in request_rx {
+                    let responses = batch
+                        .iter()
+                        .map(|request| remote.respond(request))
+                        .collect();
+                    response_tx.send(responses).unwrap();
+                }
+            });
+
+            let (mut session, mut requests) = SyncSession::initiate(local);
+            let mut round_trips = 0;
+            while !requests.is_empty() {
+                round_trips += 1;
+                request_tx.send(requests).unwrap();
+                requests = Vec::new();
+                for response in response_rx.recv().unwrap() {
+                    requests.extend(session.handle(response)?);
+                }
+            }
+            drop(request_tx);
+            Ok((session.into_tree().unwrap(), round_trips))
+        })
+    }
+
+    /// Returns a tree over `0..len` with the given indices overwritten.
+    fn tree_with(len: u32, changed: &[u32]) -> MerkleTree {
+        let values: Vec<u32> = (0..len)
+            .map(|value| {
+                if changed.contains(&value) {
+                    value + 1_000_000
+                } else {
+                    value
+                }
+            })
+            .collect();
+        MerkleTree::build(&values)
+    }
+
+    #[test]
+    fn three_differing_leaves_reconciled() {
+        let remote = tree_with(4096, &[]);
+        let local = tree_with(4096, &[17, 2000, 4095]);
+        let (repaired, round_trips) = reconcile(&local, &remote).unwrap();
+        assert_eq!(repaired.root(), remote.root());
+        assert!(repaired.diff(&remote).is_empty());
+        repaired.validate().unwrap();
+        assert_eq!(repaired.epoch(), local.epoch() + 1);
+
+        // The head, then a level per round trip: nowhere near a leaf per round trip.
+        assert_eq!(repaired.height(), 13);
+        assert!(
+            round_trips <= repaired.height(),
+            "{round_trips} round trips"
+        );
+
+        let (same, round_trips) = reconcile(&remote, &remote).unwrap();
+        assert_eq!(round_trips, 1);
+        assert_eq!(same.epoch(), remote.epoch());
+    }
+
+    #[test]
+    fn lengths_reconciled() {
+        let remote = tree_with(1000, &[5]);
+        for local in [
+            tree_with(600, &[]),
+            tree_with(1500, &[999]),
+            MerkleTree::build::<u8>(&[]),
+        ] {
+            let (repaired, _) = reconcile(&local, &remote).unwrap();
+            assert_eq!(repaired.len(), 1000);
+            assert_eq!(repaired.capacity(), 1024);
+            assert_eq!(repaired.root(), remote.root());
+            repaired.validate().unwrap();
+        }
+
+        let (repaired, _) = reconcile(&remote, &MerkleTree::build::<u8>(&[])).unwrap();
+        assert!(repaired.is_empty());
+        repaired.validate().unwrap();
+    }
+
+    #[test]
+    fn repaired_trees_match_remote_ones() {
+        let remote = tree_with(300, &[5, 299]);
+        for local in [
+            tree_with(300, &[]),
+            tree_with(100, &[5]),
+            tree_with(700, &[]),
+        ] {
+            let (mut repaired, _) = reconcile(&local, &remote).unwrap();
+            assert_eq!(repaired.leaves(), remote.leaves());
+            assert_eq!(repaired.epoch(), local.epoch() + 1);
+            check_invariants(&repaired).unwrap();
+            for index in [0, 5, 150, 299] {
+                let (
+                    MerkleProof::Proof { nodes, .. },
+                    MerkleProof::Proof {
+                        nodes: expected, ..
+                    },
+                ) = (repaired.get_proof(index), remote.get_proof(index))
+                else {
+                    panic!("The leaf is occupied");
+                };
+                assert_eq!(nodes, expected);
+            }
+            repaired.enable_index();
+            assert_eq!(repaired.index_of_hash(remote.leaves()[299]), Some(299));
+        }
+    }
+
+    #[test]
+    fn lying_remotes_detected() {
+        let local = tree_with(64, &[]);
+        let (mut session, _) = SyncSession::initiate(&local);
+        assert_eq!(
+            session.handle(SyncResponse::Nodes(Vec::new())),
+            Err(SyncError::UnexpectedResponse)
+        );
+
+        // The announced root does not match the leaves sent.
+        let requests = session
+            .handle(SyncResponse::Head {
+                len: 64,
+                root: Some(7),
+            })
+            .unwrap();
+        assert_eq!(
+            requests,
+            [SyncRequest::LevelRange {
+                level: 5,
+                start: 0,
+                count: 2
+            }]
+        );
+        let remote = tree_with(64, &[1]);
+        let mut result = Ok(Vec::new());
+        let mut pending = requests;
+        while let Some(request) = pending.pop() {
+            result = session.handle(remote.respond(&request));
+            match &result {
+                Ok(requests) => pending.extend(requests.iter().cloned()),
+                Err(_) => break,
+            }
+        }
+        assert_eq!(result, Err(SyncError::RootMismatch));
+        assert!(!session.is_complete());
+
+        let (mut session, _) = SyncSession::initiate(&local);
+        session
+            .handle(SyncResponse::Head {
+                len: 64,
+                root: Some(7),
+            })
+            .unwrap();
+        let out_of_range = NodeCoord { level: 9, index: 0 };
+        assert_eq!(
+            session.handle(SyncResponse::Nodes(vec![(out_of_range, 1)])),
+            Err(SyncError::OutOfRange(out_of_range))
+        );
+    }
+
+    #[test]
+    fn responses_are_bounded() {
+        let tree = tree_with(1000, &[]);
+        let response = tree.respond(&SyncRequest::LevelRange {
+            level: 0,
+            start: 900,
+            count: 10_000,
+        });
+        assert_eq!(
+            response,
+            SyncResponse::Leaves {
+                start: 900,
+                hashes: tree.leaves()[900..]
+                    .iter()
+                    .copied()
+                    .chain([MerkleTree::PAD_HASH; 24])
+                    .collect()
+            }
+        );
+        let SyncResponse::Leaves { hashes, .. } = tree.respond(&SyncRequest::LevelRange {
+            level: 0,
+            start: 0,
+            count: 10_000,
+        }) else {
+            panic!("leaf ranges are answered with leaves");
+        };
+        assert_eq!(hashes.len(), MAX_SYNC_BATCH);
+        assert_eq!(
+            tree.respond(&SyncRequest::Node {
+                level: 40,
+                index: 0
+            }),
+            SyncResponse::Nodes(Vec::new())
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn messages_round_trip() {
+        let tree = tree_with(1000, &[]);
+        for request in [
+            SyncRequest::Head,
+            SyncRequest::Node { level: 3, index: 7 },
+            SyncRequest::LevelRange {
+                level: 1,
+                start: 4,
+                count: 8,
+            },
+        ] {
+            let json = serde_json::to_string(&request).unwrap();
+            assert_eq!(serde_json::from_str::<SyncRequest>(&json).unwrap(), request);
+            let response = tree.respond(&request);
+            let json = serde_json::to_string(&response).unwrap();
+            assert_eq!(
+                serde_json::from_str::<SyncResponse>(&json).unwrap(),
+                response
+            );
+        }
+
+        let oversized = SyncResponse::Leaves {
+            start: 0,
+            hashes: vec![0; MAX_SYNC_BATCH + 1],
+        };
+        let json = serde_json::to_string(&oversized).unwrap();
+        assert!(serde_json::from_str::<SyncResponse>(&json).is_err());
+    }
+}
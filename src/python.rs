@@ -0,0 +1,139 @@
+use pyo3::prelude::*;
+
+use crate::{MerkleProof, MerkleTree, root_to_hex};
+
+/// Merkle tree usable from Python. Values are handled as byte strings, hashed the same
+/// way as `&[u8]` or `Vec<u8>` elements.
+#[pyclass(name = "MerkleTree", module = "merkle_tree")]
+pub struct PyMerkleTree {
+    tree: MerkleTree,
+}
+
+#[pymethods]
+impl PyMerkleTree {
+    /// Builds a tree out of a list of byte strings. The GIL is released while the tree
+    /// is built, so other Python threads keep running during large builds.
+    /// * `values` - The leaves of the tree.
+    #[new]
+    #[pyo3(signature = (values = Vec::new()))]
+    fn new(py: Python<'_>, values: Vec<Vec<u8>>) -> PyMerkleTree {
+        let tree = py.detach(|| MerkleTree::build(&values));
+        PyMerkleTree { tree }
+    }
+
+    /// Pushes a byte string into the tree.
+    /// * `value` - The value to be added to the tree.
+    fn push(&mut self, value: &[u8]) {
+        self.tree.push(value);
+    }
+
+    /// Returns the root in its canonical hex representation, or `None` if the tree is
+    /// empty.
+    #[getter]
+    fn root(&self) -> Option<String> {
+        self.tree.root_hex()
+    }
+
+    /// Returns the proof for a leaf. Proofs for indices that do not correspond to a leaf
+    /// never verify.
+    /// * `index` - Index of the leaf.
+    fn get_proof(&self, index: usize) -> PyMerkleProof {
+        PyMerkleProof {
+            proof: self.tree.get_proof(index),
+        }
+    }
+
+    fn __len__(&self) -> usize {
+        self.tree.len()
+    }
+}
+
+/// Proof of inclusion returned by `MerkleTree.get_proof` in Python.
+#[pyclass(name = "MerkleProof", module = "merkle_tree")]
+pub struct PyMerkleProof {
+    proof: MerkleProof,
+}
+
+#[pymethods]
+impl PyMerkleProof {
+    /// Returns whether the byte string verifies the proof.
+    /// * `value` - The value to be tested.
+    fn verify(&self, value: &[u8]) -> bool {
+        self.proof.verify(value)
+    }
+
+    /// Returns the index of the proven leaf, or `None` for invalid proofs.
+    #[getter]
+    fn index(&self) -> Option<usize> {
+        match &self.proof {
+            MerkleProof::Invalid => None,
+            MerkleProof::Proof { index, .. } => Some(*index),
+        }
+    }
+
+    /// Returns the root the proof was generated for in its canonical hex representation,
+    /// or `None` for invalid proofs.
+    #[getter]
+    fn root(&self) -> Option<String> {
+        match &self.proof {
+            MerkleProof::Invalid => None,
+            MerkleProof::Proof { root, .. } => Some(root_to_hex(*root)),
+        }
+    }
+}
+
+/// The `merkle_tree` Python module.
+#[pymodule(name = "merkle_tree")]
+pub fn python_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyMerkleTree>()?;
+    module.add_class::<PyMerkleProof>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+
+    /// Runs Python code with the `merkle_tree` module imported.
+    fn run(code: &std::ffi::CStr) {
+        Python::initialize();
+        Python::attach(|py| {
+            let module = PyModule::new(py, "merkle_tree").unwrap();
+            python_module(&module).unwrap();
+            let globals = PyDict::new(py);
+            globals.set_item("merkle_tree", module).unwrap();
+            py.run(code, Some(&globals), None).unwrap();
+        });
+    }
+
+    #[test]
+    fn build_prove_verify() {
+        run(c"
+tree = merkle_tree.MerkleTree([b'alpha', b'beta', b'gamma'])
+assert len(tree) == 3
+for index, value in enumerate([b'alpha', b'beta', b'gamma']):
+    proof = tree.get_proof(index)
+    assert proof.index == index
+    assert proof.root == tree.root
+    assert proof.verify(value)
+    assert not proof.verify(b'delta')
+
+tree.push(b'delta')
+assert tree.get_proof(3).verify(b'delta')
+assert not tree.get_proof(4).verify(b'delta')
+assert tree.get_proof(4).index is None
+");
+    }
+
+    #[test]
+    fn matches_rust_tree() {
+        let values: [&[u8]; 3] = [b"alpha", b"beta", b"gamma"];
+        let root = MerkleTree::build(&values).root_hex().unwrap();
+        run(&std::ffi::CString::new(format!(
+            "assert merkle_tree.MerkleTree([b'alpha', b'beta', b'gamma']).root == '{root}'\n\
+             assert merkle_tree.MerkleTree().root is None"
+        ))
+        .unwrap());
+    }
+}
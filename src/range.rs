@@ -0,0 +1,95 @@
+use std::ops::Range;
+
+use crate::{MerkleTree, hash_pair};
+
+impl MerkleTree {
+    /// Returns a single hash committing to the leaves in the given range.
+    /// The range is split, left to right, into the largest subtrees aligned to their
+    /// own size that fit in it, and the stored roots of those subtrees are then bagged
+    /// from left to right: `hash_pair(hash_pair(first, second), third)` and so on.
+    /// Consequently, a range covering exactly one subtree hashes to that subtree's
+    /// internal node, and the hash only depends on the leaves inside the range.
+    /// The full range `0..len()` only equals `root()` when the length is a power of two,
+    /// since the root also commits to the padding.
+    /// Returns `None` for empty ranges, ranges reaching beyond the tree's length and
+    /// ranges holding pruned leaves (see `from_frontier`).
+    /// * `range` - The range of leaf indices to commit to.
+    pub fn leaf_range_hash(&self, range: Range<usize>) -> Option<u64> {
+        if range.is_empty() || range.end > self.len() || range.start < self.pruned {
+            return None;
+        }
+
+        let mut start = range.start;
+        let mut bagged: Option<u64> = None;
+
+        while start < range.end {
+            // Largest aligned subtree starting at `start` that does not exceed the range.
+            let mut level = start.trailing_zeros().min(usize::BITS - 1) as usize;
+            while start + (1 << level) > range.end {
+                level -= 1;
+            }
+
+            let node = self.levels[(level, start >> level)];
+            bagged = Some(match bagged {
+                Option::None => node,
+                Option::Some(left) => hash_pair(left, node),
+            });
+            start += 1 << level;
+        }
+
+        bagged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash_single;
+
+    #[test]
+    fn aligned_range_equals_internal_node() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(tree.leaf_range_hash(4..8), Some(tree.levels[(2, 1)]));
+        assert_eq!(tree.leaf_range_hash(2..4), Some(tree.levels[(1, 1)]));
+        assert_eq!(tree.leaf_range_hash(5..6), Some(hash_single(6)));
+    }
+
+    #[test]
+    fn misaligned_range_is_bagged_left_to_right() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        let expected = hash_pair(
+            hash_pair(hash_single(2), tree.levels[(1, 1)]),
+            tree.levels[(2, 1)],
+        );
+        assert_eq!(tree.leaf_range_hash(1..8), Some(expected));
+    }
+
+    #[test]
+    fn range_hash_only_depends_on_range() {
+        let first = MerkleTree::build(&[0, 2, 3, 4, 5, 0]);
+        let second = MerkleTree::build(&[9, 2, 3, 4, 5, 9, 9, 9, 9]);
+        assert_eq!(first.leaf_range_hash(1..5), second.leaf_range_hash(1..5));
+        assert_ne!(first.leaf_range_hash(0..5), second.leaf_range_hash(0..5));
+    }
+
+    #[test]
+    fn empty_and_out_of_range() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        assert_eq!(tree.leaf_range_hash(1..1), None);
+        assert_eq!(tree.leaf_range_hash(0..4), None);
+        assert_eq!(MerkleTree::build::<u8>(&[]).leaf_range_hash(0..0), None);
+    }
+
+    #[test]
+    fn full_range_and_root() {
+        // Power of two lengths have no padding, so the full range is the root.
+        let tree = MerkleTree::build(&[1, 2, 3, 4]);
+        assert_eq!(tree.leaf_range_hash(0..4), tree.root());
+
+        // Otherwise, the root also commits to the padding.
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let expected = hash_pair(tree.levels[(1, 0)], hash_single(3));
+        assert_eq!(tree.leaf_range_hash(0..3), Some(expected));
+        assert_ne!(tree.leaf_range_hash(0..3), tree.root());
+    }
+}
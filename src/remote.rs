@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::{MerkleProof, MerkleTree, path_indices, proof_depth, sibling_index};
+
+/// Asynchronous read access to the nodes of a remote tree, implemented over whatever
+/// fetches them, such as an object store or an HTTP endpoint, for `AsyncMerkleReader`.
+/// Coordinates are the ones of `MerkleTree::node`, in the remote tree, whose capacity is
+/// its length rounded up to a power of two.
+pub trait AsyncNodeStore {
+    /// Error returned when a fetch fails.
+    type Error;
+
+    /// Fetches the node at the given coordinates, or `None` if the store does not hold
+    /// it.
+    /// * `level` - Level of the node.
+    /// * `index` - Index of the node within its level.
+    fn get(
+        &self,
+        level: usize,
+        index: usize,
+    ) -> impl Future<Output = Result<Option<u64>, Self::Error>>;
+
+    /// Fetches the nodes at the given coordinates, in their order. Readers fetch every
+    /// node a proof needs through a single call, so stores should override it whenever
+    /// fetching nodes one by one is slow, as it does by default.
+    /// * `coords` - The coordinates of the nodes, as `(level, index)` pairs.
+    fn get_many(
+        &self,
+        coords: &[(usize, usize)],
+    ) -> impl Future<Output = Result<Vec<Option<u64>>, Self::Error>> {
+        async move {
+            let mut nodes = Vec::with_capacity(coords.len());
+            for &(level, index) in coords {
+                nodes.push(self.get(level, index).await?);
+            }
+            Ok(nodes)
+        }
+    }
+}
+
+impl AsyncNodeStore for MerkleTree {
+    type Error = Infallible;
+
+    async fn get(&self, level: usize, index: usize) -> Result<Option<u64>, Infallible> {
+        Ok(self.node(level, index))
+    }
+}
+
+/// Reader of a tree whose nodes are held by an `AsyncNodeStore`, fetching only the nodes
+/// it is asked for, so that proofs can be generated without holding the tree locally.
+/// Fetched nodes are cached for the life of the reader, so no node is fetched twice: a
+/// proof costs a single batched fetch of the nodes of its path not already fetched, and
+/// none at all if every one of them is.
+pub struct AsyncMerkleReader<S> {
+    store: S,
+    len: usize,
+    epoch: u64,
+    nodes: Mutex<HashMap<(usize, usize), u64>>,
+}
+
+impl<S: AsyncNodeStore> AsyncMerkleReader<S> {
+    /// Creates a reader of the remote tree held by a store.
+    /// * `store` - The store holding the nodes.
+    /// * `len` - The length of the remote tree, such as the one of its `TreeHead`.
+    /// * `epoch` - The epoch of the remote tree, recorded in the proofs.
+    pub fn new(store: S, len: usize, epoch: u64) -> AsyncMerkleReader<S> {
+        AsyncMerkleReader {
+            store,
+            len,
+            epoch,
+            nodes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the length of the remote tree.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the remote tree is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the amount of nodes fetched and cached so far.
+    pub fn cached_nodes(&self) -> usize {
+        self.cache().len()
+    }
+
+    /// Returns the store, dropping the cached nodes.
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    /// Returns the cached nodes, recovering them if a panic poisoned the lock.
+    fn cache(&self) -> MutexGuard<'_, HashMap<(usize, usize), u64>> {
+        self.nodes.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the level of the root of the remote tree.
+    fn root_level(&self) -> usize {
+        proof_depth(self.len)
+    }
+
+    /// Returns the root of the remote tree, or `None` if it is empty or the store does
+    /// not hold its root. Fails if the fetch fails.
+    pub async fn root(&self) -> Result<Option<u64>, S::Error> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        self.node(self.root_level(), 0).await
+    }
+
+    /// Returns the node at the given coordinates, fetching it unless it is cached, or
+    /// `None` if the store does not hold it. Fails if the fetch fails.
+    /// * `level` - Level of the node.
+    /// * `index` - Index of the node within its level.
+    pub async fn node(&self, level: usize, index: usize) -> Result<Option<u64>, S::Error> {
+        if let Some(&node) = self.cache().get(&(level, index)) {
+            return Ok(Some(node));
+        }
+
+        let node = self.store.get(level, index).await?;
+        if let Some(node) = node {
+            self.cache().insert((level, index), node);
+        }
+        Ok(node)
+    }
+
+    /// Creates a `MerkleProof` for a given index out of the remote nodes, fetching the
+    /// siblings along its path and the root that are not cached through a single
+    /// `get_many` call. Returns `MerkleProof::Invalid` if the index does not hold an
+    /// element or the store does not hold a node of the path. Fails if the fetch fails.
+    /// * `index` - index value to generate the proof for.
+    pub async fn get_proof(&self, index: usize) -> Result<MerkleProof, S::Error> {
+        if index >= self.len {
+            return Ok(MerkleProof::Invalid);
+        }
+
+        let root_level = self.root_level();
+        let coords: Vec<(usize, usize)> = path_indices(index)
+            .take(root_level)
+            .enumerate()
+            .map(|(level, ancestor)| (level, sibling_index(ancestor)))
+            .chain([(root_level, 0)])
+            .collect();
+
+        let missing: Vec<(usize, usize)> = {
+            let cache = self.cache();
+            coords
+                .iter()
+                .filter(|coord| !cache.contains_key(coord))
+                .copied()
+                .collect()
+        };
+        if !missing.is_empty() {
+            let fetched = self.store.get_many(&missing).await?;
+            let mut cache = self.cache();
+            for (coord, node) in missing.into_iter().zip(fetched) {
+                if let Some(node) = node {
+                    cache.insert(coord, node);
+                }
+            }
+        }
+
+        let cache = self.cache();
+        let Some(mut nodes) = coords
+            .iter()
+            .map(|coord| cache.get(coord).copied())
+            .collect::<Option<Vec<u64>>>()
+        else {
+            return Ok(MerkleProof::Invalid);
+        };
+        let root = nodes.pop().unwrap_or_default();
+        Ok(MerkleProof::Proof {
+            index,
+            nodes,
+            root,
+            len: self.len,
+            epoch: self.epoch,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Store serving the nodes of a tree, counting the fetches it serves.
+    struct MockStore {
+        tree: MerkleTree,
+        fetches: AtomicUsize,
+        fetched_nodes: AtomicUsize,
+        failing: bool,
+    }
+
+    impl MockStore {
+        fn new(tree: MerkleTree) -> MockStore {
+            MockStore {
+                tree,
+                fetches: AtomicUsize::new(0),
+                fetched_nodes: AtomicUsize::new(0),
+                failing: false,
+            }
+        }
+
+        fn fetches(&self) -> usize {
+            self.fetches.load(Ordering::Relaxed)
+        }
+
+        fn fetched_nodes(&self) -> usize {
+            self.fetched_nodes.load(Ordering::Relaxed)
+        }
+    }
+
+    impl AsyncNodeStore for MockStore {
+        type Error = io::Error;
+
+        async fn get(&self, level: usize, index: usize) -> io::Result<Option<u64>> {
+            self.get_many(&[(level, index)]).await.map(|nodes| nodes[0])
+        }
+
+        async fn get_many(&self, coords: &[(usize, usize)]) -> io::Result<Vec<Option<u64>>> {
+            if self.failing {
+                return Err(io::Error::other("unreachable store"));
+            }
+            self.fetches.fetch_add(1, Ordering::Relaxed);
+            self.fetched_nodes
+                .fetch_add(coords.len(), Ordering::Relaxed);
+            Ok(coords
+                .iter()
+                .map(|&(level, index)| self.tree.node(level, index))
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn proofs_match_local_ones() {
+        for len in [1, 2, 5, 8, 13] {
+            let values: Vec<u32> = (0..len).collect();
+            let tree = MerkleTree::build(&values);
+            let root = tree.root();
+            let reader = AsyncMerkleReader::new(tree, len as usize, 0);
+
+            assert_eq!(reader.root().await, Ok(root));
+            for (index, value) in values.iter().enumerate() {
+                let proof = reader.get_proof(index).await.unwrap();
+                assert!(proof == reader.store.get_proof(index));
+                assert!(proof.verify(value));
+            }
+            assert!(reader.get_proof(len as usize).await.unwrap() == MerkleProof::Invalid);
+        }
+
+        let reader = AsyncMerkleReader::new(MerkleTree::build::<u32>(&[]), 0, 0);
+        assert_eq!(reader.root().await, Ok(None));
+        assert!(reader.get_proof(0).await.unwrap() == MerkleProof::Invalid);
+    }
+
+    #[tokio::test]
+    async fn proofs_cost_one_batched_fetch() {
+        let mut tree = MerkleTree::build(&(0..13).collect::<Vec<u32>>());
+        tree.push(13);
+        let reader = AsyncMerkleReader::new(MockStore::new(tree), 14, 0);
+
+        // Four siblings and the root, in a single fetch.
+        let proof = reader.get_proof(6).await.unwrap();
+        assert!(proof.verify(6));
+        assert_eq!(reader.store.fetches(), 1);
+        assert_eq!(reader.store.fetched_nodes(), 5);
+
+        // Cached nodes are never fetched again: a proof of the same leaf fetches
+        // nothing, and one of its sibling only the leaf the first one did not need.
+        assert!(reader.get_proof(6).await.unwrap() == proof);
+        assert_eq!(reader.store.fetches(), 1);
+        assert!(reader.get_proof(7).await.unwrap().verify(7));
+        assert_eq!(reader.store.fetches(), 2);
+        assert_eq!(reader.store.fetched_nodes(), 6);
+        assert_eq!(reader.cached_nodes(), 6);
+        assert!(reader.root().await.unwrap().is_some());
+        assert_eq!(
+            reader.node(0, 6).await.unwrap(),
+            reader.store.tree.node(0, 6)
+        );
+        assert_eq!(reader.store.fetches(), 2);
+
+        // Indices beyond the length fetch nothing.
+        assert!(reader.get_proof(14).await.unwrap() == MerkleProof::Invalid);
+        assert_eq!(reader.store.fetches(), 2);
+    }
+
+    #[tokio::test]
+    async fn fetch_failures_and_missing_nodes() {
+        let mut store = MockStore::new(MerkleTree::build(&[1, 2, 3]));
+        store.failing = true;
+        let reader = AsyncMerkleReader::new(store, 3, 0);
+        assert!(reader.get_proof(0).await.is_err());
+        assert!(reader.root().await.is_err());
+        assert_eq!(reader.cached_nodes(), 0);
+
+        // A length beyond the one of the stored tree addresses nodes it does not hold.
+        let reader = AsyncMerkleReader::new(MockStore::new(MerkleTree::build(&[1, 2, 3])), 5, 0);
+        assert!(reader.get_proof(4).await.unwrap() == MerkleProof::Invalid);
+        assert_eq!(reader.root().await.unwrap(), None);
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::HashSet;
+
+use crate::{MerkleTree, ancestor_index, sibling_index};
+
+/// Maximum amount of levels drawn by the ASCII renderer. Deeper trees only get their
+/// top levels drawn.
+const ASCII_MAX_LEVELS: usize = 5;
+
+/// Width of the cell each node of the lowest drawn level is centered in.
+const ASCII_CELL_WIDTH: usize = 9;
+
+/// Marker drawn in place of nodes whose whole subtree is padding.
+const PAD_MARKER: &str = "·";
+
+/// Options of the Graphviz DOT export.
+#[derive(Clone, Debug, Default)]
+pub struct DotOptions {
+    /// Leaf whose proof path (the leaf, its ancestors and their siblings) is colored.
+    pub proof_path: Option<usize>,
+    /// Leaves that are colored together with all their ancestors, such as the leaves
+    /// differing from another tree.
+    pub highlight_leaves: Vec<usize>,
+}
+
+/// How a node is drawn.
+enum Mark {
+    Plain,
+    Path,
+    Sibling,
+}
+
+impl MerkleTree {
+    /// Returns whether every leaf under the node at the given coordinates is padding.
+    /// * `level` - Level of the node.
+    /// * `index` - Index of the node within its level.
+    pub(crate) fn is_padded_subtree(&self, level: usize, index: usize) -> bool {
+        (index << level) >= self.len()
+    }
+
+    /// Renders the tree as an ASCII figure, with the root at the top and every level
+    /// below it. Hashes are abbreviated to their first 6 hex digits and nodes whose
+    /// subtree only holds padding are drawn as `·`.
+    /// Only the top 5 levels of deeper trees are drawn, followed by a note telling how
+    /// many levels were elided.
+    pub fn render_ascii(&self) -> String {
+        self.render(None)
+    }
+
+    /// Renders the tree like `render_ascii`, highlighting the proof path of a leaf:
+    /// the leaf and its ancestors up to the root are drawn as `[hash]`, and the siblings
+    /// making up its proof as `(hash)`.
+    /// Returns `None` if the index does not correspond to an occupied leaf.
+    /// * `index` - Index of the leaf whose proof path is highlighted.
+    pub fn render_ascii_with_proof(&self, index: usize) -> Option<String> {
+        self.leaf(index)?;
+        Some(self.render(Some(index)))
+    }
+
+    /// Renders the tree as an ASCII figure, optionally highlighting a leaf's proof path.
+    /// * `highlight` - Index of the leaf whose proof path is highlighted, if any.
+    fn render(&self, highlight: Option<usize>) -> String {
+        let lowest = self.height().saturating_sub(ASCII_MAX_LEVELS);
+        let mut lines = Vec::new();
+
+        for (level_n, level) in self.levels_iter().enumerate().skip(lowest).rev() {
+            let width = ASCII_CELL_WIDTH << (level_n - lowest);
+            let mut line = String::new();
+
+            for (index, &hash) in level.iter().enumerate() {
+                let mark = match highlight {
+                    Option::Some(leaf) if ancestor_index(leaf, level_n) == index => Mark::Path,
+                    Option::Some(leaf)
+                        if level_n < self.height() - 1
+                            && sibling_index(ancestor_index(leaf, level_n)) == index =>
+                    {
+                        Mark::Sibling
+                    }
+                    _ => Mark::Plain,
+                };
+
+                let label = if self.is_padded_subtree(level_n, index) {
+                    PAD_MARKER.to_string()
+                } else {
+                    let short = format!("{:06x}", hash >> 40);
+                    match mark {
+                        Mark::Plain => short,
+                        Mark::Path => format!("[{short}]"),
+                        Mark::Sibling => format!("({short})"),
+                    }
+                };
+                line.push_str(&format!("{label:^width$}"));
+            }
+
+            lines.push(line.trim_end().to_string());
+        }
+
+        if lowest > 0 {
+            lines.push(format!("... {lowest} lower levels elided"));
+        }
+
+        lines.join("\n")
+    }
+
+    /// Exports the tree as a Graphviz DOT digraph. Each node is labeled with its
+    /// `(level, index)` coordinates and the first 6 hex digits of its hash, edges go from
+    /// parents to their children, and nodes whose subtree only holds padding are dashed.
+    pub fn to_dot(&self) -> String {
+        self.to_dot_with(&DotOptions::default())
+    }
+
+    /// Exports the tree like `to_dot`, coloring the nodes selected by the options.
+    /// * `options` - The nodes to be colored.
+    pub fn to_dot_with(&self, options: &DotOptions) -> String {
+        let mut path = HashSet::new();
+        let mut siblings = HashSet::new();
+        let mut highlighted = HashSet::new();
+
+        for level_n in 0..self.height() {
+            if let Some(leaf) = options.proof_path {
+                path.insert((level_n, ancestor_index(leaf, level_n)));
+                if level_n < self.height() - 1 {
+                    siblings.insert((level_n, sibling_index(ancestor_index(leaf, level_n))));
+                }
+            }
+            for &leaf in &options.highlight_leaves {
+                highlighted.insert((level_n, ancestor_index(leaf, level_n)));
+            }
+        }
+
+        let mut dot = String::from("digraph merkle_tree {\n    node [shape=box];\n");
+
+        for (level_n, level) in self.levels_iter().enumerate() {
+            for (index, &hash) in level.iter().enumerate() {
+                let coord = (level_n, index);
+                let mut attributes = vec![format!(
+                    "label=\"({level_n}, {index})\\n{:06x}\"",
+                    hash >> 40
+                )];
+                if self.is_padded_subtree(level_n, index) {
+                    attributes.push("style=dashed".to_string());
+                    attributes.push("fontcolor=gray".to_string());
+                }
+                if path.contains(&coord) {
+                    attributes.push("color=red".to_string());
+                } else if siblings.contains(&coord) {
+                    attributes.push("color=blue".to_string());
+                } else if highlighted.contains(&coord) {
+                    attributes.push("color=orange".to_string());
+                }
+                dot.push_str(&format!(
+                    "    n{level_n}_{index} [{}];\n",
+                    attributes.join(", ")
+                ));
+            }
+        }
+
+        for level_n in 1..self.height() {
+            for index in 0..self.level_len(level_n).unwrap_or(0) {
+                for child in [2 * index, 2 * index + 1] {
+                    dot.push_str(&format!(
+                        "    n{level_n}_{index} -> n{}_{child};\n",
+                        level_n - 1
+                    ));
+                }
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_small_tree() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        assert_eq!(
+            tree.render_ascii(),
+            [
+                "               a483dd",
+                "      b89651            11e471",
+                " 53de5c   aaf8fa   b93fa1      ·",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn render_proof_path() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        assert_eq!(
+            tree.render_ascii_with_proof(2).unwrap(),
+            [
+                "              [a483dd]",
+                "     (b89651)          [11e471]",
+                " 53de5c   aaf8fa  [b93fa1]     ·",
+            ]
+            .join("\n")
+        );
+        assert!(tree.render_ascii_with_proof(3).is_none());
+    }
+
+    #[test]
+    fn render_large_tree_elides_levels() {
+        let tree = MerkleTree::build(&[0; 100]);
+        let rendered = tree.render_ascii();
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[5], "... 3 lower levels elided");
+        assert!(lines.iter().all(|line| line.chars().count() <= 9 * 16));
+    }
+
+    #[test]
+    fn dot_structure() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        let dot = tree.to_dot();
+
+        assert!(dot.starts_with("digraph merkle_tree {"));
+        assert!(dot.ends_with("}\n"));
+        assert_eq!(
+            dot.lines().filter(|line| line.contains("[label=")).count(),
+            15
+        );
+        assert_eq!(dot.lines().filter(|line| line.contains(" -> ")).count(), 14);
+        assert!(dot.contains("n3_0 -> n2_1;"));
+        assert!(dot.contains("n0_7 [label=\"(0, 7)\\n000000\", style=dashed"));
+        assert!(
+            dot.lines()
+                .any(|line| line.starts_with("    n0_4 ") && !line.contains("dashed"))
+        );
+        assert!(!dot.contains(", color="));
+    }
+
+    #[test]
+    fn dot_colors_proof_path() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4]);
+        let dot = tree.to_dot_with(&DotOptions {
+            proof_path: Some(2),
+            ..DotOptions::default()
+        });
+
+        assert_eq!(dot.matches("color=red").count(), 3);
+        assert_eq!(dot.matches("color=blue").count(), 2);
+        assert!(
+            dot.lines()
+                .any(|line| line.starts_with("    n0_3 ") && line.contains("color=blue"))
+        );
+    }
+
+    #[test]
+    fn dot_colors_highlighted_leaves() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4]);
+        let dot = tree.to_dot_with(&DotOptions {
+            highlight_leaves: vec![0, 1],
+            ..DotOptions::default()
+        });
+
+        // Both leaves, their shared parent and the root.
+        assert_eq!(dot.matches("color=orange").count(), 4);
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::BTreeSet;
+use std::hash::{BuildHasher, RandomState};
+
+use crate::{MerkleTree, ValidationError};
+
+/// How thoroughly `MerkleTree::from_levels` checks the parents of the given levels.
+/// The shape of the levels is always checked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LevelCheck {
+    /// Every parent is recomputed from its children.
+    Strict,
+    /// Up to the given amount of randomly chosen parents is recomputed on each level.
+    /// The choice is unpredictable, so tampered levels are caught with a probability
+    /// that grows with the amount of tampered nodes and samples.
+    Sampled(usize),
+    /// No parent is recomputed, so they are trusted. Only meant for levels coming from a
+    /// trusted source.
+    ShapeOnly,
+}
+
+impl MerkleTree {
+    /// Reconstitutes a tree out of its levels, as returned by `levels_iter`, without
+    /// re-hashing its leaves.
+    /// The capacity is inferred from the amount of leaves and the padding from the
+    /// trailing leaves equal to `PAD_HASH`, so a trailing element whose hash happens to
+    /// be `PAD_HASH` is restored as padding.
+    /// Fails if the levels are malformed or if a checked parent is not the combination
+    /// of its children.
+    /// * `levels` - The levels of the tree, bottom-up.
+    /// * `check` - Which parents are recomputed.
+    pub fn from_levels(
+        levels: Vec<Vec<u64>>,
+        check: LevelCheck,
+    ) -> Result<MerkleTree, ValidationError> {
+        let Some(leaves) = levels.first() else {
+            return Err(ValidationError::Height {
+                expected: 1,
+                found: 0,
+            });
+        };
+        if leaves.is_empty() {
+            return Err(ValidationError::LevelLength {
+                level: 0,
+                expected: 1,
+                found: 0,
+            });
+        }
+
+        let tree = MerkleTree::from_levels_unchecked(levels);
+        tree.check_levels(check)?;
+        Ok(tree)
+    }
+
+    /// Checks the shape of the tree and the parents selected by the given mode.
+    /// * `check` - Which parents are recomputed.
+    pub(crate) fn check_levels(&self, check: LevelCheck) -> Result<(), ValidationError> {
+        self.validate_shape()?;
+
+        match check {
+            LevelCheck::Strict => self.validate(),
+            LevelCheck::ShapeOnly => Ok(()),
+            LevelCheck::Sampled(samples) => {
+                let random = RandomState::new();
+                for level_n in 1..self.height() {
+                    let level_len = self.levels.width(level_n);
+                    let indices: BTreeSet<usize> = if samples >= level_len {
+                        (0..level_len).collect()
+                    } else {
+                        (0..samples)
+                            .map(|sample| random.hash_one((level_n, sample)) as usize % level_len)
+                            .collect()
+                    };
+                    for index in indices {
+                        self.validate_node(level_n, index)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Reconstitutes a tree out of its levels like `from_levels`, without checking
+    /// them at all. Only meant for levels coming from a trusted source: malformed
+    /// levels result in a tree that may panic or produce wrong proofs.
+    /// * `levels` - The levels of the tree, bottom-up.
+    pub fn from_levels_unchecked(levels: Vec<Vec<u64>>) -> MerkleTree {
+        let leaves = levels.first().map(Vec::as_slice).unwrap_or_default();
+        let capacity = leaves.len();
+        let padding = leaves
+            .iter()
+            .rev()
+            .take_while(|&&leaf| leaf == MerkleTree::PAD_HASH)
+            .count();
+        MerkleTree::from_parts(levels, capacity, padding)
+    }
+}
+
+/// Reconstitutes a tree out of its levels, recomputing every parent.
+/// See `MerkleTree::from_levels`.
+impl TryFrom<Vec<Vec<u64>>> for MerkleTree {
+    type Error = ValidationError;
+
+    fn try_from(levels: Vec<Vec<u64>>) -> Result<MerkleTree, ValidationError> {
+        MerkleTree::from_levels(levels, LevelCheck::Strict)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+    use crate::MerkleProof;
+
+    /// Returns a copy of the tree's levels.
+    fn levels_of(tree: &MerkleTree) -> Vec<Vec<u64>> {
+        tree.levels_iter().map(Cow::into_owned).collect()
+    }
+
+    #[test]
+    fn round_trip_levels() {
+        for len in 0..12 {
+            let elements: Vec<usize> = (0..len).collect();
+            let tree = MerkleTree::build(&elements);
+
+            let restored = MerkleTree::try_from(levels_of(&tree)).unwrap();
+            assert!(restored == tree);
+            assert_eq!(restored.capacity(), tree.capacity());
+            assert_eq!(restored.root(), tree.root());
+
+            let sampled = MerkleTree::from_levels(levels_of(&tree), LevelCheck::Sampled(2));
+            assert!(sampled.unwrap() == tree);
+        }
+    }
+
+    #[test]
+    fn restored_tree_behaves_like_original() {
+        let mut tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        let mut restored = MerkleTree::try_from(levels_of(&tree)).unwrap();
+
+        for index in 0..5 {
+            assert!(restored.get_proof(index) == tree.get_proof(index));
+        }
+        assert!(restored.get_proof(5) == MerkleProof::Invalid);
+
+        for value in 6..12 {
+            tree.push(value);
+            restored.push(value);
+            assert_eq!(restored.root(), tree.root());
+        }
+        assert_eq!(restored.validate(), Ok(()));
+    }
+
+    #[test]
+    fn tampered_node_caught_in_strict_mode() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        let mut levels = levels_of(&tree);
+        levels[1][2] ^= 1;
+
+        assert_eq!(
+            MerkleTree::try_from(levels.clone()).err(),
+            Some(ValidationError::Hash {
+                level: 1,
+                index: 2,
+                expected: tree.node(1, 2).unwrap(),
+                found: tree.node(1, 2).unwrap() ^ 1,
+            })
+        );
+
+        // Sampling every node of the level is as thorough as the strict mode.
+        assert!(MerkleTree::from_levels(levels.clone(), LevelCheck::Sampled(4)).is_err());
+        assert!(MerkleTree::from_levels(levels, LevelCheck::ShapeOnly).is_ok());
+    }
+
+    #[test]
+    fn malformed_levels_rejected() {
+        assert_eq!(
+            MerkleTree::try_from(vec![vec![1, 2, 3]]).err(),
+            Some(ValidationError::CapacityNotPowerOfTwo(3))
+        );
+        assert_eq!(
+            MerkleTree::try_from(Vec::new()).err(),
+            Some(ValidationError::Height {
+                expected: 1,
+                found: 0
+            })
+        );
+        assert_eq!(
+            MerkleTree::try_from(vec![Vec::new()]).err(),
+            Some(ValidationError::LevelLength {
+                level: 0,
+                expected: 1,
+                found: 0
+            })
+        );
+
+        let mut levels = levels_of(&MerkleTree::build(&[1, 2, 3, 4]));
+        levels[1].push(0);
+        assert!(matches!(
+            MerkleTree::try_from(levels),
+            Err(ValidationError::LevelLength { level: 1, .. })
+        ));
+    }
+}
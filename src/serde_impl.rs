@@ -0,0 +1,401 @@
+use std::fmt::{self, Formatter};
+
+use serde::de::{Error, SeqAccess, Visitor};
+use serde::ser::SerializeSeq;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{BuilderCheckpoint, DecodeOptions, MerkleProof, MerkleTree, is_consistent_path};
+
+/// Serialized form of a tree: only its occupied leaves and metadata. Upper levels are
+/// recomputed on deserialization, so tampered internal nodes can never be trusted.
+#[derive(Serialize)]
+#[serde(rename = "MerkleTree")]
+struct TreeRef<'a> {
+    len: usize,
+    capacity: usize,
+    epoch: u64,
+    leaves: OccupiedLeaves<'a>,
+}
+
+/// Hashes of the occupied leaves of a tree without pruned ones, serialized as a sequence
+/// out of `iter_occupied`.
+struct OccupiedLeaves<'a>(&'a MerkleTree);
+
+impl Serialize for OccupiedLeaves<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut leaves = serializer.serialize_seq(Some(self.0.len()))?;
+        for (_, hash) in self.0.iter_occupied() {
+            leaves.serialize_element(&hash)?;
+        }
+        leaves.end()
+    }
+}
+
+/// Owned counterpart of `TreeRef`, checked before building the tree.
+#[derive(Deserialize)]
+#[serde(rename = "MerkleTree", deny_unknown_fields)]
+struct TreeData {
+    len: usize,
+    capacity: usize,
+    epoch: u64,
+    leaves: Vec<u64>,
+}
+
+/// Serializes the occupied leaves together with the length, capacity and epoch.
+/// Internal nodes are not serialized.
+/// Fails if the tree has pruned leaves (see `from_frontier`), whose hashes are not held.
+impl Serialize for MerkleTree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.pruned_len() > 0 {
+            return Err(serde::ser::Error::custom(format!(
+                "the first {} leaves are pruned",
+                self.pruned_len()
+            )));
+        }
+        TreeRef {
+            len: self.len(),
+            capacity: self.capacity(),
+            epoch: self.epoch(),
+            leaves: OccupiedLeaves(self),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Rebuilds the tree out of its serialized leaves, recomputing every internal node.
+/// Fails if the length or the capacity disagree with the leaves.
+impl<'de> Deserialize<'de> for MerkleTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MerkleTree, D::Error> {
+        let data = TreeData::deserialize(deserializer)?;
+        if data.leaves.len() != data.len {
+            return Err(D::Error::custom(format!(
+                "length {} disagrees with the {} serialized leaves",
+                data.len,
+                data.leaves.len()
+            )));
+        }
+
+        let mut tree = MerkleTree::from_leaf_hashes(data.leaves);
+        if tree.capacity() != data.capacity {
+            return Err(D::Error::custom(format!(
+                "capacity {} is invalid for length {}, expected {}",
+                data.capacity,
+                data.len,
+                tree.capacity()
+            )));
+        }
+        tree.epoch = data.epoch;
+        Ok(tree)
+    }
+}
+
+/// Serialized form of a valid proof.
+#[derive(Serialize)]
+#[serde(rename = "Proof")]
+struct ProofRef<'a> {
+    index: usize,
+    nodes: &'a [u64],
+    root: u64,
+    len: usize,
+    epoch: u64,
+}
+
+/// Owned counterpart of `ProofRef`, checked before building the proof.
+#[derive(Deserialize)]
+#[serde(rename = "Proof", deny_unknown_fields)]
+struct ProofData {
+    index: usize,
+    #[serde(deserialize_with = "bounded_nodes")]
+    nodes: Vec<u64>,
+    root: u64,
+    len: usize,
+    epoch: u64,
+}
+
+/// Visitor collecting a proof's nodes within the default `DecodeOptions`.
+struct NodesVisitor;
+
+impl<'de> Visitor<'de> for NodesVisitor {
+    type Value = Vec<u64>;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("a sequence of node hashes")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u64>, A::Error> {
+        let mut nodes = Vec::new();
+        while let Some(node) = seq.next_element()? {
+            DecodeOptions::DEFAULT
+                .check_node_count(nodes.len() as u64 + 1)
+                .map_err(A::Error::custom)?;
+            nodes.push(node);
+        }
+        Ok(nodes)
+    }
+}
+
+/// Deserializes a proof's nodes, failing as soon as they exceed the default limits
+/// instead of trusting a declared length.
+fn bounded_nodes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u64>, D::Error> {
+    deserializer.deserialize_seq(NodesVisitor)
+}
+
+/// Serializes valid proofs as their fields, and `Invalid` as a missing value (`null` in
+/// JSON), so it can never be confused with a proof holding no nodes. Binary formats
+/// only spend the option tag on top of the fields.
+impl Serialize for MerkleProof {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let proof = match self {
+            MerkleProof::Invalid => None,
+            MerkleProof::Proof {
+                index,
+                nodes,
+                root,
+                len,
+                epoch,
+            } => Some(ProofRef {
+                index: *index,
+                nodes,
+                root: *root,
+                len: *len,
+                epoch: *epoch,
+            }),
+        };
+        proof.serialize(serializer)
+    }
+}
+
+/// Deserializes a proof, failing if its index is not below its length, if its nodes do
+/// not form a path in a tree of its length or if they exceed the default
+/// `DecodeOptions`. Serde exposes no input length, so
+/// `max_total_bytes` must be enforced by the caller before deserializing.
+impl<'de> Deserialize<'de> for MerkleProof {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MerkleProof, D::Error> {
+        let Some(data) = Option::<ProofData>::deserialize(deserializer)? else {
+            return Ok(MerkleProof::Invalid);
+        };
+        if data.index >= data.len {
+            return Err(D::Error::custom(format!(
+                "index {} out of range for length {}",
+                data.index, data.len
+            )));
+        }
+        if !is_consistent_path(data.index, data.len, &data.nodes) {
+            return Err(D::Error::custom(format!(
+                "a path of {} nodes does not fit a tree of length {}",
+                data.nodes.len(),
+                data.len
+            )));
+        }
+        Ok(MerkleProof::Proof {
+            index: data.index,
+            nodes: data.nodes,
+            root: data.root,
+            len: data.len,
+            epoch: data.epoch,
+        })
+    }
+}
+
+/// Serialized form of a builder checkpoint.
+#[derive(Serialize)]
+#[serde(rename = "BuilderCheckpoint")]
+struct CheckpointRef<'a> {
+    len: u64,
+    frontier: &'a [u64],
+}
+
+/// Owned counterpart of `CheckpointRef`, checked before building the checkpoint.
+#[derive(Deserialize)]
+#[serde(rename = "BuilderCheckpoint", deny_unknown_fields)]
+struct CheckpointData {
+    len: u64,
+    frontier: Vec<u64>,
+}
+
+impl Serialize for BuilderCheckpoint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CheckpointRef {
+            len: self.len,
+            frontier: &self.frontier,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Deserializes a checkpoint, failing if its length exceeds `1 << 63` or if its frontier
+/// does not hold a node per set bit of it.
+impl<'de> Deserialize<'de> for BuilderCheckpoint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<BuilderCheckpoint, D::Error> {
+        let data = CheckpointData::deserialize(deserializer)?;
+        let checkpoint = BuilderCheckpoint {
+            len: data.len,
+            frontier: data.frontier,
+        };
+        checkpoint.check().map_err(D::Error::custom)?;
+        Ok(checkpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BuilderCheckpoint, MerkleProof, MerkleTree, MerkleTreeBuilder};
+
+    /// Returns a tree whose epoch was advanced by pushes.
+    fn pushed_tree() -> MerkleTree {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        tree.push(4);
+        tree.push(5);
+        tree
+    }
+
+    /// Asserts that both trees are identical in content, shape and epoch.
+    fn assert_same(restored: &MerkleTree, tree: &MerkleTree) {
+        assert!(restored == tree);
+        assert_eq!(restored.root(), tree.root());
+        assert_eq!(restored.capacity(), tree.capacity());
+        assert_eq!(restored.epoch(), tree.epoch());
+        for index in 0..tree.len() {
+            assert!(restored.get_proof(index) == tree.get_proof(index));
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        for tree in [pushed_tree(), MerkleTree::build::<u8>(&[])] {
+            let json = serde_json::to_string(&tree).unwrap();
+            let restored: MerkleTree = serde_json::from_str(&json).unwrap();
+            assert_same(&restored, &tree);
+        }
+    }
+
+    #[test]
+    fn bincode_round_trip() {
+        for tree in [pushed_tree(), MerkleTree::build::<u8>(&[])] {
+            let bytes = bincode::serialize(&tree).unwrap();
+            let restored: MerkleTree = bincode::deserialize(&bytes).unwrap();
+            assert_same(&restored, &tree);
+        }
+    }
+
+    #[test]
+    fn json_layout() {
+        let tree = MerkleTree::from_leaf_hashes(vec![1, 2, 3]);
+        assert_eq!(
+            serde_json::to_string(&tree).unwrap(),
+            r#"{"len":3,"capacity":4,"epoch":0,"leaves":[1,2,3]}"#
+        );
+    }
+
+    #[test]
+    fn pruned_trees_rejected() {
+        let tree = MerkleTree::from_frontier(pushed_tree().export_frontier()).unwrap();
+        assert!(serde_json::to_string(&tree).is_err());
+        assert!(bincode::serialize(&tree).is_err());
+    }
+
+    #[test]
+    fn inconsistent_data_rejected() {
+        for json in [
+            r#"{"len":2,"capacity":4,"epoch":0,"leaves":[1,2,3]}"#,
+            r#"{"len":3,"capacity":8,"epoch":0,"leaves":[1,2,3]}"#,
+            r#"{"len":3,"capacity":4,"epoch":0,"leaves":[1,2,3],"levels":[]}"#,
+            r#"{"len":3,"capacity":4,"leaves":[1,2,3]}"#,
+        ] {
+            assert!(serde_json::from_str::<MerkleTree>(json).is_err(), "{json}");
+        }
+    }
+
+    #[test]
+    fn proof_round_trips() {
+        let tree = pushed_tree();
+        for index in 0..tree.len() {
+            let proof = tree.get_proof(index);
+
+            let json = serde_json::to_string(&proof).unwrap();
+            let from_json: MerkleProof = serde_json::from_str(&json).unwrap();
+            assert!(from_json == proof);
+            assert!(from_json.verify((index + 1) as i32));
+
+            let bytes = bincode::serialize(&proof).unwrap();
+            let from_bincode: MerkleProof = bincode::deserialize(&bytes).unwrap();
+            assert!(from_bincode == proof);
+            assert!(from_bincode.verify((index + 1) as i32));
+        }
+    }
+
+    #[test]
+    fn invalid_proof_round_trips() {
+        let json = serde_json::to_string(&MerkleProof::Invalid).unwrap();
+        assert_eq!(json, "null");
+        assert!(serde_json::from_str::<MerkleProof>(&json).unwrap() == MerkleProof::Invalid);
+
+        let bytes = bincode::serialize(&MerkleProof::Invalid).unwrap();
+        assert_eq!(bytes, [0]);
+        assert!(bincode::deserialize::<MerkleProof>(&bytes).unwrap() == MerkleProof::Invalid);
+    }
+
+    #[test]
+    fn proof_layout() {
+        let tree = MerkleTree::build(&[1, 2]);
+        let root = tree.root().unwrap();
+        let json = serde_json::to_string(&tree.get_proof(1)).unwrap();
+        assert_eq!(
+            json,
+            format!(
+                r#"{{"index":1,"nodes":[{}],"root":{root},"len":2,"epoch":0}}"#,
+                tree.leaf(0).unwrap()
+            )
+        );
+
+        // Option tag, then index, node count, one node, root, len and epoch.
+        let bytes = bincode::serialize(&tree.get_proof(1)).unwrap();
+        assert_eq!(bytes.len(), 1 + 6 * 8);
+    }
+
+    #[test]
+    fn malformed_proofs_rejected() {
+        for json in [
+            r#"{"index":1,"nodes":[],"root":0,"len":1,"epoch":0}"#,
+            r#"{"index":0,"nodes":[],"root":0,"len":1,"epoch":0,"extra":1}"#,
+            r#"{"index":0,"nodes":[],"root":0,"len":1}"#,
+            r#"{"index":0,"nodes":"","root":0,"len":1,"epoch":0}"#,
+            r#"[]"#,
+        ] {
+            assert!(serde_json::from_str::<MerkleProof>(json).is_err(), "{json}");
+        }
+    }
+
+    #[test]
+    fn oversized_proofs_rejected() {
+        let proof = |depth: usize| {
+            let nodes = vec!["1"; depth].join(",");
+            let len = usize::MAX;
+            format!(r#"{{"index":0,"nodes":[{nodes}],"root":0,"len":{len},"epoch":0}}"#)
+        };
+        assert!(serde_json::from_str::<MerkleProof>(&proof(64)).is_ok());
+        let error = serde_json::from_str::<MerkleProof>(&proof(1000)).unwrap_err();
+        assert!(error.to_string().contains("depth 65 exceeds 64"), "{error}");
+
+        // Option tag, index, then a declared node count of u64::MAX with a 10 byte body.
+        let mut bytes = vec![1];
+        bytes.extend_from_slice(&0_u64.to_le_bytes());
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        bytes.extend_from_slice(&[0; 10]);
+        assert!(bincode::deserialize::<MerkleProof>(&bytes).is_err());
+    }
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let mut builder = MerkleTreeBuilder::new();
+        (0..11).for_each(|value| builder.push(value));
+        let json = serde_json::to_string(&builder.checkpoint()).unwrap();
+        let checkpoint: BuilderCheckpoint = serde_json::from_str(&json).unwrap();
+        assert_eq!(checkpoint, builder.checkpoint());
+
+        let json = r#"{"len":3,"frontier":[1]}"#;
+        assert!(serde_json::from_str::<BuilderCheckpoint>(json).is_err());
+        let json = format!(r#"{{"len":{},"frontier":{:?}}}"#, u64::MAX, [1; 64]);
+        assert!(serde_json::from_str::<BuilderCheckpoint>(&json).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use std::hash::Hash;
+
+use crate::{MerkleProof, MerkleTree, TreeHead};
+
+/// Produces signatures over arbitrary bytes.
+/// Implement it on top of the signature scheme of choice (e.g. ed25519 or HMAC).
+pub trait Signer {
+    /// Returns the signature of the given message.
+    /// * `message` - The bytes to be signed.
+    fn sign(&self, message: &[u8]) -> Vec<u8>;
+}
+
+/// Checks signatures produced by a `Signer`.
+pub trait Verifier {
+    /// Returns whether the signature is valid for the given message.
+    /// * `message` - The bytes that were signed.
+    /// * `signature` - The signature to be checked.
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool;
+}
+
+/// A `TreeHead` together with a signature over its canonical byte encoding.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SignedTreeHead {
+    pub head: TreeHead,
+    pub signature: Vec<u8>,
+}
+
+impl SignedTreeHead {
+    /// Returns whether the signature matches the head.
+    /// * `verifier` - The `Verifier` of the expected signer.
+    pub fn verify_head<V: Verifier>(&self, verifier: &V) -> bool {
+        verifier.verify(&self.head.to_bytes(), &self.signature)
+    }
+
+    /// Returns whether a given `Hash` value verifies the proof against the signed head.
+    /// The signature is checked first, so a tampered head is always rejected.
+    /// * `verifier` - The `Verifier` of the expected signer.
+    /// * `proof` - The proof to be checked.
+    /// * `value` - The `Hash` value to be tested.
+    pub fn verify_proof_against<V: Verifier, H: Hash>(
+        &self,
+        verifier: &V,
+        proof: &MerkleProof,
+        value: H,
+    ) -> bool {
+        self.verify_head(verifier) && proof.verify_against(&self.head, value)
+    }
+}
+
+impl MerkleTree {
+    /// Signs the tree's current head. If the tree is empty, there is no head
+    /// to sign and `None` is returned.
+    /// * `signer` - The `Signer` used to sign the head's canonical encoding.
+    pub fn sign_head<S: Signer>(&self, signer: &S) -> Option<SignedTreeHead> {
+        let head = self.head()?;
+        let signature = signer.sign(&head.to_bytes());
+        Some(SignedTreeHead { head, signature })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hash::{DefaultHasher, Hasher};
+
+    /// Keyed checksum standing in for a real signature scheme.
+    struct TestKey(u64);
+
+    impl TestKey {
+        fn mac(&self, message: &[u8]) -> Vec<u8> {
+            let mut hasher = DefaultHasher::new();
+            self.0.hash(&mut hasher);
+            message.hash(&mut hasher);
+            hasher.finish().to_be_bytes().to_vec()
+        }
+    }
+
+    impl Signer for TestKey {
+        fn sign(&self, message: &[u8]) -> Vec<u8> {
+            self.mac(message)
+        }
+    }
+
+    impl Verifier for TestKey {
+        fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+            self.mac(message) == signature
+        }
+    }
+
+    #[test]
+    fn signed_head_verifies() {
+        let key = TestKey(42);
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let sth = tree.sign_head(&key).unwrap();
+
+        assert!(sth.verify_head(&key));
+        assert!(sth.verify_proof_against(&key, &tree.get_proof(1), 2));
+        assert!(!sth.verify_proof_against(&key, &tree.get_proof(1), 3));
+    }
+
+    #[test]
+    fn empty_tree_has_no_signed_head() {
+        assert!(
+            MerkleTree::build::<u8>(&[])
+                .sign_head(&TestKey(42))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn tampered_root_is_rejected() {
+        let key = TestKey(42);
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let other = MerkleTree::build(&[4, 5, 6]);
+        let mut sth = tree.sign_head(&key).unwrap();
+        sth.head.root = other.root().unwrap();
+
+        assert!(!sth.verify_head(&key));
+        assert!(!sth.verify_proof_against(&key, &other.get_proof(1), 5));
+    }
+
+    #[test]
+    fn tampered_size_is_rejected() {
+        let key = TestKey(42);
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let mut sth = tree.sign_head(&key).unwrap();
+        sth.head.len = 4;
+
+        assert!(!sth.verify_head(&key));
+        assert!(!sth.verify_proof_against(&key, &tree.get_proof(1), 2));
+    }
+
+    #[test]
+    fn signature_mismatch_is_rejected() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let sth = tree.sign_head(&TestKey(42)).unwrap();
+        assert!(!sth.verify_head(&TestKey(7)));
+        assert!(!sth.verify_proof_against(&TestKey(7), &tree.get_proof(1), 2));
+
+        let mut sth = sth;
+        sth.signature[0] ^= 1;
+        assert!(!sth.verify_head(&TestKey(42)));
+    }
+}
@@ -0,0 +1,411 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Read, Write};
+
+use crate::wire::{Format, HEADER_LEN, WireError, read_u64, read_usize};
+use crate::{LevelCheck, MemStore, MerkleTree, ValidationError};
+
+/// Bytes every snapshot starts with.
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"MRKL";
+
+/// Version of the snapshot format written by `write_snapshot`. Version 1 snapshots,
+/// which hold every node of every level, padding included, can still be read.
+pub const SNAPSHOT_VERSION: u8 = 2;
+
+/// Largest capacity `read_snapshot` accepts. A tree only reaches a capacity once it
+/// holds half as many leaves, or once given that many (see `from_levels`), which takes
+/// terabytes at this one, so larger capacities only come from forged snapshots.
+pub const MAX_SNAPSHOT_CAPACITY: usize = 1 << 40;
+
+/// Snapshots define no flags yet.
+const SNAPSHOT_FORMAT: Format = Format {
+    magic: SNAPSHOT_MAGIC,
+    version: SNAPSHOT_VERSION,
+    known_flags: 0,
+};
+
+/// Error returned when loading a snapshot fails.
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// Reading failed, including reaching the end of the input before the snapshot did.
+    Io(io::Error),
+    /// The snapshot's header was rejected, for example because it does not start with
+    /// `SNAPSHOT_MAGIC` or because it was written in a newer version.
+    Header(WireError),
+    /// The snapshot's levels are not a consistent tree.
+    Invalid(ValidationError),
+    /// The snapshot's capacity exceeds `MAX_SNAPSHOT_CAPACITY`.
+    Capacity(usize),
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "failed to read snapshot: {error}"),
+            SnapshotError::Header(error) => write!(f, "invalid snapshot header: {error}"),
+            SnapshotError::Invalid(error) => write!(f, "inconsistent snapshot: {error}"),
+            SnapshotError::Capacity(capacity) => write!(
+                f,
+                "snapshot capacity {capacity} exceeds {MAX_SNAPSHOT_CAPACITY}"
+            ),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SnapshotError::Io(error) => Some(error),
+            SnapshotError::Header(error) => Some(error),
+            SnapshotError::Invalid(error) => Some(error),
+            SnapshotError::Capacity(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> SnapshotError {
+        SnapshotError::Io(error)
+    }
+}
+
+/// Header read failures are reported as `Io`, like the rest of the snapshot's.
+impl From<WireError> for SnapshotError {
+    fn from(error: WireError) -> SnapshotError {
+        match error {
+            WireError::Io(error) => SnapshotError::Io(error),
+            error => SnapshotError::Header(error),
+        }
+    }
+}
+
+impl From<ValidationError> for SnapshotError {
+    fn from(error: ValidationError) -> SnapshotError {
+        SnapshotError::Invalid(error)
+    }
+}
+
+impl MerkleTree {
+    /// Writes a binary snapshot of the whole tree, returning the amount of bytes written.
+    /// The snapshot starts with a wire header holding `SNAPSHOT_MAGIC` and
+    /// `SNAPSHOT_VERSION`, followed by the epoch, the capacity and the padding as
+    /// little-endian `u64` values, and every level bottom-up. Like `MemStore`, levels
+    /// only hold the nodes up to the frontier of real data: each one is written as the
+    /// amount of its leading nodes, followed by those nodes, while the rest root subtrees
+    /// filled with padding only and are rebuilt when read. Every node is a little-endian
+    /// `u64`.
+    /// Nodes are written one at a time, so the writer should be buffered.
+    /// Fails with `io::ErrorKind::InvalidInput`, writing nothing, if the tree has pruned
+    /// leaves (see `from_frontier`), whose nodes are not held.
+    /// * `writer` - Where the snapshot is written.
+    pub fn write_snapshot<W: Write>(&self, mut writer: W) -> io::Result<u64> {
+        if self.pruned > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("the first {} leaves are pruned", self.pruned),
+            ));
+        }
+        SNAPSHOT_FORMAT.write_header(&mut writer, 0)?;
+        for value in [self.epoch, self.capacity as u64, self.padding as u64] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        let mut written = (HEADER_LEN + 3 * 8) as u64;
+
+        // The leaves beyond the occupied ones are padding. In leaves-only mode, the levels
+        // which are not stored are hashed out of the leaves, so only the nodes above the
+        // occupied leaves can differ from padding.
+        let len = self.len();
+        writer.write_all(&(len as u64).to_le_bytes())?;
+        for (_, leaf) in self.iter_occupied() {
+            writer.write_all(&leaf.to_le_bytes())?;
+        }
+        written += 8 * (1 + len as u64);
+        for level_n in 1..self.height() {
+            let leading = self
+                .levels
+                .stored(level_n)
+                .len()
+                .max(len.div_ceil(1 << level_n));
+            writer.write_all(&(leading as u64).to_le_bytes())?;
+            for node in self.levels.iter_level(level_n).take(leading) {
+                writer.write_all(&node.to_le_bytes())?;
+            }
+            written += 8 * (1 + leading as u64);
+        }
+
+        writer.flush()?;
+        Ok(written)
+    }
+
+    /// Loads a tree out of a snapshot written by `write_snapshot`, without re-hashing its
+    /// leaves. The header and the shape of the levels are always validated, while the
+    /// parents are checked as selected by `check`.
+    /// Fails with `SnapshotError::Capacity` if the capacity exceeds
+    /// `MAX_SNAPSHOT_CAPACITY`, before any level is read.
+    /// Nodes are read one at a time, so the reader should be buffered.
+    /// * `reader` - Where the snapshot is read from.
+    /// * `check` - Which parents are recomputed.
+    pub fn read_snapshot<R: Read>(
+        mut reader: R,
+        check: LevelCheck,
+    ) -> Result<MerkleTree, SnapshotError> {
+        let header = SNAPSHOT_FORMAT.read_header(&mut reader)?;
+        let epoch = read_u64(&mut reader)?;
+        let capacity = read_usize(&mut reader)?;
+        let padding = read_usize(&mut reader)?;
+        if !capacity.is_power_of_two() {
+            return Err(ValidationError::CapacityNotPowerOfTwo(capacity).into());
+        }
+        if padding > capacity {
+            return Err(ValidationError::Padding { capacity, padding }.into());
+        }
+        if capacity > MAX_SNAPSHOT_CAPACITY {
+            return Err(SnapshotError::Capacity(capacity));
+        }
+
+        // Levels grow as nodes are read, so the nodes allocated are bounded by the input's
+        // length, while the rest of the tree only takes a few words per level.
+        let height = capacity.ilog2() as usize + 1;
+        let widths: Vec<usize> = (0..height).map(|level_n| capacity >> level_n).collect();
+        let mut levels = Vec::with_capacity(height);
+        for (level_n, &width) in widths.iter().enumerate() {
+            let leading = match header.version {
+                1 => width,
+                _ => read_usize(&mut reader)?,
+            };
+            if leading > width {
+                return Err(ValidationError::LevelLength {
+                    level: level_n,
+                    expected: width,
+                    found: leading,
+                }
+                .into());
+            }
+            let mut level = Vec::new();
+            for _ in 0..leading {
+                level.push(read_u64(&mut reader)?);
+            }
+            levels.push(level);
+        }
+
+        let len = capacity - padding;
+        if levels[0].len() < len {
+            return Err(ValidationError::LevelLength {
+                level: 0,
+                expected: len,
+                found: levels[0].len(),
+            }
+            .into());
+        }
+        let store = MemStore::from_prefixes(levels, widths, len);
+        let mut tree = MerkleTree::from_store(store, capacity, padding);
+        tree.epoch = epoch;
+        tree.check_levels(check)?;
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{generate_tree_levels, hash_single};
+
+    /// Returns the snapshot of the given tree.
+    fn snapshot(tree: &MerkleTree) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let written = tree.write_snapshot(&mut bytes).unwrap();
+        assert_eq!(written, bytes.len() as u64);
+        bytes
+    }
+
+    #[test]
+    fn large_tree_round_trip() {
+        let elements: Vec<u32> = (0..100_000).collect();
+        let mut tree = MerkleTree::build(&elements);
+        tree.push(100_000);
+        let bytes = snapshot(&tree);
+
+        for check in [LevelCheck::Strict, LevelCheck::Sampled(16)] {
+            let restored = MerkleTree::read_snapshot(Cursor::new(&bytes), check).unwrap();
+            assert!(restored == tree);
+            assert_eq!(restored.root(), tree.root());
+            assert_eq!(restored.capacity(), tree.capacity());
+            assert_eq!(restored.epoch(), tree.epoch());
+            assert!(restored.get_proof(77_777).verify(77_777));
+        }
+    }
+
+    #[test]
+    fn padding_not_written() {
+        let mut leaves: Vec<u64> = (1..=5).map(hash_single).collect();
+        leaves.resize(1 << 16, MerkleTree::PAD_HASH);
+        let mut levels = Vec::new();
+        generate_tree_levels(&leaves, &mut levels);
+        let tree = MerkleTree::from_levels(levels, LevelCheck::Strict).unwrap();
+        assert_eq!(tree.capacity(), 1 << 16);
+
+        // A dense snapshot takes a megabyte: only the paths to the frontier are written.
+        let bytes = snapshot(&tree);
+        assert!(bytes.len() < 1024, "{} bytes", bytes.len());
+        let restored = MerkleTree::read_snapshot(Cursor::new(&bytes), LevelCheck::Strict).unwrap();
+        assert!(restored == tree);
+        assert!(restored.stats().heap_bytes < tree.stats().heap_bytes + 1024);
+        assert!(restored.get_proof(4).verify(5));
+
+        let mut tree = MerkleTree::build(&(0..1000).collect::<Vec<u32>>());
+        tree.keep_leaves_only(1).unwrap();
+        let restored =
+            MerkleTree::read_snapshot(Cursor::new(snapshot(&tree)), LevelCheck::Strict).unwrap();
+        assert_eq!(restored.root(), tree.root());
+        assert_eq!(restored.leaves(), tree.leaves());
+    }
+
+    #[test]
+    fn pruned_trees_rejected() {
+        let tree =
+            MerkleTree::from_frontier(MerkleTree::build(&[1, 2, 3]).export_frontier()).unwrap();
+        let mut bytes = Vec::new();
+        let error = tree.write_snapshot(&mut bytes).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn version_1_snapshots_read() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        let mut bytes = b"MRKL\x01\x00\x00".to_vec();
+        for value in [tree.epoch(), 8, 3] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for level_n in 0..tree.height() {
+            for node in tree.level(level_n).unwrap().iter() {
+                bytes.extend_from_slice(&node.to_le_bytes());
+            }
+        }
+        let restored = MerkleTree::read_snapshot(Cursor::new(&bytes), LevelCheck::Strict).unwrap();
+        assert!(restored == tree);
+    }
+
+    #[test]
+    fn missing_leaves_rejected() {
+        let mut bytes = snapshot(&MerkleTree::build(&[1, 2, 3]));
+        // The leaves' level announces 2 leading nodes, fewer than the tree's length.
+        bytes[HEADER_LEN + 24] = 2;
+        bytes.drain(HEADER_LEN + 32 + 16..HEADER_LEN + 32 + 24);
+        assert!(matches!(
+            MerkleTree::read_snapshot(Cursor::new(&bytes), LevelCheck::Strict),
+            Err(SnapshotError::Invalid(ValidationError::LevelLength {
+                level: 0,
+                expected: 3,
+                found: 2
+            }))
+        ));
+    }
+
+    #[test]
+    fn empty_tree_round_trip() {
+        let tree = MerkleTree::build::<u8>(&[]);
+        let restored =
+            MerkleTree::read_snapshot(Cursor::new(snapshot(&tree)), LevelCheck::Strict).unwrap();
+        assert!(restored.is_empty());
+        assert_eq!(restored.capacity(), 1);
+    }
+
+    #[test]
+    fn flipped_byte_detected() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        let header_len = HEADER_LEN + 24;
+        let len = snapshot(&tree).len();
+
+        for position in header_len..len {
+            let mut bytes = snapshot(&tree);
+            bytes[position] ^= 0x10;
+            assert!(
+                matches!(
+                    MerkleTree::read_snapshot(Cursor::new(bytes), LevelCheck::Strict),
+                    Err(SnapshotError::Invalid(_))
+                ),
+                "flipped byte {position}"
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_header_rejected() {
+        let mut bytes = snapshot(&MerkleTree::build(&[1, 2, 3]));
+        bytes[0] = b'X';
+        assert!(matches!(
+            MerkleTree::read_snapshot(Cursor::new(&bytes), LevelCheck::Strict),
+            Err(SnapshotError::Header(WireError::BadMagic(magic))) if &magic == b"XRKL"
+        ));
+
+        let mut bytes = snapshot(&MerkleTree::build(&[1, 2, 3]));
+        bytes[4] = 3;
+        assert!(matches!(
+            MerkleTree::read_snapshot(Cursor::new(&bytes), LevelCheck::Strict),
+            Err(SnapshotError::Header(WireError::UnsupportedVersion(3)))
+        ));
+
+        let mut bytes = snapshot(&MerkleTree::build(&[1, 2, 3]));
+        bytes[6] = 0x01;
+        assert!(matches!(
+            MerkleTree::read_snapshot(Cursor::new(&bytes), LevelCheck::Strict),
+            Err(SnapshotError::Header(WireError::UnknownRequiredFlag(8)))
+        ));
+
+        let mut bytes = snapshot(&MerkleTree::build(&[1, 2, 3]));
+        bytes[15] = 3;
+        assert!(matches!(
+            MerkleTree::read_snapshot(Cursor::new(&bytes), LevelCheck::Strict),
+            Err(SnapshotError::Invalid(
+                ValidationError::CapacityNotPowerOfTwo(3)
+            ))
+        ));
+    }
+
+    #[test]
+    fn forged_capacity_rejected() {
+        // An empty tree of capacity 2^44, announcing no nodes on any of its 45 levels.
+        let mut bytes = b"MRKL\x02\x00\x00".to_vec();
+        for value in [0, 1 << 44, 1 << 44] {
+            bytes.extend_from_slice(&u64::to_le_bytes(value));
+        }
+        bytes.resize(bytes.len() + 45 * 8, 0);
+        assert_eq!(bytes.len(), 391);
+        assert!(matches!(
+            MerkleTree::read_snapshot(Cursor::new(&bytes), LevelCheck::Strict),
+            Err(SnapshotError::Capacity(capacity)) if capacity == 1 << 44
+        ));
+    }
+
+    #[test]
+    fn header_always_written() {
+        let bytes = snapshot(&MerkleTree::build::<u8>(&[]));
+        assert_eq!(
+            bytes[..HEADER_LEN],
+            [b'M', b'R', b'K', b'L', SNAPSHOT_VERSION, 0, 0]
+        );
+    }
+
+    #[test]
+    fn unknown_optional_flag_ignored() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let mut bytes = snapshot(&tree);
+        bytes[5] = 0x80;
+        let restored = MerkleTree::read_snapshot(Cursor::new(&bytes), LevelCheck::Strict);
+        assert!(restored.unwrap() == tree);
+    }
+
+    #[test]
+    fn truncated_snapshot_rejected() {
+        let bytes = snapshot(&MerkleTree::build(&[1, 2, 3]));
+        for len in 0..bytes.len() {
+            assert!(matches!(
+                MerkleTree::read_snapshot(Cursor::new(&bytes[..len]), LevelCheck::ShapeOnly),
+                Err(SnapshotError::Io(_))
+            ));
+        }
+    }
+}
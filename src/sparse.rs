@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::{MerkleTree, empty_nodes, hash_pair, hash_single};
+
+/// Maximum depth of a `SparseMerkleTree`: keys are placed by their 64 bit hash.
+pub const MAX_SPARSE_DEPTH: usize = 64;
+
+/// Fixed-depth tree with a leaf slot per possible key path, almost all of them empty.
+/// Keys are placed at the slot given by the top `depth` bits of their hash, and only
+/// the nodes differing from the default hash of their level are stored, so memory grows
+/// with the amount of keys rather than with the `2^depth` slots.
+/// Empty slots hold `MerkleTree::PAD_HASH`, so the default hash of each level is the
+/// one of a dense tree's padding-only subtree. Keys sharing a path share a slot, which
+/// depths close to `MAX_SPARSE_DEPTH` make unlikely.
+#[derive(Clone, Debug)]
+pub struct SparseMerkleTree {
+    depth: usize,
+    /// Default hash of each level, from the leaves (`0`) up to the root (`depth`).
+    defaults: Vec<u64>,
+    /// Non-default nodes, keyed by level and index within the level.
+    nodes: HashMap<(usize, u64), u64>,
+    /// Amount of occupied leaf slots.
+    len: usize,
+}
+
+/// Proof of the content of a `SparseMerkleTree`'s slot: either the leaf a key holds,
+/// or that the key's slot is empty.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SparseProof {
+    /// Siblings of the slot's path, bottom-up: one per level below the root.
+    pub siblings: Vec<u64>,
+}
+
+/// Returns the slot of a key in a tree of the given depth.
+fn key_path<K: Hash>(key: &K, depth: usize) -> u64 {
+    hash_single(key) >> (MAX_SPARSE_DEPTH - depth)
+}
+
+impl SparseMerkleTree {
+    /// Creates an empty tree of `2^depth` leaf slots.
+    /// Panics if the depth is zero or above `MAX_SPARSE_DEPTH`.
+    /// * `depth` - Amount of levels below the root.
+    pub fn new(depth: usize) -> SparseMerkleTree {
+        assert!(
+            (1..=MAX_SPARSE_DEPTH).contains(&depth),
+            "depth {depth} is not within 1..={MAX_SPARSE_DEPTH}"
+        );
+        SparseMerkleTree {
+            depth,
+            defaults: empty_nodes(depth + 1),
+            nodes: HashMap::new(),
+            len: 0,
+        }
+    }
+
+    /// Returns the amount of levels below the root.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the amount of occupied leaf slots.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether every leaf slot is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the hash of a subtree without leaves, which is the root of an empty tree
+    /// of that depth.
+    /// Panics if the level is above the tree's depth.
+    /// * `level` - Level of the subtree's root, `0` being the leaves.
+    pub fn default_hash(&self, level: usize) -> u64 {
+        self.defaults[level]
+    }
+
+    /// Returns the node at a position, which is its level's default hash unless stored.
+    fn node(&self, level: usize, index: u64) -> u64 {
+        self.nodes
+            .get(&(level, index))
+            .copied()
+            .unwrap_or(self.defaults[level])
+    }
+
+    /// Stores a node, dropping it if it holds its level's default hash.
+    fn set_node(&mut self, level: usize, index: u64, hash: u64) {
+        if hash == self.defaults[level] {
+            self.nodes.remove(&(level, index));
+        } else {
+            self.nodes.insert((level, index), hash);
+        }
+    }
+
+    /// Returns the root, which is the top default hash while the tree is empty.
+    pub fn root(&self) -> u64 {
+        self.node(self.depth, 0)
+    }
+
+    /// Places a value at the key's slot, replacing what it held, and recomputes the
+    /// slot's path up to the root.
+    /// * `key` - The `Hash` key giving the slot.
+    /// * `value` - The `Hash` value to be stored.
+    pub fn insert<K: Hash, V: Hash>(&mut self, key: K, value: V) {
+        let mut index = key_path(&key, self.depth);
+        if !self.nodes.contains_key(&(0, index)) {
+            self.len += 1;
+        }
+        // Leaves are stored even if they hash to `PAD_HASH`, so they count as occupied.
+        let mut node = hash_single(value);
+        self.nodes.insert((0, index), node);
+
+        for level in 0..self.depth {
+            let sibling = self.node(level, index ^ 1);
+            node = if index.is_multiple_of(2) {
+                hash_pair(node, sibling)
+            } else {
+                hash_pair(sibling, node)
+            };
+            index >>= 1;
+            self.set_node(level + 1, index, node);
+        }
+    }
+
+    /// Returns the leaf hash stored at the key's slot, or `None` if the slot is empty.
+    /// * `key` - The `Hash` key giving the slot.
+    pub fn get<K: Hash>(&self, key: K) -> Option<u64> {
+        self.nodes.get(&(0, key_path(&key, self.depth))).copied()
+    }
+
+    /// Returns the proof of the key's slot, proving either the value it holds (see
+    /// `SparseProof::verify_inclusion`) or that it is empty (see
+    /// `SparseProof::verify_empty`).
+    /// * `key` - The `Hash` key giving the slot.
+    pub fn prove<K: Hash>(&self, key: K) -> SparseProof {
+        let path = key_path(&key, self.depth);
+        let siblings = (0..self.depth)
+            .map(|level| self.node(level, (path >> level) ^ 1))
+            .collect();
+        SparseProof { siblings }
+    }
+}
+
+impl SparseProof {
+    /// Returns the root obtained by combining a leaf with the siblings along the key's
+    /// path, or `None` if the proof's depth is not supported.
+    fn compute_root<K: Hash>(&self, key: &K, leaf: u64) -> Option<u64> {
+        let depth = self.siblings.len();
+        if !(1..=MAX_SPARSE_DEPTH).contains(&depth) {
+            return None;
+        }
+        let mut index = key_path(key, depth);
+        let mut node = leaf;
+        for &sibling in &self.siblings {
+            node = if index.is_multiple_of(2) {
+                hash_pair(node, sibling)
+            } else {
+                hash_pair(sibling, node)
+            };
+            index >>= 1;
+        }
+        Some(node)
+    }
+
+    /// Returns whether the proof shows that the key's slot holds the value under the
+    /// given root.
+    /// * `key` - The `Hash` key giving the slot.
+    /// * `value` - The `Hash` value expected at the slot.
+    /// * `root` - The trusted root of the tree.
+    pub fn verify_inclusion<K: Hash, V: Hash>(&self, key: K, value: V, root: u64) -> bool {
+        self.compute_root(&key, hash_single(value)) == Some(root)
+    }
+
+    /// Returns whether the proof shows that the key's slot is empty under the given root.
+    /// * `key` - The `Hash` key giving the slot.
+    /// * `root` - The trusted root of the tree.
+    pub fn verify_empty<K: Hash>(&self, key: K, root: u64) -> bool {
+        self.compute_root(&key, MerkleTree::PAD_HASH) == Some(root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_root_is_default_hash() {
+        let tree = SparseMerkleTree::new(32);
+        assert!(tree.is_empty());
+        assert_eq!(tree.root(), tree.default_hash(32));
+
+        // A padding-only dense tree of 2^4 slots shares the depth-4 default hash.
+        let dense = MerkleTree::from_leaf_hashes(vec![MerkleTree::PAD_HASH; 16]);
+        assert_eq!(
+            dense.level(4).unwrap()[0],
+            SparseMerkleTree::new(4).default_hash(4)
+        );
+    }
+
+    #[test]
+    fn inclusion_and_emptiness_proofs() {
+        let mut tree = SparseMerkleTree::new(32);
+        let entries = [("alice", 10), ("bob", 20), ("carol", 30), ("dave", 40)];
+        for (key, value) in entries {
+            tree.insert(key, value);
+        }
+        assert_eq!(tree.len(), 4);
+        let root = tree.root();
+        assert_ne!(root, tree.default_hash(32));
+
+        for (key, value) in entries {
+            assert_eq!(tree.get(key), Some(hash_single(value)));
+            let proof = tree.prove(key);
+            assert_eq!(proof.siblings.len(), 32);
+            assert!(proof.verify_inclusion(key, value, root));
+            assert!(!proof.verify_inclusion(key, value + 1, root));
+            assert!(!proof.verify_empty(key, root));
+        }
+
+        assert_eq!(tree.get("eve"), None);
+        let proof = tree.prove("eve");
+        assert!(proof.verify_empty("eve", root));
+        assert!(!proof.verify_inclusion("eve", 50, root));
+        assert!(!proof.verify_empty("eve", tree.default_hash(32)));
+    }
+
+    #[test]
+    fn overwrites_replace_values() {
+        let mut tree = SparseMerkleTree::new(32);
+        tree.insert("alice", 1);
+        let stale = tree.prove("alice");
+        tree.insert("alice", 2);
+        assert_eq!(tree.len(), 1);
+
+        let root = tree.root();
+        assert!(!stale.verify_inclusion("alice", 1, root));
+        assert!(tree.prove("alice").verify_inclusion("alice", 2, root));
+
+        // Insertion order does not matter.
+        let mut other = SparseMerkleTree::new(32);
+        other.insert("bob", 3);
+        other.insert("alice", 2);
+        tree.insert("bob", 3);
+        assert_eq!(other.root(), tree.root());
+    }
+
+    #[test]
+    fn only_non_default_nodes_stored() {
+        let mut tree = SparseMerkleTree::new(64);
+        tree.insert(7_u64, 1);
+        // A leaf and an ancestor per level.
+        assert_eq!(tree.nodes.len(), 65);
+        assert!(tree.prove(7_u64).verify_inclusion(7_u64, 1, tree.root()));
+    }
+
+    #[test]
+    #[should_panic(expected = "depth 65")]
+    fn excessive_depth_rejected() {
+        SparseMerkleTree::new(65);
+    }
+}
@@ -0,0 +1,258 @@
+use std::hash::Hash;
+
+use crate::{
+    MerkleError, MerkleTree, child_indices, combine, hash_pair, hash_single, path_indices,
+    path_root, sibling_index,
+};
+
+/// Tree of a capacity fixed at compile time, storing its nodes inline so that it never
+/// allocates: it can live on the stack or in a `static`, and proofs are written into
+/// arrays provided by the caller.
+/// It hashes and orders nodes exactly like a `MerkleTree` of capacity `N`, so roots and
+/// proofs match the ones of the dynamic tree holding the same elements. Unlike it, the
+/// tree does not grow: pushes past its capacity fail.
+/// `N` must be a power of two, which is checked when the tree is constructed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StaticMerkleTree<const N: usize> {
+    /// Leaf hashes, padded leaves holding `PAD_HASH`.
+    leaves: [u64; N],
+    /// Nodes above the leaves, laid out as a binary heap: the root is at index 1 and the
+    /// children of the node at `i` are at `2 * i` and `2 * i + 1`, the ones from `N` on
+    /// being leaves. Index 0 is unused.
+    branches: [u64; N],
+    /// Amount of pushed elements.
+    len: usize,
+}
+
+impl<const N: usize> StaticMerkleTree<N> {
+    /// Amount of sibling nodes in a proof, one per level below the root.
+    pub const DEPTH: usize = N.trailing_zeros() as usize;
+
+    /// Creates an empty tree, every leaf being padding.
+    /// Fails to compile if `N` is not a power of two.
+    pub fn new() -> StaticMerkleTree<N> {
+        const { assert!(N.is_power_of_two(), "The capacity must be a power of two") };
+
+        let mut branches = [0; N];
+        // The nodes of the level at `level_n` start at `N >> level_n`.
+        let mut empty = MerkleTree::PAD_HASH;
+        for level_n in 1..=Self::DEPTH {
+            empty = combine(empty, empty);
+            branches[N >> level_n..N >> (level_n - 1)].fill(empty);
+        }
+
+        StaticMerkleTree {
+            leaves: [MerkleTree::PAD_HASH; N],
+            branches,
+            len: 0,
+        }
+    }
+
+    /// Returns the amount of pushed elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether no element was pushed.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the amount of elements the tree can hold.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the root, or `None` if the tree is empty.
+    pub fn root(&self) -> Option<u64> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.node(1))
+        }
+    }
+
+    /// Returns the leaf hash at an index, or `None` if it does not hold an element.
+    /// * `index` - Index of the leaf.
+    pub fn leaf(&self, index: usize) -> Option<u64> {
+        self.leaves[..self.len].get(index).copied()
+    }
+
+    /// Pushes an `Hash` element, rehashing its path up to the root. Returns its index.
+    /// Fails if the tree is full.
+    /// * `value` - The `Hash` value to be added to the tree.
+    pub fn push<H: Hash>(&mut self, value: H) -> Result<usize, MerkleError> {
+        let index = self.len;
+        if index == N {
+            return Err(MerkleError::Full { capacity: N });
+        }
+
+        self.leaves[index] = hash_single(value);
+        self.len += 1;
+        for (level_n, parent) in path_indices(index)
+            .enumerate()
+            .take(Self::DEPTH + 1)
+            .skip(1)
+        {
+            let [left, right] = child_indices(parent);
+            let node = hash_pair(
+                self.node_at(level_n - 1, left),
+                self.node_at(level_n - 1, right),
+            );
+            self.branches[(N >> level_n) + parent] = node;
+        }
+        Ok(index)
+    }
+
+    /// Writes the sibling nodes of an element's path, from the leaves' level upwards,
+    /// into a caller-provided array: together with the root, they make the element's
+    /// proof. `D` must be the tree's `DEPTH`, which is checked at compile time.
+    /// Fails if the index does not hold an element.
+    /// * `index` - index value to generate the proof for.
+    /// * `siblings` - The array the sibling nodes are written into.
+    pub fn get_proof<const D: usize>(
+        &self,
+        index: usize,
+        siblings: &mut [u64; D],
+    ) -> Result<(), MerkleError> {
+        const { assert!(D == Self::DEPTH, "A proof holds a sibling per level") };
+
+        if index >= self.len {
+            return Err(MerkleError::IndexOutOfRange {
+                index,
+                len: self.len,
+            });
+        }
+        for (level_n, (sibling, ancestor)) in
+            siblings.iter_mut().zip(path_indices(index)).enumerate()
+        {
+            *sibling = self.node_at(level_n, sibling_index(ancestor));
+        }
+        Ok(())
+    }
+
+    /// Returns whether a given `Hash` value at an index verifies against a root, given
+    /// the sibling nodes written by `get_proof`. Does not allocate either.
+    /// * `root` - The trusted root.
+    /// * `index` - The index the value is claimed to be at.
+    /// * `value` - The `Hash` value to be verified.
+    /// * `siblings` - The sibling nodes of the value's path.
+    pub fn verify<H: Hash, const D: usize>(
+        root: u64,
+        index: usize,
+        value: H,
+        siblings: &[u64; D],
+    ) -> bool {
+        const { assert!(D == Self::DEPTH, "A proof holds a sibling per level") };
+
+        index < N && path_root(index, hash_single(value), siblings.iter().copied()) == root
+    }
+
+    /// Returns the node at a level and index within it.
+    /// * `level` - Level of the node, `0` being the leaves'.
+    /// * `index` - Index of the node within its level.
+    fn node_at(&self, level: usize, index: usize) -> u64 {
+        self.node((N >> level) + index)
+    }
+
+    /// Returns the node at a position of the heap layout, leaves included.
+    /// * `position` - Position of the node, from `1` (the root) to `2 * N - 1`.
+    fn node(&self, position: usize) -> u64 {
+        match position.checked_sub(N) {
+            Some(leaf) => self.leaves[leaf],
+            None => self.branches[position],
+        }
+    }
+}
+
+impl<const N: usize> Default for StaticMerkleTree<N> {
+    fn default() -> StaticMerkleTree<N> {
+        StaticMerkleTree::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{MerkleProof, generate_tree_levels};
+
+    /// Builds the dynamic tree of capacity `capacity` holding the given values.
+    fn dynamic_tree(values: &[u32], capacity: usize) -> MerkleTree {
+        let mut leaves: Vec<u64> = values.iter().map(hash_single).collect();
+        leaves.resize(capacity, MerkleTree::PAD_HASH);
+        let mut levels = Vec::new();
+        generate_tree_levels(&leaves, &mut levels);
+        MerkleTree::from_parts(levels, capacity, capacity - values.len())
+    }
+
+    /// Pushes the first `N` values one by one into both trees, comparing their roots and
+    /// the proofs of every element after each push.
+    fn check_against_dynamic<const N: usize, const D: usize>(
+        values: &[u32],
+    ) -> Result<(), TestCaseError> {
+        let values = &values[..values.len().min(N)];
+        let mut tree = StaticMerkleTree::<N>::new();
+        prop_assert_eq!(tree.root(), None);
+        for (len, &value) in values.iter().enumerate() {
+            prop_assert_eq!(tree.push(value), Ok(len));
+            let expected = dynamic_tree(&values[..=len], N);
+            prop_assert_eq!(tree.root(), expected.root());
+
+            let mut siblings = [0; D];
+            for (index, &value) in values[..=len].iter().enumerate() {
+                tree.get_proof(index, &mut siblings).unwrap();
+                let MerkleProof::Proof { nodes, .. } = expected.get_proof(index) else {
+                    return Err(TestCaseError::fail("pushed elements are provable"));
+                };
+                prop_assert_eq!(&siblings[..], &nodes[..]);
+                let root = tree.root().unwrap();
+                prop_assert!(StaticMerkleTree::<N>::verify(root, index, value, &siblings));
+                prop_assert!(!StaticMerkleTree::<N>::verify(
+                    root, index, !value, &siblings
+                ));
+            }
+            prop_assert_eq!(
+                tree.get_proof(len + 1, &mut siblings),
+                Err(MerkleError::IndexOutOfRange {
+                    index: len + 1,
+                    len: len + 1
+                })
+            );
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn matches_dynamic_tree(values in vec(any::<u32>(), 0..=70)) {
+            check_against_dynamic::<1, 0>(&values)?;
+            check_against_dynamic::<2, 1>(&values)?;
+            check_against_dynamic::<8, 3>(&values)?;
+            check_against_dynamic::<64, 6>(&values)?;
+        }
+    }
+
+    #[test]
+    fn full_tree_rejects_pushes() {
+        let mut tree = StaticMerkleTree::<4>::default();
+        for value in 0..4 {
+            tree.push(value).unwrap();
+        }
+        let full = tree.clone();
+        assert_eq!(tree.push(4), Err(MerkleError::Full { capacity: 4 }));
+        assert_eq!(tree, full);
+        assert_eq!(tree.len(), tree.capacity());
+        assert_eq!(tree.leaf(3), Some(hash_single(3)));
+        assert_eq!(tree.leaf(4), None);
+    }
+
+    #[test]
+    fn nodes_are_stored_inline() {
+        assert_eq!(size_of::<StaticMerkleTree<64>>(), (2 * 64 + 1) * 8);
+        assert_eq!(StaticMerkleTree::<64>::DEPTH, 6);
+        assert_eq!(StaticMerkleTree::<1>::DEPTH, 0);
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::MerkleTree;
+
+/// Structural and memory usage figures of a tree, as returned by `MerkleTree::stats`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TreeStats {
+    pub len: usize,
+    pub capacity: usize,
+    pub padding: usize,
+    pub height: usize,
+    /// Amount of nodes across every level, padding included. Nodes rooting subtrees of
+    /// padding only are counted even though they are not stored.
+    pub node_count: usize,
+    /// Heap memory allocated by the tree's node storage and occupancy bitmap, computed
+    /// from the allocated capacity of the underlying vectors rather than their length.
+    /// Nodes rooting subtrees of padding only are not stored, so they take no memory.
+    pub heap_bytes: usize,
+    /// Amount of times the tree's capacity was doubled since it was built.
+    pub growth_events: u64,
+}
+
+impl Display for TreeStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "len={} capacity={} padding={} height={} nodes={} heap_bytes={} growth_events={}",
+            self.len,
+            self.capacity,
+            self.padding,
+            self.height,
+            self.node_count,
+            self.heap_bytes,
+            self.growth_events
+        )
+    }
+}
+
+impl MerkleTree {
+    /// Returns figures describing the tree's structure and memory usage.
+    pub fn stats(&self) -> TreeStats {
+        TreeStats {
+            len: self.len(),
+            capacity: self.capacity(),
+            padding: self.padding,
+            height: self.height(),
+            node_count: (0..self.height())
+                .map(|level| self.levels.width(level))
+                .sum(),
+            heap_bytes: self.levels.heap_bytes() + self.occupancy.heap_bytes(),
+            growth_events: self.growth_events,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::MerkleTree;
+
+    #[test]
+    fn node_count_is_sum_of_levels() {
+        let tree = MerkleTree::build(&[1; 13]);
+        let stats = tree.stats();
+        let level_sum: usize = tree.levels_iter().map(|level| level.len()).sum();
+
+        assert_eq!(stats.node_count, level_sum);
+        assert_eq!(stats.node_count, 31);
+        assert_eq!(
+            (stats.len, stats.capacity, stats.padding, stats.height),
+            (13, 16, 3, 5)
+        );
+        // The 3 padded leaves, and the 1 parent of two of them, are not stored.
+        assert!(stats.heap_bytes >= (stats.node_count - 4) * 8);
+    }
+
+    #[test]
+    fn growth_events_across_pushes() {
+        let mut tree = MerkleTree::build::<u8>(&[]);
+        let mut expected = 0;
+
+        for len in 1..=33_u8 {
+            let was_full = tree.is_full();
+            tree.push(len);
+            if was_full {
+                expected += 1;
+            }
+            assert_eq!(tree.stats().growth_events, expected);
+        }
+
+        // Capacity went 1, 2, 4, 8, 16, 32, 64.
+        assert_eq!(expected, 6);
+    }
+
+    #[test]
+    fn stats_display() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let output = tree.stats().to_string();
+        assert!(output.starts_with("len=3 capacity=4 padding=1 height=3 nodes=7 heap_bytes="));
+        assert!(output.ends_with("growth_events=0"));
+    }
+}
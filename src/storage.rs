@@ -0,0 +1,182 @@
+use crate::{MerkleError, MerkleTree};
+
+impl MerkleTree {
+    /// Stops storing the levels between the leaves and the top `cached_levels` ones, the
+    /// root's included, for write-heavy workloads which rarely ask for proofs. The tree
+    /// then takes about the memory of its leaves, and `push` appends the leaf hash and
+    /// only rewrites the path through the cached levels.
+    /// The nodes which are not stored are hashed out of the leaves when needed: every
+    /// push rehashes the lowest cached node over the `2^(height - cached_levels)` leaves
+    /// below it, and `root` and `get_proof` hash the subtrees below the cached levels.
+    /// With no cached level, computing the root takes a hash per leaf. Operations reading
+    /// whole levels, such as `diff` or serialization, compute every level once and keep
+    /// them until the next push.
+    /// Pruned leaves (see `from_frontier`) cannot be hashed again, so trees holding some
+    /// keep every node.
+    /// * `cached_levels` - Amount of top levels to be stored.
+    pub fn keep_leaves_only(&mut self, cached_levels: usize) -> Result<(), MerkleError> {
+        if self.pruned > 0 {
+            return Err(MerkleError::Pruned {
+                index: 0,
+                pruned: self.pruned,
+            });
+        }
+        self.levels.set_cached(Some(cached_levels));
+        Ok(())
+    }
+
+    /// Goes back to storing every node, hashing the ones which are not stored out of the
+    /// leaves.
+    pub fn keep_all_nodes(&mut self) {
+        if self.levels.cached().is_some() {
+            self.levels.set_cached(None);
+        }
+    }
+
+    /// Returns the amount of top levels stored by a tree keeping only its leaves (see
+    /// `keep_leaves_only`), or `None` if it stores every node.
+    pub fn cached_levels(&self) -> Option<usize> {
+        self.levels.cached()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{MerkleError, MerkleProof, MerkleTree};
+
+    /// Builds a tree out of the values storing every node, or only its leaves and the
+    /// given amount of top levels.
+    fn build_in_mode(values: &[u32], cached_levels: Option<usize>) -> MerkleTree {
+        let mut tree = MerkleTree::build(values);
+        if let Some(cached_levels) = cached_levels {
+            tree.keep_leaves_only(cached_levels).unwrap();
+        }
+        tree
+    }
+
+    #[test]
+    fn every_mode_behaves_alike() {
+        for len in [0, 1, 2, 3, 5, 8, 13] {
+            let values: Vec<u32> = (0..len).collect();
+            let full = MerkleTree::build(&values);
+            for cached_levels in [None, Some(0), Some(2)] {
+                let mut tree = build_in_mode(&values, cached_levels);
+                assert_eq!(tree.cached_levels(), cached_levels);
+                assert_eq!(tree.height(), full.height());
+                assert_eq!(tree.root(), full.root());
+                assert_eq!(tree.leaves(), full.leaves());
+                for level in 0..full.height() {
+                    assert_eq!(tree.level(level).as_deref(), full.level(level).as_deref());
+                }
+                for (index, value) in values.iter().enumerate() {
+                    let proof = tree.get_proof(index);
+                    assert_eq!(proof, full.get_proof(index));
+                    assert!(proof.verify(value));
+                }
+                assert_eq!(tree.get_proof(len as usize), MerkleProof::Invalid);
+
+                for value in len..len + 10 {
+                    tree.push(value);
+                    let index = value as usize;
+                    assert!(tree.get_proof(index).verify(value));
+                }
+                let values: Vec<u32> = (0..len + 10).collect();
+                assert_eq!(tree.root(), MerkleTree::build(&values).root());
+                assert_eq!(tree.epoch(), 10);
+            }
+        }
+    }
+
+    #[test]
+    fn only_leaves_resident() {
+        let values: Vec<u32> = (0..100_000).collect();
+        let full = MerkleTree::build(&values);
+        let mut tree = MerkleTree::build(&values);
+        tree.keep_leaves_only(0).unwrap();
+        assert_eq!(tree.cached_levels(), Some(0));
+        assert_eq!(tree.root(), full.root());
+        assert_eq!(tree.get_proof(12_345), full.get_proof(12_345));
+
+        // Reading the root and proofs hashes the missing nodes without storing them.
+        let leaves = 100_000 * 8;
+        let stats = tree.stats();
+        assert_eq!(stats.node_count, full.stats().node_count);
+        assert!(stats.heap_bytes < leaves + 20 * 1024, "{stats}");
+        assert!(full.stats().heap_bytes > 2 * leaves);
+
+        for value in 100_000..150_000 {
+            tree.push(value);
+        }
+        let stats = tree.stats();
+        assert!(stats.heap_bytes < 2 * 150_000 * 8 + 20 * 1024, "{stats}");
+        let values: Vec<u32> = (0..150_000).collect();
+        assert_eq!(tree.root(), MerkleTree::build(&values).root());
+    }
+
+    #[test]
+    fn modes_switch_without_changes() {
+        let values: Vec<u32> = (0..1000).collect();
+        let expected = MerkleTree::build(&values);
+        let mut tree = MerkleTree::build(&values[..300]);
+        for cached_levels in [0, 1, 3, 10, 40] {
+            tree.keep_leaves_only(cached_levels).unwrap();
+            assert_eq!(tree.root(), MerkleTree::build(&values[..tree.len()]).root());
+            tree.extend(&values[tree.len()..tree.len() + 100]);
+        }
+        tree.keep_all_nodes();
+        assert_eq!(tree.cached_levels(), None);
+        tree.extend(&values[tree.len()..]);
+        assert_eq!(tree.root(), expected.root());
+        assert_eq!(tree.validate(), Ok(()));
+
+        // Borrowing whole levels materializes them, which the next push forgets.
+        tree.keep_leaves_only(2).unwrap();
+        let stored = tree.stats().heap_bytes;
+        assert_eq!(tree.level(3).as_deref(), expected.level(3).as_deref());
+        let materialized = tree.stats().heap_bytes;
+        assert!(materialized > stored + 1000 * 8);
+        tree.push(1000);
+        assert!(tree.stats().heap_bytes < materialized - 1000 * 8);
+    }
+
+    #[test]
+    fn pruned_trees_keep_every_node() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        let mut pruned = MerkleTree::from_frontier(tree.export_frontier()).unwrap();
+        assert_eq!(
+            pruned.keep_leaves_only(1),
+            Err(MerkleError::Pruned {
+                index: 0,
+                pruned: 3
+            })
+        );
+        assert_eq!(pruned.cached_levels(), None);
+
+        let mut synced = MerkleTree::build(&[4, 5]);
+        synced.keep_leaves_only(0).unwrap();
+        synced.sync_from(&pruned);
+        assert_eq!(synced.cached_levels(), None);
+        assert_eq!(synced.root(), tree.root());
+    }
+
+    #[cfg(feature = "instrumentation")]
+    #[test]
+    fn pushes_only_rehash_cached_levels() {
+        let mut tree = MerkleTree::build(&[0_u32; 1024]);
+        tree.keep_leaves_only(0).unwrap();
+        crate::reset_counters();
+        tree.push(1);
+        // Doubling the capacity hashes the old root out of the leaves, then the new one.
+        assert_eq!(crate::hash_ops().pair, 1023 + 1);
+        crate::reset_counters();
+        tree.push(2);
+        assert_eq!(crate::hash_ops().pair, 0);
+
+        // With the top 3 levels cached, the lowest of them is hashed out of 256 leaves.
+        let mut tree = MerkleTree::build(&[0_u32; 1024]);
+        tree.keep_leaves_only(3).unwrap();
+        crate::reset_counters();
+        tree.replace_leaf_hash(5, 7);
+        assert_eq!(crate::hash_ops().pair, 255 + 2);
+    }
+}
@@ -0,0 +1,498 @@
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use crate::disk::open_level;
+use crate::{MerkleError, MerkleTree, Reporter, empty_node, generate_reported_levels, hash_single};
+
+/// Backend holding the nodes of a `MerkleTree`, addressed by level and index as in
+/// `MerkleTree::node`: level 0 holds the leaves, padding included, and the last level
+/// holds the root.
+/// Every node a store has not been written holds the empty node of its level, the hash
+/// of a subtree filled with padding only, so stores only need to keep what was written.
+/// Trees only write the nodes covering their elements, and read nodes through `get`, so
+/// a store fails a read by returning `None`, which trees report as a corrupted node.
+pub trait NodeStore {
+    /// Returns the amount of levels.
+    fn height(&self) -> usize;
+
+    /// Returns the amount of nodes of a level, written or not, or `None` if the level is
+    /// out of range.
+    /// * `level` - The level to be measured.
+    fn len(&self, level: usize) -> Option<usize>;
+
+    /// Returns the node at the given coordinates, or `None` if they are out of range or
+    /// it cannot be read.
+    /// * `level` - Level of the node.
+    /// * `index` - Index of the node within its level.
+    fn get(&self, level: usize, index: usize) -> Option<u64>;
+
+    /// Writes the node at the given coordinates.
+    /// Panics if the coordinates are out of range.
+    /// * `level` - Level of the node.
+    /// * `index` - Index of the node within its level.
+    /// * `node` - The new node.
+    fn put(&mut self, level: usize, index: usize, node: u64);
+
+    /// Writes consecutive nodes of a level. Trees write whole levels through it when
+    /// built, so stores should override it whenever writing nodes one by one is slow.
+    /// Panics if the nodes do not fit in the level.
+    /// * `level` - Level of the nodes.
+    /// * `first` - Index of the first node within its level.
+    /// * `nodes` - The nodes to be written.
+    fn put_batch(&mut self, level: usize, first: usize, nodes: &[u64]) {
+        for (offset, &node) in nodes.iter().enumerate() {
+            self.put(level, first + offset, node);
+        }
+    }
+
+    /// Drops every node, laying out the levels of a tree of the given capacity with
+    /// every node empty.
+    /// * `capacity` - The capacity of the tree, a power of two.
+    fn reset(&mut self, capacity: usize);
+
+    /// Doubles the amount of nodes of every level and adds a level on top, holding a
+    /// new root. The new nodes of the other levels are empty.
+    /// * `root` - The new root.
+    fn grow(&mut self, root: u64);
+
+    /// Writes every pending change to the backend, reporting any write which failed
+    /// since the last flush. Does nothing by default.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Returns the lowest level above the leaves whose nodes must be written when a leaf
+    /// changes. Stores hashing the levels below it out of the leaves when read, like
+    /// `MemStore` in leaves-only mode, return a higher one. Defaults to 1.
+    fn lowest_written(&self) -> usize {
+        1
+    }
+
+    /// Releases whatever is only kept to speed up reads. Trees call it before pushing,
+    /// the write-heavy path. Does nothing by default.
+    fn release_reads(&mut self) {}
+}
+
+/// Level files of a `FileStore`, and the first write which failed since the last flush.
+struct Files {
+    /// The file of each level, or `None` if it could not be opened.
+    files: Vec<Option<File>>,
+    error: Option<io::Error>,
+}
+
+impl Files {
+    /// Records a failure, keeping the first one until the next flush.
+    fn fail(&mut self, error: io::Error) {
+        self.error.get_or_insert(error);
+    }
+}
+
+/// `NodeStore` keeping each level in a file inside a directory, with the layout of
+/// `DiskMerkleTree`: level `n` is held by `level-n`, its nodes stored in index order as
+/// little-endian `u64`s. Files only hold the nodes up to the last one written, and the
+/// nodes beyond them are empty.
+/// Every read and write goes to the files. Failed writes are reported by the next
+/// `flush`, which also syncs the files, and failed reads as corrupted nodes.
+pub struct FileStore {
+    dir: PathBuf,
+    files: Mutex<Files>,
+    /// Amount of nodes of each level, written or not.
+    widths: Vec<usize>,
+    /// Amount of nodes held by the file of each level.
+    stored: Vec<usize>,
+}
+
+impl FileStore {
+    /// Creates an empty store in the given directory, which is created if needed.
+    /// Level files of a previous store or disk tree are overwritten once a tree is built
+    /// in it.
+    /// * `dir` - The directory holding the level files.
+    pub fn create(dir: impl AsRef<Path>) -> io::Result<FileStore> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        Ok(FileStore {
+            dir: dir.to_path_buf(),
+            files: Mutex::new(Files {
+                files: Vec::new(),
+                error: None,
+            }),
+            widths: Vec::new(),
+            stored: Vec::new(),
+        })
+    }
+
+    /// Returns the directory holding the level files.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the level files.
+    fn files(&mut self) -> &mut Files {
+        self.files.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Adds a level with the given amount of nodes, truncating its file.
+    /// * `width` - The amount of nodes of the level.
+    fn add_level(&mut self, width: usize) {
+        let level = self.widths.len();
+        let opened = open_level(&self.dir, level).and_then(|file| file.set_len(0).map(|()| file));
+        let files = self.files();
+        match opened {
+            Ok(file) => files.files.push(Some(file)),
+            Err(error) => {
+                files.fail(error);
+                files.files.push(None);
+            }
+        }
+        self.widths.push(width);
+        self.stored.push(0);
+    }
+
+    /// Writes consecutive nodes to the file of a level, recording the failure if any.
+    /// * `level` - Level of the nodes.
+    /// * `first` - Index of the first node within its level.
+    /// * `nodes` - The nodes to be written.
+    fn write(&mut self, level: usize, first: usize, nodes: &[u64]) {
+        let bytes: Vec<u8> = nodes.iter().flat_map(|node| node.to_le_bytes()).collect();
+        let files = self.files();
+        let written = match &files.files[level] {
+            Some(file) => {
+                let mut file = file;
+                file.seek(SeekFrom::Start(8 * first as u64))
+                    .and_then(|_| file.write_all(&bytes))
+            }
+            None => Err(io::Error::other(format!("level {level} is not open"))),
+        };
+        if let Err(error) = written {
+            files.fail(error);
+        }
+    }
+}
+
+impl NodeStore for FileStore {
+    fn height(&self) -> usize {
+        self.widths.len()
+    }
+
+    fn len(&self, level: usize) -> Option<usize> {
+        self.widths.get(level).copied()
+    }
+
+    fn get(&self, level: usize, index: usize) -> Option<u64> {
+        if index >= *self.widths.get(level)? {
+            return None;
+        }
+        if index >= self.stored[level] {
+            return Some(empty_node(level));
+        }
+
+        let files = self.files.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut file = files.files[level].as_ref()?;
+        let mut bytes = [0; 8];
+        file.seek(SeekFrom::Start(8 * index as u64))
+            .and_then(|_| file.read_exact(&mut bytes))
+            .ok()?;
+        Some(u64::from_le_bytes(bytes))
+    }
+
+    #[track_caller]
+    fn put(&mut self, level: usize, index: usize, node: u64) {
+        self.put_batch(level, index, &[node]);
+    }
+
+    #[track_caller]
+    fn put_batch(&mut self, level: usize, first: usize, nodes: &[u64]) {
+        let width = self.widths[level];
+        let end = first + nodes.len();
+        assert!(
+            end <= width,
+            "nodes up to {end} are out of range for a level of {width} nodes"
+        );
+        let stored = self.stored[level];
+        if first > stored {
+            self.write(level, stored, &vec![empty_node(level); first - stored]);
+        }
+        self.write(level, first, nodes);
+        self.stored[level] = stored.max(end);
+    }
+
+    fn reset(&mut self, capacity: usize) {
+        self.files().files.clear();
+        self.widths.clear();
+        self.stored.clear();
+        for level_n in 0..=capacity.trailing_zeros() as usize {
+            self.add_level(capacity >> level_n);
+        }
+    }
+
+    fn grow(&mut self, root: u64) {
+        for width in &mut self.widths {
+            *width *= 2;
+        }
+        let top = self.widths.len();
+        self.add_level(1);
+        self.put(top, 0, root);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let files = self.files();
+        if let Some(error) = files.error.take() {
+            return Err(error);
+        }
+        files.files.iter().flatten().try_for_each(File::sync_data)
+    }
+}
+
+impl<S: NodeStore> MerkleTree<S> {
+    /// Constructs a `MerkleTree` like `build`, holding its nodes in the given store
+    /// instead of memory. The store is reset first, and each level is then written in a
+    /// single batch. Trees in stores other than `MemStore` support the core operations:
+    /// reading nodes, proofs, pushes and root observers.
+    /// * `store` - The store holding the tree's nodes.
+    /// * `elements` - array of `Hash` elements used to populate the tree.
+    pub fn build_in<H: Hash>(store: S, elements: &[H]) -> MerkleTree<S> {
+        MerkleTree::from_leaf_hashes_in(store, elements.iter().map(hash_single).collect())
+    }
+
+    /// Constructs a `MerkleTree` out of already hashed leaves, like `from_leaf_hashes`,
+    /// holding its nodes in the given store.
+    /// * `store` - The store holding the tree's nodes.
+    /// * `leaves` - The leaf hashes used to populate the tree.
+    pub(crate) fn from_leaf_hashes_in(store: S, leaves: Vec<u64>) -> MerkleTree<S> {
+        MerkleTree::from_reported_leaf_hashes(store, leaves, &mut Reporter::silent())
+            .expect("Silent reporters never cancel")
+    }
+
+    /// Constructs a `MerkleTree` out of already hashed leaves, like `from_leaf_hashes_in`,
+    /// reporting every node combined and the final progress once every level is.
+    /// Fails with `MerkleError::Cancelled`, before anything is written to the store, if
+    /// the reporter's callback cancels the build.
+    /// * `store` - The store holding the tree's nodes.
+    /// * `leaves` - The leaf hashes used to populate the tree.
+    /// * `reporter` - Where the progress is reported.
+    pub(crate) fn from_reported_leaf_hashes(
+        mut store: S,
+        leaves: Vec<u64>,
+        reporter: &mut Reporter,
+    ) -> Result<MerkleTree<S>, MerkleError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("build", leaves = leaves.len()).entered();
+        let capacity = leaves.len().next_power_of_two();
+        let padding = capacity - leaves.len();
+
+        // Only the nodes covering the leaves are written, the others being empty.
+        let mut levels = Vec::new();
+        generate_reported_levels(&leaves, &mut levels, reporter)?;
+        reporter.report()?;
+        store.reset(capacity);
+        // Top-down, so that stores laying the levels out one after the other, like
+        // `MemStore`, only move the small ones when the large ones are written.
+        for (level_n, level) in levels.iter().enumerate().rev() {
+            store.put_batch(level_n, 0, level);
+        }
+        Ok(MerkleTree::from_store(store, capacity, padding))
+    }
+
+    /// Returns the store holding the tree's nodes.
+    pub fn store(&self) -> &S {
+        &self.levels
+    }
+
+    /// Consumes the tree, returning the store holding its nodes.
+    pub fn into_store(self) -> S {
+        self.levels
+    }
+
+    /// Writes every pending change of the store to its backend, failing if any write
+    /// failed since the last flush. See `NodeStore::flush`.
+    pub fn flush(&mut self) -> io::Result<()> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("flush").entered();
+        let result = self.levels.flush();
+        #[cfg(feature = "tracing")]
+        if let Err(error) = &result {
+            tracing::warn!(%error, "flush failed");
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{MemStore, MerkleError, MerkleProof};
+
+    /// Behavioral tests run against every store, each given a fresh one. Trees held in
+    /// memory are the reference.
+    mod suite {
+        use super::*;
+
+        /// Checks that both trees have the same shape, nodes and proofs.
+        fn assert_same<S: NodeStore>(tree: &MerkleTree<S>, expected: &MerkleTree) {
+            assert_eq!(tree.len(), expected.len());
+            assert_eq!(tree.capacity(), expected.capacity());
+            assert_eq!(tree.height(), expected.height());
+            assert_eq!(tree.root(), expected.root());
+            for level in 0..=tree.height() {
+                assert_eq!(tree.level_len(level), expected.level_len(level));
+                for index in 0..=expected.level_len(level).unwrap_or(0) {
+                    assert_eq!(tree.node(level, index), expected.node(level, index));
+                }
+            }
+            for index in 0..=tree.len() {
+                assert!(tree.get_proof(index) == expected.get_proof(index));
+            }
+        }
+
+        pub(super) fn builds_match_memory<S: NodeStore>(mut store: S) {
+            for len in 0..=33 {
+                let elements: Vec<u32> = (0..len).collect();
+                let tree = MerkleTree::build_in(store, &elements);
+                assert_same(&tree, &MerkleTree::build(&elements));
+                store = tree.into_store();
+            }
+        }
+
+        pub(super) fn pushes_match_memory<S: NodeStore>(store: S) {
+            let mut tree = MerkleTree::build_in(store, &[1, 2, 3]);
+            let mut expected = MerkleTree::build(&[1, 2, 3]);
+            for value in 4..70 {
+                tree.push(value);
+                expected.push(value);
+                assert_same(&tree, &expected);
+            }
+            assert_eq!(tree.epoch(), expected.epoch());
+            assert_eq!(tree.head(), expected.head());
+
+            let mut tree = MerkleTree::build_in(tree.into_store(), &[0_u32; 0]);
+            assert_eq!(tree.root(), None);
+            assert!(tree.get_proof(0) == MerkleProof::Invalid);
+            let mut expected = MerkleTree::build(&[0_u32; 0]);
+            for value in 0..9 {
+                tree.try_push(value).unwrap();
+                expected.push(value);
+            }
+            assert_same(&tree, &expected);
+            tree.flush().unwrap();
+        }
+
+        pub(super) fn errors_match_memory<S: NodeStore>(store: S) {
+            let tree = MerkleTree::build_in(store, &[1, 2, 3]);
+            let expected = MerkleTree::build(&[1, 2, 3]);
+            for index in [3, 4, usize::MAX] {
+                assert_eq!(
+                    tree.try_get_proof(index).err(),
+                    Some(MerkleError::IndexOutOfRange { index, len: 3 })
+                );
+                assert_eq!(tree.try_get_proof(index), expected.try_get_proof(index));
+            }
+        }
+
+        pub(super) fn root_changes_observed<S: NodeStore>(store: S) {
+            let mut tree = MerkleTree::build_in(store, &[1, 2, 3]);
+            let calls = Arc::new(Mutex::new(Vec::new()));
+            let recorder = Arc::clone(&calls);
+            tree.on_root_change(move |old, new| recorder.lock().unwrap().push((old, new)));
+
+            let mut expected = Vec::new();
+            for value in 4..10 {
+                let old = tree.root().unwrap();
+                tree.push(value);
+                expected.push((old, tree.root().unwrap()));
+            }
+            assert_eq!(*calls.lock().unwrap(), expected);
+        }
+    }
+
+    /// Runs every test of the suite against the store returned by an expression, along
+    /// with whatever must outlive it.
+    macro_rules! store_suite {
+        ($name:ident, $store:expr) => {
+            mod $name {
+                use super::*;
+
+                #[test]
+                fn builds_match_memory() {
+                    let (store, _guard) = $store;
+                    suite::builds_match_memory(store);
+                }
+
+                #[test]
+                fn pushes_match_memory() {
+                    let (store, _guard) = $store;
+                    suite::pushes_match_memory(store);
+                }
+
+                #[test]
+                fn errors_match_memory() {
+                    let (store, _guard) = $store;
+                    suite::errors_match_memory(store);
+                }
+
+                #[test]
+                fn root_changes_observed() {
+                    let (store, _guard) = $store;
+                    suite::root_changes_observed(store);
+                }
+            }
+        };
+    }
+
+    store_suite!(mem_store, (MemStore::default(), ()));
+    store_suite!(file_store, {
+        let dir = tempfile::tempdir().unwrap();
+        (FileStore::create(dir.path()).unwrap(), dir)
+    });
+    #[cfg(feature = "allocator-api2")]
+    store_suite!(alloc_store, {
+        let alloc = allocator_api2::alloc::Global;
+        (crate::AllocStore::new_in(alloc), ())
+    });
+
+    #[test]
+    fn files_only_hold_written_nodes() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::create(dir.path().join("tree")).unwrap();
+        let mut tree = MerkleTree::build_in(store, &[1, 2, 3, 4, 5]);
+        tree.flush().unwrap();
+        let file_len = |level| {
+            fs::metadata(dir.path().join("tree").join(format!("level-{level}")))
+                .unwrap()
+                .len()
+        };
+        // Five leaves, and the nodes above them: the last pair at each level is
+        // completed with empty nodes, which are not written.
+        assert_eq!([0, 1, 2, 3].map(file_len), [40, 24, 16, 8]);
+
+        tree.push(6);
+        tree.flush().unwrap();
+        assert_eq!([0, 1, 2, 3].map(file_len), [48, 24, 16, 8]);
+    }
+
+    #[test]
+    fn unreadable_nodes_are_corrupted() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::create(dir.path()).unwrap();
+        let mut tree = MerkleTree::build_in(store, &[1, 2, 3, 4, 5]);
+        assert!(tree.get_proof(4).verify(5));
+
+        // Level 1 loses its second node behind the store's back.
+        File::options()
+            .write(true)
+            .open(dir.path().join("level-1"))
+            .unwrap()
+            .set_len(8)
+            .unwrap();
+        assert_eq!(
+            tree.try_get_proof(0).err(),
+            Some(MerkleError::Corrupted { level: 1, index: 1 })
+        );
+        assert!(tree.get_proof(4).verify(5));
+        tree.flush().unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+use crate::diff::same_node;
+use crate::{MerkleTree, TreeOp};
+
+/// Work done by `MerkleTree::sync_from`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SyncStats {
+    /// Amount of nodes compared with the source's.
+    pub compared: usize,
+    /// Amount of nodes copied from the source, leaves included.
+    pub copied: usize,
+    /// Amount of leaves copied from the source.
+    pub leaves_copied: usize,
+}
+
+impl MerkleTree {
+    /// Makes the tree hold the same leaves as another one, copying only what differs:
+    /// nodes are compared top-down like `diff` does, and every differing node is copied
+    /// from the source rather than rehashed, so `d` differing leaves touch O(d log n)
+    /// nodes. Afterwards both trees have the same length, capacity and root.
+    /// The tree grows or shrinks to the source's capacity first: growing hashes the new
+    /// padding subtrees, while shrinking drops the slots beyond the source's capacity.
+    /// Like the leaves pruned from the source (see `from_frontier`), the nodes covering
+    /// them are not held afterwards.
+    /// This rewrites history: the root history and `root_at` only describe the synced
+    /// leaves from then on. If the lookup index is enabled, it is rebuilt when anything
+    /// changed. A tree keeping only its leaves (see `keep_leaves_only`) goes back to
+    /// storing every node if the source has pruned leaves.
+    /// * `source` - The tree to be copied from.
+    pub fn sync_from(&mut self, source: &MerkleTree) -> SyncStats {
+        let old_root = self.watched_root();
+        let old_len = self.len();
+        let old_capacity = self.capacity;
+
+        if source.pruned > 0 {
+            self.keep_all_nodes();
+        }
+        self.resize_capacity(source.capacity);
+        let mut stats = SyncStats::default();
+        let mut leaves = Vec::new();
+        self.sync_subtree(source, self.height() - 1, 0, &mut stats, &mut leaves);
+        self.levels.prune(source.pruned);
+        self.padding = source.padding;
+        self.occupancy = source.occupancy.clone();
+        self.pruned = source.pruned;
+
+        if stats.copied > 0 || self.len() != old_len || self.capacity != old_capacity {
+            self.epoch += 1;
+            self.invalidate_proof_cache();
+            if self.is_indexed() {
+                self.enable_index();
+            }
+            self.notify_root_change(old_root);
+            self.record_op(TreeOp::Rewrite {
+                len: self.len(),
+                capacity: self.capacity,
+                epoch: self.epoch,
+                leaves,
+            });
+        }
+        stats
+    }
+
+    /// Grows or shrinks the tree's storage to a capacity. Growing hashes the new padding
+    /// subtrees, while shrinking drops the slots beyond the capacity along with the levels
+    /// above the new root. The leaves, length and occupancy are left for the caller to
+    /// set.
+    /// * `capacity` - The new capacity, a power of two.
+    pub(crate) fn resize_capacity(&mut self, capacity: usize) {
+        while self.capacity < capacity {
+            self.duplicate_capacity();
+        }
+        if self.capacity > capacity {
+            self.levels.shrink(capacity.ilog2() as usize + 1);
+            self.capacity = capacity;
+        }
+    }
+
+    /// Copies the differing nodes of a subtree from the source.
+    /// * `level` - Level of the subtree's root.
+    /// * `index` - Index of the subtree's root within its level.
+    /// * `leaves` - Where the copied leaves are collected, with their indices.
+    fn sync_subtree(
+        &mut self,
+        source: &MerkleTree,
+        level: usize,
+        index: usize,
+        stats: &mut SyncStats,
+        leaves: &mut Vec<(usize, u64)>,
+    ) {
+        // The nodes pruned from the source cover only the leaves pruned along with them.
+        if index < source.levels.first(level) {
+            return;
+        }
+        stats.compared += 1;
+        let node = source.levels[(level, index)];
+        // Subtrees holding leaves pruned from the tree but not from the source are copied
+        // whole, even where they match.
+        let pruned_below = index << level < self.pruned && source.pruned < self.pruned;
+        if !pruned_below && same_node(self.levels[(level, index)], node) {
+            return;
+        }
+        self.levels.set(level, index, node);
+        stats.copied += 1;
+        if level == 0 {
+            stats.leaves_copied += 1;
+            leaves.push((index, node));
+            return;
+        }
+        self.sync_subtree(source, level - 1, 2 * index, stats, leaves);
+        self.sync_subtree(source, level - 1, 2 * index + 1, stats, leaves);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn corrupted_leaves_repaired() {
+        let values: Vec<u32> = (0..1000).collect();
+        let source = MerkleTree::build(&values);
+        let mut replica = source.fork();
+        let corrupted = [3, 500, 999];
+        for index in corrupted {
+            replica.replace_leaf_hash(index, 7);
+        }
+        assert_eq!(replica.diff(&source).differing, corrupted);
+
+        #[cfg(feature = "instrumentation")]
+        crate::reset_counters();
+        let stats = replica.sync_from(&source);
+        #[cfg(feature = "instrumentation")]
+        assert_eq!(crate::hash_ops(), crate::HashOps::default());
+
+        assert_eq!(replica.root(), source.root());
+        assert!(replica.diff(&source).is_empty());
+        replica.validate().unwrap();
+
+        // Only the ancestors of the corrupted leaves were rewritten.
+        let paths: HashSet<(usize, usize)> = corrupted
+            .iter()
+            .flat_map(|&index| (0..source.height()).map(move |level| (level, index >> level)))
+            .collect();
+        assert_eq!(stats.copied, paths.len());
+        assert_eq!(stats.leaves_copied, 3);
+        assert_eq!(stats.compared, 1 + 2 * (paths.len() - 3));
+
+        assert_eq!(
+            replica.sync_from(&source),
+            SyncStats {
+                compared: 1,
+                copied: 0,
+                leaves_copied: 0
+            }
+        );
+    }
+
+    #[test]
+    fn lengths_synced() {
+        let values: Vec<u32> = (0..150).collect();
+        let long = MerkleTree::build(&values);
+
+        let mut tree = MerkleTree::build(&values[..100]);
+        tree.sync_from(&long);
+        assert_eq!(tree.len(), 150);
+        assert_eq!(tree.capacity(), 256);
+        assert_eq!(tree.root(), long.root());
+        assert!(tree.get_proof(140).verify(140_u32));
+        tree.validate().unwrap();
+
+        let mut tree = MerkleTree::build(&values[..20]);
+        tree.push(99_u32);
+        let mut grown = MerkleTree::build(&values[..20]);
+        grown.sync_from(&tree);
+        assert_eq!(grown.root(), tree.root());
+
+        let short = MerkleTree::build(&values[..100]);
+        let mut tree = MerkleTree::build_indexed(&values);
+        tree.sync_from(&short);
+        assert_eq!(tree.len(), 100);
+        assert_eq!(tree.capacity(), 128);
+        assert_eq!(tree.root(), short.root());
+        assert!(!tree.get_proof(120).verify(120_u32));
+        assert!(!tree.contains(&120_u32));
+        assert!(tree.contains(&99_u32));
+        tree.validate().unwrap();
+
+        // Snapshots taken before shrinking keep their length.
+        let snapshot = tree.freeze();
+        tree.sync_from(&MerkleTree::build(&values[..10]));
+        assert_eq!(snapshot.len(), 100);
+        assert_eq!(snapshot.root(), short.root());
+        assert!(snapshot.get_proof(50).verify(50_u32));
+
+        tree.sync_from(&MerkleTree::build::<u8>(&[]));
+        assert!(tree.is_empty());
+        assert_eq!(tree.root(), None);
+        tree.validate().unwrap();
+    }
+
+    #[test]
+    fn pruned_trees_synced() {
+        let values: Vec<u32> = (0..300).collect();
+        let full = MerkleTree::build(&values);
+        let mut pruned =
+            MerkleTree::from_frontier(MerkleTree::build(&values[..200]).export_frontier()).unwrap();
+        pruned.extend(&values[200..]);
+
+        let mut tree = MerkleTree::build(&values[..50]);
+        tree.sync_from(&pruned);
+        assert!(tree == pruned);
+        assert_eq!(tree.node(0, 10), None);
+        assert!(tree.stats().heap_bytes <= pruned.stats().heap_bytes + 4096);
+        tree.validate().unwrap();
+
+        // Syncing from a tree holding every leaf holds them again.
+        tree.sync_from(&full);
+        assert!(tree == full);
+        assert_eq!(tree.pruned_len(), 0);
+        assert!(tree.get_proof(10).verify(10_u32));
+        tree.validate().unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+use proptest::collection::{SizeRange, vec};
+use proptest::prelude::*;
+use proptest::sample::{Index, select};
+use proptest::test_runner::TestCaseError;
+
+use crate::{MerkleProof, MerkleTree};
+
+/// Returns a strategy producing trees built out of random leaves.
+/// * `len` - Range of the amount of leaves, such as `0..=256`.
+pub fn arb_tree(len: impl Into<SizeRange>) -> impl Strategy<Value = MerkleTree> {
+    vec(any::<u64>(), len).prop_map(|values| MerkleTree::build(&values))
+}
+
+/// Returns the index and proof of every leaf of a tree whose proof can be generated.
+fn proofs(tree: &MerkleTree) -> Vec<(usize, MerkleProof)> {
+    tree.iter_occupied()
+        .map(|(index, _)| (index, tree.get_proof(index)))
+        .collect()
+}
+
+/// Returns a strategy producing the index of a random leaf of the tree together with its
+/// proof, which verifies the leaf's value.
+/// Panics if the tree holds no leaves whose proof can be generated.
+/// * `tree` - The tree the proofs are generated from.
+pub fn arb_proof_for(tree: &MerkleTree) -> impl Strategy<Value = (usize, MerkleProof)> + use<> {
+    select(proofs(tree))
+}
+
+/// Returns a strategy producing the index of a random leaf of the tree together with a
+/// tampered proof, in which a sibling node or the root was altered, so the leaf's value
+/// no longer verifies it.
+/// Panics if the tree holds no leaves whose proof can be generated.
+/// * `tree` - The tree the proofs are generated from.
+pub fn arb_tampered_proof_for(
+    tree: &MerkleTree,
+) -> impl Strategy<Value = (usize, MerkleProof)> + use<> {
+    (select(proofs(tree)), any::<Index>(), 1..=u64::MAX).prop_map(
+        |((index, mut proof), slot, mask)| {
+            if let MerkleProof::Proof { nodes, root, .. } = &mut proof {
+                let slot = slot.index(nodes.len() + 1);
+                match nodes.get_mut(slot) {
+                    Some(node) => *node ^= mask,
+                    None => *root ^= mask,
+                }
+            }
+            (index, proof)
+        },
+    )
+}
+
+/// Checks the invariants every tree upholds after any sequence of operations, failing
+/// the test case with the first one broken: everything `validate` checks, the root is
+/// the top node, and the proof of every leaf verifies it against the root.
+/// * `tree` - The tree to be checked.
+pub fn check_invariants(tree: &MerkleTree) -> Result<(), TestCaseError> {
+    tree.validate()
+        .map_err(|error| TestCaseError::fail(error.to_string()))?;
+    prop_assert!(tree.len() <= tree.capacity());
+    prop_assert_eq!(tree.leaves().len(), tree.len() - tree.pruned_len());
+    prop_assert_eq!(tree.is_empty(), tree.leaves().is_empty());
+
+    let top = tree.level(tree.height() - 1).map(|level| level[0]);
+    let expected_root = if tree.is_empty() { None } else { top };
+    prop_assert_eq!(tree.root(), expected_root);
+
+    for (index, proof) in proofs(tree) {
+        let leaf = tree.leaf(index).expect("Proven leaves are held");
+        prop_assert!(
+            proof.verify_leaf(leaf),
+            "proof of leaf {} does not verify",
+            index
+        );
+        prop_assert_eq!(proof.epoch(), Some(tree.epoch()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_leaves_match_reference;
+
+    /// Returns a tree together with a sequence of values pushed into it.
+    fn tree_and_pushes() -> impl Strategy<Value = (MerkleTree, Vec<u64>)> {
+        (arb_tree(0..=64), vec(any::<u64>(), 0..=64))
+    }
+
+    /// Returns the values of a tree together with a proof of one of its leaves.
+    /// * `arb_proof` - Strategy constructor of the proofs.
+    fn values_and_proofs<S: Strategy<Value = (usize, MerkleProof)>>(
+        arb_proof: fn(&MerkleTree) -> S,
+    ) -> impl Strategy<Value = (Vec<u64>, (usize, MerkleProof))> {
+        vec(any::<u64>(), 1..=256).prop_flat_map(move |values| {
+            let proofs = arb_proof(&MerkleTree::build(&values));
+            (Just(values), proofs)
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn built_trees_uphold_invariants(tree in arb_tree(0..=256)) {
+            check_invariants(&tree)?;
+        }
+
+        #[test]
+        fn pushes_uphold_invariants((mut tree, values) in tree_and_pushes()) {
+            let mut leaves = tree.leaves().to_vec();
+            for value in values {
+                tree.push(value);
+                check_invariants(&tree)?;
+                leaves.push(crate::hash_single(value));
+                assert_leaves_match_reference(&tree, &leaves);
+            }
+        }
+
+        #[test]
+        fn extending_matches_building((mut tree, values) in tree_and_pushes()) {
+            let mut leaves = tree.leaves().to_vec();
+            tree.extend(&values);
+            check_invariants(&tree)?;
+
+            leaves.extend(values.iter().map(crate::hash_single));
+            assert_leaves_match_reference(&tree, &leaves);
+            prop_assert_eq!(tree.root(), MerkleTree::from_leaf_hashes(leaves).root());
+        }
+
+        #[test]
+        fn frontier_continuations_uphold_invariants((tree, values) in tree_and_pushes()) {
+            let mut continued = MerkleTree::from_frontier(tree.export_frontier()).unwrap();
+            for value in values {
+                continued.push(value);
+                check_invariants(&continued)?;
+            }
+        }
+
+        #[test]
+        fn proofs_verify((values, (index, proof)) in values_and_proofs(arb_proof_for)) {
+            prop_assert!(proof.verify(values[index]));
+        }
+
+        #[test]
+        fn tampered_proofs_fail((values, (index, proof)) in values_and_proofs(arb_tampered_proof_for)) {
+            prop_assert!(!proof.verify(values[index]));
+        }
+    }
+
+    #[test]
+    fn broken_invariants_reported() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        check_invariants(&tree).unwrap();
+        tree.corrupt_node(1, 0, 7);
+        assert!(check_invariants(&tree).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use std::hash::Hash;
+
+use crate::{MerkleProof, MerkleTree, hash_pair, hash_single};
+
+/// How the naive reference completes a level holding an odd amount of nodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NaivePadding {
+    /// Pads the leaves with `PAD_HASH` up to a power of two: the padding of `MerkleTree`.
+    PadHash,
+    /// Pairs the last node of odd levels with itself.
+    DuplicateLast,
+    /// Moves the last node of odd levels up to the next level unchanged.
+    Promote,
+}
+
+/// Returns every level of the tree over some leaves, from the leaves to the root, padded
+/// with `PAD_HASH` up to `width` leaves. No node is ever skipped or cached.
+/// * `leaf_hashes` - The leaves of the tree.
+/// * `width` - Amount of leaves once padded, at least as many as there are leaves.
+fn naive_levels(leaf_hashes: &[u64], width: usize) -> Vec<Vec<u64>> {
+    let mut level = leaf_hashes.to_vec();
+    while level.len() < width {
+        level.push(MerkleTree::PAD_HASH);
+    }
+    let mut levels = vec![level];
+    while levels[levels.len() - 1].len() > 1 {
+        let below = &levels[levels.len() - 1];
+        let mut level = Vec::new();
+        let mut index = 0;
+        while index < below.len() {
+            level.push(hash_pair(below[index], below[index + 1]));
+            index += 2;
+        }
+        levels.push(level);
+    }
+    levels
+}
+
+/// Returns the root of the tree over some leaves, or `None` if there are none, computed
+/// as naively as possible: each level is hashed pair by pair out of the whole level
+/// below, without any of the shortcuts of `MerkleTree`.
+/// * `leaf_hashes` - The leaves of the tree.
+/// * `padding` - How odd levels are completed.
+pub fn naive_root(leaf_hashes: &[u64], padding: NaivePadding) -> Option<u64> {
+    if leaf_hashes.is_empty() {
+        return None;
+    }
+    if padding == NaivePadding::PadHash {
+        let levels = naive_levels(leaf_hashes, leaf_hashes.len().next_power_of_two());
+        return Some(levels[levels.len() - 1][0]);
+    }
+
+    let mut level = leaf_hashes.to_vec();
+    while level.len() > 1 {
+        let mut above = Vec::new();
+        let mut index = 0;
+        while index + 1 < level.len() {
+            above.push(hash_pair(level[index], level[index + 1]));
+            index += 2;
+        }
+        if index < level.len() {
+            let last = level[index];
+            above.push(match padding {
+                NaivePadding::DuplicateLast => hash_pair(last, last),
+                _ => last,
+            });
+        }
+        level = above;
+    }
+    Some(level[0])
+}
+
+/// Returns the siblings along the path of a leaf, from the leaves' level upwards, in the
+/// tree over some leaves padded with `PAD_HASH` up to a power of two, or `None` if the
+/// index does not hold a leaf. These are the nodes of the `MerkleProof` a `MerkleTree`
+/// of that capacity returns.
+/// * `leaf_hashes` - The leaves of the tree.
+/// * `index` - Index of the leaf.
+pub fn naive_proof(leaf_hashes: &[u64], index: usize) -> Option<Vec<u64>> {
+    if index >= leaf_hashes.len() {
+        return None;
+    }
+    let levels = naive_levels(leaf_hashes, leaf_hashes.len().next_power_of_two());
+    Some(naive_siblings(&levels, index))
+}
+
+/// Returns the siblings along the path of a leaf, out of every level of a tree.
+/// * `levels` - The levels of the tree, from the leaves to the root.
+/// * `index` - Index of the leaf.
+fn naive_siblings(levels: &[Vec<u64>], index: usize) -> Vec<u64> {
+    let mut siblings = Vec::new();
+    let mut index = index;
+    for level in &levels[..levels.len() - 1] {
+        let sibling = if index.is_multiple_of(2) {
+            index + 1
+        } else {
+            index - 1
+        };
+        siblings.push(level[sibling]);
+        index /= 2;
+    }
+    siblings
+}
+
+/// Returns whether hashing a leaf with its siblings, as returned by `naive_proof`, leads
+/// to a root. The siblings must address exactly the leaf's index.
+/// * `leaf_hash` - Hash of the leaf.
+/// * `index` - Index of the leaf.
+/// * `siblings` - The siblings along the leaf's path, from the leaves' level upwards.
+/// * `root` - The trusted root.
+pub fn naive_verify(leaf_hash: u64, index: usize, siblings: &[u64], root: u64) -> bool {
+    let mut node = leaf_hash;
+    let mut index = index;
+    for &sibling in siblings {
+        node = if index.is_multiple_of(2) {
+            hash_pair(node, sibling)
+        } else {
+            hash_pair(sibling, node)
+        };
+        index /= 2;
+    }
+    index == 0 && node == root
+}
+
+/// Asserts that a tree holds exactly the nodes of the naive reference over some
+/// elements, level by level, and that the proof of every leaf holds the reference's
+/// siblings.
+/// Panics if anything differs. Trees with pruned leaves (see `from_frontier`) never match.
+/// * `tree` - The tree to be checked.
+/// * `elements` - The `Hash` values the tree should have been built out of.
+pub fn assert_tree_matches_reference<H: Hash>(tree: &MerkleTree, elements: &[H]) {
+    let leaf_hashes: Vec<u64> = elements.iter().map(hash_single).collect();
+    assert_leaves_match_reference(tree, &leaf_hashes);
+}
+
+/// Asserts that a tree holds exactly the nodes of the naive reference over some leaves,
+/// like `assert_tree_matches_reference` does over elements.
+/// Panics if anything differs.
+/// * `tree` - The tree to be checked.
+/// * `leaf_hashes` - The leaves the tree should hold.
+pub fn assert_leaves_match_reference(tree: &MerkleTree, leaf_hashes: &[u64]) {
+    assert_eq!(tree.len(), leaf_hashes.len(), "lengths differ");
+    let levels = naive_levels(leaf_hashes, tree.capacity());
+    assert_eq!(tree.height(), levels.len(), "heights differ");
+    for (level_n, (level, expected)) in tree.levels_iter().zip(&levels).enumerate() {
+        assert_eq!(
+            &level[..],
+            &expected[..],
+            "level {level_n} differs from the reference"
+        );
+    }
+    let expected_root = levels[levels.len() - 1][0];
+    assert_eq!(
+        tree.root(),
+        Some(expected_root).filter(|_| !leaf_hashes.is_empty()),
+        "root differs from the reference"
+    );
+
+    for (index, &leaf) in leaf_hashes.iter().enumerate() {
+        let siblings = naive_siblings(&levels, index);
+        let MerkleProof::Proof { nodes, root, .. } = tree.get_proof(index) else {
+            panic!("leaf {index} has no proof");
+        };
+        assert_eq!(
+            nodes, siblings,
+            "proof of leaf {index} differs from the reference"
+        );
+        assert!(naive_verify(leaf, index, &siblings, root));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate_tree_levels;
+
+    #[test]
+    fn reference_matches_hand_computed_roots() {
+        let leaves: Vec<u64> = (0..3).map(hash_single).collect();
+        let pad = MerkleTree::PAD_HASH;
+        let left = hash_pair(leaves[0], leaves[1]);
+        let cases = [
+            (
+                NaivePadding::PadHash,
+                hash_pair(left, hash_pair(leaves[2], pad)),
+            ),
+            (
+                NaivePadding::DuplicateLast,
+                hash_pair(left, hash_pair(leaves[2], leaves[2])),
+            ),
+            (NaivePadding::Promote, hash_pair(left, leaves[2])),
+        ];
+        for (padding, root) in cases {
+            assert_eq!(naive_root(&leaves, padding), Some(root));
+            assert_eq!(naive_root(&leaves[..1], padding), Some(leaves[0]));
+            assert_eq!(naive_root(&[], padding), None);
+        }
+    }
+
+    #[test]
+    fn reference_matches_level_generation() {
+        for len in 1..40 {
+            let leaves: Vec<u64> = (0..len).map(hash_single).collect();
+            let mut levels = Vec::new();
+            generate_tree_levels(&leaves, &mut levels);
+            assert_eq!(
+                naive_root(&leaves, NaivePadding::PadHash),
+                Some(levels[levels.len() - 1][0])
+            );
+        }
+    }
+
+    #[test]
+    fn reference_proofs_verify() {
+        let leaves: Vec<u64> = (0..13).map(hash_single).collect();
+        let root = naive_root(&leaves, NaivePadding::PadHash).unwrap();
+        for (index, &leaf) in leaves.iter().enumerate() {
+            let siblings = naive_proof(&leaves, index).unwrap();
+            assert_eq!(siblings.len(), 4);
+            assert!(naive_verify(leaf, index, &siblings, root));
+            assert!(!naive_verify(leaf, index ^ 1, &siblings, root));
+            assert!(!naive_verify(leaf, index + 16, &siblings, root));
+        }
+        assert_eq!(naive_proof(&leaves, 13), None);
+    }
+
+    #[test]
+    fn trees_match_the_reference() {
+        let values: Vec<u32> = (0..100).collect();
+        for len in [0, 1, 2, 5, 64, 100] {
+            assert_tree_matches_reference(&MerkleTree::build(&values[..len]), &values[..len]);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "level 0 differs from the reference")]
+    fn differing_trees_are_reported() {
+        assert_tree_matches_reference(&MerkleTree::build(&[1, 2, 3]), &[1, 2, 4]);
+    }
+}
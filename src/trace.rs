@@ -0,0 +1,92 @@
+//! Spans emitted through `tracing` when the `tracing` feature is enabled. Without it, no
+//! call is compiled in. Span names and fields are stable:
+//!
+//! - `build` (info), with `leaves`: building a tree out of its elements or leaf hashes,
+//!   in memory or in a store.
+//! - `duplicate_capacity` (info), with `old_capacity` and `new_capacity`: a tree growing.
+//! - `get_proof` (debug), with `index`: creating a proof, whether cached or not.
+//! - `validate` (info), with `leaves`: checking every node of a tree.
+//! - `flush` (info): writing a store's pending changes, followed by a warning event with
+//!   an `error` field if it fails.
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fmt::Debug;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id};
+    use tracing::{Subscriber, subscriber};
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+    use tracing_subscriber::registry::Registry;
+
+    use crate::MerkleTree;
+
+    /// Span names with their fields, formatted, in creation order.
+    type Spans = Arc<Mutex<Vec<(&'static str, HashMap<&'static str, String>)>>>;
+
+    struct Recorder(Spans);
+
+    struct Fields<'a>(&'a mut HashMap<&'static str, String>);
+
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.insert(field.name(), format!("{value:?}"));
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for Recorder {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _: &Id, _: Context<'_, S>) {
+            let mut fields = HashMap::new();
+            attrs.record(&mut Fields(&mut fields));
+            self.0
+                .lock()
+                .unwrap()
+                .push((attrs.metadata().name(), fields));
+        }
+    }
+
+    /// Runs a function with a subscriber recording every span, returning them.
+    fn spans_of(run: impl FnOnce()) -> Spans {
+        let spans = Spans::default();
+        let subscriber = Registry::default().with(Recorder(Arc::clone(&spans)));
+        subscriber::with_default(subscriber, run);
+        spans
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn builds_emit_spans() {
+        let spans = spans_of(|| {
+            let mut tree = MerkleTree::build(&(0..1000).collect::<Vec<u32>>());
+            tree.extend(1000..1100_u32);
+            tree.get_proof(7);
+            tree.validate().unwrap();
+            tree.flush().unwrap();
+        });
+        let spans = spans.lock().unwrap();
+        let field = |name, field| {
+            let (_, fields) = spans.iter().find(|(span, _)| *span == name).unwrap();
+            fields[field].clone()
+        };
+        assert_eq!(field("build", "leaves"), "1000");
+        assert_eq!(field("duplicate_capacity", "old_capacity"), "1024");
+        assert_eq!(field("duplicate_capacity", "new_capacity"), "2048");
+        assert_eq!(field("get_proof", "index"), "7");
+        assert_eq!(field("validate", "leaves"), "1100");
+        assert!(spans.iter().any(|(span, _)| *span == "flush"));
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    #[test]
+    fn nothing_is_emitted_without_the_feature() {
+        let spans = spans_of(|| {
+            let mut tree = MerkleTree::build(&(0..1000).collect::<Vec<u32>>());
+            tree.extend(1000..1100_u32);
+            tree.get_proof(7);
+            tree.validate().unwrap();
+        });
+        assert!(spans.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,284 @@
+use std::ops::Range;
+
+use crate::MerkleTree;
+
+/// Read access to the nodes of a remote tree, implemented over whatever fetches them,
+/// for `compute_transfer_plan`. Coordinates are the ones of `MerkleTree::node`, in the
+/// remote tree, whose capacity is its length rounded up to a power of two.
+pub trait RemoteNodes {
+    /// Returns the length of the remote tree, such as the one of its `TreeHead`.
+    fn len(&self) -> usize;
+
+    /// Returns whether the remote tree is empty.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the node at the given coordinates, or `None` if it cannot be fetched, in
+    /// which case every leaf below it is planned for download.
+    /// * `level` - Level of the node.
+    /// * `index` - Index of the node within its level.
+    fn node(&self, level: usize, index: usize) -> Option<u64>;
+
+    /// Returns the amount of bytes downloading a leaf takes, for
+    /// `TransferPlan::bytes_estimate`. Defaults to the size of its hash.
+    /// * `index` - Index of the leaf.
+    fn leaf_bytes(&self, index: usize) -> u64 {
+        let _ = index;
+        size_of::<u64>() as u64
+    }
+}
+
+impl RemoteNodes for MerkleTree {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn node(&self, level: usize, index: usize) -> Option<u64> {
+        self.node(level, index)
+    }
+}
+
+/// Leaves to be downloaded from a remote tree for a local one to converge to it, as
+/// returned by `compute_transfer_plan`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransferPlan {
+    /// Ranges of remote leaf indices to be downloaded, in order and never adjacent.
+    pub ranges: Vec<Range<usize>>,
+    /// Amount of bytes the ranges take, see `RemoteNodes::leaf_bytes`.
+    pub bytes_estimate: u64,
+}
+
+impl TransferPlan {
+    /// Returns the amount of leaves to be downloaded.
+    pub fn leaf_count(&self) -> usize {
+        self.ranges.iter().map(Range::len).sum()
+    }
+}
+
+/// Returns the leaves a local tree must download from a remote one to hold the same
+/// leaves, like `MerkleTree::diff` does with two local trees: only subtrees whose hashes
+/// differ are descended into, so `d` differing leaves fetch O(d log n) remote nodes.
+/// Leaves beyond the local tree's length are downloaded without fetching any node, and
+/// so are the differing subtrees covering leaves pruned from the local tree (see
+/// `from_frontier`), whose nodes are not all held. Leaves beyond the remote length are
+/// never planned: the local tree is truncated to it rather than downloading them.
+/// * `local` - The tree to be converged.
+/// * `remote` - The tree to be converged to.
+pub fn compute_transfer_plan<R: RemoteNodes + ?Sized>(
+    local: &MerkleTree,
+    remote: &R,
+) -> TransferPlan {
+    let mut planner = Planner {
+        local,
+        remote,
+        ranges: Vec::new(),
+    };
+    let remote_len = remote.len();
+    if remote_len > 0 {
+        let remote_height = remote_len.next_power_of_two().ilog2() as usize + 1;
+        let top = local.height().min(remote_height) - 1;
+        for index in 0..remote_len.div_ceil(1 << top) {
+            planner.plan_subtree(top, index);
+        }
+    }
+
+    let bytes_estimate = planner
+        .ranges
+        .iter()
+        .flat_map(Range::clone)
+        .map(|index| remote.leaf_bytes(index))
+        .sum();
+    TransferPlan {
+        ranges: planner.ranges,
+        bytes_estimate,
+    }
+}
+
+/// State of a `compute_transfer_plan` descent.
+struct Planner<'a, R: ?Sized> {
+    local: &'a MerkleTree,
+    remote: &'a R,
+    ranges: Vec<Range<usize>>,
+}
+
+impl<R: RemoteNodes + ?Sized> Planner<'_, R> {
+    /// Plans the download of the differing leaves of a subtree.
+    /// * `level` - Level of the subtree's root.
+    /// * `index` - Index of the subtree's root within its level.
+    fn plan_subtree(&mut self, level: usize, index: usize) {
+        let first_leaf = index << level;
+        let end_leaf = ((index + 1) << level).min(self.remote.len());
+        if first_leaf >= end_leaf {
+            return;
+        }
+        if end_leaf > self.local.len() && first_leaf >= self.local.len() {
+            self.download(first_leaf..end_leaf);
+            return;
+        }
+
+        let Some(remote_node) = self.remote.node(level, index) else {
+            self.download(first_leaf..end_leaf);
+            return;
+        };
+        if self.local.node(level, index) == Some(remote_node) {
+            return;
+        }
+        if level == 0 || first_leaf < self.local.pruned {
+            self.download(first_leaf..end_leaf);
+            return;
+        }
+        self.plan_subtree(level - 1, 2 * index);
+        self.plan_subtree(level - 1, 2 * index + 1);
+    }
+
+    /// Adds a range of leaves to the plan, merging it with the previous one if adjacent.
+    fn download(&mut self, range: Range<usize>) {
+        match self.ranges.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => self.ranges.push(range),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    /// Remote tree held in memory, counting the nodes fetched from it.
+    struct FakeRemote {
+        tree: MerkleTree,
+        fetches: Cell<usize>,
+    }
+
+    impl FakeRemote {
+        fn new(values: &[u32]) -> FakeRemote {
+            FakeRemote {
+                tree: MerkleTree::build(values),
+                fetches: Cell::new(0),
+            }
+        }
+    }
+
+    impl RemoteNodes for FakeRemote {
+        fn len(&self) -> usize {
+            self.tree.len()
+        }
+
+        fn node(&self, level: usize, index: usize) -> Option<u64> {
+            self.fetches.set(self.fetches.get() + 1);
+            self.tree.node(level, index)
+        }
+
+        fn leaf_bytes(&self, _: usize) -> u64 {
+            1024
+        }
+    }
+
+    /// Returns the planned ranges as pairs of bounds.
+    fn spans(plan: &TransferPlan) -> Vec<(usize, usize)> {
+        plan.ranges
+            .iter()
+            .map(|range| (range.start, range.end))
+            .collect()
+    }
+
+    /// Returns `0..len` with the given indices overwritten.
+    fn values_with(len: u32, changed: &[u32]) -> Vec<u32> {
+        (0..len)
+            .map(|value| {
+                if changed.contains(&value) {
+                    value + 1_000_000
+                } else {
+                    value
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scattered_differences() {
+        let local = MerkleTree::build(&values_with(4096, &[]));
+        let changed = [3, 700, 701, 2048, 4095];
+        let remote = FakeRemote::new(&values_with(4096, &changed));
+        let plan = compute_transfer_plan(&local, &remote);
+        assert_eq!(plan.ranges, [3..4, 700..702, 2048..2049, 4095..4096]);
+        assert_eq!(plan.bytes_estimate, 5 * 1024);
+        assert_eq!(
+            plan.ranges
+                .iter()
+                .flat_map(Range::clone)
+                .collect::<Vec<_>>(),
+            local.diff(&remote.tree).differing
+        );
+        // Two nodes per level along each differing leaf's path, at most.
+        assert!(remote.fetches.get() <= changed.len() * 2 * local.height());
+
+        let remote = FakeRemote::new(&values_with(4096, &[]));
+        assert_eq!(
+            compute_transfer_plan(&local, &remote),
+            TransferPlan::default()
+        );
+        assert_eq!(remote.fetches.get(), 1);
+    }
+
+    #[test]
+    fn contiguous_differences() {
+        let local = MerkleTree::build(&values_with(4096, &[]));
+        let changed: Vec<u32> = (1000..1100).collect();
+        let remote = FakeRemote::new(&values_with(4096, &changed));
+        let plan = compute_transfer_plan(&local, &remote);
+        assert_eq!(spans(&plan), [(1000, 1100)]);
+        assert_eq!(plan.leaf_count(), 100);
+        assert!(remote.fetches.get() <= 2 * (changed.len() + 2 * local.height()));
+
+        // Leaves appended remotely are downloaded without fetching their nodes.
+        let remote = FakeRemote::new(&values_with(5000, &[]));
+        let plan = compute_transfer_plan(&local, &remote);
+        assert_eq!(spans(&plan), [(4096, 5000)]);
+        assert!(remote.fetches.get() <= 2 * local.height());
+
+        // Leaves beyond a shorter remote tree are not planned.
+        let remote = FakeRemote::new(&values_with(3000, &[2999]));
+        let plan = compute_transfer_plan(&local, &remote);
+        assert_eq!(spans(&plan), [(2999, 3000)]);
+        assert!(remote.fetches.get() <= 2 * 2 * local.height());
+
+        // Smaller remote trees are compared from their own root down.
+        let remote = FakeRemote::new(&values_with(1000, &[]));
+        assert_eq!(
+            compute_transfer_plan(&local, &remote),
+            TransferPlan::default()
+        );
+        assert!(remote.fetches.get() <= 2 * local.height());
+    }
+
+    #[test]
+    fn unavailable_nodes_and_empty_trees() {
+        struct Unavailable(usize);
+
+        impl RemoteNodes for Unavailable {
+            fn len(&self) -> usize {
+                self.0
+            }
+
+            fn node(&self, _: usize, _: usize) -> Option<u64> {
+                None
+            }
+        }
+
+        let local = MerkleTree::build(&values_with(10, &[]));
+        let plan = compute_transfer_plan(&local, &Unavailable(12));
+        assert_eq!(spans(&plan), [(0, 12)]);
+        assert_eq!(plan.bytes_estimate, 12 * 8);
+
+        let empty = MerkleTree::build::<u32>(&[]);
+        assert_eq!(
+            compute_transfer_plan(&local, &Unavailable(0)),
+            TransferPlan::default()
+        );
+        assert_eq!(spans(&compute_transfer_plan(&empty, &local)), [(0, 10)]);
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::VecDeque;
+use std::hash::Hash;
+
+use crate::MerkleProof;
+
+/// Root trusted by a `TrustedRoots` set, along with the metadata it was inserted with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RootMeta<M = ()> {
+    pub root: u64,
+    pub metadata: M,
+}
+
+/// Bounded set of the most recently published roots a verifier trusts, oldest first, so
+/// that proofs taken against any of them verify while newer roots propagate.
+/// Inserting into a full set evicts its oldest root, and with it every proof taken
+/// against that root stops verifying.
+#[derive(Clone, Debug)]
+pub struct TrustedRoots<M = ()> {
+    max_entries: usize,
+    entries: VecDeque<RootMeta<M>>,
+}
+
+impl<M> TrustedRoots<M> {
+    /// Creates an empty set holding up to `max_entries` roots.
+    /// * `max_entries` - The maximum amount of roots to be trusted at once.
+    pub fn new(max_entries: usize) -> TrustedRoots<M> {
+        TrustedRoots {
+            max_entries,
+            entries: VecDeque::with_capacity(max_entries),
+        }
+    }
+
+    /// Trusts a root as the newest one, evicting the oldest root if the set is full.
+    /// Inserting a root already trusted makes it the newest, replacing its metadata.
+    /// Returns the evicted root, if any.
+    /// * `root` - The root to be trusted.
+    /// * `metadata` - Data describing the root, returned when a proof matches it.
+    pub fn insert(&mut self, root: u64, metadata: M) -> Option<RootMeta<M>> {
+        if self.max_entries == 0 {
+            return None;
+        }
+        if let Some(position) = self.position(root) {
+            self.entries.remove(position);
+        }
+        let evicted = if self.entries.len() == self.max_entries {
+            self.entries.pop_front()
+        } else {
+            None
+        };
+        self.entries.push_back(RootMeta { root, metadata });
+        evicted
+    }
+
+    /// Stops trusting a root, returning it if it was trusted.
+    /// * `root` - The root to be removed.
+    pub fn remove(&mut self, root: u64) -> Option<RootMeta<M>> {
+        self.entries.remove(self.position(root)?)
+    }
+
+    /// Returns the trusted root with the given hash, if any.
+    /// * `root` - The root to be looked up.
+    pub fn get(&self, root: u64) -> Option<&RootMeta<M>> {
+        self.entries.get(self.position(root)?)
+    }
+
+    /// Returns the trusted roots, newest first.
+    pub fn iter(&self) -> impl Iterator<Item = &RootMeta<M>> + '_ {
+        self.entries.iter().rev()
+    }
+
+    /// Returns the amount of trusted roots.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether no root is trusted.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the maximum amount of roots trusted at once.
+    pub fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+
+    /// Verifies a proof against the trusted roots, returning the root it matched. Proofs
+    /// whose root is not trusted fail, even if they are internally consistent.
+    /// * `proof` - The proof to be verified.
+    /// * `value` - The `Hash` value the proof is expected to prove.
+    pub fn verify<H: Hash>(&self, proof: &MerkleProof, value: H) -> Option<&RootMeta<M>> {
+        let MerkleProof::Proof { root, .. } = proof else {
+            return None;
+        };
+        let trusted = self.get(*root)?;
+        proof.verify(value).then_some(trusted)
+    }
+
+    /// Returns the position of a root among the trusted ones.
+    fn position(&self, root: u64) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.root == root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    /// Publishes the roots of a growing tree, with their lengths as metadata.
+    fn published(max_entries: usize) -> (MerkleTree, TrustedRoots<usize>) {
+        let mut tree = MerkleTree::build::<u32>(&[]);
+        let mut trusted = TrustedRoots::new(max_entries);
+        for value in 0..5_u32 {
+            tree.push(value);
+            trusted.insert(tree.root().unwrap(), tree.len());
+        }
+        (tree, trusted)
+    }
+
+    #[test]
+    fn proofs_match_older_roots() {
+        let (mut tree, trusted) = published(3);
+        let lagging = MerkleTree::build(&[0_u32, 1, 2, 3]);
+        let proof = lagging.get_proof(2);
+        assert_eq!(
+            trusted.verify(&proof, 2_u32),
+            Some(&RootMeta {
+                root: lagging.root().unwrap(),
+                metadata: 4
+            })
+        );
+        assert_eq!(trusted.verify(&proof, 3_u32), None);
+        assert_eq!(trusted.verify(&MerkleProof::Invalid, 2_u32), None);
+
+        tree.push(5_u32);
+        assert_eq!(trusted.verify(&tree.get_proof(2), 2_u32), None);
+        assert_eq!(
+            trusted
+                .iter()
+                .map(|entry| entry.metadata)
+                .collect::<Vec<_>>(),
+            [5, 4, 3]
+        );
+    }
+
+    #[test]
+    fn untrusted_roots_fail_consistent_proofs() {
+        let (_, trusted) = published(3);
+        let other = MerkleTree::build(&[0_u32, 1, 2, 3, 9]);
+        let proof = other.get_proof(0);
+        assert!(proof.verify(0_u32));
+        assert_eq!(trusted.verify(&proof, 0_u32), None);
+    }
+
+    #[test]
+    fn evicted_roots_stop_verifying() {
+        let (mut tree, mut trusted) = published(3);
+        let oldest = MerkleTree::build(&[0_u32, 1, 2]);
+        let proof = oldest.get_proof(1);
+        assert!(trusted.verify(&proof, 1_u32).is_some());
+
+        tree.push(5_u32);
+        let evicted = trusted.insert(tree.root().unwrap(), tree.len());
+        assert_eq!(
+            evicted,
+            Some(RootMeta {
+                root: oldest.root().unwrap(),
+                metadata: 3
+            })
+        );
+        assert_eq!(trusted.len(), 3);
+        assert_eq!(trusted.verify(&proof, 1_u32), None);
+
+        // Reinserting a trusted root refreshes it instead of evicting anything.
+        let refreshed = trusted.iter().last().unwrap().root;
+        assert_eq!(trusted.insert(refreshed, 4), None);
+        assert_eq!(trusted.iter().next().unwrap().root, refreshed);
+        assert_eq!(trusted.len(), 3);
+    }
+}
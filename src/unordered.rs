@@ -0,0 +1,205 @@
+use std::hash::Hash;
+
+use crate::{MerkleProof, MerkleTree, child_indices, hash_pair, hash_single};
+
+impl MerkleTree {
+    /// Inserts a leaf at an index, shifting the leaves from it onwards one slot to the
+    /// right, and recomputes the ancestors of every moved leaf once.
+    /// Like `replace_leaf_hash`, this rewrites history: it must only be used on trees
+    /// which keep no root history and have no lookup index.
+    /// * `index` - The index of the new leaf, at most the tree's length.
+    /// * `leaf` - The new leaf hash.
+    pub(crate) fn insert_leaf_hash(&mut self, index: usize, leaf: u64) {
+        let old_root = self.watched_root();
+        let len = self.len();
+        if self.is_full() {
+            self.duplicate_capacity();
+        }
+
+        self.levels.forget_materialized();
+        for moved in (index + 1..=len).rev() {
+            let node = self.levels[(0, moved - 1)];
+            self.levels.set(0, moved, node);
+        }
+        self.levels.set(0, index, leaf);
+        self.occupancy.set(len);
+        for level_n in self.levels.lowest_kept()..self.height() {
+            // The moved leaves span from the index to the old length.
+            for parent in (index >> level_n)..=(len >> level_n) {
+                let child = |index| {
+                    self.levels
+                        .get(level_n - 1, index)
+                        .expect("Children are within their level")
+                };
+                let [left, right] = child_indices(parent);
+                let node = hash_pair(child(left), child(right));
+                self.levels.set(level_n, parent, node);
+            }
+        }
+
+        self.padding -= 1;
+        self.epoch += 1;
+        self.invalidate_proof_cache();
+        self.notify_root_change(old_root);
+    }
+}
+
+/// Commitment to a multiset: its root only depends on which values were inserted and
+/// how many times, not on the order they were inserted in.
+/// The leaves are kept sorted by hash, so every permutation of the same values yields
+/// the same tree. Inserting a value finds its position by binary search and shifts the
+/// leaves after it, rehashing the ancestors of the moved leaves, rather than sorting
+/// everything again. Equal values get adjacent leaves, so each copy changes the root.
+/// Proofs are the ones of the underlying `MerkleTree` and verify against the value,
+/// but their index is only meaningful until the next insertion.
+#[derive(Debug)]
+pub struct UnorderedCommitment {
+    tree: MerkleTree,
+}
+
+impl UnorderedCommitment {
+    /// Creates an empty commitment.
+    pub fn new() -> UnorderedCommitment {
+        UnorderedCommitment {
+            tree: MerkleTree::build::<u8>(&[]),
+        }
+    }
+
+    /// Constructs a commitment to every element at once, sorting their hashes a single
+    /// time. The result is identical to inserting them one by one, in any order.
+    /// * `elements` - array of `Hash` elements to be committed to.
+    pub fn build<H: Hash>(elements: &[H]) -> UnorderedCommitment {
+        let mut leaves: Vec<u64> = elements.iter().map(hash_single).collect();
+        leaves.sort_unstable();
+        UnorderedCommitment {
+            tree: MerkleTree::from_leaf_hashes(leaves),
+        }
+    }
+
+    /// Returns the amount of values inserted, copies included.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns whether no value was inserted.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Returns the root committing to the multiset, or `None` if it is empty.
+    pub fn root(&self) -> Option<u64> {
+        self.tree.root()
+    }
+
+    /// Inserts a copy of a value, keeping the leaves sorted.
+    /// * `value` - The `Hash` value to be inserted.
+    pub fn insert<H: Hash>(&mut self, value: H) {
+        let leaf = hash_single(value);
+        let index = self.tree.leaves().partition_point(|&other| other <= leaf);
+        if index == self.tree.len() {
+            self.tree.push_hash(leaf);
+        } else {
+            self.tree.insert_leaf_hash(index, leaf);
+        }
+    }
+
+    /// Returns how many copies of a value were inserted.
+    /// * `value` - The `Hash` value to be counted.
+    pub fn count<H: Hash>(&self, value: &H) -> usize {
+        let leaf = hash_single(value);
+        let leaves = self.tree.leaves();
+        leaves.partition_point(|&other| other <= leaf)
+            - leaves.partition_point(|&other| other < leaf)
+    }
+
+    /// Returns a proof that a value was inserted, or `None` if it was not.
+    /// * `value` - The `Hash` value to be proven.
+    pub fn prove<H: Hash>(&self, value: &H) -> Option<MerkleProof> {
+        let leaf = hash_single(value);
+        let index = self.tree.leaves().binary_search(&leaf).ok()?;
+        Some(self.tree.get_proof(index))
+    }
+}
+
+impl Default for UnorderedCommitment {
+    fn default() -> UnorderedCommitment {
+        UnorderedCommitment::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn permutations_share_the_root(
+            (values, shuffled) in vec(0_u16..50, 0..=80)
+                .prop_flat_map(|values| (Just(values.clone()), Just(values).prop_shuffle())),
+        ) {
+            let mut first = UnorderedCommitment::new();
+            let mut second = UnorderedCommitment::new();
+            for (value, other) in values.iter().zip(&shuffled) {
+                first.insert(value);
+                second.insert(other);
+            }
+            prop_assert_eq!(first.root(), second.root());
+            prop_assert_eq!(first.root(), UnorderedCommitment::build(&shuffled).root());
+            prop_assert_eq!(first.tree.validate(), Ok(()));
+
+            let root = first.root();
+            for value in &values {
+                let proof = first.prove(&value).unwrap();
+                prop_assert!(proof.verify(value));
+                let MerkleProof::Proof { root: proven, .. } = proof else {
+                    return Err(TestCaseError::fail("inserted values are provable"));
+                };
+                prop_assert_eq!(Some(proven), root);
+            }
+        }
+    }
+
+    #[test]
+    fn duplicates_change_the_root() {
+        let mut commitment = UnorderedCommitment::build(&["a", "b", "c"]);
+        let root = commitment.root();
+        assert_eq!(commitment.count(&"b"), 1);
+
+        commitment.insert("b");
+        assert_ne!(commitment.root(), root);
+        assert_eq!(commitment.count(&"b"), 2);
+        assert_eq!(commitment.count(&"d"), 0);
+        assert_eq!(commitment.len(), 4);
+        assert_eq!(
+            commitment.root(),
+            UnorderedCommitment::build(&["b", "c", "b", "a"]).root()
+        );
+        assert_ne!(
+            commitment.root(),
+            UnorderedCommitment::build(&["a", "b", "c", "c"]).root()
+        );
+    }
+
+    #[test]
+    fn proofs_verify_after_out_of_order_inserts() {
+        let mut commitment = UnorderedCommitment::default();
+        assert!(commitment.is_empty());
+        assert_eq!(commitment.root(), None);
+        assert_eq!(commitment.prove(&1), None);
+
+        for value in [500_u32, 3, 999, 3, 250, 0, 750, 1, 998] {
+            commitment.insert(value);
+        }
+        let root = commitment.root().unwrap();
+        for value in [500_u32, 3, 999, 250, 0, 750, 1, 998] {
+            let proof = commitment.prove(&value).unwrap();
+            assert!(proof.verify(value));
+            assert!(matches!(proof, MerkleProof::Proof { root: proven, .. } if proven == root));
+        }
+        assert_eq!(commitment.prove(&2), None);
+        commitment.tree.validate().unwrap();
+    }
+}
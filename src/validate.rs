@@ -0,0 +1,317 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use crate::{MerkleTree, hash_pair};
+
+/// Inconsistency found by `MerkleTree::validate`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The capacity is not a power of two.
+    CapacityNotPowerOfTwo(usize),
+    /// The tree does not have one level per halving of its capacity, plus the root's.
+    Height { expected: usize, found: usize },
+    /// A level does not hold half as many nodes as the one below it.
+    LevelLength {
+        level: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// The padding count exceeds the capacity.
+    Padding { capacity: usize, padding: usize },
+    /// The occupancy bitmap disagrees with the padding count about a leaf slot.
+    Occupancy { index: usize, expected: bool },
+    /// A node does not hold the expected hash: padded leaves must hold `PAD_HASH`
+    /// and parents the combination of their children.
+    Hash {
+        level: usize,
+        index: usize,
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::CapacityNotPowerOfTwo(capacity) => {
+                write!(f, "capacity {capacity} is not a power of two")
+            }
+            ValidationError::Height { expected, found } => {
+                write!(f, "expected {expected} levels, found {found}")
+            }
+            ValidationError::LevelLength {
+                level,
+                expected,
+                found,
+            } => write!(
+                f,
+                "expected {expected} nodes at level {level}, found {found}"
+            ),
+            ValidationError::Padding { capacity, padding } => {
+                write!(f, "padding {padding} exceeds capacity {capacity}")
+            }
+            ValidationError::Occupancy { index, expected } => write!(
+                f,
+                "leaf slot {index} is marked as {}",
+                if *expected { "padding" } else { "occupied" }
+            ),
+            ValidationError::Hash {
+                level,
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "node ({level}, {index}) holds {found:016x}, expected {expected:016x}"
+            ),
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+impl MerkleTree {
+    /// Checks the whole structure of the tree: the capacity is a power of two, every
+    /// level holds half as many nodes as the one below it, the padding count agrees with
+    /// the occupancy bitmap and the padded leaves, and every parent (the root included)
+    /// is the combination of its children. Nodes covering only pruned leaves (see
+    /// `from_frontier`) are skipped.
+    /// Returns the first inconsistency found, checking levels bottom-up and nodes left
+    /// to right.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("validate", leaves = self.len()).entered();
+        self.validate_shape()?;
+        for level_n in 1..self.height() {
+            // Nodes covering only pruned leaves are not held, so they cannot be checked.
+            let first_held = self.pruned >> level_n;
+            for index in first_held..self.levels.width(level_n) {
+                self.validate_node(level_n, index)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks everything `validate` does except for the parents' hashes.
+    pub(crate) fn validate_shape(&self) -> Result<(), ValidationError> {
+        if !self.capacity.is_power_of_two() {
+            return Err(ValidationError::CapacityNotPowerOfTwo(self.capacity));
+        }
+
+        let expected_height = self.capacity.ilog2() as usize + 1;
+        if self.height() != expected_height {
+            return Err(ValidationError::Height {
+                expected: expected_height,
+                found: self.height(),
+            });
+        }
+
+        for level_n in 0..self.height() {
+            let expected = self.capacity >> level_n;
+            let found = self.levels.width(level_n);
+            if found != expected {
+                return Err(ValidationError::LevelLength {
+                    level: level_n,
+                    expected,
+                    found,
+                });
+            }
+        }
+
+        if self.padding > self.capacity {
+            return Err(ValidationError::Padding {
+                capacity: self.capacity,
+                padding: self.padding,
+            });
+        }
+
+        let len = self.len();
+        for index in 0..self.capacity {
+            let expected = index < len;
+            if self.is_occupied(index) != expected {
+                return Err(ValidationError::Occupancy { index, expected });
+            }
+            let found = self.levels[(0, index)];
+            if !expected && found != MerkleTree::PAD_HASH {
+                return Err(ValidationError::Hash {
+                    level: 0,
+                    index,
+                    expected: MerkleTree::PAD_HASH,
+                    found,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a parent node is the combination of its children. The tree's shape
+    /// must have already been validated.
+    /// * `level` - Level of the node, above the leaves.
+    /// * `index` - Index of the node within its level.
+    pub(crate) fn validate_node(&self, level: usize, index: usize) -> Result<(), ValidationError> {
+        let expected = hash_pair(
+            self.levels[(level - 1, 2 * index)],
+            self.levels[(level - 1, 2 * index + 1)],
+        );
+        let found = self.levels[(level, index)];
+        if found != expected {
+            return Err(ValidationError::Hash {
+                level,
+                index,
+                expected,
+                found,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::AssertUnwindSafe;
+
+    use crate::{MerkleError, MerkleProof};
+
+    #[test]
+    fn consistent_trees_validate() {
+        for len in 0..20 {
+            let elements: Vec<usize> = (0..len).collect();
+            assert_eq!(MerkleTree::build(&elements).validate(), Ok(()));
+        }
+
+        let mut tree = MerkleTree::build::<u8>(&[]);
+        for value in 0..40 {
+            tree.push(value);
+            assert_eq!(tree.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn corrupted_internal_node_is_pinpointed() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5, 6]);
+        for level in 1..tree.height() {
+            for index in 0..tree.level_len(level).unwrap() {
+                let mut corrupted = MerkleTree::build(&[1, 2, 3, 4, 5, 6]);
+                let expected = tree.node(level, index).unwrap();
+                corrupted.corrupt_node(level, index, expected ^ 1);
+
+                assert_eq!(
+                    corrupted.validate(),
+                    Err(ValidationError::Hash {
+                        level,
+                        index,
+                        expected,
+                        found: expected ^ 1,
+                    })
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_leaf_is_reported_at_its_parent() {
+        let mut tree = MerkleTree::build(&[1, 2, 3, 4]);
+        let parent = tree.node(1, 1).unwrap();
+        tree.corrupt_node(0, 2, 7);
+
+        assert!(matches!(
+            tree.validate(),
+            Err(ValidationError::Hash {
+                level: 1,
+                index: 1,
+                found,
+                ..
+            }) if found == parent
+        ));
+    }
+
+    #[test]
+    fn corrupted_padding_is_pinpointed() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        tree.corrupt_node(0, 3, 7);
+
+        assert_eq!(
+            tree.validate(),
+            Err(ValidationError::Hash {
+                level: 0,
+                index: 3,
+                expected: MerkleTree::PAD_HASH,
+                found: 7,
+            })
+        );
+    }
+
+    #[test]
+    fn inconsistent_padding_count() {
+        let mut tree = MerkleTree::build(&[1, 2, 3]);
+        tree.padding = 2;
+        assert_eq!(
+            tree.validate(),
+            Err(ValidationError::Occupancy {
+                index: 2,
+                expected: false,
+            })
+        );
+    }
+
+    #[test]
+    fn corrupted_trees_report_errors() {
+        let mut tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        tree.levels.set_width(1, 1);
+        let corrupted = |level, index| MerkleError::Corrupted { level, index };
+
+        assert!(tree.get_proof(4) == MerkleProof::Invalid);
+        assert_eq!(tree.try_get_proof(4), Err(corrupted(1, 3)));
+        assert_eq!(
+            tree.try_get_proof(5),
+            Err(MerkleError::IndexOutOfRange { index: 5, len: 5 })
+        );
+        assert_eq!(tree.try_push(6), Err(corrupted(1, 2)));
+        assert_eq!(tree.len(), 5);
+
+        let error = std::panic::catch_unwind(AssertUnwindSafe(|| tree.push(6))).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<String>().map(String::as_str),
+            Some("cannot push into the tree: node (1, 2) is missing from a corrupted tree")
+        );
+
+        // A full tree reads its root through the last leaf's path when growing.
+        let mut tree = MerkleTree::build(&[1, 2, 3, 4]);
+        tree.levels.set_width(1, 1);
+        assert_eq!(tree.try_push(5), Err(corrupted(1, 1)));
+        tree.levels.shrink(0);
+        assert_eq!(tree.root(), None);
+        assert_eq!(tree.try_get_proof(0), Err(corrupted(0, 0)));
+    }
+
+    #[test]
+    fn malformed_shape() {
+        let mut tree = MerkleTree::build(&[1, 2, 3, 4]);
+        tree.levels.set_width(1, 1);
+        assert_eq!(
+            tree.validate(),
+            Err(ValidationError::LevelLength {
+                level: 1,
+                expected: 2,
+                found: 1,
+            })
+        );
+
+        tree.levels.shrink(2);
+        assert_eq!(
+            tree.validate(),
+            Err(ValidationError::Height {
+                expected: 3,
+                found: 2,
+            })
+        );
+
+        tree.capacity = 3;
+        assert_eq!(
+            tree.validate(),
+            Err(ValidationError::CapacityNotPowerOfTwo(3))
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{HashAlgorithm, MerkleProof, MerkleTree, root_to_hex};
+
+/// Amounts of leaves of the canonical inputs.
+pub const VECTOR_SIZES: [usize; 7] = [0, 1, 2, 3, 5, 8, 13];
+
+/// Returns the canonical input of the given amount of leaves: the byte strings
+/// `leaf-0`, `leaf-1` and so on, each hashed as a `&[u8]` value.
+/// * `len` - Amount of leaves.
+pub fn vector_input(len: usize) -> Vec<String> {
+    (0..len).map(|index| format!("leaf-{index}")).collect()
+}
+
+/// Proof of a leaf in a test vector.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VectorProof {
+    pub index: usize,
+    /// Sibling path, bottom-up, as canonical hex.
+    pub nodes: Vec<String>,
+}
+
+/// Known answers for a canonical input under a hash algorithm. Every hash is given in
+/// its canonical hex representation (see `root_to_hex`).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestVector {
+    /// Name of the hash algorithm, as in `HashAlgorithm`'s `Debug` representation.
+    pub algorithm: String,
+    /// The byte strings used as leaves, as UTF-8.
+    pub inputs: Vec<String>,
+    /// Hash of every leaf.
+    pub leaves: Vec<String>,
+    /// Every level bottom-up, padding included.
+    pub levels: Vec<Vec<String>>,
+    /// Root of the tree, or `None` for the empty input.
+    pub root: Option<String>,
+    /// Proof of every leaf.
+    pub proofs: Vec<VectorProof>,
+}
+
+/// Error returned when checking the crate against test vectors fails.
+#[derive(Debug)]
+pub enum VectorError {
+    /// The fixture is not valid JSON for a list of vectors.
+    Parse(serde_json::Error),
+    /// The fixture does not hold a vector per canonical input and algorithm.
+    Count { expected: usize, found: usize },
+    /// A value computed by the crate differs from the fixture's.
+    Mismatch {
+        /// Index of the vector within the fixture.
+        vector: usize,
+        /// Path of the value within the vector, such as `levels[1][0]`.
+        field: String,
+        expected: String,
+        found: String,
+    },
+}
+
+impl Display for VectorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            VectorError::Parse(error) => write!(f, "invalid test vectors: {error}"),
+            VectorError::Count { expected, found } => {
+                write!(f, "expected {expected} test vectors, found {found}")
+            }
+            VectorError::Mismatch {
+                vector,
+                field,
+                expected,
+                found,
+            } => write!(
+                f,
+                "vector {vector} drifted at {field}: fixture holds {expected}, crate computes {found}"
+            ),
+        }
+    }
+}
+
+impl Error for VectorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VectorError::Parse(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Computes the vector of an input under an algorithm.
+fn vector(algorithm: HashAlgorithm, inputs: Vec<String>) -> TestVector {
+    let values: Vec<&[u8]> = inputs.iter().map(|input| input.as_bytes()).collect();
+    let tree = MerkleTree::build(&values);
+    let hex = |nodes: &[u64]| nodes.iter().copied().map(root_to_hex).collect::<Vec<_>>();
+
+    let proofs = (0..tree.len())
+        .map(|index| match tree.get_proof(index) {
+            MerkleProof::Proof { nodes, .. } => VectorProof {
+                index,
+                nodes: hex(&nodes),
+            },
+            MerkleProof::Invalid => unreachable!("Every leaf has a proof"),
+        })
+        .collect();
+
+    TestVector {
+        algorithm: format!("{algorithm:?}"),
+        leaves: hex(tree.leaves()),
+        levels: tree.levels_iter().map(|level| hex(&level)).collect(),
+        root: tree.root_hex(),
+        proofs,
+        inputs,
+    }
+}
+
+/// Computes the vectors of every canonical input under every supported algorithm.
+pub fn generate_vectors() -> Vec<TestVector> {
+    [HashAlgorithm::DefaultHasher]
+        .into_iter()
+        .flat_map(|algorithm| {
+            VECTOR_SIZES
+                .into_iter()
+                .map(move |len| vector(algorithm, vector_input(len)))
+        })
+        .collect()
+}
+
+/// Returns the JSON representation of the vectors, as stored in fixtures.
+/// * `vectors` - The vectors to be encoded.
+pub fn vectors_to_json(vectors: &[TestVector]) -> String {
+    serde_json::to_string_pretty(vectors).expect("Vectors always serialize")
+}
+
+/// Difference between the fixture and the crate: the path of the value, the fixture's
+/// value and the crate's.
+type Difference = (String, String, String);
+
+/// Returns the difference between two values, if any.
+fn compare<T: PartialEq + fmt::Debug>(
+    field: String,
+    expected: &T,
+    found: &T,
+) -> Option<Difference> {
+    (expected != found).then(|| (field, format!("{expected:?}"), format!("{found:?}")))
+}
+
+/// Returns the first difference between two lists, lengths first.
+/// * `compare_item` - Returns the difference between the items at an index.
+fn compare_list<T>(
+    field: &str,
+    expected: &[T],
+    found: &[T],
+    compare_item: impl Fn(String, &T, &T) -> Option<Difference>,
+) -> Option<Difference> {
+    compare(format!("{field}.len"), &expected.len(), &found.len()).or_else(|| {
+        expected
+            .iter()
+            .zip(found)
+            .enumerate()
+            .find_map(|(index, (expected, found))| {
+                compare_item(format!("{field}[{index}]"), expected, found)
+            })
+    })
+}
+
+/// Returns the first difference between two vectors.
+fn compare_vectors(expected: &TestVector, found: &TestVector) -> Option<Difference> {
+    compare("algorithm".into(), &expected.algorithm, &found.algorithm)
+        .or_else(|| compare_list("inputs", &expected.inputs, &found.inputs, compare))
+        .or_else(|| compare_list("leaves", &expected.leaves, &found.leaves, compare))
+        .or_else(|| compare("root".into(), &expected.root, &found.root))
+        .or_else(|| {
+            compare_list(
+                "levels",
+                &expected.levels,
+                &found.levels,
+                |field, expected, found| compare_list(&field, expected, found, compare),
+            )
+        })
+        .or_else(|| {
+            compare_list(
+                "proofs",
+                &expected.proofs,
+                &found.proofs,
+                |field, expected, found| {
+                    compare(format!("{field}.index"), &expected.index, &found.index).or_else(|| {
+                        compare_list(
+                            &format!("{field}.nodes"),
+                            &expected.nodes,
+                            &found.nodes,
+                            compare,
+                        )
+                    })
+                },
+            )
+        })
+}
+
+/// Checks the crate against a fixture, as returned by `vectors_to_json`: every value
+/// the crate computes must equal the fixture's. The first difference is reported.
+/// * `json` - The fixture.
+pub fn verify_vectors(json: &str) -> Result<(), VectorError> {
+    let fixture: Vec<TestVector> = serde_json::from_str(json).map_err(VectorError::Parse)?;
+    let computed = generate_vectors();
+    if fixture.len() != computed.len() {
+        return Err(VectorError::Count {
+            expected: computed.len(),
+            found: fixture.len(),
+        });
+    }
+
+    for (vector, (expected, found)) in fixture.iter().zip(&computed).enumerate() {
+        if let Some((field, expected, found)) = compare_vectors(expected, found) {
+            return Err(VectorError::Mismatch {
+                vector,
+                field,
+                expected,
+                found,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../tests/vectors.json");
+
+    #[test]
+    fn crate_matches_fixture() {
+        if let Err(error) = verify_vectors(FIXTURE) {
+            panic!("{error}\nregenerate tests/vectors.json only if the change is intended");
+        }
+    }
+
+    #[test]
+    fn drift_reported() {
+        let mut vectors = generate_vectors();
+        vectors[4].levels[1][0] = root_to_hex(0);
+        let error = verify_vectors(&vectors_to_json(&vectors)).unwrap_err();
+        assert!(matches!(
+            error,
+            VectorError::Mismatch { vector: 4, ref field, .. } if field == "levels[1][0]"
+        ));
+
+        let mut vectors = generate_vectors();
+        vectors[6].proofs[2].nodes.pop();
+        let error = verify_vectors(&vectors_to_json(&vectors)).unwrap_err();
+        assert!(matches!(
+            error,
+            VectorError::Mismatch { vector: 6, ref field, ref expected, ref found }
+                if field == "proofs[2].nodes.len" && expected == "3" && found == "4"
+        ));
+
+        vectors.pop();
+        assert!(matches!(
+            verify_vectors(&vectors_to_json(&vectors)),
+            Err(VectorError::Count {
+                expected: 7,
+                found: 6
+            })
+        ));
+        assert!(matches!(verify_vectors("{}"), Err(VectorError::Parse(_))));
+    }
+
+    #[test]
+    fn vectors_are_consistent() {
+        for vector in generate_vectors() {
+            assert_eq!(vector.leaves.len(), vector.inputs.len());
+            assert_eq!(vector.proofs.len(), vector.inputs.len());
+            assert_eq!(vector.root.is_some(), !vector.inputs.is_empty());
+            assert_eq!(
+                vector.levels.len(),
+                vector.inputs.len().next_power_of_two().ilog2() as usize + 1
+            );
+        }
+    }
+}
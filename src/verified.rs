@@ -0,0 +1,270 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, ErrorKind, Write};
+
+use crate::{FileMerkle, FileMeta, MerkleProof, MerkleTree, hash_single};
+
+/// What a `VerifiedWriter` checks every chunk against.
+#[derive(Clone, Debug)]
+pub enum ChunkCheck {
+    /// The proof of every chunk, in chunk order.
+    Proofs(Vec<MerkleProof>),
+    /// The leaf hash of every chunk, in chunk order: the whole tree's description.
+    LeafHashes(Vec<u64>),
+}
+
+/// Error carried by the `io::Error` a `VerifiedWriter` returns when a chunk does not
+/// verify. It can be retrieved with `io::Error::get_ref`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkMismatch {
+    /// Index of the rejected chunk.
+    pub index: u64,
+}
+
+impl Display for ChunkMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "chunk {} does not verify", self.index)
+    }
+}
+
+impl Error for ChunkMismatch {}
+
+/// `Write` adapter verifying a chunked file as its bytes arrive. Each chunk is buffered
+/// until complete, checked against the trusted root, and only then forwarded to the
+/// inner writer, so unverified bytes never reach it.
+/// Writes stop at chunk boundaries, so `write_all` should be used. After a chunk is
+/// rejected, every write fails.
+pub struct VerifiedWriter<W: Write> {
+    inner: W,
+    root: u64,
+    meta: FileMeta,
+    check: ChunkCheck,
+    buffer: Vec<u8>,
+    next_chunk: u64,
+    failed: bool,
+}
+
+impl<W: Write> VerifiedWriter<W> {
+    /// Creates a writer forwarding verified chunks to `inner`.
+    /// Fails if the layout's chunk size is zero, if `check` does not hold an entry per
+    /// chunk, or if its leaf hashes do not produce the root.
+    /// * `inner` - Where verified bytes are written.
+    /// * `root` - The trusted root of the file's tree.
+    /// * `meta` - The trusted layout of the file.
+    /// * `check` - What every chunk is checked against.
+    pub fn new(inner: W, root: u64, meta: FileMeta, check: ChunkCheck) -> io::Result<Self> {
+        if meta.chunk_size == 0 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "chunk size must not be zero",
+            ));
+        }
+        let entries = match &check {
+            ChunkCheck::Proofs(proofs) => proofs.len(),
+            ChunkCheck::LeafHashes(leaves) => leaves.len(),
+        };
+        if entries as u64 != meta.chunk_count() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("expected {} chunks, found {entries}", meta.chunk_count()),
+            ));
+        }
+        if let ChunkCheck::LeafHashes(leaves) = &check
+            && MerkleTree::from_leaf_hashes(leaves.clone()).root() != Some(root)
+        {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "leaf hashes do not produce the root",
+            ));
+        }
+
+        Ok(VerifiedWriter {
+            inner,
+            root,
+            meta,
+            check,
+            buffer: Vec::with_capacity(meta.chunk_size),
+            next_chunk: 0,
+            failed: false,
+        })
+    }
+
+    /// Returns the amount of chunks verified and forwarded so far.
+    pub fn verified_chunks(&self) -> u64 {
+        self.next_chunk
+    }
+
+    /// Returns whether the buffered chunk verifies.
+    fn verify_buffer(&self) -> bool {
+        let index = self.next_chunk;
+        match &self.check {
+            ChunkCheck::Proofs(proofs) => {
+                let proof = &proofs[index as usize];
+                FileMerkle::verify_chunk(index, &self.buffer, proof, self.root, &self.meta)
+            }
+            ChunkCheck::LeafHashes(leaves) => {
+                leaves[index as usize] == hash_single(self.buffer.as_slice())
+            }
+        }
+    }
+
+    /// Checks that every chunk was received, flushes the inner writer and returns it.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.failed || self.next_chunk != self.meta.chunk_count() {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                format!(
+                    "{} of {} chunks received",
+                    self.next_chunk,
+                    self.meta.chunk_count()
+                ),
+            ));
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for VerifiedWriter<W> {
+    /// Buffers bytes up to the end of the current chunk. Once the chunk is complete, it is
+    /// verified and forwarded. Fails with a `ChunkMismatch` if it does not verify, and
+    /// with `InvalidInput` on bytes beyond the end of the file.
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        if self.failed {
+            return Err(io::Error::other("a previous chunk was rejected"));
+        }
+        if bytes.is_empty() {
+            return Ok(0);
+        }
+        let Some(chunk_len) = self.meta.chunk_len(self.next_chunk) else {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "bytes beyond the end of the file",
+            ));
+        };
+
+        let taken = bytes.len().min(chunk_len - self.buffer.len());
+        self.buffer.extend_from_slice(&bytes[..taken]);
+        if self.buffer.len() == chunk_len {
+            if !self.verify_buffer() {
+                self.failed = true;
+                self.buffer.clear();
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    ChunkMismatch {
+                        index: self.next_chunk,
+                    },
+                ));
+            }
+            self.inner.write_all(&self.buffer)?;
+            self.buffer.clear();
+            self.next_chunk += 1;
+        }
+        Ok(taken)
+    }
+
+    /// Flushes the inner writer. Bytes of an incomplete chunk stay buffered, since they
+    /// cannot be verified yet.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns `len` bytes of deterministic test data, its tree and its layout.
+    fn file(len: usize, chunk_size: usize) -> (Vec<u8>, MerkleTree, FileMeta) {
+        let bytes: Vec<u8> = (0..len).map(|byte| (byte * 13 % 251) as u8).collect();
+        let (tree, meta) = FileMerkle::build(&bytes[..], chunk_size).unwrap();
+        (bytes, tree, meta)
+    }
+
+    /// Returns the proof of every chunk.
+    fn proofs(tree: &MerkleTree) -> ChunkCheck {
+        ChunkCheck::Proofs((0..tree.len()).map(|index| tree.get_proof(index)).collect())
+    }
+
+    #[test]
+    fn clean_transfer() {
+        let (bytes, tree, meta) = file(2500, 1000);
+        let root = tree.root().unwrap();
+        for check in [
+            proofs(&tree),
+            ChunkCheck::LeafHashes(tree.leaves().to_vec()),
+        ] {
+            let mut writer = VerifiedWriter::new(Vec::new(), root, meta, check).unwrap();
+            // Writes straddling chunk boundaries.
+            for part in bytes.chunks(700) {
+                writer.write_all(part).unwrap();
+            }
+            assert_eq!(writer.verified_chunks(), 3);
+            assert_eq!(writer.finish().unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn byte_at_a_time() {
+        let (bytes, tree, meta) = file(300, 64);
+        let root = tree.root().unwrap();
+        let mut writer = VerifiedWriter::new(Vec::new(), root, meta, proofs(&tree)).unwrap();
+        for byte in &bytes {
+            assert_eq!(writer.write(std::slice::from_ref(byte)).unwrap(), 1);
+        }
+        assert_eq!(writer.finish().unwrap(), bytes);
+    }
+
+    #[test]
+    fn corrupted_chunk_not_forwarded() {
+        let (mut bytes, tree, meta) = file(2500, 1000);
+        let root = tree.root().unwrap();
+        bytes[1500] ^= 1;
+
+        let mut writer = VerifiedWriter::new(Vec::new(), root, meta, proofs(&tree)).unwrap();
+        let error = writer.write_all(&bytes).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let mismatch = error.get_ref().unwrap().downcast_ref::<ChunkMismatch>();
+        assert_eq!(mismatch, Some(&ChunkMismatch { index: 1 }));
+
+        assert_eq!(writer.inner, bytes[..1000]);
+        assert!(writer.write_all(&bytes[2000..]).is_err());
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn incomplete_and_excess_input_rejected() {
+        let (bytes, tree, meta) = file(2500, 1000);
+        let root = tree.root().unwrap();
+
+        let mut writer = VerifiedWriter::new(Vec::new(), root, meta, proofs(&tree)).unwrap();
+        writer.write_all(&bytes[..2499]).unwrap();
+        assert_eq!(
+            writer.finish().unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+
+        let mut writer = VerifiedWriter::new(Vec::new(), root, meta, proofs(&tree)).unwrap();
+        writer.write_all(&bytes).unwrap();
+        assert_eq!(
+            writer.write_all(&[0]).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+
+        let forged = ChunkCheck::LeafHashes(vec![1, 2, 3]);
+        assert!(VerifiedWriter::new(Vec::new(), root, meta, forged).is_err());
+        assert!(VerifiedWriter::new(Vec::new(), root, meta, ChunkCheck::Proofs(vec![])).is_err());
+    }
+
+    #[test]
+    fn zero_chunk_size_rejected() {
+        let meta = FileMeta {
+            size: 0,
+            chunk_size: 0,
+        };
+        let error = VerifiedWriter::new(Vec::new(), 0, meta, ChunkCheck::Proofs(vec![]))
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+}
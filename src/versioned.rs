@@ -0,0 +1,227 @@
+use std::collections::VecDeque;
+use std::hash::Hash;
+
+use crate::{DiffReport, MerkleProof, PersistentMerkleTree};
+
+/// Tree keeping every version it went through, queryable long after the fact: each
+/// mutation creates a new version, numbered after the previous one, and roots, proofs
+/// and differences can be asked of any retained version.
+/// Versions are `PersistentMerkleTree`s sharing their unchanged subtrees, so each one
+/// only takes the nodes along the path of its update. Versions older than a retention
+/// point can be dropped with `retain_from`, which frees the nodes no retained version
+/// holds.
+/// A version number is the epoch of its tree, so proofs record the version they were
+/// generated at.
+#[derive(Clone, Debug, Default)]
+pub struct VersionedMerkleTree {
+    /// Every retained version, the oldest first and the current one last.
+    versions: VecDeque<PersistentMerkleTree>,
+}
+
+impl VersionedMerkleTree {
+    /// Creates an empty tree, at version 0.
+    pub fn new() -> VersionedMerkleTree {
+        VersionedMerkleTree::from_tree(PersistentMerkleTree::new())
+    }
+
+    /// Constructs a tree populated with the provided elements as leaf nodes, at version 0.
+    /// * `elements` - array of `Hash` elements used to populate the tree.
+    pub fn build<H: Hash>(elements: &[H]) -> VersionedMerkleTree {
+        VersionedMerkleTree::from_tree(PersistentMerkleTree::build(elements))
+    }
+
+    /// Starts the history of versions at a tree.
+    /// * `tree` - The first version.
+    fn from_tree(tree: PersistentMerkleTree) -> VersionedMerkleTree {
+        VersionedMerkleTree {
+            versions: VecDeque::from([tree]),
+        }
+    }
+
+    /// Returns the tree at the current version.
+    pub fn current(&self) -> &PersistentMerkleTree {
+        self.versions
+            .back()
+            .expect("The current version is always retained")
+    }
+
+    /// Returns the current version number.
+    pub fn current_version(&self) -> u64 {
+        self.current().epoch()
+    }
+
+    /// Returns the number of the oldest retained version.
+    pub fn oldest_version(&self) -> u64 {
+        self.versions
+            .front()
+            .expect("The current version is always retained")
+            .epoch()
+    }
+
+    /// Returns the tree as of a version, or `None` if the version does not exist yet or
+    /// is no longer retained.
+    /// * `version` - The version number.
+    pub fn version(&self, version: u64) -> Option<&PersistentMerkleTree> {
+        let offset = version.checked_sub(self.oldest_version())?;
+        self.versions.get(usize::try_from(offset).ok()?)
+    }
+
+    /// Returns the root as of a version, or `None` if the tree was empty then or the
+    /// version is not retained.
+    /// * `version` - The version number.
+    pub fn root_of(&self, version: u64) -> Option<u64> {
+        self.version(version)?.root()
+    }
+
+    /// Creates a `MerkleProof` for a given index as of a version, or returns `None` if
+    /// the version is not retained. Indices which did not hold an element then return a
+    /// `MerkleProof::Invalid` value.
+    /// * `version` - The version number.
+    /// * `index` - index value to generate the proof for.
+    pub fn get_proof_of(&self, version: u64, index: usize) -> Option<MerkleProof> {
+        Some(self.version(version)?.get_proof(index))
+    }
+
+    /// Returns the leaves which differ between two versions, or `None` if either is not
+    /// retained. Only the subtrees updated in between are descended into.
+    /// * `first` - The version `diff` is called on.
+    /// * `second` - The version compared with.
+    pub fn diff_versions(&self, first: u64, second: u64) -> Option<DiffReport> {
+        Some(self.version(first)?.diff(self.version(second)?))
+    }
+
+    /// Pushes an `Hash` element, creating a new version. Returns its number.
+    /// * `value` - The `Hash` value to be added to the tree.
+    pub fn push<H: Hash>(&mut self, value: H) -> u64 {
+        let next = self.current().push(value);
+        self.record(next)
+    }
+
+    /// Replaces the element at an index, creating a new version. Returns its number.
+    /// Panics if the index does not hold an element.
+    /// * `index` - Index of the leaf.
+    /// * `value` - The new `Hash` value of the leaf.
+    pub fn set<H: Hash>(&mut self, index: usize, value: H) -> u64 {
+        let next = self.current().set(index, value);
+        self.record(next)
+    }
+
+    /// Makes a tree the current version.
+    /// * `tree` - The tree following the current version.
+    fn record(&mut self, tree: PersistentMerkleTree) -> u64 {
+        let version = tree.epoch();
+        self.versions.push_back(tree);
+        version
+    }
+
+    /// Drops every version older than a retention point, freeing the nodes which no
+    /// retained version holds. The current version is always retained.
+    /// Returns the amount of versions dropped.
+    /// * `version` - The oldest version to be retained.
+    pub fn retain_from(&mut self, version: u64) -> usize {
+        let version = version.min(self.current_version());
+        let dropped = usize::try_from(version.saturating_sub(self.oldest_version()))
+            .expect("Retained versions fit in memory");
+        self.versions.drain(..dropped);
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{MerkleTree, NaivePadding, assert_tree_matches_reference, naive_root};
+
+    /// Mutation of a tree: a push, or a write at an index taken modulo the length.
+    fn arb_mutation() -> impl Strategy<Value = (Option<usize>, u32)> {
+        (proptest::option::of(any::<usize>()), any::<u32>())
+    }
+
+    proptest! {
+        #[test]
+        fn past_versions_stay_provable(mutations in vec(arb_mutation(), 100)) {
+            let mut tree = VersionedMerkleTree::build(&[0_u32]);
+            let mut history = vec![vec![0_u32]];
+            for (index, value) in mutations {
+                let mut values = history.last().unwrap().clone();
+                let version = match index {
+                    Some(index) => {
+                        let index = index % values.len();
+                        values[index] = value;
+                        tree.set(index, value)
+                    }
+                    None => {
+                        values.push(value);
+                        tree.push(value)
+                    }
+                };
+                history.push(values);
+                prop_assert_eq!(version, history.len() as u64 - 1);
+            }
+            prop_assert_eq!(tree.current_version(), 100);
+
+            for version in [0, 1, 17, 50, 99, 100] {
+                let values = &history[version as usize];
+                let expected = MerkleTree::build(values);
+                assert_tree_matches_reference(&expected, values);
+                prop_assert_eq!(tree.root_of(version), expected.root());
+                let leaves: Vec<u64> = values.iter().map(crate::hash_single).collect();
+                prop_assert_eq!(
+                    tree.root_of(version),
+                    naive_root(&leaves, NaivePadding::PadHash)
+                );
+                for index in [0, values.len() / 2, values.len() - 1] {
+                    let proof = tree.get_proof_of(version, index).unwrap();
+                    prop_assert!(proof.verify(values[index]));
+                    prop_assert_eq!(proof.epoch(), Some(version));
+                }
+
+                let report = tree.diff_versions(version, 100).unwrap();
+                let current = MerkleTree::build(&history[100]);
+                prop_assert_eq!(report, expected.diff(&current));
+            }
+            prop_assert_eq!(tree.version(101).map(|tree| tree.len()), None);
+        }
+    }
+
+    #[test]
+    fn retention_frees_only_old_versions() {
+        let mut tree = VersionedMerkleTree::new();
+        for value in 0..100_u32 {
+            tree.push(value);
+        }
+        for index in 0..50 {
+            tree.set(index, 1000);
+        }
+        let handles: Vec<_> = (0..=150)
+            .map(|version| tree.version(version).unwrap().root_handle())
+            .collect();
+        let proofs: Vec<_> = (120..=150)
+            .map(|version| tree.get_proof_of(version, 30))
+            .collect();
+
+        assert_eq!(tree.retain_from(120), 120);
+        assert_eq!(tree.oldest_version(), 120);
+        assert_eq!(tree.root_of(119), None);
+        assert_eq!(tree.get_proof_of(50, 0), None);
+        assert_eq!(tree.diff_versions(100, 150), None);
+
+        // The roots of dropped versions were only held by them.
+        assert!(handles[..120].iter().all(|root| root.upgrade().is_none()));
+        assert!(handles[120..].iter().all(|root| root.upgrade().is_some()));
+        let retained: Vec<_> = (120..=150)
+            .map(|version| tree.get_proof_of(version, 30))
+            .collect();
+        assert_eq!(retained, proofs);
+        assert_eq!(tree.diff_versions(120, 150).unwrap().differing.len(), 30);
+
+        // The current version is never dropped.
+        assert_eq!(tree.retain_from(1000), 30);
+        assert_eq!(tree.oldest_version(), 150);
+        assert_eq!(tree.current().len(), 100);
+        assert_eq!(tree.retain_from(0), 0);
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::VecDeque;
+
+use crate::MerkleTree;
+
+/// Order in which `MerkleTree::visit` walks the nodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Traversal {
+    /// Depth-first, visiting each node before its children.
+    PreOrder,
+    /// Depth-first, visiting each node after its children.
+    PostOrder,
+    /// Breadth-first, from the root down, left to right within each level.
+    LevelOrder,
+}
+
+/// Returned by the visitor to decide how the traversal continues.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Control {
+    /// Keep visiting nodes.
+    Continue,
+    /// Do not visit the children of the current node. In post-order traversals the
+    /// children have already been visited, so it behaves like `Continue`.
+    SkipSubtree,
+    /// Stop the traversal.
+    Stop,
+}
+
+/// A node visited by `MerkleTree::visit`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NodeRef {
+    pub level: usize,
+    pub index: usize,
+    pub hash: u64,
+    /// Whether every leaf under the node is padding.
+    pub is_padding: bool,
+}
+
+impl NodeRef {
+    /// Returns whether the node is a leaf.
+    pub fn is_leaf(&self) -> bool {
+        self.level == 0
+    }
+
+    /// Returns the `(level, index)` coordinates of the node's children, or `None` for leaves.
+    pub fn children(&self) -> Option<[(usize, usize); 2]> {
+        if self.is_leaf() {
+            return None;
+        }
+        let level = self.level - 1;
+        Some([(level, 2 * self.index), (level, 2 * self.index + 1)])
+    }
+}
+
+impl MerkleTree {
+    /// Walks the nodes of the tree in the given order, calling the visitor on each of them.
+    /// The visitor's return value allows skipping the current node's subtree or stopping
+    /// the traversal. Nodes whose subtree only holds padding are only visited if
+    /// `include_padding` is set.
+    /// * `order` - The traversal order.
+    /// * `include_padding` - Whether padding nodes are visited.
+    /// * `visitor` - The function called on each visited node.
+    pub fn visit<F: FnMut(NodeRef) -> Control>(
+        &self,
+        order: Traversal,
+        include_padding: bool,
+        mut visitor: F,
+    ) {
+        let root = (self.height() - 1, 0);
+        match order {
+            Traversal::PreOrder => {
+                self.visit_pre_order(root, include_padding, &mut visitor);
+            }
+            Traversal::PostOrder => {
+                self.visit_post_order(root, include_padding, &mut visitor);
+            }
+            Traversal::LevelOrder => {
+                let mut queue = VecDeque::from([root]);
+                while let Some(coord) = queue.pop_front() {
+                    let Some(node) = self.node_ref(coord, include_padding) else {
+                        continue;
+                    };
+                    match visitor(node) {
+                        Control::Stop => return,
+                        Control::SkipSubtree => {}
+                        Control::Continue => queue.extend(node.children().into_iter().flatten()),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the `NodeRef` at the given coordinates, or `None` if it must not be visited.
+    fn node_ref(&self, (level, index): (usize, usize), include_padding: bool) -> Option<NodeRef> {
+        let is_padding = self.is_padded_subtree(level, index);
+        if is_padding && !include_padding {
+            return None;
+        }
+        Some(NodeRef {
+            level,
+            index,
+            hash: self.node(level, index)?,
+            is_padding,
+        })
+    }
+
+    /// Visits a subtree in pre-order. Returns `false` if the traversal was stopped.
+    fn visit_pre_order<F: FnMut(NodeRef) -> Control>(
+        &self,
+        coord: (usize, usize),
+        include_padding: bool,
+        visitor: &mut F,
+    ) -> bool {
+        let Some(node) = self.node_ref(coord, include_padding) else {
+            return true;
+        };
+        match visitor(node) {
+            Control::Stop => false,
+            Control::SkipSubtree => true,
+            Control::Continue => node
+                .children()
+                .into_iter()
+                .flatten()
+                .all(|child| self.visit_pre_order(child, include_padding, visitor)),
+        }
+    }
+
+    /// Visits a subtree in post-order. Returns `false` if the traversal was stopped.
+    fn visit_post_order<F: FnMut(NodeRef) -> Control>(
+        &self,
+        coord: (usize, usize),
+        include_padding: bool,
+        visitor: &mut F,
+    ) -> bool {
+        let Some(node) = self.node_ref(coord, include_padding) else {
+            return true;
+        };
+        let completed = node
+            .children()
+            .into_iter()
+            .flatten()
+            .all(|child| self.visit_post_order(child, include_padding, visitor));
+        completed && visitor(node) != Control::Stop
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the coordinates of the visited nodes.
+    fn visited(
+        tree: &MerkleTree,
+        order: Traversal,
+        include_padding: bool,
+        mut control: impl FnMut(&NodeRef) -> Control,
+    ) -> Vec<(usize, usize)> {
+        let mut coords = Vec::new();
+        tree.visit(order, include_padding, |node| {
+            coords.push((node.level, node.index));
+            control(&node)
+        });
+        coords
+    }
+
+    #[test]
+    fn node_counts() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4, 5]);
+        for order in [
+            Traversal::PreOrder,
+            Traversal::PostOrder,
+            Traversal::LevelOrder,
+        ] {
+            assert_eq!(visited(&tree, order, true, |_| Control::Continue).len(), 15);
+            // Without padding: 5 leaves, 3 parents, 2 grandparents and the root.
+            assert_eq!(
+                visited(&tree, order, false, |_| Control::Continue).len(),
+                11
+            );
+        }
+    }
+
+    #[test]
+    fn traversal_orders() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4]);
+        assert_eq!(
+            visited(&tree, Traversal::PreOrder, false, |_| Control::Continue),
+            [(2, 0), (1, 0), (0, 0), (0, 1), (1, 1), (0, 2), (0, 3)]
+        );
+        assert_eq!(
+            visited(&tree, Traversal::PostOrder, false, |_| Control::Continue),
+            [(0, 0), (0, 1), (1, 0), (0, 2), (0, 3), (1, 1), (2, 0)]
+        );
+        assert_eq!(
+            visited(&tree, Traversal::LevelOrder, false, |_| Control::Continue),
+            [(2, 0), (1, 0), (1, 1), (0, 0), (0, 1), (0, 2), (0, 3)]
+        );
+    }
+
+    #[test]
+    fn visited_hashes_match_nodes() {
+        let tree = MerkleTree::build(&[1, 2, 3]);
+        tree.visit(Traversal::PreOrder, true, |node| {
+            assert_eq!(tree.node(node.level, node.index), Some(node.hash));
+            assert_eq!(node.is_padding, (node.level, node.index) == (0, 3));
+            Control::Continue
+        });
+    }
+
+    #[test]
+    fn skip_subtree() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4]);
+        let skip_left = |node: &NodeRef| {
+            if (node.level, node.index) == (1, 0) {
+                Control::SkipSubtree
+            } else {
+                Control::Continue
+            }
+        };
+        assert_eq!(
+            visited(&tree, Traversal::PreOrder, false, skip_left),
+            [(2, 0), (1, 0), (1, 1), (0, 2), (0, 3)]
+        );
+        assert_eq!(
+            visited(&tree, Traversal::LevelOrder, false, skip_left),
+            [(2, 0), (1, 0), (1, 1), (0, 2), (0, 3)]
+        );
+    }
+
+    #[test]
+    fn early_termination() {
+        let tree = MerkleTree::build(&[1, 2, 3, 4]);
+        let stop_at_leaf = |node: &NodeRef| {
+            if node.is_leaf() {
+                Control::Stop
+            } else {
+                Control::Continue
+            }
+        };
+        assert_eq!(
+            visited(&tree, Traversal::PreOrder, false, stop_at_leaf),
+            [(2, 0), (1, 0), (0, 0)]
+        );
+        assert_eq!(
+            visited(&tree, Traversal::PostOrder, false, stop_at_leaf),
+            [(0, 0)]
+        );
+        assert_eq!(
+            visited(&tree, Traversal::LevelOrder, false, stop_at_leaf),
+            [(2, 0), (1, 0), (1, 1), (0, 0)]
+        );
+    }
+
+    #[test]
+    fn empty_tree_visits_padding_only_when_asked() {
+        let tree = MerkleTree::build::<u8>(&[]);
+        assert!(visited(&tree, Traversal::PreOrder, false, |_| Control::Continue).is_empty());
+        assert_eq!(
+            visited(&tree, Traversal::PreOrder, true, |_| Control::Continue),
+            [(0, 0)]
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use js_sys::{Array, Uint8Array};
+use wasm_bindgen::prelude::*;
+
+use crate::{MerkleProof, MerkleTree, hash_single, parse_root};
+
+/// Proof of inclusion usable from JavaScript, decoded from the compact encoding
+/// (see `MerkleProof::encode_into`).
+/// Values are handled as byte strings: proofs verify values committed as `&[u8]` or
+/// `Vec<u8>` elements.
+#[wasm_bindgen]
+pub struct WasmMerkleProof {
+    proof: MerkleProof,
+}
+
+#[wasm_bindgen]
+impl WasmMerkleProof {
+    /// Decodes a proof out of its compact encoding.
+    /// * `bytes` - The encoded proof.
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<WasmMerkleProof, JsError> {
+        Ok(WasmMerkleProof {
+            proof: MerkleProof::decode_compact(bytes)?,
+        })
+    }
+
+    /// Decodes a proof out of the hex representation of its compact encoding.
+    /// * `hex` - The encoded proof as hex digits.
+    #[wasm_bindgen(js_name = fromHex)]
+    pub fn from_hex(hex: &str) -> Result<WasmMerkleProof, JsError> {
+        if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+            return Err(JsError::new("invalid hex encoding"));
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|position| u8::from_str_radix(&hex[position..position + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| JsError::new("invalid hex encoding"))?;
+        WasmMerkleProof::from_bytes(&bytes)
+    }
+
+    /// Returns whether the proof shows that the value is committed in the tree with the
+    /// given root. Malformed roots never verify.
+    /// * `value` - The bytes of the committed value.
+    /// * `root_hex` - The expected root, in its canonical hex representation.
+    pub fn verify(&self, value: &[u8], root_hex: &str) -> bool {
+        match (&self.proof, parse_root(root_hex)) {
+            (MerkleProof::Proof { root, .. }, Ok(expected)) if *root == expected => {
+                self.proof.verify(value)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the index of the proven leaf, or `undefined` for invalid proofs.
+    pub fn index(&self) -> Option<usize> {
+        match &self.proof {
+            MerkleProof::Invalid => None,
+            MerkleProof::Proof { index, .. } => Some(*index),
+        }
+    }
+}
+
+/// Builds a tree out of byte string leaves and returns its root in its canonical hex
+/// representation, or `undefined` if there are no leaves.
+/// * `leaves` - Array of `Uint8Array` leaves.
+#[wasm_bindgen(js_name = buildTree)]
+pub fn build_tree(leaves: Array) -> Option<String> {
+    let leaves: Vec<u64> = leaves
+        .iter()
+        .map(|leaf| hash_single(Uint8Array::new(&leaf).to_vec()))
+        .collect();
+    MerkleTree::from_leaf_hashes(leaves).root_hex()
+}
@@ -0,0 +1,206 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Read, Write};
+
+/// Length of an encoded `Header`: the magic bytes, the version byte and the flags.
+pub const HEADER_LEN: usize = 4 + 1 + 2;
+
+/// Flags in this mask are optional: decoders ignore the ones they do not know.
+/// Flags outside of it are required: decoders reject the ones they do not know, since
+/// they change how the rest of the data must be read.
+pub const OPTIONAL_FLAGS: u16 = 0x00ff;
+
+/// Header preceding every persistent encoding of the crate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// Version of the format the data was written in.
+    pub version: u8,
+    /// Features of the encoding, see `OPTIONAL_FLAGS`.
+    pub flags: u16,
+}
+
+/// Description of an encoding, used to decode its header.
+pub(crate) struct Format {
+    /// Bytes identifying the encoding.
+    pub(crate) magic: [u8; 4],
+    /// Latest version of the encoding, which is the one written.
+    pub(crate) version: u8,
+    /// Flags understood by the decoder.
+    pub(crate) known_flags: u16,
+}
+
+/// Error returned when decoding a header fails.
+#[derive(Debug)]
+pub enum WireError {
+    /// Reading failed, including reaching the end of the input before the header did.
+    Io(io::Error),
+    /// The data does not start with the encoding's magic bytes.
+    BadMagic([u8; 4]),
+    /// The data was written in a version this crate cannot read.
+    UnsupportedVersion(u8),
+    /// The data uses a required flag, given by its bit, this crate does not know.
+    UnknownRequiredFlag(u8),
+}
+
+impl Display for WireError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            WireError::Io(error) => write!(f, "failed to read header: {error}"),
+            WireError::BadMagic(magic) => write!(f, "invalid magic bytes {magic:02x?}"),
+            WireError::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {version}")
+            }
+            WireError::UnknownRequiredFlag(bit) => write!(f, "unknown required flag bit {bit}"),
+        }
+    }
+}
+
+impl Error for WireError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WireError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for WireError {
+    fn from(error: io::Error) -> WireError {
+        WireError::Io(error)
+    }
+}
+
+impl Format {
+    /// Writes the header of the latest version of the encoding, returning the amount of
+    /// bytes written.
+    /// * `writer` - Where the header is written.
+    /// * `flags` - The flags describing the data that follows.
+    pub(crate) fn write_header<W: Write>(&self, writer: &mut W, flags: u16) -> io::Result<usize> {
+        writer.write_all(&self.magic)?;
+        writer.write_all(&[self.version])?;
+        writer.write_all(&flags.to_le_bytes())?;
+        Ok(HEADER_LEN)
+    }
+
+    /// Reads and checks a header: the magic bytes must match, the version must not be
+    /// newer than the encoding's and every required flag must be known. Unknown optional
+    /// flags are kept in the returned header, so callers must ignore them.
+    /// * `reader` - Where the header is read from.
+    pub(crate) fn read_header<R: Read>(&self, reader: &mut R) -> Result<Header, WireError> {
+        let mut bytes = [0; HEADER_LEN];
+        reader.read_exact(&mut bytes)?;
+
+        let magic = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if magic != self.magic {
+            return Err(WireError::BadMagic(magic));
+        }
+
+        let version = bytes[4];
+        if version == 0 || version > self.version {
+            return Err(WireError::UnsupportedVersion(version));
+        }
+
+        let flags = u16::from_le_bytes([bytes[5], bytes[6]]);
+        let unknown_required = flags & !OPTIONAL_FLAGS & !self.known_flags;
+        if unknown_required != 0 {
+            return Err(WireError::UnknownRequiredFlag(
+                unknown_required.trailing_zeros() as u8,
+            ));
+        }
+
+        Ok(Header { version, flags })
+    }
+}
+
+/// Reads a little-endian `u64`.
+pub(crate) fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Reads a little-endian `u64` and converts it into a `usize`.
+pub(crate) fn read_usize<R: Read>(reader: &mut R) -> io::Result<usize> {
+    usize::try_from(read_u64(reader)?)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "value does not fit in a usize"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMAT: Format = Format {
+        magic: *b"TEST",
+        version: 2,
+        known_flags: 0x0100,
+    };
+
+    /// Returns the encoded header.
+    fn encoded(version: u8, flags: u16) -> Vec<u8> {
+        let mut bytes = b"TEST".to_vec();
+        bytes.push(version);
+        bytes.extend_from_slice(&flags.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let mut bytes = Vec::new();
+        assert_eq!(FORMAT.write_header(&mut bytes, 0x0101).unwrap(), HEADER_LEN);
+        assert_eq!(bytes, encoded(2, 0x0101));
+        assert_eq!(
+            FORMAT.read_header(&mut bytes.as_slice()).unwrap(),
+            Header {
+                version: 2,
+                flags: 0x0101
+            }
+        );
+    }
+
+    #[test]
+    fn older_versions_accepted() {
+        let header = FORMAT.read_header(&mut encoded(1, 0).as_slice()).unwrap();
+        assert_eq!(header.version, 1);
+    }
+
+    #[test]
+    fn future_version_rejected() {
+        assert!(matches!(
+            FORMAT.read_header(&mut encoded(3, 0).as_slice()),
+            Err(WireError::UnsupportedVersion(3))
+        ));
+        assert!(matches!(
+            FORMAT.read_header(&mut encoded(0, 0).as_slice()),
+            Err(WireError::UnsupportedVersion(0))
+        ));
+    }
+
+    #[test]
+    fn flag_policy() {
+        // Unknown optional flags are ignored.
+        let header = FORMAT
+            .read_header(&mut encoded(2, 0x0080).as_slice())
+            .unwrap();
+        assert_eq!(header.flags, 0x0080);
+
+        // Unknown required flags are rejected, naming the lowest one.
+        assert!(matches!(
+            FORMAT.read_header(&mut encoded(2, 0x8600).as_slice()),
+            Err(WireError::UnknownRequiredFlag(9))
+        ));
+    }
+
+    #[test]
+    fn malformed_headers_rejected() {
+        let mut bytes = encoded(2, 0);
+        bytes[0] = b'X';
+        assert!(matches!(
+            FORMAT.read_header(&mut bytes.as_slice()),
+            Err(WireError::BadMagic(magic)) if &magic == b"XEST"
+        ));
+        assert!(matches!(
+            FORMAT.read_header(&mut &encoded(2, 0)[..HEADER_LEN - 1]),
+            Err(WireError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,157 @@
+use std::io::{self, Write};
+
+use crate::{MerkleTree, hash_single};
+
+/// `Write` adapter building the tree of the bytes written through it, so that copying a
+/// stream and building its tree take a single pass. Bytes are forwarded to the inner
+/// writer as they arrive, and split into chunks whose leaves are hashed as `&[u8]`
+/// values, like `MerkleTree::build_from_read` does.
+pub struct TreeWriter<W: Write> {
+    inner: W,
+    chunk_size: usize,
+    buffer: Vec<u8>,
+    leaves: Vec<u64>,
+}
+
+impl<W: Write> TreeWriter<W> {
+    /// Creates a writer forwarding bytes to `inner`.
+    /// Panics if `chunk_size` is zero.
+    /// * `inner` - Where the bytes are written.
+    /// * `chunk_size` - Length of every chunk but the last.
+    pub fn new(inner: W, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "chunk size must not be zero");
+        TreeWriter {
+            inner,
+            chunk_size,
+            buffer: Vec::with_capacity(chunk_size),
+            leaves: Vec::new(),
+        }
+    }
+
+    /// Returns the amount of complete chunks written so far.
+    pub fn chunk_count(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Hashes the final chunk if it is incomplete, flushes the inner writer and returns
+    /// it, together with the tree of every byte written. The tree is identical to the
+    /// one `MerkleTree::build_from_read` builds out of the same bytes, and is empty if
+    /// nothing was written.
+    pub fn finish(mut self) -> io::Result<(W, MerkleTree)> {
+        if !self.buffer.is_empty() {
+            self.leaves.push(hash_single(self.buffer.as_slice()));
+        }
+        self.inner.flush()?;
+        Ok((self.inner, MerkleTree::from_leaf_hashes(self.leaves)))
+    }
+}
+
+impl<W: Write> Write for TreeWriter<W> {
+    /// Writes bytes to the inner writer, then adds the ones it accepted to the chunks.
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(bytes)?;
+        let mut rest = &bytes[..written];
+        while !rest.is_empty() {
+            let taken = rest.len().min(self.chunk_size - self.buffer.len());
+            self.buffer.extend_from_slice(&rest[..taken]);
+            rest = &rest[taken..];
+            if self.buffer.len() == self.chunk_size {
+                self.leaves.push(hash_single(self.buffer.as_slice()));
+                self.buffer.clear();
+            }
+        }
+        Ok(written)
+    }
+
+    /// Flushes the inner writer. Bytes of an incomplete chunk stay buffered until the
+    /// chunk is complete or the writer is finished.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns `len` bytes, varied enough for every chunk to differ.
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|index| (index * 31 % 251) as u8).collect()
+    }
+
+    /// Writes the bytes in writes of the given sizes, cycling through them, and returns
+    /// what the inner writer received with the tree.
+    fn write_in(bytes: &[u8], sizes: &[usize], chunk_size: usize) -> (Vec<u8>, MerkleTree) {
+        let mut writer = TreeWriter::new(Vec::new(), chunk_size);
+        let mut rest = bytes;
+        for &size in sizes.iter().cycle() {
+            if rest.is_empty() {
+                break;
+            }
+            let (head, tail) = rest.split_at(size.min(rest.len()));
+            writer.write_all(head).unwrap();
+            rest = tail;
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn streamed_trees_match_chunked_builds() {
+        for len in [1, 63, 64, 65, 1000, 4096, 10_000] {
+            let bytes = data(len);
+            let expected = MerkleTree::build_from_read(&bytes[..], 64).unwrap();
+            for sizes in [&[1][..], &[64], &[1000], &[3, 200, 64, 1]] {
+                let (written, tree) = write_in(&bytes, sizes, 64);
+                assert_eq!(written, bytes);
+                assert_eq!(tree, expected);
+                assert_eq!(tree.len(), len.div_ceil(64));
+            }
+        }
+    }
+
+    #[test]
+    fn empty_streams_produce_empty_trees() {
+        let (written, tree) = TreeWriter::new(Vec::new(), 64).finish().unwrap();
+        assert!(written.is_empty());
+        assert!(tree.is_empty());
+        assert_eq!(tree, MerkleTree::build_from_read(&[][..], 64).unwrap());
+
+        let mut writer = TreeWriter::new(Vec::new(), 64);
+        writer.write_all(&[]).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.chunk_count(), 0);
+        assert!(writer.finish().unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn partial_inner_writes_are_hashed_as_accepted() {
+        /// Writer accepting a few bytes at a time.
+        struct Narrow(Vec<u8>);
+
+        impl Write for Narrow {
+            fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+                let accepted = bytes.len().min(5);
+                self.0.extend_from_slice(&bytes[..accepted]);
+                Ok(accepted)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let bytes = data(1000);
+        let mut writer = TreeWriter::new(Narrow(Vec::new()), 64);
+        writer.write_all(&bytes).unwrap();
+        assert_eq!(writer.chunk_count(), 15);
+        let (inner, tree) = writer.finish().unwrap();
+        assert_eq!(inner.0, bytes);
+        assert_eq!(tree, MerkleTree::build_from_read(&bytes[..], 64).unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "chunk size must not be zero")]
+    fn zero_chunk_sizes_panic() {
+        TreeWriter::new(Vec::new(), 0);
+    }
+}
@@ -0,0 +1,33 @@
+//! Run with `wasm-pack test --node -- --features wasm`.
+#![cfg(all(feature = "wasm", target_arch = "wasm32"))]
+
+use js_sys::{Array, Uint8Array};
+use merkle_tree::{MerkleTree, WasmMerkleProof, build_tree};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const VALUES: [&[u8]; 3] = [b"alpha", b"beta", b"gamma"];
+
+#[wasm_bindgen_test]
+fn proof_round_trip() {
+    let tree = MerkleTree::build(&VALUES);
+    let root = tree.root_hex().unwrap();
+
+    let leaves: Array = VALUES
+        .iter()
+        .map(|&value| Uint8Array::from(value))
+        .collect();
+    assert_eq!(build_tree(leaves), Some(root.clone()));
+
+    for (index, value) in VALUES.iter().enumerate() {
+        let proof = tree.get_proof(index);
+        let mut bytes = vec![0; proof.compact_len()];
+        proof.encode_into(&mut bytes).unwrap();
+        let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+
+        let decoded = WasmMerkleProof::from_hex(&hex).unwrap();
+        assert!(decoded.verify(value, &root));
+        assert!(!decoded.verify(b"delta", &root));
+        assert!(!decoded.verify(value, "0000000000000000"));
+        assert_eq!(decoded.index(), Some(index));
+    }
+}